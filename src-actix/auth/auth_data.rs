@@ -30,7 +30,7 @@ pub struct UpdateUserRequest {
     pub permissions: Option<Vec<String>>,
 }
 
-#[derive(Debug, HashIds)]
+#[derive(Debug, Clone, HashIds)]
 pub struct User {
     #[hash]
     pub id: u64,
@@ -7,6 +7,13 @@ use sqlx::{Error, Executor, SqlitePool};
 
 pub async fn initialize() -> Result<()> {
     let pool = create_pool().await?;
+    initialize_with_pool(&pool).await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+pub async fn initialize_with_pool(pool: &SqlitePool) -> Result<()> {
     pool.execute(
         r#"
 CREATE TABLE IF NOT EXISTS users
@@ -19,8 +26,6 @@ CREATE TABLE IF NOT EXISTS users
 "#,
     )
     .await?;
-    pool.close().await;
-
     Ok(())
 }
 
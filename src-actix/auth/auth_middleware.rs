@@ -1,9 +1,9 @@
 use crate::auth::auth_data::User;
 use crate::auth::auth_endpoint::TOKEN_COOKIE_KEY;
-use actix_web::Error;
 use actix_web::dev::{Service, forward_ready};
 use actix_web::dev::{ServiceRequest, ServiceResponse, Transform};
 use actix_web::error::ErrorUnauthorized;
+use actix_web::{Error, HttpMessage, HttpRequest};
 use futures::future::{LocalBoxFuture, Ready, ready};
 use std::rc::Rc;
 
@@ -64,6 +64,7 @@ where
                                         let host = connection_info.host().to_owned();
                                         if let Ok(is_valid) = user.authenticate_with_session_token(ip_address, &host, token) {
                                             if is_valid {
+                                                req.extensions_mut().insert(user);
                                                 return service.call(req).await;
                                             }
                                         }
@@ -85,6 +86,7 @@ where
                     for user in users {
                         if let Ok(is_valid) = user.authenticate_with_session_token(ip, &host, &token) {
                             if is_valid {
+                                req.extensions_mut().insert(user);
                                 return service.call(req).await;
                             }
                         }
@@ -96,3 +98,11 @@ where
         })
     }
 }
+
+/// The user that was authenticated by the [`Authentication`] middleware for this request.
+pub fn authenticated_user(req: &HttpRequest) -> crate::helpers::http_error::Result<User> {
+    req.extensions()
+        .get::<User>()
+        .cloned()
+        .ok_or_else(|| crate::helpers::http_error::Error::authentication_error("Missing or invalid authentication token"))
+}
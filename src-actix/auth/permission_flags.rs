@@ -101,6 +101,12 @@ impl PermissionFlags {
     pub fn all() -> u8 {
         (Self::Read | Self::Write | Self::Delete | Self::Create | Self::Upload | Self::Download).bits_c()
     }
+    /// The permissions needed to move or rename a file or directory, over any protocol.
+    ///
+    /// The item is changed at its destination and taken away from its source, so both `Write` and `Delete` are needed.
+    pub fn moving() -> BitFlags<PermissionFlags> {
+        Self::Write | Self::Delete
+    }
     /// Converts permissions strings into a `BitFlags<PermissionFlags>`.
     ///
     /// # Parameters
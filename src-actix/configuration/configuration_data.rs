@@ -1,12 +1,15 @@
+use crate::jobs::schedule_data::ScheduleConfiguration;
 use log::{debug, warn};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
+use std::time::Duration;
 
 static CONFIGURATION: OnceLock<Configuration> = OnceLock::new();
 static CONFIGURATION_PATH: OnceLock<Option<String>> = OnceLock::new();
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Configuration {
     pub port: u16,
     pub root_path: String,
@@ -21,6 +24,115 @@ pub struct Configuration {
     /// By default, it includes localhost, 127.0.0.1 and the server's own IP address.
     pub authorized_hosts: Vec<String>,
     pub cors_enabled: bool,
+    /// Serves the filesystem over SFTP on `sftp_port`.
+    pub sftp_enabled: bool,
+    pub sftp_port: u16,
+    /// Roots every SFTP user at `root_path/<username>` instead of `root_path`.
+    pub sftp_home_directories: bool,
+    /// Directories and buckets published as named folders at the top of the tree, next to the contents of `root_path`.
+    pub mounts: Vec<MountConfiguration>,
+    /// Paths, as clients see them, that are indexed and watched instead of `root_path` and the mounts.
+    pub index_roots: Vec<String>,
+    /// Indexes the text of files with an `included_extensions` extension for full-text search.
+    pub content_indexing_enabled: bool,
+    /// The size in bytes above which files are left out of the full-text index.
+    pub content_max_file_size: u64,
+    /// Hashes the files that may be copies of each other when the index is reconciled, to find duplicates.
+    pub content_hashing_enabled: bool,
+    /// When the periodic maintenance tasks run. Tasks left out only run when triggered.
+    pub schedules: Vec<ScheduleConfiguration>,
+    /// How the file watcher follows `root_path` and the mounts that don't set their own mode.
+    pub watcher_mode: WatcherMode,
+    /// The seconds between two scans of a directory watched in `poll` mode.
+    pub watcher_poll_interval: u64,
+}
+
+/// How the file watcher follows the changes of a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatcherMode {
+    /// Notifications from the operating system, such as inotify. Changes made to a network share by other machines
+    /// are not reported.
+    #[default]
+    Native,
+    /// Scans the directory every `watcher_poll_interval` seconds.
+    Poll,
+    /// Not watched, changes are only picked up when the index is reconciled.
+    Off,
+}
+
+/// A directory or bucket published as a top-level folder of the tree.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MountConfiguration {
+    /// The name of the folder the mount appears as, e.g. `Media`.
+    pub name: String,
+    /// Rejects uploads, copies into, moves, renames and deletes inside the mount.
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default = "default_mount_indexing")]
+    pub indexing: bool,
+    /// Glob patterns, relative to the mount, of paths left out of the index. Used instead of the global `filter`.
+    #[serde(default)]
+    pub filter: Vec<String>,
+    /// Used instead of the global `watcher_mode` and `watcher_poll_interval` when set.
+    #[serde(default)]
+    pub watcher_mode: Option<WatcherMode>,
+    #[serde(default)]
+    pub watcher_poll_interval: Option<u64>,
+    #[serde(flatten)]
+    pub storage: MountStorage,
+}
+
+fn default_mount_indexing() -> bool {
+    true
+}
+
+impl MountConfiguration {
+    /// The path clients see the mount at.
+    pub fn virtual_path(&self) -> String {
+        format!("/{}", self.name)
+    }
+
+    /// Where the mount lives among the OS paths produced by `NormalizePath`.
+    ///
+    /// Local mounts are their own directory, other storage is given a place below `root_path` that the
+    /// storage backend is looked up by.
+    pub fn os_path(&self, root_path: &str) -> PathBuf {
+        match &self.storage {
+            MountStorage::Local(local) => PathBuf::from(&local.path),
+            MountStorage::S3(_) => PathBuf::from(root_path).join(&self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MountStorage {
+    Local(LocalMountConfiguration),
+    S3(S3MountConfiguration),
+}
+
+/// A directory of this machine.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LocalMountConfiguration {
+    pub path: String,
+}
+
+/// An S3 compatible bucket, such as AWS S3 or MinIO.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct S3MountConfiguration {
+    /// The service URL, e.g. `https://s3.eu-central-1.amazonaws.com` or `http://localhost:9000`.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// The key prefix the mount starts at, empty for the whole bucket.
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Addresses the bucket as `<endpoint>/<bucket>` instead of `<bucket>.<endpoint>`, as MinIO expects.
+    #[serde(default)]
+    pub path_style: bool,
 }
 
 impl Configuration {
@@ -33,7 +145,8 @@ impl Configuration {
         }
     }
     pub fn get_path() -> &'static Option<String> {
-        CONFIGURATION_PATH.get().expect("Configuration path OnceLock not initialized")
+        // Without a path, loading and saving fail instead, as in tests that never set one
+        CONFIGURATION_PATH.get().unwrap_or(&None)
     }
     pub fn set_path(path: impl AsRef<Path>) -> anyhow::Result<()> {
         debug!("Setting configuration path to {:?}", path.as_ref());
@@ -72,6 +185,51 @@ impl Configuration {
         debug!("Resetting configuration");
         Self::default().save()
     }
+    /// A copy to hand out over the API, with the secret keys of the S3 mounts left empty.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for mount in &mut config.mounts {
+            if let MountStorage::S3(s3) = &mut mount.storage {
+                s3.secret_access_key.clear();
+            }
+        }
+        config
+    }
+    /// Fills in the secret keys [`Configuration::redacted`] left empty from `previous`, for S3 mounts that kept their
+    /// name, bucket and access key.
+    pub fn restore_secrets(&mut self, previous: &Configuration) {
+        for mount in &mut self.mounts {
+            let MountStorage::S3(s3) = &mut mount.storage else {
+                continue;
+            };
+            if !s3.secret_access_key.is_empty() {
+                continue;
+            }
+            let stored = previous.mounts.iter().find_map(|previous_mount| match &previous_mount.storage {
+                MountStorage::S3(stored)
+                    if previous_mount.name == mount.name && stored.bucket == s3.bucket && stored.access_key_id == s3.access_key_id =>
+                {
+                    Some(stored)
+                }
+                _ => None,
+            });
+            if let Some(stored) = stored {
+                s3.secret_access_key = stored.secret_access_key.clone();
+            }
+        }
+    }
+    /// The mount that stores the OS path `path`, if any.
+    pub fn mount_for(&self, path: &Path) -> Option<&MountConfiguration> {
+        self.mounts.iter().find(|mount| path.starts_with(mount.os_path(&self.root_path)))
+    }
+    /// How the directory `path` is watched and how often it is scanned in `poll` mode, by the settings of its mount
+    /// or else the global ones.
+    pub fn watcher_for(&self, path: &Path) -> (WatcherMode, Duration) {
+        let mount = self.mount_for(path);
+        let mode = mount.and_then(|mount| mount.watcher_mode).unwrap_or(self.watcher_mode);
+        let poll_interval = mount.and_then(|mount| mount.watcher_poll_interval).unwrap_or(self.watcher_poll_interval);
+        (mode, Duration::from_secs(poll_interval))
+    }
 }
 
 impl Default for Configuration {
@@ -141,6 +299,9 @@ impl Default for Configuration {
             filter: ignored_paths,
             included_extensions: vec![
                 ".txt".to_string(),
+                ".md".to_string(),
+                ".csv".to_string(),
+                ".json".to_string(),
                 ".pdf".to_string(),
                 ".doc".to_string(),
                 ".docx".to_string(),
@@ -154,6 +315,17 @@ impl Default for Configuration {
             upnp_enabled: false,
             authorized_hosts: vec!["127.0.0.1".to_string(), "localhost".to_string(), server_computer_ip_address],
             cors_enabled: true,
+            sftp_enabled: false,
+            sftp_port: 2222,
+            sftp_home_directories: false,
+            mounts: Vec::new(),
+            index_roots: Vec::new(),
+            content_indexing_enabled: true,
+            content_max_file_size: 5 * 1024 * 1024,
+            content_hashing_enabled: false,
+            schedules: ScheduleConfiguration::defaults(),
+            watcher_mode: WatcherMode::Native,
+            watcher_poll_interval: 60,
         }
     }
 }
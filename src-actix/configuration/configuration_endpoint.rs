@@ -1,6 +1,10 @@
 use crate::configuration::configuration_data::Configuration;
 use crate::configuration::upnp;
-use crate::helpers::http_error::Result;
+use crate::helpers::http_error::{Error, Result};
+use crate::io::fs::indexer::indexer_data;
+use crate::io::fs::indexer::path_filter::PathFilter;
+use crate::jobs::schedule_data::CronSchedule;
+use crate::jobs::scheduler;
 use actix_web::{HttpResponse, get, post, web};
 use actix_web::{Responder, delete};
 use serde_json::json;
@@ -9,19 +13,49 @@ use std::collections::HashMap;
 #[get("/")]
 pub async fn get_config(query: web::Query<HashMap<String, String>>) -> Result<impl Responder> {
     let config = if query.get("reload").is_some() { &Configuration::load()? } else { Configuration::get() };
-    Ok(HttpResponse::Ok().json(config))
+    Ok(HttpResponse::Ok().json(config.redacted()))
 }
 
 #[post("/")]
 pub async fn update_config(body: web::Json<Configuration>) -> Result<impl Responder> {
     // Store the old configuration for comparison
     let old_config = Configuration::get().clone();
+    // The configuration is handed out without the mount secrets, keep the stored ones of mounts that come back without
+    let mut config = body.into_inner();
+    config.restore_secrets(&Configuration::load().unwrap_or_else(|_| old_config.clone()));
+
+    for schedule in &config.schedules {
+        CronSchedule::parse(&schedule.cron)
+            .map_err(|e| Error::validation_error(format!("Invalid schedule for {}: {}", schedule.task.as_str(), e), Some("schedules")))?;
+    }
+
+    let poll_intervals = config.mounts.iter().filter_map(|mount| mount.watcher_poll_interval).chain([config.watcher_poll_interval]);
+    for poll_interval in poll_intervals {
+        if poll_interval == 0 {
+            return Err(Error::validation_error("The watcher poll interval must be at least 1 second", Some("watcher_poll_interval")));
+        }
+    }
+
+    PathFilter::compile(&config).map_err(|e| Error::validation_error(format!("Invalid filter pattern: {}", e.message), Some(e.field)))?;
+
+    // Paths are mapped into the mounts, and the mounts given their storage, once at startup, so a change would
+    // only reach the index
+    if config.mounts != old_config.mounts {
+        return Err(Error::validation_error(
+            "Changing mounts requires a restart, edit the configuration file and restart the server",
+            Some("mounts"),
+        ));
+    }
 
     // Save the new configuration
-    body.0.save()?;
+    config.save()?;
+
+    // Move the index and the file watcher along with the directories they cover
+    indexer_data::handle_config_change(&config);
+    scheduler::handle_config_change(&config);
 
     // Handle UPnP port forwarding based on configuration changes
-    if let Err(upnp_error) = upnp::handle_config_change(&old_config, &body.0) {
+    if let Err(upnp_error) = upnp::handle_config_change(&old_config, &config) {
         // Configuration was saved, but UPnP failed
         return Ok(HttpResponse::Ok().json(json!({
             "success": true,
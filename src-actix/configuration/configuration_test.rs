@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests {
+    use crate::configuration::configuration_data::{Configuration, MountConfiguration, MountStorage, S3MountConfiguration};
+
+    fn s3_mount(name: &str, secret_access_key: &str) -> MountConfiguration {
+        MountConfiguration {
+            name: name.to_string(),
+            read_only: false,
+            indexing: true,
+            filter: Vec::new(),
+            watcher_mode: None,
+            watcher_poll_interval: None,
+            storage: MountStorage::S3(S3MountConfiguration {
+                endpoint: "http://localhost:9000".to_string(),
+                region: "us-east-1".to_string(),
+                bucket: "media".to_string(),
+                prefix: String::new(),
+                access_key_id: "FILERTESTKEY".to_string(),
+                secret_access_key: secret_access_key.to_string(),
+                path_style: true,
+            }),
+        }
+    }
+
+    fn secret(config: &Configuration) -> &str {
+        match &config.mounts[0].storage {
+            MountStorage::S3(s3) => &s3.secret_access_key,
+            MountStorage::Local(_) => panic!("Expected an S3 mount"),
+        }
+    }
+
+    #[test]
+    fn test_mount_secrets_are_redacted() {
+        let config = Configuration { mounts: vec![s3_mount("Bucket", "very-secret-key")], ..Configuration::default() };
+        let json = serde_json::to_string(&config.redacted()).unwrap();
+        assert!(!json.contains("very-secret-key"));
+        assert!(json.contains("FILERTESTKEY"));
+        // The stored configuration keeps it
+        assert!(serde_json::to_string(&config).unwrap().contains("very-secret-key"));
+
+        // Sent back as it was handed out, the secret is taken from the stored configuration
+        let mut updated = config.redacted();
+        updated.restore_secrets(&config);
+        assert_eq!(secret(&updated), "very-secret-key");
+
+        // A new secret is kept, and a renamed mount doesn't inherit one
+        let mut updated = Configuration { mounts: vec![s3_mount("Bucket", "new-secret-key")], ..Configuration::default() };
+        updated.restore_secrets(&config);
+        assert_eq!(secret(&updated), "new-secret-key");
+        let mut updated = Configuration { mounts: vec![s3_mount("Other", "")], ..Configuration::default() };
+        updated.restore_secrets(&config);
+        assert_eq!(secret(&updated), "");
+    }
+}
+
+#[cfg(test)]
+mod request_tests {
+    use crate::configuration::configuration_data::{Configuration, LocalMountConfiguration, MountConfiguration, MountStorage};
+    use crate::configuration::configuration_endpoint;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test, web};
+
+    #[actix_web::test]
+    async fn test_mount_changes_require_a_restart() {
+        let app = test::init_service(App::new().service(web::scope("/api").configure(configuration_endpoint::configure))).await;
+        let mount = MountConfiguration {
+            name: "Media".to_string(),
+            read_only: false,
+            indexing: true,
+            filter: Vec::new(),
+            watcher_mode: None,
+            watcher_poll_interval: None,
+            storage: MountStorage::Local(LocalMountConfiguration { path: "/mnt/media".to_string() }),
+        };
+        let config = Configuration { mounts: vec![mount], ..Configuration::get().clone() };
+
+        // Refused before anything is saved, as the running server would keep serving the mounts it started with
+        let req = test::TestRequest::post().uri("/api/config/").set_json(&config).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "mounts");
+    }
+}
@@ -1,3 +1,6 @@
 pub mod configuration_data;
 pub mod configuration_endpoint;
 pub mod upnp;
+
+#[cfg(test)]
+mod configuration_test;
@@ -0,0 +1,186 @@
+use crate::auth::auth_data::User;
+use crate::auth::permission_flags::PermissionFlags;
+use crate::dav::dav_filesystem::FilerFs;
+use crate::helpers::db::create_pool;
+use crate::io::fs::normalize_path::resolve_path;
+use crate::io::storage::storage_backend;
+use crate::io::storage::storage_backend::StorageTree;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use dav_server::actix::{DavRequest, DavResponse};
+use dav_server::davpath::DavPath;
+use dav_server::memls::MemLs;
+use dav_server::{DavConfig, DavHandler};
+use enumflags2::BitFlags;
+use log::*;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// The url prefix the WebDAV server is mounted under.
+pub(crate) const DAV_PREFIX: &str = "/dav";
+
+/// How long verified Basic credentials are remembered.
+/// WebDAV clients authenticate every request, and verifying a bcrypt hash each time would make browsing crawl.
+const CREDENTIALS_CACHE_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// The password hash each authorization header was verified against, and when.
+type CredentialsCache = Arc<Mutex<HashMap<String, (String, Instant)>>>;
+
+static DAV_HANDLER: OnceLock<DavHandler> = OnceLock::new();
+static VERIFIED_CREDENTIALS: OnceLock<CredentialsCache> = OnceLock::new();
+
+/// The shared handler, which keeps the WebDAV locks alive across requests.
+fn get_dav_handler() -> &'static DavHandler {
+    DAV_HANDLER.get_or_init(|| DavHandler::builder().strip_prefix(DAV_PREFIX).locksystem(MemLs::new()).build_handler())
+}
+
+fn get_verified_credentials() -> &'static CredentialsCache {
+    VERIFIED_CREDENTIALS.get_or_init(|| Arc::new(Mutex::new(HashMap::new())))
+}
+
+/// Splits the value of a `Basic` authorization header into the username and password.
+pub(crate) fn parse_basic_credentials(authorization: &str) -> Option<(String, String)> {
+    let encoded = authorization.strip_prefix("Basic ").or_else(|| authorization.strip_prefix("basic "))?;
+    let decoded = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Authenticates a WebDAV client with Basic auth, or with the session token headers used by the rest of the API.
+///
+/// The user is looked up on every request, so that deleted users, new passwords and changed permissions apply right
+/// away. Only the password check is remembered, for as long as the password stays the same.
+async fn authenticate(req: &HttpRequest, pool: &SqlitePool) -> Option<User> {
+    let headers = req.headers();
+    if let Some(authorization) = headers.get(header::AUTHORIZATION).and_then(|header| header.to_str().ok()) {
+        let (username, password) = parse_basic_credentials(authorization)?;
+        let user = User::get_by_username_with_connection(&username, pool).await.ok()??;
+        {
+            let cache = get_verified_credentials().lock().await;
+            if let Some((hash, verified_at)) = cache.get(authorization)
+                && *hash == user.password
+                && verified_at.elapsed() < CREDENTIALS_CACHE_DURATION
+            {
+                return Some(user);
+            }
+        }
+
+        if !bcrypt::verify(&password, &user.password).unwrap_or(false) {
+            warn!("Failed WebDAV login for {}", username);
+            return None;
+        }
+        let mut cache = get_verified_credentials().lock().await;
+        cache.retain(|_, (_, verified_at)| verified_at.elapsed() < CREDENTIALS_CACHE_DURATION);
+        cache.insert(authorization.to_string(), (user.password.clone(), Instant::now()));
+        return Some(user);
+    }
+
+    let token = headers.get("X-Authentication")?.to_str().ok()?;
+    let username = headers.get("X-Username")?.to_str().ok()?;
+    let user = User::get_by_username_with_connection(username, pool).await.ok()??;
+    let connection_info = req.connection_info();
+    let ip_address = connection_info.realip_remote_addr()?;
+    user.authenticate_with_session_token(ip_address, connection_info.host(), token).ok()?.then_some(user)
+}
+
+/// The permissions a user needs for a WebDAV method.
+///
+/// `target_exists` tells whether the request target is already on disk, so that `PUT` can tell
+/// overwriting a file apart from uploading a new one.
+pub(crate) fn required_permissions(method: &str, target_exists: bool) -> BitFlags<PermissionFlags> {
+    match method {
+        "OPTIONS" => BitFlags::empty(),
+        "PROPFIND" => PermissionFlags::Read.into(),
+        "GET" | "HEAD" => PermissionFlags::Read | PermissionFlags::Download,
+        "PUT" if target_exists => PermissionFlags::Write.into(),
+        "PUT" => PermissionFlags::Upload.into(),
+        "MKCOL" => PermissionFlags::Create.into(),
+        "COPY" => PermissionFlags::Read | PermissionFlags::Create,
+        "MOVE" => PermissionFlags::moving(),
+        "DELETE" => PermissionFlags::Delete.into(),
+        // PROPPATCH, LOCK, UNLOCK and anything unknown modify the resource
+        _ => PermissionFlags::Write.into(),
+    }
+}
+
+/// Converts a url path under [`DAV_PREFIX`] to a path relative to the root path.
+pub(crate) fn relative_path(url_path: &str) -> Option<PathBuf> {
+    let mut path = DavPath::new(url_path).ok()?;
+    path.set_prefix(DAV_PREFIX).ok()?;
+    Some(path.as_rel_ospath().to_path_buf())
+}
+
+/// Extracts the url path from a `Destination` header, which holds an absolute url.
+pub(crate) fn destination_path(destination: &str) -> &str {
+    match destination.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|index| &rest[index..]).unwrap_or("/"),
+        None => destination,
+    }
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"Filer\", charset=\"UTF-8\"")).finish()
+}
+
+/// Handles every request under [`DAV_PREFIX`].
+async fn handle_dav(req: HttpRequest, dav_request: DavRequest) -> HttpResponse {
+    match create_pool().await {
+        Ok(pool) => handle_dav_with(req, dav_request, storage_backend::tree(), &pool).await,
+        Err(e) => {
+            error!("Failed to open the database for WebDAV: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Serves the files of `tree` over WebDAV to the users of `pool`.
+pub(crate) async fn handle_dav_with(req: HttpRequest, dav_request: DavRequest, tree: &Arc<StorageTree>, pool: &SqlitePool) -> HttpResponse {
+    let method = req.method().as_str().to_uppercase();
+    // Clients probe the server with OPTIONS before sending credentials
+    let user = match authenticate(&req, pool).await {
+        Some(user) => Some(user),
+        None if method == "OPTIONS" => None,
+        None => return unauthorized(),
+    };
+
+    let resolve = |url_path: &str| relative_path(url_path).and_then(|relative| resolve_path(&relative.to_string_lossy(), tree.config()));
+    let Some(target) = resolve(req.path()) else {
+        return HttpResponse::Forbidden().finish();
+    };
+    // dav-server empties a folder file by file before removing it, which would clear a mount that can't be removed
+    if matches!(method.as_str(), "DELETE" | "MOVE") && (tree.is_mount(&target) || target == Path::new(&tree.config().root_path)) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let mut required = required_permissions(&method, tree.backend_for(&target).stat(&target).await.is_ok());
+    if let Some(destination) = req.headers().get("Destination").and_then(|header| header.to_str().ok()) {
+        let Some(destination) = resolve(destination_path(destination)) else {
+            return HttpResponse::Forbidden().finish();
+        };
+        // Replacing an existing destination deletes it first
+        if tree.backend_for(&destination).stat(&destination).await.is_ok() {
+            required |= PermissionFlags::Delete;
+        }
+    }
+    if let Some(user) = &user
+        && !user.permissions.contains(required)
+    {
+        debug!("{} is missing {:?} for WebDAV {} {}", user.username, required, method, req.path());
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let mut config = DavConfig::new().filesystem(FilerFs::new(tree.clone()));
+    if let Some(user) = &user {
+        config = config.principal(user.username.clone());
+    }
+    DavResponse::from(get_dav_handler().handle_with(config, dav_request.request).await).respond_to(&req)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope(DAV_PREFIX).default_service(web::to(handle_dav)));
+}
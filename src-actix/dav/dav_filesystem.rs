@@ -0,0 +1,270 @@
+use crate::io::fs::normalize_path::resolve_path;
+use crate::io::storage::storage_backend::{ByteReader, ByteWriter, StorageBackend, StorageEntry, StorageTree};
+use actix_web::web::Bytes;
+use dav_server::davpath::DavPath;
+use dav_server::fs::{DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsError, FsFuture, FsResult, FsStream, OpenOptions, ReadDirMeta};
+use futures::{FutureExt, future, stream};
+use std::fmt;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+/// Serves a [`StorageTree`] over WebDAV. Paths are mapped like the rest of the API, mounts included, and every
+/// file is reached through the backend that stores it.
+#[derive(Clone)]
+pub(crate) struct FilerFs {
+    tree: Arc<StorageTree>,
+}
+
+fn fs_error(error: std::io::Error) -> FsError {
+    match error.kind() {
+        ErrorKind::NotFound => FsError::NotFound,
+        ErrorKind::PermissionDenied => FsError::Forbidden,
+        ErrorKind::AlreadyExists => FsError::Exists,
+        _ if error.raw_os_error().is_some() => error.into(),
+        _ => FsError::GeneralFailure,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Metadata(StorageEntry);
+
+impl DavMetaData for Metadata {
+    fn len(&self) -> u64 {
+        self.0.size
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.0.last_modified.ok_or(FsError::NotImplemented)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.0.is_dir
+    }
+
+    fn created(&self) -> FsResult<SystemTime> {
+        self.0.created.ok_or(FsError::NotImplemented)
+    }
+}
+
+struct DirEntry(StorageEntry);
+
+impl DavDirEntry for DirEntry {
+    fn name(&self) -> Vec<u8> {
+        self.0.name.as_bytes().to_vec()
+    }
+
+    fn metadata(&self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        future::ready(Ok(Box::new(Metadata(self.0.clone())) as Box<dyn DavMetaData>)).boxed()
+    }
+}
+
+enum Stream {
+    /// Opened from the current position on the first read after a seek.
+    Read { size: u64, reader: Option<Mutex<ByteReader>> },
+    /// Taken once the file is flushed, which is when backends store it.
+    Write(Option<Mutex<ByteWriter>>),
+}
+
+/// A file opened over WebDAV. Backends read and write files as streams, so a file is read from wherever the client
+/// seeks to, but only ever written from the start.
+struct StreamFile {
+    path: PathBuf,
+    backend: Arc<dyn StorageBackend>,
+    position: u64,
+    stream: Stream,
+}
+
+impl fmt::Debug for StreamFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamFile").field("path", &self.path).field("position", &self.position).finish_non_exhaustive()
+    }
+}
+
+impl DavFile for StreamFile {
+    fn metadata(&mut self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        async move {
+            let entry = self.backend.stat(&self.path).await.map_err(fs_error)?;
+            Ok(Box::new(Metadata(entry)) as Box<dyn DavMetaData>)
+        }
+        .boxed()
+    }
+
+    fn write_buf(&mut self, mut buf: Box<dyn actix_web::web::Buf + Send>) -> FsFuture<'_, ()> {
+        let bytes = buf.copy_to_bytes(buf.remaining());
+        self.write_bytes(bytes)
+    }
+
+    fn write_bytes(&mut self, buf: Bytes) -> FsFuture<'_, ()> {
+        async move {
+            let Stream::Write(Some(writer)) = &mut self.stream else {
+                return Err(FsError::GeneralFailure);
+            };
+            writer.get_mut().write_all(&buf).await.map_err(fs_error)?;
+            self.position += buf.len() as u64;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn read_bytes(&mut self, count: usize) -> FsFuture<'_, Bytes> {
+        async move {
+            let Stream::Read { size, reader } = &mut self.stream else {
+                return Err(FsError::GeneralFailure);
+            };
+            if self.position >= *size {
+                return Ok(Bytes::new());
+            }
+            if reader.is_none() {
+                let opened = self.backend.read(&self.path, Some(self.position..*size)).await.map_err(fs_error)?;
+                *reader = Some(Mutex::new(opened));
+            }
+            let mut data = vec![0u8; count.min((*size - self.position) as usize)];
+            let read = reader.as_mut().map(Mutex::get_mut).ok_or(FsError::GeneralFailure)?.read(&mut data).await.map_err(fs_error)?;
+            data.truncate(read);
+            self.position += read as u64;
+            Ok(Bytes::from(data))
+        }
+        .boxed()
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
+        let position = match (pos, &self.stream) {
+            (SeekFrom::Start(offset), _) => Some(offset),
+            (SeekFrom::Current(offset), _) => self.position.checked_add_signed(offset),
+            (SeekFrom::End(offset), Stream::Read { size, .. }) => size.checked_add_signed(offset),
+            (SeekFrom::End(_), Stream::Write(_)) => None,
+        };
+        let result = match (position, &mut self.stream) {
+            (Some(position), _) if position == self.position => Ok(position),
+            (Some(position), Stream::Read { reader, .. }) => {
+                *reader = None;
+                self.position = position;
+                Ok(position)
+            }
+            (Some(_), Stream::Write(_)) => Err(FsError::NotImplemented),
+            (None, _) => Err(FsError::GeneralFailure),
+        };
+        future::ready(result).boxed()
+    }
+
+    fn flush(&mut self) -> FsFuture<'_, ()> {
+        async move {
+            if let Stream::Write(writer) = &mut self.stream
+                && let Some(mut writer) = writer.take()
+            {
+                writer.get_mut().shutdown().await.map_err(fs_error)?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+impl FilerFs {
+    pub(crate) fn new(tree: Arc<StorageTree>) -> Box<Self> {
+        Box::new(Self { tree })
+    }
+
+    fn os_path(&self, path: &DavPath) -> FsResult<PathBuf> {
+        resolve_path(&path.as_rel_ospath().to_string_lossy(), self.tree.config()).ok_or(FsError::Forbidden)
+    }
+
+    async fn stat(&self, path: &Path) -> FsResult<StorageEntry> {
+        self.tree.backend_for(path).stat(path).await.map_err(fs_error)
+    }
+}
+
+impl DavFileSystem for FilerFs {
+    fn open<'a>(&'a self, path: &'a DavPath, options: OpenOptions) -> FsFuture<'a, Box<dyn DavFile>> {
+        async move {
+            let path = self.os_path(path)?;
+            let backend = self.tree.backend_for(&path);
+            let existing = backend.stat(&path).await.ok();
+            if existing.as_ref().is_some_and(|entry| entry.is_dir) {
+                return Err(FsError::Forbidden);
+            }
+            let stream = if options.write || options.append {
+                match &existing {
+                    Some(_) if options.create_new => return Err(FsError::Exists),
+                    None if !options.create => return Err(FsError::NotFound),
+                    _ => {}
+                }
+                if options.append || (!options.truncate && existing.is_some_and(|entry| entry.size > 0)) {
+                    return Err(FsError::NotImplemented);
+                }
+                Stream::Write(Some(Mutex::new(backend.write(&path).await.map_err(fs_error)?)))
+            } else {
+                Stream::Read { size: existing.ok_or(FsError::NotFound)?.size, reader: None }
+            };
+            Ok(Box::new(StreamFile { path, backend, position: 0, stream }) as Box<dyn DavFile>)
+        }
+        .boxed()
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a DavPath, _meta: ReadDirMeta) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        async move {
+            let entries = self.tree.list(&self.os_path(path)?).await.map_err(fs_error)?;
+            let entries = entries.into_iter().map(|entry| Ok(Box::new(DirEntry(entry)) as Box<dyn DavDirEntry>));
+            Ok(Box::pin(stream::iter(entries)) as FsStream<Box<dyn DavDirEntry>>)
+        }
+        .boxed()
+    }
+
+    fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        async move { Ok(Box::new(Metadata(self.stat(&self.os_path(path)?).await?)) as Box<dyn DavMetaData>) }.boxed()
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let path = self.os_path(path)?;
+            if self.stat(&path).await.is_ok() {
+                return Err(FsError::Exists);
+            }
+            // Backends create missing parents, which MKCOL must not
+            if let Some(parent) = path.parent() {
+                self.stat(parent).await?;
+            }
+            self.tree.backend_for(&path).create_dir(&path).await.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn remove_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let path = self.os_path(path)?;
+            if path == Path::new(&self.tree.config().root_path) || self.tree.is_mount(&path) {
+                return Err(FsError::Forbidden);
+            }
+            self.tree.backend_for(&path).delete(&path).await.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let path = self.os_path(path)?;
+            self.tree.backend_for(&path).delete(&path).await.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let (from, to) = (self.os_path(from)?, self.os_path(to)?);
+            if self.tree.is_mount(&from) {
+                return Err(FsError::Forbidden);
+            }
+            // Moves between mounts are copied over and removed, like moves over HTTP
+            self.tree.rename(&from, &to).await.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn copy<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+        async move { self.tree.copy(&self.os_path(from)?, &self.os_path(to)?).await.map_err(fs_error) }.boxed()
+    }
+}
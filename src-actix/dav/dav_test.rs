@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission_flags::PermissionFlags;
+    use crate::dav::dav_endpoint::{destination_path, parse_basic_credentials, relative_path, required_permissions};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_basic_credentials() {
+        // "testuser:pass:word" encoded as base64
+        assert_eq!(parse_basic_credentials("Basic dGVzdHVzZXI6cGFzczp3b3Jk"), Some(("testuser".to_string(), "pass:word".to_string())));
+        assert_eq!(parse_basic_credentials("Bearer dGVzdHVzZXI6cGFzczp3b3Jk"), None);
+        assert_eq!(parse_basic_credentials("Basic not-base64!"), None);
+    }
+
+    #[test]
+    fn test_required_permissions() {
+        assert!(required_permissions("OPTIONS", true).is_empty());
+        assert_eq!(required_permissions("PROPFIND", true), PermissionFlags::Read);
+        assert!(required_permissions("GET", true).contains(PermissionFlags::Download));
+        assert_eq!(required_permissions("PUT", false), PermissionFlags::Upload);
+        assert_eq!(required_permissions("PUT", true), PermissionFlags::Write);
+        assert_eq!(required_permissions("MKCOL", false), PermissionFlags::Create);
+        assert_eq!(required_permissions("DELETE", true), PermissionFlags::Delete);
+        assert_eq!(required_permissions("LOCK", true), PermissionFlags::Write);
+    }
+
+    #[test]
+    fn test_dav_paths() {
+        assert_eq!(relative_path("/dav"), Some(PathBuf::from("")));
+        assert_eq!(relative_path("/dav/Documents/My%20Report.pdf"), Some(PathBuf::from("Documents/My Report.pdf")));
+        assert_eq!(relative_path("/dav/../etc/passwd"), None);
+        assert_eq!(relative_path("/other/file.txt"), None);
+        assert_eq!(destination_path("http://localhost:7667/dav/Documents/copy.txt"), "/dav/Documents/copy.txt");
+        assert_eq!(destination_path("/dav/Documents/copy.txt"), "/dav/Documents/copy.txt");
+    }
+}
+
+#[cfg(test)]
+mod request_tests {
+    use crate::auth::auth_data::User;
+    use crate::auth::auth_db;
+    use crate::auth::permission_flags::PermissionFlags;
+    use crate::configuration::configuration_data::{Configuration, LocalMountConfiguration, MountConfiguration, MountStorage};
+    use crate::dav::dav_endpoint::{DAV_PREFIX, handle_dav_with};
+    use crate::io::storage::storage_backend::StorageTree;
+    use actix_web::http::{Method, StatusCode, header};
+    use actix_web::{App, Scope, test, web};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use dav_server::actix::DavRequest;
+    use enumflags2::BitFlags;
+    use sqlx::SqlitePool;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn dav_request(method: &str, path: &str, password: &str) -> test::TestRequest {
+        test::TestRequest::default()
+            .method(Method::from_bytes(method.as_bytes()).unwrap())
+            .uri(path)
+            .insert_header((header::AUTHORIZATION, format!("Basic {}", BASE64.encode(format!("davuser:{}", password)))))
+    }
+
+    /// A database with the WebDAV user, holding `permissions`.
+    async fn user_pool(permissions: BitFlags<PermissionFlags>) -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.expect("Failed to open database");
+        auth_db::initialize_with_pool(&pool).await.unwrap();
+        let user = User { id: 0, username: "davuser".to_string(), password: "secret".to_string(), permissions };
+        user.create_with_pool(&pool).await.unwrap();
+        pool
+    }
+
+    /// Serves the tree of `root` and `mounts` over WebDAV.
+    fn dav_scope(root: &Path, mounts: Vec<MountConfiguration>, pool: &SqlitePool) -> Scope {
+        let config = Configuration { root_path: root.to_string_lossy().to_string(), mounts, ..Configuration::default() };
+        let tree = Arc::new(StorageTree::new(config));
+        let pool = pool.clone();
+        web::scope(DAV_PREFIX).default_service(web::to(move |req, dav_request: DavRequest| {
+            let (tree, pool) = (tree.clone(), pool.clone());
+            async move { handle_dav_with(req, dav_request, &tree, &pool).await }
+        }))
+    }
+
+    #[actix_web::test]
+    async fn test_dav_requests() {
+        let root = tempdir().expect("Failed to create temp dir");
+        std::fs::write(root.path().join("notes.txt"), b"notes").unwrap();
+        let permissions = PermissionFlags::Read | PermissionFlags::Write | PermissionFlags::Upload;
+        let pool = user_pool(permissions).await;
+        let app = test::init_service(App::new().service(dav_scope(root.path(), Vec::new(), &pool))).await;
+
+        let resp = test::call_service(&app, dav_request("PROPFIND", "/dav/", "secret").insert_header(("Depth", "1")).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("notes.txt"));
+        let resp = test::call_service(&app, dav_request("PROPFIND", "/dav/", "wrong").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let resp = test::call_service(&app, dav_request("PUT", "/dav/upload.txt", "secret").set_payload("uploaded").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(std::fs::read_to_string(root.path().join("upload.txt")).unwrap(), "uploaded");
+
+        // Moving a file takes it away from its source, which needs Delete
+        let move_request = || dav_request("MOVE", "/dav/upload.txt", "secret").insert_header(("Destination", "/dav/moved.txt"));
+        let resp = test::call_service(&app, move_request().to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // Permission changes apply to the next request, with the stored password hash kept as is
+        let mut stored = User::get_by_username_with_connection("davuser", &pool).await.unwrap().unwrap();
+        stored.permissions = permissions | BitFlags::from(PermissionFlags::Delete);
+        stored.update_with_pool(&pool).await.unwrap();
+        let resp = test::call_service(&app, move_request().to_request()).await;
+        assert!(resp.status().is_success(), "MOVE failed with {}", resp.status());
+        assert!(root.path().join("moved.txt").exists());
+        assert!(!root.path().join("upload.txt").exists());
+
+        // So does removing the user, even though its credentials were verified before
+        stored.delete_with_pool(&pool).await.unwrap();
+        let resp = test::call_service(&app, dav_request("PROPFIND", "/dav/", "secret").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_dav_mounts() {
+        let (root, media, archive) = (
+            tempdir().expect("Failed to create temp dir"),
+            tempdir().expect("Failed to create temp dir"),
+            tempdir().expect("Failed to create temp dir"),
+        );
+        std::fs::write(media.path().join("song.mp3"), b"music").unwrap();
+        std::fs::write(archive.path().join("old.txt"), b"old").unwrap();
+        let mount = |name: &str, path: &Path, read_only: bool| MountConfiguration {
+            name: name.to_string(),
+            read_only,
+            indexing: false,
+            filter: Vec::new(),
+            watcher_mode: None,
+            watcher_poll_interval: None,
+            storage: MountStorage::Local(LocalMountConfiguration { path: path.to_string_lossy().to_string() }),
+        };
+        let mounts = vec![mount("Media", media.path(), false), mount("Archive", archive.path(), true)];
+        let pool = user_pool(BitFlags::all()).await;
+        let app = test::init_service(App::new().service(dav_scope(root.path(), mounts, &pool))).await;
+
+        // Mounts are listed at the top of the tree and served from where they are stored, as over HTTP
+        let resp = test::call_service(&app, dav_request("PROPFIND", "/dav/", "secret").insert_header(("Depth", "1")).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("/dav/Media/") && body.contains("/dav/Archive/"));
+        let resp = test::call_service(&app, dav_request("GET", "/dav/Media/song.mp3", "secret").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(test::read_body(resp).await, "music");
+
+        let resp = test::call_service(&app, dav_request("PUT", "/dav/Media/new.txt", "secret").set_payload("uploaded").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(std::fs::read_to_string(media.path().join("new.txt")).unwrap(), "uploaded");
+
+        // Read-only mounts refuse every change, and mounts can't be removed like the folders they appear as
+        let resp = test::call_service(&app, dav_request("PUT", "/dav/Archive/new.txt", "secret").set_payload("uploaded").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, dav_request("DELETE", "/dav/Archive/old.txt", "secret").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, dav_request("DELETE", "/dav/Media/", "secret").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(archive.path().join("old.txt").exists());
+        assert!(!archive.path().join("new.txt").exists());
+        assert!(media.path().join("song.mp3").exists());
+    }
+}
@@ -0,0 +1,5 @@
+pub(crate) mod dav_endpoint;
+pub(crate) mod dav_filesystem;
+
+#[cfg(test)]
+mod dav_test;
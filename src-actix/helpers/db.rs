@@ -2,10 +2,13 @@ use log::LevelFilter;
 use sqlx::sqlite::SqliteSynchronous::Normal;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
 use sqlx::{ConnectOptions, SqlitePool};
-use std::str::FromStr;
+
+/// The database file every pool opens, relative to the working directory.
+pub const DATABASE_PATH: &str = "./app.db";
 
 pub async fn create_pool() -> anyhow::Result<SqlitePool> {
-    let options = SqliteConnectOptions::from_str("sqlite:./app.db")?
+    let options = SqliteConnectOptions::new()
+        .filename(DATABASE_PATH)
         .create_if_missing(true)
         .journal_mode(SqliteJournalMode::Wal)
         .log_statements(LevelFilter::Trace)
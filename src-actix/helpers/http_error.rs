@@ -44,6 +44,14 @@ pub enum Error {
     #[error("Validation error: {message}")]
     ValidationError { message: String, field: Option<String> },
 
+    /// The resource existed but is no longer available
+    #[error("Gone: {message}")]
+    Gone { message: String },
+
+    /// The request conflicts with something already in progress
+    #[error("Conflict: {message}")]
+    Conflict { message: String },
+
     /// Rate limit exceeded error
     #[error("Rate limit exceeded")]
     RateLimitExceeded,
@@ -68,6 +76,8 @@ impl ResponseError for Error {
             Self::AuthenticationError { .. } => StatusCode::UNAUTHORIZED,
             Self::AuthorizationError { .. } => StatusCode::FORBIDDEN,
             Self::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            Self::Gone { .. } => StatusCode::GONE,
+            Self::Conflict { .. } => StatusCode::CONFLICT,
             Self::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
             Self::DatabaseError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -153,6 +163,14 @@ impl Error {
         Self::AuthorizationError { message: message.into() }
     }
 
+    pub fn gone<S: Into<String>>(message: S) -> Self {
+        Self::Gone { message: message.into() }
+    }
+
+    pub fn conflict<S: Into<String>>(message: S) -> Self {
+        Self::Conflict { message: message.into() }
+    }
+
     pub fn database_error<S: Into<String>>(message: S, source: Option<anyhow::Error>) -> Self {
         Self::DatabaseError { message: message.into(), source }
     }
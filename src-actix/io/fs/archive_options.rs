@@ -0,0 +1,220 @@
+use crate::helpers::http_error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Extensions of files whose contents are already compressed.
+/// Compressing them again costs time and rarely saves any space, so they are always stored as-is in zip archives.
+const PRECOMPRESSED_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "avif", "mp4", "mkv", "mov", "avi", "webm", "m4v", "mp3", "aac", "ogg", "opus", "flac", "m4a",
+    "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "docx", "xlsx", "pptx", "jar", "apk", "woff", "woff2",
+];
+
+/// The container format of an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.gz", alias = "tgz")]
+    TarGz,
+    #[serde(rename = "tar.zst", alias = "tzst")]
+    TarZst,
+    #[serde(rename = "7z")]
+    SevenZip,
+}
+
+impl ArchiveFormat {
+    /// Detects the archive format from a filename, e.g. `logs.tar.zst`.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let filename = filename.to_lowercase();
+        if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if filename.ends_with(".tar.zst") || filename.ends_with(".tzst") {
+            Some(Self::TarZst)
+        } else if filename.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if filename.ends_with(".7z") {
+            Some(Self::SevenZip)
+        } else if filename.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+            Self::TarZst => "tar.zst",
+            Self::SevenZip => "7z",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Zip => "application/zip",
+            Self::Tar => "application/x-tar",
+            Self::TarGz => "application/gzip",
+            Self::TarZst => "application/zstd",
+            Self::SevenZip => "application/x-7z-compressed",
+        }
+    }
+
+    /// Whether the archive can be written to a non-seekable stream, such as an HTTP response.
+    pub fn is_streamable(&self) -> bool {
+        !matches!(self, Self::SevenZip)
+    }
+}
+
+/// The compression method used for entries of a zip archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionMethod {
+    Stored,
+    Deflate,
+    Zstd,
+    Bzip2,
+}
+
+/// The options used when creating an archive.
+///
+/// `compression` only applies to zip archives, the tar formats compress the whole stream instead.
+/// `level` is interpreted by the selected compression algorithm.
+/// `password` encrypts every zip entry with AES-256, which 7-Zip and the Windows extractor can open.
+#[derive(Clone, Default, Deserialize)]
+pub struct ArchiveOptions {
+    pub format: Option<ArchiveFormat>,
+    pub compression: Option<CompressionMethod>,
+    pub level: Option<i64>,
+    pub password: Option<String>,
+}
+
+impl std::fmt::Debug for ArchiveOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The password is deliberately left out so it never ends up in the logs
+        f.debug_struct("ArchiveOptions")
+            .field("format", &self.format)
+            .field("compression", &self.compression)
+            .field("level", &self.level)
+            .field("encrypted", &self.password.is_some())
+            .finish()
+    }
+}
+
+impl ArchiveOptions {
+    pub fn format(&self) -> ArchiveFormat {
+        self.format.unwrap_or_default()
+    }
+
+    /// Uses the format the extension of `filename` stands for when none was chosen, rejecting a chosen format the
+    /// extension doesn't match.
+    pub fn resolve_format(&mut self, filename: &str) -> Result<()> {
+        match (self.format, ArchiveFormat::from_filename(filename)) {
+            (Some(format), Some(detected)) if format != detected => Err(Error::validation_error(
+                format!("{} is not a {} archive, use the .{} extension", filename, format.extension(), format.extension()),
+                Some("format"),
+            )),
+            (None, detected) => {
+                self.format = Some(detected.unwrap_or_default());
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the compression method, password and level are valid for the selected format.
+    pub fn validate(&self) -> Result<()> {
+        if self.compression.is_some() && self.format() != ArchiveFormat::Zip {
+            return Err(Error::validation_error("A compression method can only be chosen for zip archives", Some("compression")));
+        }
+        if let Some(password) = &self.password {
+            if self.format() != ArchiveFormat::Zip {
+                return Err(Error::validation_error("Only zip archives can be password protected", Some("password")));
+            }
+            if password.is_empty() {
+                return Err(Error::validation_error("The archive password can not be empty", Some("password")));
+            }
+        }
+        if let (Some(level), Some(range)) = (self.level, self.level_range())
+            && !range.contains(&level)
+        {
+            let message = format!("Compression level {} is out of range, expected {} to {}", level, range.start(), range.end());
+            return Err(Error::validation_error(message, Some("level")));
+        }
+        Ok(())
+    }
+
+    fn level_range(&self) -> Option<RangeInclusive<i64>> {
+        match self.format() {
+            ArchiveFormat::Zip => match self.compression.unwrap_or(CompressionMethod::Deflate) {
+                CompressionMethod::Stored => None,
+                CompressionMethod::Deflate => Some(0..=9),
+                CompressionMethod::Zstd => Some(1..=22),
+                CompressionMethod::Bzip2 => Some(1..=9),
+            },
+            ArchiveFormat::Tar => None,
+            ArchiveFormat::TarGz | ArchiveFormat::SevenZip => Some(0..=9),
+            ArchiveFormat::TarZst => Some(1..=22),
+        }
+    }
+
+    /// The compression method used for a file inside a zip archive.
+    ///
+    /// Files that are already compressed, such as JPEGs and MP4s, are always stored.
+    pub fn method_for(&self, path: &Path) -> CompressionMethod {
+        if is_precompressed(path) { CompressionMethod::Stored } else { self.compression.unwrap_or(CompressionMethod::Deflate) }
+    }
+
+    /// Whether the archive entries will be encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// The zip crate options for a file inside a zip archive.
+    pub fn zip_options(&self, path: &Path, size: u64) -> zip::write::FileOptions<'_, ()> {
+        let (method, level) = match self.method_for(path) {
+            CompressionMethod::Stored => (zip::CompressionMethod::Stored, None),
+            CompressionMethod::Deflate => (zip::CompressionMethod::Deflated, self.level),
+            CompressionMethod::Zstd => (zip::CompressionMethod::Zstd, self.level),
+            CompressionMethod::Bzip2 => (zip::CompressionMethod::Bzip2, self.level),
+        };
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(level)
+            .large_file(size > u32::MAX as u64)
+            .unix_permissions(0o755);
+        match &self.password {
+            Some(password) => options.with_aes_encryption(zip::AesMode::Aes256, password),
+            None => options,
+        }
+    }
+
+    /// The archflow options for a file inside a streamed zip archive.
+    pub fn archflow_options(&self, path: &Path) -> archflow::compress::FileOptions<'static> {
+        use archflow::compression::{CompressionMethod as ArchflowMethod, Level};
+        use archflow::types::FileDateTime;
+
+        let method = match self.method_for(path) {
+            CompressionMethod::Stored => ArchflowMethod::Store(),
+            CompressionMethod::Deflate => ArchflowMethod::Deflate(),
+            CompressionMethod::Zstd => ArchflowMethod::Zstd(),
+            CompressionMethod::Bzip2 => ArchflowMethod::BZip2(),
+        };
+        let level = match (method, self.level) {
+            (ArchflowMethod::Store(), _) => Level::None,
+            (_, Some(level)) => Level::Precise(level as i32),
+            (_, None) => Level::Default,
+        };
+        archflow::compress::FileOptions::default().last_modified_time(FileDateTime::Now).compression_method(method).compression_level(level)
+    }
+}
+
+/// Whether the file at `path` is already compressed, based on its extension.
+pub fn is_precompressed(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| PRECOMPRESSED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
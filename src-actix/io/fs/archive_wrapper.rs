@@ -1,191 +1,742 @@
+use crate::io::fs::archive_options::{ArchiveFormat, ArchiveOptions};
+use crate::io::fs::filesystem_data::{FilesystemData, FilesystemEntry};
+use crate::io::fs::normalize_path::NormalizePath;
+use crate::io::storage::storage_backend;
+use crate::io::storage::storage_backend::StorageBackend;
+use actix_web::web::Bytes;
 use actix_web_lab::sse;
 use actix_web_lab::sse::Event;
 use anyhow::Result;
 use log::{debug, error, info, trace, warn};
-use std::io::{BufReader, Read, Write};
-use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::io;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::Handle;
+use tokio_util::io::SyncIoBridge;
 
+/// A file or directory that will be written into an archive.
+pub struct ArchiveEntry {
+    pub source: PathBuf,
+    /// The path of the entry inside the archive, using forward slashes.
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub last_modified: Option<SystemTime>,
+}
+
+/// Expands the selected items into the list of entries to archive.
+///
+/// Directories are walked recursively through their storage backend, and every entry is named
+/// relative to the directory containing the selected item, so selecting `/data/logs` produces
+/// `logs/...` entries.
+pub async fn collect_entries(items: &[PathBuf]) -> Vec<ArchiveEntry> {
+    let mut entries = Vec::new();
+    for item in items {
+        let base = item.parent().unwrap_or(item);
+        let walked = match storage_backend::backend_for(item).walk(item).await {
+            Ok(walked) => walked,
+            Err(e) => {
+                warn!("Error walking directory {}: {}", item.display(), e);
+                continue;
+            }
+        };
+        for entry in walked {
+            let Ok(relative) = entry.path.strip_prefix(base) else {
+                continue;
+            };
+            let name = relative.to_string_lossy().replace('\\', "/");
+            if name.is_empty() {
+                continue;
+            }
+            entries.push(ArchiveEntry { source: entry.path, name, is_dir: entry.is_dir, size: entry.size, last_modified: entry.last_modified });
+        }
+    }
+    entries
+}
+
+/// A writer for one of the supported archive formats.
+pub trait ArchiveSink {
+    fn add_directory(&mut self, entry: &ArchiveEntry) -> Result<()>;
+    fn add_file(&mut self, entry: &ArchiveEntry, reader: &mut dyn Read) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+struct ZipSink<W: Write + Seek> {
+    writer: zip::ZipWriter<W>,
+    options: ArchiveOptions,
+}
+
+impl<W: Write + Seek> ArchiveSink for ZipSink<W> {
+    fn add_directory(&mut self, entry: &ArchiveEntry) -> Result<()> {
+        self.writer.add_directory(entry.name.as_str(), zip::write::SimpleFileOptions::default().unix_permissions(0o755))?;
+        Ok(())
+    }
+
+    fn add_file(&mut self, entry: &ArchiveEntry, reader: &mut dyn Read) -> Result<()> {
+        self.writer.start_file(entry.name.as_str(), self.options.zip_options(&entry.source, entry.size))?;
+        io::copy(reader, &mut self.writer)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+struct TarSink<W: Write> {
+    builder: tar::Builder<W>,
+    finish: fn(W) -> io::Result<()>,
+}
+
+impl<W: Write> ArchiveSink for TarSink<W> {
+    fn add_directory(&mut self, entry: &ArchiveEntry) -> Result<()> {
+        let mut header = tar_header(entry, tar::EntryType::Directory, 0o755);
+        self.builder.append_data(&mut header, &entry.name, io::empty())?;
+        Ok(())
+    }
+
+    fn add_file(&mut self, entry: &ArchiveEntry, reader: &mut dyn Read) -> Result<()> {
+        let mut header = tar_header(entry, tar::EntryType::Regular, 0o644);
+        self.builder.append_data(&mut header, &entry.name, reader)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let writer = self.builder.into_inner()?;
+        (self.finish)(writer)?;
+        Ok(())
+    }
+}
+
+/// Builds the header of a tar entry from the metadata reported by the storage backend.
+fn tar_header(entry: &ArchiveEntry, entry_type: tar::EntryType, mode: u32) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode);
+    header.set_size(if entry.is_dir { 0 } else { entry.size });
+    header.set_mtime(entry.last_modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map_or(0, |duration| duration.as_secs()));
+    header
+}
+
+struct SevenZipSink<W: Write + Seek> {
+    writer: sevenz_rust::SevenZWriter<W>,
+}
+
+impl<W: Write + Seek> ArchiveSink for SevenZipSink<W> {
+    fn add_directory(&mut self, entry: &ArchiveEntry) -> Result<()> {
+        self.writer
+            .push_archive_entry::<&[u8]>(seven_zip_entry(entry), None)
+            .map_err(|e| anyhow::anyhow!("Failed to add directory to 7z archive: {}", e))?;
+        Ok(())
+    }
+
+    fn add_file(&mut self, entry: &ArchiveEntry, reader: &mut dyn Read) -> Result<()> {
+        self.writer
+            .push_archive_entry(seven_zip_entry(entry), Some(reader))
+            .map_err(|e| anyhow::anyhow!("Failed to add file to 7z archive: {}", e))?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+fn seven_zip_entry(entry: &ArchiveEntry) -> sevenz_rust::SevenZArchiveEntry {
+    let mut archive_entry = sevenz_rust::SevenZArchiveEntry::new();
+    archive_entry.name = entry.name.clone();
+    archive_entry.has_stream = !entry.is_dir;
+    archive_entry.is_directory = entry.is_dir;
+    if let Some(Ok(date)) = entry.last_modified.map(|time| time.try_into()) {
+        archive_entry.last_modified_date = date;
+        archive_entry.has_last_modified_date = true;
+    }
+    archive_entry
+}
+
+fn tar_sink<W: Write + 'static>(writer: W, options: &ArchiveOptions) -> Result<Box<dyn ArchiveSink>> {
+    let level = options.level;
+    Ok(match options.format() {
+        ArchiveFormat::TarGz => {
+            let compression = level.map(|l| flate2::Compression::new(l as u32)).unwrap_or_default();
+            Box::new(TarSink { builder: tar::Builder::new(flate2::write::GzEncoder::new(writer, compression)), finish: |w| w.finish().map(|_| ()) })
+        }
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::stream::write::Encoder::new(writer, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL as i64) as i32)?;
+            Box::new(TarSink { builder: tar::Builder::new(encoder), finish: |w| w.finish().map(|_| ()) })
+        }
+        _ => Box::new(TarSink { builder: tar::Builder::new(writer), finish: |mut w| w.flush() }),
+    })
+}
+
+/// Creates an archive writer for a seekable destination, such as a file on disk.
+pub fn create_sink<W: Write + Seek + 'static>(writer: W, options: &ArchiveOptions) -> Result<Box<dyn ArchiveSink>> {
+    Ok(match options.format() {
+        ArchiveFormat::Zip => Box::new(ZipSink { writer: zip::ZipWriter::new(writer), options: options.clone() }),
+        ArchiveFormat::SevenZip => {
+            let mut writer = sevenz_rust::SevenZWriter::new(writer).map_err(|e| anyhow::anyhow!("Failed to create 7z archive: {}", e))?;
+            if let Some(level) = options.level {
+                writer.set_content_methods(vec![sevenz_rust::lzma::LZMA2Options::with_preset(level as u32).into()]);
+            }
+            Box::new(SevenZipSink { writer })
+        }
+        _ => tar_sink(writer, options)?,
+    })
+}
+
+/// Creates an archive writer for a non-seekable destination, such as an HTTP response.
+pub fn create_stream_sink<W: Write + 'static>(writer: W, options: &ArchiveOptions) -> Result<Box<dyn ArchiveSink>> {
+    match options.format() {
+        ArchiveFormat::Tar | ArchiveFormat::TarGz | ArchiveFormat::TarZst => tar_sink(writer, options),
+        format => Err(anyhow::anyhow!("{} archives can not be written to a stream", format.extension())),
+    }
+}
+
+/// A writer that forwards everything written to it through a channel, used to stream archives
+/// that are built on a blocking thread into an HTTP response.
+pub struct ChannelWriter {
+    sender: tokio::sync::mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl ChannelWriter {
+    pub fn new(sender: tokio::sync::mpsc::Sender<io::Result<Bytes>>) -> Self {
+        Self { sender }
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Archive stream closed by the client"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Tracks the progress of an archive operation, reporting it over SSE and checking for cancellation.
+///
+/// Lives on the blocking thread building the archive, where waiting for room in the channel holds up the archive
+/// rather than dropping updates.
+struct ProgressTracker {
+    sender: tokio::sync::mpsc::Sender<Event>,
+    cancelled: Arc<AtomicBool>,
+    processed_bytes: u64,
+    total_bytes: u64,
+    last_progress_update: Instant,
+}
+
+impl ProgressTracker {
+    fn add(&mut self, bytes: u64) {
+        self.processed_bytes += bytes;
+        // Send progress update with rate limiting (max once per 100ms)
+        let now = Instant::now();
+        if now.duration_since(self.last_progress_update).as_millis() > 100 {
+            let progress = if self.total_bytes > 0 { (self.processed_bytes as f32 / self.total_bytes as f32) * 100.0 } else { 0.0 };
+            let _ = self.sender.blocking_send(Event::from(sse::Data::new(format!("{{ \"progress\": {:.1} }}", progress))));
+            debug!("Progress update: {:.1}%", progress);
+            self.last_progress_update = now;
+        }
+    }
+}
+
+/// Wraps a file reader, reporting every read to a [`ProgressTracker`] and aborting once cancelled.
+struct ProgressReader<'a, R: Read> {
+    inner: R,
+    tracker: &'a mut ProgressTracker,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.tracker.cancelled.load(Ordering::Relaxed) {
+            return Err(io::Error::other("Archive operation cancelled"));
+        }
+        let bytes_read = self.inner.read(buf)?;
+        self.tracker.add(bytes_read as u64);
+        Ok(bytes_read)
+    }
+}
+
+/// Archives `entries` into `archive_path`, reporting the progress to `sender` and stopping once `cancelled` is set.
+///
+/// The archive is compressed on a blocking thread, so that slow formats such as 7z and zstd don't hold up the runtime.
 pub async fn archive(
     archive_path: impl AsRef<Path>,
     entries: Vec<PathBuf>,
+    options: &ArchiveOptions,
     sender: &tokio::sync::mpsc::Sender<Event>,
-    cancelled: &AtomicBool,
+    cancelled: Arc<AtomicBool>,
 ) -> Result<()> {
-    let file = fs::File::create(archive_path.as_ref()).await?;
-    info!("Created archive file at: {}", archive_path.as_ref().display());
-    let file = file.into_std().await;
-    let mut archive = zip::ZipWriter::new(file);
-    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored).unix_permissions(0o755);
-    debug!("Using zip options: compression=Stored, permissions=0o755");
-    // Calculate total bytes to process
-    let mut total_bytes: u64 = 0;
-    let mut processed_bytes: u64 = 0;
+    let archive_path = archive_path.as_ref();
+    let backend = storage_backend::backend_for(archive_path);
+    // Zip and 7z seek while writing, so archives for backends without local files are built in a
+    // temporary file and uploaded once complete
+    let (file, staging) = match backend.local_path(archive_path) {
+        Some(local) => (fs::File::create(&local).await?.into_std().await, None),
+        None => {
+            let staging = tempfile::NamedTempFile::new()?;
+            (staging.reopen()?, Some(staging.into_temp_path()))
+        }
+    };
+    info!("Created archive file at: {}", archive_path.display());
+
     info!("Beginning archive creation with {} entries", entries.len());
+    let entries = collect_entries(&entries).await;
     // First, calculate total bytes
-    for entry in &entries {
-        if entry.is_dir() {
-            debug!("Calculating size for directory: {}", entry.display());
-            let dirs = walkdir::WalkDir::new(entry);
-            for dir_entry in dirs.into_iter().flatten() {
-                if dir_entry.path().is_file() {
-                    if let Ok(metadata) = std::fs::metadata(dir_entry.path()) {
-                        total_bytes += metadata.len();
-                        trace!("Added {} bytes from file: {}", metadata.len(), dir_entry.path().display());
-                    } else {
-                        warn!("Failed to read metadata for file: {}", dir_entry.path().display());
-                    }
-                }
-            }
-        } else if let Ok(metadata) = std::fs::metadata(entry) {
-            total_bytes += metadata.len();
-            debug!("Added {} bytes from file: {}", metadata.len(), entry.display());
-        } else {
-            warn!("Failed to read metadata for entry: {}", entry.display());
-        }
-    }
+    let total_bytes: u64 = entries.iter().filter(|entry| !entry.is_dir).map(|entry| entry.size).sum();
     info!("Total bytes to process: {}", total_bytes);
     // Send initial progress
     let _ = sender.send(Event::from(sse::Data::new(format!("{{ \"progress\": {:.1} }}", 0.0)))).await;
     debug!("Sent initial progress update (0.0%)");
-    // Process the files and update progress
+
+    debug!("Using archive options: {:?}", options);
+    let options = options.clone();
+    let tracker = ProgressTracker {
+        sender: sender.clone(),
+        cancelled: cancelled.clone(),
+        processed_bytes: 0,
+        total_bytes,
+        last_progress_update: Instant::now(),
+    };
+    let processed_bytes =
+        tokio::task::spawn_blocking(move || write_entries(create_sink(io::BufWriter::new(file), &options)?, entries, tracker)).await??;
+
+    if cancelled.load(Ordering::Relaxed) {
+        info!("Archive operation cancelled by user");
+        if staging.is_none() {
+            let _ = backend.delete(archive_path).await;
+        }
+        let _ = sender.send(Event::from(sse::Data::new("{ \"progress\": 0, \"status\": \"cancelled\" }"))).await;
+        return Ok(());
+    }
+
+    if let Some(staging) = staging {
+        let mut writer = backend.write(archive_path).await?;
+        tokio::io::copy(&mut fs::File::open(&staging).await?, &mut writer).await?;
+        writer.shutdown().await?;
+    }
+    // Send the completion message
+    let _ = sender.send(Event::from(sse::Data::new("{ \"progress\": 100.0, \"status\": \"complete\" }"))).await;
+    info!("Archive creation complete. Total bytes processed: {}", processed_bytes);
+    info!("Archive finalized successfully at: {}", archive_path.display());
+    Ok(())
+}
+
+/// Writes `entries` to `sink` from a blocking thread, returning how many bytes were read. A cancelled archive is left
+/// unfinished.
+fn write_entries(mut sink: Box<dyn ArchiveSink>, entries: Vec<ArchiveEntry>, mut tracker: ProgressTracker) -> Result<u64> {
+    let cancelled = tracker.cancelled.clone();
     for entry in entries {
         // Check if operation was cancelled
         if cancelled.load(Ordering::Relaxed) {
-            info!("Archive operation cancelled by user");
-            let _ = sender.send(Event::from(sse::Data::new("{ \"progress\": 0, \"status\": \"cancelled\" }"))).await;
-            return Ok(());
+            return Ok(tracker.processed_bytes);
         }
-        if entry.is_dir() {
-            let dir_name = entry.file_name().ok_or_else(|| {
-                error!("Could not get directory name for: {}", entry.display());
-                anyhow::anyhow!("Invalid directory name")
-            })?;
-            let dir_path = dir_name.to_string_lossy();
-            info!("Processing directory: {}", dir_path);
-            archive.add_directory(&*dir_path, options)?;
-            debug!("Added directory to archive: {}", dir_path);
-            let dirs = walkdir::WalkDir::new(&entry);
-            for dir_entry in dirs.into_iter() {
-                let dir_entry = match dir_entry {
-                    Ok(entry) => entry,
-                    Err(e) => {
-                        warn!("Error walking directory {}: {}", entry.display(), e);
-                        continue;
-                    }
-                };
-                let path = dir_entry.path();
-                if path == entry {
-                    continue;
-                }
-                let rel_path = path.strip_prefix(&entry)?;
-                let archive_path = Path::new(&*dir_path).join(rel_path);
-                let archive_path_str = archive_path.to_string_lossy().replace('\\', "/");
-                if path.is_file() {
-                    debug!("Adding file to archive: {} -> {}", path.display(), archive_path_str);
-                    archive.start_file(archive_path_str, options)?;
-                    let file = match std::fs::File::open(path) {
-                        Ok(f) => f,
-                        Err(e) => {
-                            error!("Failed to open file {}: {}", path.display(), e);
-                            return Err(anyhow::anyhow!("Error opening file: {}", e));
-                        }
-                    };
-                    let mut reader = BufReader::with_capacity(8192, file);
-                    trace!("Created buffered reader with capacity 8192 for {}", path.display());
-                    // Use a custom buffer to track progress
-                    let mut buffer = [0; 4096]; // 4KB buffer
-                    let mut last_progress_update = std::time::Instant::now();
-                    loop {
-                        // Check if operation was cancelled
-                        if cancelled.load(Ordering::Relaxed) {
-                            info!("Archive operation cancelled by user while processing {}", path.display());
-                            let _ = sender.send(Event::from(sse::Data::new("{ \"progress\": 0, \"status\": \"cancelled\" }"))).await;
-                            return Ok(());
-                        }
-
-                        let bytes_read = match reader.read(&mut buffer) {
-                            Ok(0) => break, // EOF
-                            Ok(n) => n,
-                            Err(e) => {
-                                error!("Error reading from file {}: {}", path.display(), e);
-                                return Err(anyhow::anyhow!("Error reading file: {}", e));
-                            }
-                        };
-                        archive.write_all(&buffer[..bytes_read])?;
-                        processed_bytes += bytes_read as u64;
-                        trace!("Wrote {} bytes from {}, total processed: {}/{}", bytes_read, path.display(), processed_bytes, total_bytes);
-                        // Send progress update with rate limiting (max once per 100ms)
-                        let now = std::time::Instant::now();
-                        if now.duration_since(last_progress_update).as_millis() > 100 {
-                            let progress = if total_bytes > 0 { (processed_bytes as f32 / total_bytes as f32) * 100.0 } else { 0.0 };
-                            let _ = sender.send(Event::from(sse::Data::new(format!("{{ \"progress\": {:.1} }}", progress)))).await;
-                            debug!("Progress update: {:.1}%", progress);
-                            last_progress_update = now;
-                        }
-                    }
-                    debug!("Finished adding file: {}", path.display());
-                } else if path.is_dir() {
-                    trace!("Adding directory to archive: {}", archive_path_str);
-                    archive.add_directory(archive_path_str, options)?;
-                }
+        if entry.is_dir {
+            trace!("Adding directory to archive: {}", entry.name);
+            sink.add_directory(&entry)?;
+            continue;
+        }
+        debug!("Adding file to archive: {} -> {}", entry.source.display(), entry.name);
+        let file = storage_backend::blocking_reader(&entry.source).map_err(|e| {
+            error!("Failed to open file {}: {}", entry.source.display(), e);
+            anyhow::anyhow!("Error opening file: {}", e)
+        })?;
+        let mut reader = ProgressReader { inner: BufReader::with_capacity(8192, file), tracker: &mut tracker };
+        if let Err(e) = sink.add_file(&entry, &mut reader) {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(tracker.processed_bytes);
             }
+            error!("Error adding file {} to archive: {}", entry.source.display(), e);
+            return Err(e);
+        }
+    }
+    if !cancelled.load(Ordering::Relaxed) {
+        sink.finish()?;
+    }
+    Ok(tracker.processed_bytes)
+}
+
+/// The archive formats that can be browsed as virtual directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+    TarBz2,
+}
+
+impl ArchiveKind {
+    /// Detects the archive kind from a filename, matching compound extensions such as `.tar.gz` first.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let filename = filename.to_lowercase();
+        if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if filename.ends_with(".tar.zst") || filename.ends_with(".tzst") {
+            Some(Self::TarZst)
+        } else if filename.ends_with(".tar.bz2") || filename.ends_with(".tbz2") {
+            Some(Self::TarBz2)
+        } else if filename.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if filename.ends_with(".zip") {
+            Some(Self::Zip)
         } else {
-            let rel_path = entry.file_name().ok_or_else(|| {
-                error!("Could not get file name for: {}", entry.display());
-                anyhow::anyhow!("Invalid file name")
-            })?;
-            let archive_path = rel_path.to_string_lossy();
-            info!("Adding file to archive: {} -> {}", entry.display(), archive_path);
-            archive.start_file(archive_path, options)?;
-            let file = match std::fs::File::open(&entry) {
-                Ok(f) => f,
-                Err(e) => {
-                    error!("Failed to open file {}: {}", entry.display(), e);
-                    return Err(anyhow::anyhow!("Error opening file: {}", e));
-                }
+            None
+        }
+    }
+}
+
+/// A path that points at (or into) an archive file on disk.
+///
+/// `archive` is the OS path of the archive file itself, and `member` is the path inside
+/// the archive using forward slashes, without leading or trailing slashes.
+/// An empty `member` refers to the root of the archive.
+#[derive(Debug, Clone)]
+pub struct ArchivePath {
+    pub archive: PathBuf,
+    pub kind: ArchiveKind,
+    pub member: String,
+}
+
+impl ArchivePath {
+    /// Splits a virtual filesystem path such as `/backups/site.zip/www/index.html` into the archive
+    /// file and the member path inside it.
+    ///
+    /// Returns `None` when no component of the path is an existing archive file.
+    pub fn parse(path: &str) -> Option<Self> {
+        let normalized = path.replace('\\', "/");
+        let segments: Vec<&str> = normalized.split('/').collect();
+        for (index, segment) in segments.iter().enumerate() {
+            let Some(kind) = ArchiveKind::from_filename(segment) else {
+                continue;
             };
-            let mut reader = BufReader::with_capacity(8192, file);
-            trace!("Created buffered reader with capacity 8192 for {}", entry.display());
-            // Use a custom buffer to track progress
-            let mut buffer = [0; 4096]; // 4KB buffer
-            let mut last_progress_update = std::time::Instant::now();
+            let archive = segments[..=index].join("/").to_os_path();
+            // Archives are read with random access, which needs the file on the local filesystem
+            let local = storage_backend::backend_for(&archive).local_path(&archive);
+            if !local.is_some_and(|local| local.is_file()) {
+                continue;
+            }
+            let member = segments[index + 1..].iter().filter(|s| !s.is_empty()).copied().collect::<Vec<_>>().join("/");
+            return Some(Self { archive, kind, member });
+        }
+        None
+    }
+
+    /// The path shown to clients for an entry inside this archive.
+    fn display_path(&self, member: &str) -> String {
+        format!("{}/{}", self.archive.to_string_lossy().replace('\\', "/"), member)
+    }
+}
+
+/// A single file or directory stored inside an archive.
+struct ArchiveMember {
+    name: String,
+    size: u64,
+    last_modified: Option<SystemTime>,
+    is_dir: bool,
+}
+
+fn open_tar(path: &Path, kind: ArchiveKind) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = BufReader::new(std::fs::File::open(path)?);
+    let reader: Box<dyn Read> = match kind {
+        ArchiveKind::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveKind::TarZst => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+        ArchiveKind::TarBz2 => Box::new(bzip2::read::BzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn read_members(path: &ArchivePath) -> Result<Vec<ArchiveMember>> {
+    let mut members = Vec::new();
+    if path.kind == ArchiveKind::Zip {
+        let mut archive = zip::ZipArchive::new(BufReader::new(std::fs::File::open(&path.archive)?))?;
+        for index in 0..archive.len() {
+            let file = archive.by_index_raw(index)?;
+            members.push(ArchiveMember {
+                name: file.name().trim_end_matches('/').to_string(),
+                size: file.size(),
+                last_modified: file.last_modified().and_then(|t| time::OffsetDateTime::try_from(t).ok()).map(SystemTime::from),
+                is_dir: file.is_dir(),
+            });
+        }
+    } else {
+        let mut archive = open_tar(&path.archive, path.kind)?;
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            members.push(ArchiveMember {
+                name: entry.path()?.to_string_lossy().replace('\\', "/").trim_start_matches("./").trim_end_matches('/').to_string(),
+                size: header.size().unwrap_or(0),
+                last_modified: header.mtime().ok().map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+                is_dir: header.entry_type().is_dir(),
+            });
+        }
+    }
+    Ok(members)
+}
+
+/// Lists the direct children of `path.member` inside the archive.
+///
+/// Directories that are only implied by the paths of their children are listed as well,
+/// since many archivers do not store explicit directory entries.
+pub fn list_archive(path: &ArchivePath) -> Result<FilesystemData> {
+    let prefix = if path.member.is_empty() { String::new() } else { format!("{}/", path.member) };
+    let mut entries: Vec<FilesystemEntry> = Vec::new();
+    let mut found = path.member.is_empty();
+
+    for member in read_members(path)? {
+        if member.name == path.member {
+            if !member.is_dir {
+                return Err(anyhow::anyhow!("{} is not a directory inside the archive", path.member));
+            }
+            found = true;
+            continue;
+        }
+        let Some(relative) = member.name.strip_prefix(&prefix) else {
+            continue;
+        };
+        found = true;
+        let (name, is_implied_dir) = match relative.split_once('/') {
+            Some((name, _)) => (name, true),
+            None => (relative, false),
+        };
+        if name.is_empty() {
+            continue;
+        }
+        let child = format!("{}{}", prefix, name);
+        if let Some(existing) = entries.iter_mut().find(|e| e.filename == name) {
+            // An explicit entry carries the real metadata, so it wins over an implied directory.
+            if !is_implied_dir {
+                existing.size = member.size;
+                existing.last_modified = member.last_modified;
+            }
+            continue;
+        }
+        entries.push(FilesystemEntry {
+            filename: name.to_string(),
+            path: path.display_path(&child),
+            size: if is_implied_dir { 0 } else { member.size },
+            last_modified: if is_implied_dir { None } else { member.last_modified },
+            created: None,
+            is_dir: is_implied_dir || member.is_dir,
+        });
+    }
+
+    if !found {
+        return Err(anyhow::anyhow!("{} does not exist inside the archive", path.member));
+    }
+
+    let parent = match path.member.rsplit_once('/') {
+        Some((parent, _)) => path.display_path(parent),
+        None if path.member.is_empty() => path.archive.parent().map(|p| p.to_string_lossy().replace('\\', "/")).unwrap_or_else(|| "/".to_string()),
+        None => path.archive.to_string_lossy().replace('\\', "/"),
+    };
+
+    Ok(FilesystemData { parent: Some(parent), entries })
+}
+
+/// Streams a single file out of an archive without extracting it to disk.
+///
+/// The archive is read on a blocking thread and forwarded in chunks through the returned receiver.
+/// The function resolves once the member has been located, so a missing member is reported as an
+/// error instead of an empty stream.
+///
+/// `password` is used to decrypt members of encrypted zip archives.
+pub async fn stream_archive_member(path: ArchivePath, password: Option<String>) -> Result<tokio::sync::mpsc::Receiver<io::Result<Bytes>>> {
+    let (tx, rx) = tokio::sync::mpsc::channel::<io::Result<Bytes>>(16);
+    let (ready_tx, ready_rx) = tokio::sync::oneshot::channel::<Result<()>>();
+
+    tokio::task::spawn_blocking(move || {
+        let mut ready_tx = Some(ready_tx);
+        let result = with_member_reader(&path, password.as_deref(), |reader| {
+            if let Some(ready_tx) = ready_tx.take() {
+                let _ = ready_tx.send(Ok(()));
+            }
+            let mut buffer = vec![0u8; 64 * 1024];
             loop {
-                // Check if operation was cancelled
-                if cancelled.load(Ordering::Relaxed) {
-                    info!("Archive operation cancelled by user while processing {}", entry.display());
-                    let _ = sender.send(Event::from(sse::Data::new("{ \"progress\": 0, \"status\": \"cancelled\" }"))).await;
-                    return Ok(());
+                let bytes_read = reader.read(&mut buffer)?;
+                if bytes_read == 0 {
+                    break;
+                }
+                if tx.blocking_send(Ok(Bytes::copy_from_slice(&buffer[..bytes_read]))).is_err() {
+                    debug!("Archive member stream closed by the client: {}", path.member);
+                    break;
                 }
+            }
+            Ok(())
+        });
+        match (result, ready_tx) {
+            (Err(e), Some(ready_tx)) => {
+                let _ = ready_tx.send(Err(e));
+            }
+            (Err(e), None) => {
+                error!("Failed to stream {} from {}: {}", path.member, path.archive.display(), e);
+                let _ = tx.blocking_send(Err(io::Error::other(e.to_string())));
+            }
+            _ => {}
+        }
+    });
+
+    ready_rx.await.map_err(|_| anyhow::anyhow!("Archive reader stopped unexpectedly"))??;
+    Ok(rx)
+}
+
+/// Opens `path.member` inside the archive and hands a reader for its contents to `read`.
+fn with_member_reader(path: &ArchivePath, password: Option<&str>, read: impl FnOnce(&mut dyn Read) -> io::Result<()>) -> Result<()> {
+    if path.kind == ArchiveKind::Zip {
+        let mut archive = zip::ZipArchive::new(BufReader::new(std::fs::File::open(&path.archive)?))?;
+        let file = match password {
+            Some(password) => archive.by_name_decrypt(&path.member, password.as_bytes()),
+            None => archive.by_name(&path.member),
+        };
+        let mut file = file.map_err(|e| zip_member_error(e, &path.member))?;
+        if file.is_dir() {
+            return Err(anyhow::anyhow!("{} is a directory inside the archive", path.member));
+        }
+        read(&mut file)?;
+        return Ok(());
+    }
+
+    let mut archive = open_tar(&path.archive, path.kind)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().replace('\\', "/");
+        if name.trim_start_matches("./") == path.member && entry.header().entry_type().is_file() {
+            read(&mut entry)?;
+            return Ok(());
+        }
+    }
+    Err(anyhow::anyhow!("{} does not exist inside the archive", path.member))
+}
+
+/// Wraps a zip error for `member`, keeping the original error so password failures can be detected.
+fn zip_member_error(error: zip::result::ZipError, member: &str) -> anyhow::Error {
+    match error {
+        zip::result::ZipError::FileNotFound => anyhow::anyhow!("{} does not exist inside the archive", member),
+        zip::result::ZipError::InvalidPassword => anyhow::Error::new(error).context(format!("The password for {} is incorrect", member)),
+        zip::result::ZipError::UnsupportedArchive(zip::result::ZipError::PASSWORD_REQUIRED) => {
+            anyhow::Error::new(error).context(format!("{} is encrypted and requires a password", member))
+        }
+        error => anyhow::Error::new(error).context(format!("Failed to open {} inside the archive", member)),
+    }
+}
+
+/// Whether an archive operation failed because the password was missing or incorrect.
+pub fn is_password_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<zip::result::ZipError>(),
+        Some(zip::result::ZipError::InvalidPassword | zip::result::ZipError::UnsupportedArchive(zip::result::ZipError::PASSWORD_REQUIRED))
+    )
+}
+
+/// Writes extracted entries below `destination` through the storage backend that holds it, from a blocking thread.
+struct Extractor {
+    backend: Arc<dyn StorageBackend>,
+    destination: PathBuf,
+    handle: Handle,
+    /// The folders created so far, so that every file doesn't create its folder again.
+    created: HashSet<PathBuf>,
+}
 
-                let bytes_read = match reader.read(&mut buffer) {
-                    Ok(0) => break, // EOF
-                    Ok(n) => n,
-                    Err(e) => {
-                        error!("Error reading from file {}: {}", entry.display(), e);
-                        return Err(anyhow::anyhow!("Error reading file: {}", e));
-                    }
+impl Extractor {
+    fn create_dir(&mut self, path: &Path) -> io::Result<()> {
+        if !self.created.contains(path) {
+            self.handle.block_on(self.backend.create_dir(path))?;
+            self.created.insert(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self, relative: &Path, reader: &mut dyn Read) -> io::Result<()> {
+        let target = self.destination.join(relative);
+        if let Some(parent) = target.parent() {
+            self.create_dir(parent)?;
+        }
+        let writer = self.handle.block_on(self.backend.write(&target))?;
+        let mut writer = SyncIoBridge::new_with_handle(writer, self.handle.clone());
+        io::copy(reader, &mut writer)?;
+        writer.shutdown()
+    }
+}
+
+/// The path of an archive entry as a path below the destination, or `None` for absolute paths and paths with `..`
+/// segments, which would escape it.
+fn enclosed_path(path: &Path) -> Option<PathBuf> {
+    let mut enclosed = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => enclosed.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(enclosed)
+}
+
+/// Extracts an archive into `destination`, creating it if needed.
+///
+/// The archive is read from a local copy, as zip archives are seeked through, while the entries are written through
+/// the storage backend of `destination`. Encrypted zip entries are decrypted with `password`. Entries whose paths
+/// would escape `destination` are skipped, as are links, which backends can't store.
+pub async fn extract(archive: &Path, destination: &Path, password: Option<&str>) -> Result<()> {
+    let filename = archive.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let kind = ArchiveKind::from_filename(&filename).ok_or_else(|| anyhow::anyhow!("{} is not a supported archive", filename))?;
+    let source = storage_backend::local_file(archive).await?;
+    let backend = storage_backend::backend_for(destination);
+    backend.create_dir(destination).await?;
+    let mut extractor = Extractor { backend, destination: destination.to_path_buf(), handle: Handle::current(), created: HashSet::new() };
+    let password = password.map(String::from);
+
+    tokio::task::spawn_blocking(move || {
+        if kind != ArchiveKind::Zip {
+            for entry in open_tar(source.as_ref(), kind)?.entries()? {
+                let mut entry = entry?;
+                let entry_type = entry.header().entry_type();
+                let Some(relative) = enclosed_path(&entry.path()?) else {
+                    warn!("Skipping unsafe archive entry: {}", entry.path()?.display());
+                    continue;
                 };
-                archive.write_all(&buffer[..bytes_read])?;
-                processed_bytes += bytes_read as u64;
-                trace!("Wrote {} bytes from {}, total processed: {}/{}", bytes_read, entry.display(), processed_bytes, total_bytes);
-                // Send progress update with rate limiting (max once per 100ms)
-                let now = std::time::Instant::now();
-                if now.duration_since(last_progress_update).as_millis() > 100 {
-                    let progress = if total_bytes > 0 { (processed_bytes as f32 / total_bytes as f32) * 100.0 } else { 0.0 };
-                    let _ = sender.send(Event::from(sse::Data::new(format!("{{ \"progress\": {:.1} }}", progress)))).await;
-                    debug!("Progress update: {:.1}%", progress);
-                    last_progress_update = now;
+                if entry_type.is_dir() {
+                    extractor.create_dir(&extractor.destination.join(relative))?;
+                } else if entry_type.is_file() {
+                    extractor.write_file(&relative, &mut entry)?;
+                } else {
+                    debug!("Skipping {:?} archive entry: {}", entry_type, relative.display());
                 }
             }
-            debug!("Finished adding file: {}", entry.display());
+            return Ok(());
         }
-    }
-    // Send the completion message
-    let _ = sender.send(Event::from(sse::Data::new("{ \"progress\": 100.0, \"status\": \"complete\" }"))).await;
-    info!("Archive creation complete. Total bytes processed: {}", processed_bytes);
-    archive.finish()?;
-    info!("Archive finalized successfully at: {}", archive_path.as_ref().display());
-    Ok(())
+
+        let mut zip = zip::ZipArchive::new(BufReader::new(std::fs::File::open(&source)?))?;
+        for index in 0..zip.len() {
+            let file = match &password {
+                Some(password) => zip.by_index_decrypt(index, password.as_bytes()),
+                None => zip.by_index(index),
+            };
+            let mut file = file.map_err(|e| zip_member_error(e, &filename))?;
+            let Some(relative) = file.enclosed_name() else {
+                warn!("Skipping unsafe archive entry: {}", file.name());
+                continue;
+            };
+            if file.is_dir() {
+                extractor.create_dir(&extractor.destination.join(relative))?;
+                continue;
+            }
+            extractor.write_file(&relative, &mut file)?;
+        }
+        Ok(())
+    })
+    .await?
 }
@@ -1,5 +1,11 @@
+use crate::io::fs::archive_options::{ArchiveFormat, ArchiveOptions, CompressionMethod};
+use actix_web::HttpRequest;
 use serde::{Deserialize, Deserializer};
 
+/// The header carrying the password that encrypts a zip download, or decrypts a file streamed out of an encrypted
+/// zip archive. It is not taken from the query, which ends up in access logs.
+pub const ARCHIVE_PASSWORD_HEADER: &str = "X-Archive-Password";
+
 fn json_array_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
     D: Deserializer<'de>,
@@ -13,4 +19,19 @@ pub struct DownloadParameters {
     #[serde(deserialize_with = "json_array_string")]
     pub items: Vec<String>,
     pub cwd: String,
+    pub format: Option<ArchiveFormat>,
+    pub compression: Option<CompressionMethod>,
+    pub level: Option<i64>,
+}
+
+impl DownloadParameters {
+    /// The options used when the download is archived, which is the case for directories and multiple items.
+    pub fn archive_options(&self, password: Option<String>) -> ArchiveOptions {
+        ArchiveOptions { format: self.format, compression: self.compression, level: self.level, password }
+    }
+}
+
+/// The password sent in [`ARCHIVE_PASSWORD_HEADER`], if any.
+pub fn archive_password(req: &HttpRequest) -> Option<String> {
+    req.headers().get(ARCHIVE_PASSWORD_HEADER).and_then(|value| value.to_str().ok()).map(String::from)
 }
@@ -1,6 +1,10 @@
-use anyhow::anyhow;
+use crate::configuration::configuration_data::Configuration;
+use crate::io::fs::indexer::indexer_data::{IndexerData, index_scope};
+use crate::io::fs::normalize_path::to_virtual_path;
+use crate::io::storage::storage_backend;
+use crate::io::storage::storage_backend::StorageEntry;
 use serde::Serialize;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::time::SystemTime;
 
 #[derive(Serialize)]
@@ -19,15 +23,10 @@ pub struct FilesystemData {
     pub entries: Vec<FilesystemEntry>,
 }
 
-impl TryFrom<PathBuf> for FilesystemEntry {
-    type Error = anyhow::Error;
-
-    fn try_from(path: PathBuf) -> anyhow::Result<Self> {
-        let metadata = path.metadata()?;
-        let filename = path.file_name().ok_or(anyhow!("Unable to get filename"))?.to_str().ok_or(anyhow!("Unable to convert to str"))?.to_string();
-
-        // Get path string and ensure it's properly formatted for the platform
-        let path_str = path.to_str().ok_or(anyhow!("Unable to convert to str"))?;
+impl From<StorageEntry> for FilesystemEntry {
+    fn from(entry: StorageEntry) -> Self {
+        // Get the path clients see and ensure it's properly formatted for the platform
+        let path_str = to_virtual_path(&entry.path).unwrap_or_else(|| entry.path.to_string_lossy().into_owned());
 
         // On Unix systems, ensure the path starts with "/"
         #[cfg(unix)]
@@ -37,16 +36,20 @@ impl TryFrom<PathBuf> for FilesystemEntry {
         #[cfg(windows)]
         let path = path_str.to_string();
 
-        let created = metadata.created().ok();
-        let last_modified = metadata.modified().ok();
-        Ok(FilesystemEntry { filename, path, created, last_modified, size: metadata.len(), is_dir: metadata.is_dir() })
+        FilesystemEntry {
+            filename: entry.name,
+            path,
+            created: entry.created,
+            last_modified: entry.last_modified,
+            size: entry.size,
+            is_dir: entry.is_dir,
+        }
     }
 }
 
-impl TryFrom<PathBuf> for FilesystemData {
-    type Error = anyhow::Error;
-
-    fn try_from(path: PathBuf) -> anyhow::Result<Self> {
+impl FilesystemData {
+    /// Lists the directory at `path` through the storage backend that holds it.
+    pub async fn read(path: PathBuf) -> anyhow::Result<Self> {
         // Handle an empty or root path differently on Windows vs. Unix
         #[cfg(windows)]
         let path = if path.to_str().is_some_and(|p| p.is_empty() || p == "/") {
@@ -55,7 +58,7 @@ impl TryFrom<PathBuf> for FilesystemData {
             path
         } else {
             // For non-empty paths, ensure they exist
-            if !path.exists() {
+            if storage_backend::backend_for(&path).stat(&path).await.is_err() {
                 return Err(anyhow::anyhow!("Path does not exist"));
             }
             path
@@ -71,25 +74,25 @@ impl TryFrom<PathBuf> for FilesystemData {
             if !path_str.starts_with("/") { PathBuf::from("/").join(path) } else { path }
         };
 
+        let backend = storage_backend::backend_for(&path);
         #[cfg(not(windows))]
-        if !path.exists() {
+        if backend.stat(&path).await.is_err() {
             return Err(anyhow::anyhow!("Path does not exist"));
         }
 
-        let readdir = std::fs::read_dir(&path)?;
-        let mut entries = Vec::new();
-        for entry in readdir {
-            let entry = entry?;
-            let path = entry.path();
-            if !is_special_file(&path) {
-                if let Ok(entry) = path.try_into() {
-                    entries.push(entry);
-                }
-            }
+        // Mounts show up as folders of their parent
+        let mut entries = storage_backend::list(&path).await?;
+        // Paths the filter leaves out of the index are left out of listings as well
+        let scope = index_scope();
+        entries.retain(|entry| !scope.hides(&entry.path, entry.is_dir));
+        if Configuration::get().indexing_enabled {
+            with_indexed_sizes(&mut entries).await;
         }
+        let entries = entries.into_iter().map(FilesystemEntry::from).collect();
 
-        // Format parent path according to platform
-        let parent = path.parent().map(|p| {
+        // Format parent path according to platform, going up from the path clients see so mounts lead back to the top
+        let virtual_path = to_virtual_path(&path).map(PathBuf::from).unwrap_or_else(|| path.clone());
+        let parent = virtual_path.parent().map(|p| {
             let parent_str = p.to_str().unwrap_or("");
             #[cfg(unix)]
             {
@@ -113,32 +116,21 @@ impl TryFrom<PathBuf> for FilesystemData {
     }
 }
 
-pub fn is_special_file(path: &Path) -> bool {
-    #[cfg(unix)]
-    {
-        if let Ok(metadata) = path.metadata() {
-            let file_type = metadata.file_type();
-            use std::os::unix::fs::FileTypeExt;
-            // Check for Unix special files
-            return file_type.is_char_device()
-                || file_type.is_block_device()
-                || file_type.is_fifo()
-                || file_type.is_socket()
-                || file_type.is_symlink();
-        }
+/// Replaces the size of the folders among `entries`, which is that of the folder itself, with the total size of the
+/// files in them when they are indexed.
+async fn with_indexed_sizes(entries: &mut [StorageEntry]) {
+    let folders = entries.iter().filter(|entry| entry.is_dir).map(|entry| entry.path.to_string_lossy().replace('\\', "/")).collect::<Vec<_>>();
+    if folders.is_empty() {
+        return;
     }
-
-    #[cfg(windows)]
-    {
-        // Check for Windows special files/directories
-        if let Some(file_name) = path.file_name() {
-            if let Some(name) = file_name.to_str() {
-                return name.eq_ignore_ascii_case("desktop.ini")
-                    || name.eq_ignore_ascii_case("thumbs.db")
-                    || name.starts_with("$")
-                    || name.starts_with("~$");
+    match IndexerData::get_directory_sizes(&folders).await {
+        Ok(sizes) => {
+            for entry in entries.iter_mut().filter(|entry| entry.is_dir) {
+                if let Some(size) = sizes.get(&entry.path.to_string_lossy().replace('\\', "/")) {
+                    entry.size = *size;
+                }
             }
         }
+        Err(e) => log::debug!("Failed to read the indexed folder sizes: {}", e),
     }
-    false
 }
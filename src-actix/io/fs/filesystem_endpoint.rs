@@ -1,11 +1,18 @@
 use crate::auth::auth_middleware::Authentication;
 use crate::helpers::http_error::{Error, Result};
+use crate::io::fs::archive_options::{ArchiveFormat, ArchiveOptions};
 use crate::io::fs::archive_wrapper;
-use crate::io::fs::download_parameters::DownloadParameters;
+use crate::io::fs::archive_wrapper::ArchivePath;
+use crate::io::fs::download_parameters::{DownloadParameters, archive_password};
 use crate::io::fs::filesystem_data::{FilesystemData, FilesystemEntry};
-use crate::io::fs::indexer::indexer_data;
+use crate::io::fs::indexer::duplicate_data::{DuplicateParameters, ResolveDuplicates};
 use crate::io::fs::indexer::indexer_data::IndexerData;
-use crate::io::fs::normalize_path::NormalizePath;
+use crate::io::fs::indexer::indexer_state::indexer_state;
+use crate::io::fs::indexer::pattern_search::PathPattern;
+use crate::io::fs::indexer::search_query::{SearchMode, SearchPage, SearchParameters, SearchQuery, decode_cursor};
+use crate::io::fs::indexer::{content_db, duplicate_data, duplicate_db, indexer_data};
+use crate::io::fs::normalize_path::{NormalizePath, to_virtual_path};
+use crate::io::storage::storage_backend;
 use actix_web::http::header::ContentDisposition;
 use actix_web::web::Query;
 use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
@@ -17,13 +24,12 @@ use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::io;
 use std::io::ErrorKind;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::OnceLock;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use sysinfo::Disks;
-use tokio::fs;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::io::duplex;
@@ -57,9 +63,9 @@ fn get_upload_cancel_flags() -> &'static UploadCancelFlags {
 
 #[get("/")]
 async fn get_filesystem_entries(request: HttpRequest) -> Result<impl Responder> {
-    let path = match request.headers().get("X-Filesystem-Path") {
+    let path_str = match request.headers().get("X-Filesystem-Path") {
         Some(header) => match header.to_str() {
-            Ok(path_str) => path_str.to_os_path(),
+            Ok(path_str) => path_str,
             Err(_) => {
                 return Err(Error::invalid_input("X-Filesystem-Path header is not a valid string"));
             }
@@ -69,11 +75,22 @@ async fn get_filesystem_entries(request: HttpRequest) -> Result<impl Responder>
         }
     };
 
+    // Archives are browsed as virtual directories, listing their contents without extracting them
+    if let Some(archive_path) = ArchivePath::parse(path_str) {
+        let entries = tokio::task::spawn_blocking(move || archive_wrapper::list_archive(&archive_path))
+            .await
+            .map_err(|e| anyhow::anyhow!("Archive listing task failed: {}", e))?
+            .map_err(|e| Error::not_found(e.to_string()))?;
+        return Ok(HttpResponse::Ok().json(json!(entries)));
+    }
+
+    let path = path_str.to_os_path();
+
     // Handle root or empty path
     if path.to_str() == Some("/") || path.to_str() == Some("") {
         #[cfg(target_os = "windows")]
         {
-            // On Windows, show available drives
+            // On Windows, show available drives and the configured mounts
             let disks = Disks::new_with_refreshed_list();
 
             let drives: Vec<FilesystemEntry> = disks
@@ -89,6 +106,7 @@ async fn get_filesystem_entries(request: HttpRequest) -> Result<impl Responder>
                         last_modified: None,
                     }
                 })
+                .chain(storage_backend::mount_entries(&path).into_iter().map(FilesystemEntry::from))
                 .collect();
 
             return Ok(HttpResponse::Ok().json(json!({
@@ -102,34 +120,60 @@ async fn get_filesystem_entries(request: HttpRequest) -> Result<impl Responder>
             // On Unix systems, use the root directory "/"
             let path = PathBuf::from("/");
             // Continue to the normal path handling below
-            let entries: FilesystemData = path.try_into()?;
+            let entries = FilesystemData::read(path).await?;
             return Ok(HttpResponse::Ok().json(json!(entries)));
         }
     }
 
-    let entries: FilesystemData = path.try_into()?;
+    let entries = FilesystemData::read(path).await?;
     Ok(HttpResponse::Ok().json(json!(entries)))
 }
 
 #[get("/download")]
-async fn download(query: Query<DownloadParameters>) -> Result<impl Responder> {
+async fn download(req: HttpRequest, query: Query<DownloadParameters>) -> Result<impl Responder> {
+    // A single file inside an archive is streamed straight out of the archive
+    if let [item] = query.items.as_slice()
+        && let Some(archive_path) = ArchivePath::parse(&format!("{}{}", query.cwd, item))
+        && !archive_path.member.is_empty()
+    {
+        let filename = archive_path.member.rsplit('/').next().unwrap_or(&archive_path.member).to_string();
+        debug!("Downloading {} from archive {}", archive_path.member, archive_path.archive.display());
+        let receiver = archive_wrapper::stream_archive_member(archive_path, archive_password(&req)).await.map_err(|e| {
+            if archive_wrapper::is_password_error(&e) { Error::authorization_error(format!("{:#}", e)) } else { Error::not_found(e.to_string()) }
+        })?;
+        let stream = futures::stream::unfold(receiver, |mut receiver| async move { receiver.recv().await.map(|chunk| (chunk, receiver)) });
+        return Ok(HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header(ContentDisposition::attachment(filename))
+            .streaming(stream));
+    }
+
+    let cwd = query.cwd.to_os_path();
+    let items: Vec<PathBuf> = query.items.iter().map(|item| format!("{}{}", query.cwd, item).to_os_path()).collect();
+    stream_items(cwd, items, query.archive_options(archive_password(&req))).await
+}
+
+/// Streams `items` to the client: a single file is sent as-is, while directories and multiple items
+/// are archived on the fly using `archive_options`.
+///
+/// `cwd` is the directory the archive entry names are made relative to.
+pub(crate) async fn stream_items(cwd: PathBuf, items: Vec<PathBuf>, archive_options: ArchiveOptions) -> Result<HttpResponse> {
     use archflow::compress::FileOptions;
     use archflow::compress::tokio::archive::ZipArchive;
     use archflow::compression::CompressionMethod;
     use archflow::error::ArchiveError;
     use archflow::types::FileDateTime;
-    let cwd = query.cwd.to_os_path();
-    let items: Vec<PathBuf> = query.items.iter().map(|item| format!("{}{}", query.cwd, item).to_os_path()).collect();
+    let format = archive_options.format();
 
     let is_single_entry = items.len() == 1;
-    let is_single_entry_directory = is_single_entry && items[0].is_dir();
+    let is_single_entry_directory = is_single_entry && storage_backend::backend_for(&items[0]).stat(&items[0]).await.is_ok_and(|entry| entry.is_dir);
 
     let filename: String = if is_single_entry {
         let guid = uuid::Uuid::new_v4().to_string();
         let name = items[0].file_name().unwrap_or(OsStr::new(&guid)).to_string_lossy().into_owned();
-        if is_single_entry_directory { format!("{}.zip", name) } else { name.to_string() }
+        if is_single_entry_directory { format!("{}.{}", name, format.extension()) } else { name.to_string() }
     } else {
-        format!("{}.zip", uuid::Uuid::new_v4())
+        format!("{}.{}", uuid::Uuid::new_v4(), format.extension())
     };
 
     // If there is only one entry, and it's a file,
@@ -138,10 +182,10 @@ async fn download(query: Query<DownloadParameters>) -> Result<impl Responder> {
         let filepath = items[0].clone();
         debug!("Downloading single file: {}", filepath.display());
 
-        let file = File::open(&filepath).await.map_err(|e| {
+        let reader = storage_backend::backend_for(&filepath).read(&filepath, None).await.map_err(|e| {
             Error::filesystem_error(format!("Failed to open file for download: {}", filepath.display()), Some(e), Some(filepath.clone()))
         })?;
-        let stream = ReaderStream::new(file);
+        let stream = ReaderStream::new(reader);
 
         return Ok(HttpResponse::Ok()
             .content_type("application/octet-stream")
@@ -149,46 +193,100 @@ async fn download(query: Query<DownloadParameters>) -> Result<impl Responder> {
             .streaming(stream));
     }
 
+    archive_options.validate()?;
+    if !format.is_streamable() {
+        return Err(Error::validation_error(format!("{} archives can not be streamed as a download", format.extension()), Some("format")));
+    }
+
+    // A single directory is archived by its contents rather than as a folder of its own
+    let items_to_write = if is_single_entry_directory {
+        match storage_backend::backend_for(&items[0]).list(&items[0]).await {
+            Ok(children) => children.into_iter().map(|child| child.path).collect(),
+            Err(_) => items,
+        }
+    } else {
+        items
+    };
+
+    // Tar based formats are built on a blocking thread and streamed out through a channel
+    if format != ArchiveFormat::Zip {
+        let (tx, rx) = tokio::sync::mpsc::channel::<io::Result<web::Bytes>>(16);
+        let entries = archive_wrapper::collect_entries(&items_to_write).await;
+        tokio::task::spawn_blocking(move || {
+            let writer = io::BufWriter::with_capacity(64 * 1024, archive_wrapper::ChannelWriter::new(tx));
+            let result = archive_wrapper::create_stream_sink(writer, &archive_options).and_then(|mut sink| {
+                for entry in entries {
+                    if entry.is_dir {
+                        sink.add_directory(&entry)?;
+                    } else if let Ok(mut file) = storage_backend::blocking_reader(&entry.source) {
+                        debug!("Adding file to {} archive: {} -> {}", format.extension(), entry.source.display(), entry.name);
+                        sink.add_file(&entry, &mut file)?;
+                    }
+                }
+                sink.finish()
+            });
+            if let Err(e) = result {
+                warn!("Failed to stream {} archive, this is most-likely due to the client closing the connection: {}", format.extension(), e);
+            }
+        });
+        let stream = futures::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+        return Ok(HttpResponse::Ok().content_type(format.content_type()).insert_header(ContentDisposition::attachment(filename)).streaming(stream));
+    }
+
+    // Encrypted zips can't be streamed by archflow, so they are built in a temporary file first
+    if archive_options.is_encrypted() {
+        let file = tempfile::tempfile().map_err(|e| Error::filesystem_error("Failed to create a temporary archive", Some(e), None))?;
+        let writer = file.try_clone().map_err(|e| Error::filesystem_error("Failed to create a temporary archive", Some(e), None))?;
+        let entries = archive_wrapper::collect_entries(&items_to_write).await;
+        let file = tokio::task::spawn_blocking(move || -> anyhow::Result<std::fs::File> {
+            let mut sink = archive_wrapper::create_sink(io::BufWriter::new(writer), &archive_options)?;
+            for entry in entries {
+                if entry.is_dir {
+                    sink.add_directory(&entry)?;
+                } else if let Ok(mut source) = storage_backend::blocking_reader(&entry.source) {
+                    debug!("Adding file to encrypted zip archive: {} -> {}", entry.source.display(), entry.name);
+                    sink.add_file(&entry, &mut source)?;
+                }
+            }
+            sink.finish()?;
+            let mut file = file;
+            io::Seek::seek(&mut file, io::SeekFrom::Start(0))?;
+            Ok(file)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to build the encrypted archive: {}", e))??;
+        return Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(ContentDisposition::attachment(filename))
+            .streaming(ReaderStream::new(File::from_std(file))));
+    }
+
     // For directories or multiple files, create a zip archive
     let (w, r) = duplex(4096);
-    let items = items.clone();
 
     tokio::spawn(async move {
         let mut archive = ZipArchive::new_streamable(w);
         let options = FileOptions::default().last_modified_time(FileDateTime::Now).compression_method(CompressionMethod::Store());
 
-        // Collect all files paths to put in the zip
-        let items_to_write = if is_single_entry_directory {
-            match tokio::fs::read_dir(items[0].clone()).await {
-                Ok(mut dir) => {
-                    let mut paths = Vec::new();
-                    while let Ok(Some(entry)) = dir.next_entry().await {
-                        paths.push(entry.path());
-                    }
-                    paths
-                }
-                Err(_) => items,
-            }
-        } else {
-            items
-        };
-
         for item in items_to_write {
             if let Some(filename) = item.file_name() {
                 let filename = filename.to_string_lossy().into_owned();
-                if item.is_dir() {
+                let backend = storage_backend::backend_for(&item);
+                let Ok(walked) = backend.walk(&item).await else {
+                    continue;
+                };
+                if walked.first().is_some_and(|entry| entry.is_dir) {
                     // Process directory
-                    let walker = walkdir::WalkDir::new(&item);
                     if let Err(e) = archive.append_directory(filename.as_str(), &options).await {
                         error!("Failed to add directory to zip archive: {}", e);
                         continue;
                     }
 
-                    for entry in walker.into_iter().flatten() {
-                        let path = entry.path();
+                    for entry in walked {
+                        let path = entry.path.as_path();
                         let relative_path = path.strip_prefix(&cwd).unwrap_or(path);
 
-                        if path.is_dir() {
+                        if entry.is_dir {
                             debug!("Adding directory to zip archive: {} -> {}", path.display(), relative_path.display());
                             if let Err(e) = archive.append_directory(relative_path.to_string_lossy().replace('\\', "/").as_ref(), &options).await {
                                 error!("Failed to add directory to zip archive: {}", e);
@@ -198,22 +296,28 @@ async fn download(query: Query<DownloadParameters>) -> Result<impl Responder> {
                         }
 
                         debug!("Adding file to zip archive: {} -> {}", path.display(), relative_path.display());
-                        if let Ok(mut file) = File::open(path).await {
-                            let _ = archive.append(relative_path.to_string_lossy().replace('\\', "/").as_ref(), &options, &mut file).await;
+                        if let Ok(mut reader) = backend.read(path, None).await {
+                            let _ = archive
+                                .append(
+                                    relative_path.to_string_lossy().replace('\\', "/").as_ref(),
+                                    &archive_options.archflow_options(path),
+                                    &mut reader,
+                                )
+                                .await;
                         }
                     }
                 } else {
                     // Process a single file
                     debug!("Adding file to zip archive: {} -> {}", item.display(), filename);
-                    if let Ok(mut file) = File::open(&item).await {
-                        if let Err(e) = archive.append(filename.as_str(), &options, &mut file).await {
-                            if matches!(&e, ArchiveError::IoError(err) if err.kind() == ErrorKind::BrokenPipe) {
-                                warn!("Zip archive stream closed, this is most-likely due to the client closing the connection.");
-                                break;
-                            }
-                            error!("Failed to add file to zip archive: {}", e);
-                            continue;
+                    if let Ok(mut reader) = backend.read(&item, None).await
+                        && let Err(e) = archive.append(filename.as_str(), &archive_options.archflow_options(&item), &mut reader).await
+                    {
+                        if matches!(&e, ArchiveError::IoError(err) if err.kind() == ErrorKind::BrokenPipe) {
+                            warn!("Zip archive stream closed, this is most-likely due to the client closing the connection.");
+                            break;
                         }
+                        error!("Failed to add file to zip archive: {}", e);
+                        continue;
                     }
                 }
             }
@@ -226,11 +330,35 @@ async fn download(query: Query<DownloadParameters>) -> Result<impl Responder> {
 }
 
 #[get("search")]
-async fn search(query_map: Query<HashMap<String, String>>) -> Result<impl Responder> {
+async fn search(parameters: Query<SearchParameters>) -> Result<impl Responder> {
+    if let Some(q) = &parameters.q {
+        if parameters.mode != SearchMode::Text {
+            let pattern = PathPattern::new(q, parameters.mode)?;
+            return Ok(HttpResponse::Ok().json(json!(IndexerData::find_matching(&pattern, &parameters).await?)));
+        }
+
+        let query = SearchQuery::parse(q)?;
+        if query.is_empty() {
+            return Ok(HttpResponse::Ok().json(json!(SearchPage { results: Vec::new(), total: 0, next_cursor: None, truncated: false })));
+        }
+        let cursor = parameters.cursor.as_deref().map(|cursor| decode_cursor(cursor, parameters.sort)).transpose()?;
+        let folder = match query.folder.as_deref() {
+            Some(folder) => Some(existing_folder(folder).await?),
+            None => None,
+        };
+
+        Ok(HttpResponse::Ok().json(json!(IndexerData::find(&query, folder.as_deref(), &parameters, cursor).await?)))
+    } else {
+        Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Search query is required"
+        })))
+    }
+}
+#[get("search/content")]
+async fn search_content(query_map: Query<HashMap<String, String>>) -> Result<impl Responder> {
     if let Some(query) = query_map.get("q") {
-        let filename_only = query_map.get("filename_only").map(|s| s == "true").unwrap_or(false);
-        let results = IndexerData::search(query, filename_only).await?;
-        Ok(HttpResponse::Ok().json(json!(results)))
+        let limit = query_map.get("limit").and_then(|limit| limit.parse().ok()).unwrap_or(50).min(500);
+        Ok(HttpResponse::Ok().json(json!(content_db::search(query, limit).await?)))
     } else {
         Ok(HttpResponse::BadRequest().json(json!({
             "error": "Search query is required"
@@ -239,27 +367,32 @@ async fn search(query_map: Query<HashMap<String, String>>) -> Result<impl Respon
 }
 #[post("refresh-index")]
 pub async fn refresh_index() -> Result<impl Responder> {
-    if let Err(e) = indexer_data::index_all_files().await {
-        error!("Error starting file indexer: {}", e);
+    if !indexer_data::start_reconcile() {
+        return Err(Error::conflict("The index is being reconciled already"));
     }
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::Accepted().json(json!(indexer_state().status())))
 }
 // Add a new endpoint for progress tracking
 #[get("/upload/progress/{upload_id}")]
 async fn upload_progress(upload_id: web::Path<String>) -> impl Responder {
+    track_upload_progress(upload_id.into_inner()).await
+}
+
+/// Opens the SSE stream that [`receive_upload`] reports the progress of `upload_id` to.
+pub(crate) async fn track_upload_progress(upload_id: String) -> impl Responder {
     let (tx, rx) = tokio::sync::mpsc::channel(100);
 
     // Store the sender in our tracker
     {
         let mut trackers = get_upload_trackers().lock().await;
-        trackers.insert(upload_id.to_string(), tx);
+        trackers.insert(upload_id, tx);
     }
 
     Sse::from_infallible_receiver(rx).with_keep_alive(Duration::from_secs(3))
 }
 
 #[post("/upload")]
-async fn upload(mut payload: web::Payload, request: HttpRequest) -> impl Responder {
+async fn upload(payload: web::Payload, request: HttpRequest) -> impl Responder {
     // Extract upload ID
     let upload_id = match request.headers().get("X-Upload-ID") {
         Some(header) => match header.to_str() {
@@ -293,6 +426,13 @@ async fn upload(mut payload: web::Payload, request: HttpRequest) -> impl Respond
         }
     };
 
+    receive_upload(payload, path, upload_id, None).await
+}
+
+/// Streams an upload into the file at `path`, reporting progress to the SSE tracker registered for `upload_id`.
+///
+/// When `max_bytes` is set, the upload is aborted and the partial file removed once it grows past the limit.
+pub(crate) async fn receive_upload(mut payload: web::Payload, path: PathBuf, upload_id: String, max_bytes: Option<u64>) -> HttpResponse {
     // Get the progress sender for this upload
     let progress_sender = {
         let trackers = get_upload_trackers().lock().await;
@@ -306,7 +446,8 @@ async fn upload(mut payload: web::Payload, request: HttpRequest) -> impl Respond
         cancel_flags.insert(upload_id.clone(), cancel_flag.clone());
     }
 
-    let mut file = match File::create(&path).await {
+    let backend = storage_backend::backend_for(&path);
+    let mut file = match backend.write(&path).await {
         Ok(file) => file,
         Err(_) => {
             // Clean up the cancellation flag
@@ -346,7 +487,7 @@ async fn upload(mut payload: web::Payload, request: HttpRequest) -> impl Respond
 
             // Close and delete the partial file
             file.shutdown().await.ok();
-            fs::remove_file(&path).await.ok();
+            backend.delete(&path).await.ok();
 
             return HttpResponse::Ok().json(json!({
                 "status": "cancelled",
@@ -367,6 +508,17 @@ async fn upload(mut payload: web::Payload, request: HttpRequest) -> impl Respond
                 }
 
                 total_bytes += bytes.len() as u64;
+                if max_bytes.is_some_and(|max_bytes| total_bytes > max_bytes) {
+                    warn!("Upload with ID {} exceeded its size limit", upload_id);
+                    let mut cancel_flags = get_upload_cancel_flags().lock().await;
+                    cancel_flags.remove(&upload_id);
+
+                    file.shutdown().await.ok();
+                    backend.delete(&path).await.ok();
+                    return HttpResponse::PayloadTooLarge().json(json!({
+                        "error": "The upload exceeds the size limit"
+                    }));
+                }
 
                 // Send progress update if we have a sender
                 if let Some(sender) = &progress_sender {
@@ -393,6 +545,15 @@ async fn upload(mut payload: web::Payload, request: HttpRequest) -> impl Respond
         }
     }
 
+    if file.shutdown().await.is_err() {
+        let mut cancel_flags = get_upload_cancel_flags().lock().await;
+        cancel_flags.remove(&upload_id);
+
+        return HttpResponse::InternalServerError().json(json!({
+            "error": "Failed to write file"
+        }));
+    }
+
     // Send completion event
     if let Some(sender) = progress_sender {
         let _ = sender
@@ -433,49 +594,26 @@ async fn copy_filesystem_entry(body: web::Json<serde_json::Value>) -> Result<imp
     let dest_path = body.get("path").and_then(|path| path.as_str()).ok_or_else(|| anyhow::anyhow!("Invalid destination path"))?.to_os_path();
 
     // Verify source paths exist
-    for source_path in &source_paths {
-        if !source_path.exists() {
-            return Ok(HttpResponse::NotFound().json(json!({
-                "error": format!("Source path does not exist: {}", source_path.display())
-            })));
+    let mut sources = Vec::with_capacity(source_paths.len());
+    for source_path in source_paths {
+        match storage_backend::backend_for(&source_path).stat(&source_path).await {
+            Ok(entry) => sources.push((source_path, entry.is_dir)),
+            Err(_) => {
+                return Ok(HttpResponse::NotFound().json(json!({
+                    "error": format!("Source path does not exist: {}", source_path.display())
+                })));
+            }
         }
     }
 
     // Copy each source to the destination
-    for source_path in source_paths {
+    for (source_path, is_dir) in sources {
         let dest = dest_path.join(source_path.file_name().unwrap_or_default());
-
-        if source_path.is_dir() {
-            // Create a copy function for recursive directory copy
-            fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-                std::fs::create_dir_all(&dst)?;
-                for entry in std::fs::read_dir(src)? {
-                    let entry = entry?;
-                    let ty = entry.file_type()?;
-                    let src_path = entry.path();
-                    let dst_path = dst.as_ref().join(entry.file_name());
-
-                    if ty.is_dir() {
-                        copy_dir_all(&src_path, &dst_path)?;
-                    } else {
-                        std::fs::copy(&src_path, &dst_path)?;
-                    }
-                }
-                Ok(())
-            }
-
-            if let Err(e) = copy_dir_all(&source_path, &dest) {
-                return Ok(HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to copy directory: {}", e)
-                })));
-            }
-        } else {
-            // Copy file
-            if let Err(e) = std::fs::copy(&source_path, &dest) {
-                return Ok(HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to copy file: {}", e)
-                })));
-            }
+        if let Err(e) = storage_backend::copy(&source_path, &dest).await {
+            let kind = if is_dir { "directory" } else { "file" };
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to copy {}: {}", kind, e)
+            })));
         }
     }
 
@@ -499,7 +637,7 @@ async fn move_filesystem_entry(body: web::Json<serde_json::Value>) -> Result<imp
 
     // Move each source to a destination
     for source_path in source_paths {
-        if !source_path.exists() {
+        if storage_backend::backend_for(&source_path).stat(&source_path).await.is_err() {
             return Ok(HttpResponse::NotFound().json(json!({
                 "error": format!("Source path does not exist: {}", source_path.display())
             })));
@@ -507,7 +645,7 @@ async fn move_filesystem_entry(body: web::Json<serde_json::Value>) -> Result<imp
         let dest = dest_path.join(source_path.file_name().unwrap_or_default());
 
         // Move/rename is the same operation in fs terms
-        if let Err(e) = std::fs::rename(&source_path, &dest) {
+        if let Err(e) = storage_backend::rename(&source_path, &dest).await {
             return Ok(HttpResponse::InternalServerError().json(json!({
                 "error": format!("Failed to move entry: {}", e)
             })));
@@ -525,13 +663,13 @@ async fn rename_filesystem_entry(body: web::Json<serde_json::Value>) -> Result<i
     let source_path = body.get("source").and_then(|path| path.as_str()).ok_or_else(|| anyhow::anyhow!("Invalid source path"))?.to_os_path();
     let dest_path = body.get("destination").and_then(|path| path.as_str()).ok_or_else(|| anyhow::anyhow!("Invalid destination path"))?.to_os_path();
 
-    if !source_path.exists() {
+    if storage_backend::backend_for(&source_path).stat(&source_path).await.is_err() {
         return Ok(HttpResponse::NotFound().json(json!({
             "error": format!("Source path does not exist: {}", source_path.display())
         })));
     }
 
-    if let Err(e) = std::fs::rename(&source_path, &dest_path) {
+    if let Err(e) = storage_backend::rename(&source_path, &dest_path).await {
         return Ok(HttpResponse::InternalServerError().json(json!({
             "error": format!("Failed to move entry: {}", e)
         })));
@@ -561,24 +699,20 @@ async fn delete_filesystem_entry(body: web::Json<serde_json::Value>) -> Result<i
     };
 
     for path in paths {
+        let backend = storage_backend::backend_for(&path);
         // Verify a path exists
-        if !path.exists() {
+        let Ok(entry) = backend.stat(&path).await else {
             return Ok(HttpResponse::NotFound().json(json!({
                 "error": "Path does not exist",
                 "path": path.to_string_lossy().into_owned()
             })));
-        }
+        };
 
         // Delete a file or directory
-        if path.is_dir() {
-            if let Err(e) = std::fs::remove_dir_all(&path) {
-                return Ok(HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to delete directory: {}", e)
-                })));
-            }
-        } else if let Err(e) = std::fs::remove_file(&path) {
+        if let Err(e) = backend.delete(&path).await {
+            let kind = if entry.is_dir { "directory" } else { "file" };
             return Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to delete file: {}", e)
+                "error": format!("Failed to delete {}: {}", kind, e)
             })));
         }
     }
@@ -599,14 +733,18 @@ async fn new_filesystem_entry(body: web::Json<serde_json::Value>) -> Result<impl
 
     let is_directory = body.get("is_directory").and_then(|d| d.as_bool()).unwrap_or(false);
 
+    let backend = storage_backend::backend_for(&file_path);
     if is_directory {
-        fs::create_dir_all(&file_path)
+        backend
+            .create_dir(&file_path)
             .await
             .map_err(|e| Error::filesystem_error(format!("Failed to create directory: {}", file_path.display()), Some(e), Some(file_path.clone())))?;
     } else {
-        File::create(&file_path)
-            .await
-            .map_err(|e| Error::filesystem_error(format!("Failed to create file: {}", file_path.display()), Some(e), Some(file_path.clone())))?;
+        let created = match backend.write(&file_path).await {
+            Ok(mut writer) => writer.shutdown().await,
+            Err(e) => Err(e),
+        };
+        created.map_err(|e| Error::filesystem_error(format!("Failed to create file: {}", file_path.display()), Some(e), Some(file_path.clone())))?;
     }
 
     Ok(HttpResponse::Ok().finish())
@@ -631,6 +769,85 @@ async fn get_indexer_stats() -> Result<impl Responder> {
     })))
 }
 
+#[get("/indexer/status")]
+async fn get_indexer_status() -> impl Responder {
+    HttpResponse::Ok().json(json!(indexer_state().status()))
+}
+
+/// The file watcher and how many events it received, applied and dropped since the server started.
+#[get("/indexer/watcher")]
+async fn get_watcher_status() -> impl Responder {
+    HttpResponse::Ok().json(json!(indexer_data::watcher_status()))
+}
+
+/// Streams the indexer status whenever it changes, at most four times a second.
+#[get("/indexer/events")]
+async fn indexer_events() -> impl Responder {
+    let (tx, rx) = tokio::sync::mpsc::channel(10);
+    let mut status = indexer_state().subscribe();
+    tokio::spawn(async move {
+        loop {
+            let data = json!(*status.borrow_and_update()).to_string();
+            if tx.send(Event::from(Data::new(data))).await.is_err() || status.changed().await.is_err() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    });
+
+    Sse::from_infallible_receiver(rx).with_keep_alive(Duration::from_secs(3))
+}
+
+#[post("/indexer/pause")]
+async fn pause_indexer() -> Result<impl Responder> {
+    if !indexer_state().pause() {
+        return Err(Error::conflict("The indexer is not running"));
+    }
+    Ok(HttpResponse::Ok().json(json!(indexer_state().status())))
+}
+
+#[post("/indexer/resume")]
+async fn resume_indexer() -> Result<impl Responder> {
+    if !indexer_state().resume() {
+        return Err(Error::conflict("The indexer is not running"));
+    }
+    Ok(HttpResponse::Ok().json(json!(indexer_state().status())))
+}
+
+#[post("/indexer/cancel")]
+async fn cancel_indexer() -> Result<impl Responder> {
+    if !indexer_state().cancel() {
+        return Err(Error::conflict("The indexer is not running"));
+    }
+    Ok(HttpResponse::Ok().json(json!(indexer_state().status())))
+}
+
+/// Groups of indexed files with the same content, those wasting the most space first.
+#[get("/duplicates")]
+async fn get_duplicates(parameters: Query<DuplicateParameters>) -> Result<impl Responder> {
+    let prefix = match parameters.prefix.as_deref() {
+        Some(prefix) => Some(existing_folder(prefix).await?),
+        None => None,
+    };
+    Ok(HttpResponse::Ok().json(json!(duplicate_db::find_duplicates(&parameters, prefix.as_deref()).await?)))
+}
+
+/// Deletes duplicates, or replaces them with hardlinks to the copy that is kept, as a job.
+#[post("/duplicates/resolve")]
+async fn resolve_duplicates(body: web::Json<ResolveDuplicates>) -> Result<impl Responder> {
+    let mut request = body.into_inner();
+    if request.groups.iter().all(|group| group.duplicates.is_empty()) {
+        return Err(Error::validation_error("There are no duplicates to resolve", Some("groups")));
+    }
+    let os_path = |path: &str| path.to_os_path().to_string_lossy().replace('\\', "/");
+    for group in &mut request.groups {
+        group.keep = os_path(&group.keep);
+        group.duplicates = group.duplicates.iter().map(String::as_str).map(os_path).collect();
+    }
+    let job = duplicate_data::start_resolve(request).ok_or_else(|| Error::conflict("Duplicates are being resolved already"))?;
+    Ok(HttpResponse::Accepted().json(job))
+}
+
 #[post("/archive")]
 async fn archive_paths(body: web::Json<serde_json::Value>) -> Result<impl Responder> {
     let filenames = body
@@ -651,8 +868,16 @@ async fn archive_paths(body: web::Json<serde_json::Value>) -> Result<impl Respon
         .get("tracker_id")
         .and_then(|filename| filename.as_str())
         .ok_or_else(|| Error::validation_error("Tracker ID is required", Some("tracker_id")))?;
+    let mut archive_options: ArchiveOptions =
+        serde_json::from_value(body.0.clone()).map_err(|e| Error::validation_error(format!("Invalid archive options: {}", e), None::<String>))?;
+    archive_options.resolve_format(archive_file_name)?;
+    archive_options.validate()?;
     let absolute_file_paths = filenames.iter().map(|filename| cwd.join(filename)).collect::<Vec<_>>();
     let archive_path = cwd.join(archive_file_name);
+    // Archives may be written straight to the local path, which a read-only mount would not notice
+    if storage_backend::backend_for(&archive_path).is_read_only() {
+        return Err(Error::permission_denied(format!("{} is on a read-only mount", archive_path.display())));
+    }
 
     let trackers = get_archive_trackers().lock().await;
     if let Some(tracker) = trackers.get(tracker_id) {
@@ -666,7 +891,7 @@ async fn archive_paths(body: web::Json<serde_json::Value>) -> Result<impl Respon
         }
 
         // Run the archive operation with the cancellation flag
-        archive_wrapper::archive(archive_path.clone(), absolute_file_paths, tracker, &cancel_flag).await.map_err(|_| {
+        archive_wrapper::archive(archive_path.clone(), absolute_file_paths, &archive_options, tracker, cancel_flag.clone()).await.map_err(|_| {
             Error::filesystem_error(format!("Failed to create archive: {}", archive_path.display()), None, Some(archive_path.clone()))
         })?;
 
@@ -684,6 +909,73 @@ async fn archive_paths(body: web::Json<serde_json::Value>) -> Result<impl Respon
     Ok(HttpResponse::Ok().finish())
 }
 
+#[post("/extract")]
+async fn extract_archive(body: web::Json<serde_json::Value>) -> Result<impl Responder> {
+    let archive_path = body
+        .get("path")
+        .and_then(|path| path.as_str())
+        .ok_or_else(|| Error::validation_error("Archive path is required", Some("path")))?
+        .to_os_path();
+    if !storage_backend::backend_for(&archive_path).stat(&archive_path).await.is_ok_and(|entry| !entry.is_dir) {
+        return Err(Error::not_found(archive_path.to_string_lossy()));
+    }
+    // Without an explicit destination the archive is extracted next to itself, into a folder named after it
+    let destination = match body.get("destination").and_then(|destination| destination.as_str()) {
+        Some(destination) => new_os_path(destination).await?,
+        None => {
+            let filename = archive_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            let stem = ArchiveFormat::from_filename(&filename)
+                .and_then(|format| filename.strip_suffix(&format!(".{}", format.extension())).map(String::from))
+                .unwrap_or_else(|| archive_path.file_stem().unwrap_or_default().to_string_lossy().to_string());
+            archive_path.with_file_name(stem)
+        }
+    };
+    let password = body.get("password").and_then(|password| password.as_str()).map(String::from);
+
+    if storage_backend::backend_for(&destination).is_read_only() {
+        return Err(Error::permission_denied(format!("{} is on a read-only mount", destination.display())));
+    }
+
+    debug!("Extracting {} to {}", archive_path.display(), destination.display());
+    archive_wrapper::extract(&archive_path, &destination, password.as_deref()).await.map_err(|e| {
+        if archive_wrapper::is_password_error(&e) {
+            Error::authorization_error(format!("{:#}", e))
+        } else {
+            Error::filesystem_error(format!("Failed to extract {}: {:#}", archive_path.display(), e), None, Some(archive_path.clone()))
+        }
+    })?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The OS path of `path`, a file or folder that may not exist yet, in an existing folder.
+///
+/// Paths that don't resolve fall back to `root_path`, so the folder is resolved and checked on its own before the
+/// name is added.
+async fn new_os_path(path: &str) -> Result<PathBuf> {
+    let path = path.replace('\\', "/");
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if matches!(name, "" | "." | "..") {
+        return Err(Error::validation_error(format!("{} is not a valid name", path), Some("destination")));
+    }
+    let parent = if parent.is_empty() { "/" } else { parent };
+    Ok(existing_folder(parent).await?.join(name))
+}
+
+/// The OS path of `path`, an existing folder as clients see it.
+///
+/// Paths that don't resolve fall back to `root_path`, so the OS path is mapped back and compared with `path`.
+async fn existing_folder(path: &str) -> Result<PathBuf> {
+    let os_path = path.to_os_path();
+    let requested = path.replace('\\', "/");
+    let resolved = to_virtual_path(&os_path).is_some_and(|virtual_path| virtual_path.trim_matches('/') == requested.trim_matches('/'));
+    if !resolved || !storage_backend::backend_for(&os_path).stat(&os_path).await.is_ok_and(|entry| entry.is_dir) {
+        return Err(Error::not_found(path));
+    }
+    Ok(os_path)
+}
+
 #[get("/archive/status/{tracker_id}")]
 async fn get_archive_status(tracker_id: web::Path<String>) -> impl Responder {
     let (tx, rx) = tokio::sync::mpsc::channel(100);
@@ -777,9 +1069,11 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             web::scope("")
                 .wrap(Authentication::new())
                 .service(archive_paths)
+                .service(extract_archive)
                 .service(get_archive_status)
                 .service(cancel_archive)
                 .service(download)
+                .service(search_content)
                 .service(search)
                 .service(upload)
                 .service(upload_progress)
@@ -789,7 +1083,16 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
                 .service(rename_filesystem_entry)
                 .service(delete_filesystem_entry)
                 .service(new_filesystem_entry)
-                .service(get_indexer_stats),
+                .service(get_indexer_stats)
+                .service(get_indexer_status)
+                .service(get_watcher_status)
+                .service(indexer_events)
+                .service(pause_indexer)
+                .service(resume_indexer)
+                .service(cancel_indexer)
+                .service(get_duplicates)
+                .service(resolve_duplicates)
+                .service(refresh_index),
         ),
     );
 }
@@ -420,6 +420,7 @@ mod tests {
 mod endpoint_tests {
     use crate::configuration::configuration_data::Configuration;
     use crate::io::fs::filesystem_endpoint;
+    use crate::io::fs::indexer::indexer_data;
     use actix_web::{App, http::header, test, web};
     use std::fs::File;
     use std::io::{Read, Write};
@@ -437,6 +438,8 @@ mod endpoint_tests {
         let test_file_path = temp_path.join("test_file.txt");
         let mut test_file = File::create(&test_file_path).expect("Failed to create test file");
         test_file.write_all(b"This is test content").expect("Failed to write to test file");
+        // The default filter hides temporary directories from listings, the duplicate tests use the same scope
+        indexer_data::set_index_scope(Configuration { filter: vec!["**/*.part".to_string()], ..Configuration::default() });
 
         // Create a test app
         let app = test::init_service(
@@ -690,6 +693,17 @@ mod endpoint_tests {
         assert_eq!(resp.status().as_u16(), 400); // Bad Request
     }
 
+    // Test for search endpoint with a folder that doesn't exist
+    #[actix_web::test]
+    async fn test_search_unknown_folder() {
+        let app = test::init_service(App::new().service(web::scope("/api").service(web::scope("/fs").service(filesystem_endpoint::search)))).await;
+
+        // The search isn't widened to the whole tree
+        let req = test::TestRequest::get().uri("/api/fs/search?q=report%20in:/no/such/folder").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
     // Test for delete endpoint
     #[actix_web::test]
     async fn test_delete_filesystem_entry() {
@@ -927,3 +941,224 @@ mod endpoint_tests {
         assert_eq!(dest_content, test_content);
     }
 }
+
+#[cfg(test)]
+mod archive_browse_tests {
+    use crate::io::fs::archive_wrapper::{self, ArchiveKind, ArchivePath};
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::tempdir;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    fn create_test_zip(path: &Path) {
+        let mut zip = ZipWriter::new(File::create(path).expect("Failed to create zip file"));
+        let options = SimpleFileOptions::default();
+        zip.start_file("readme.txt", options).expect("Failed to start readme.txt");
+        zip.write_all(b"top level").expect("Failed to write readme.txt");
+        // No explicit directory entry for "www", it should be implied by its children
+        zip.start_file("www/index.html", options).expect("Failed to start index.html");
+        zip.write_all(b"<html></html>").expect("Failed to write index.html");
+        zip.start_file("www/css/site.css", options).expect("Failed to start site.css");
+        zip.write_all(b"body {}").expect("Failed to write site.css");
+        zip.finish().expect("Failed to finish zip");
+    }
+
+    #[test]
+    fn test_archive_kind_detection() {
+        assert_eq!(ArchiveKind::from_filename("site.zip"), Some(ArchiveKind::Zip));
+        assert_eq!(ArchiveKind::from_filename("backup.tar"), Some(ArchiveKind::Tar));
+        assert_eq!(ArchiveKind::from_filename("backup.TAR.GZ"), Some(ArchiveKind::TarGz));
+        assert_eq!(ArchiveKind::from_filename("backup.tgz"), Some(ArchiveKind::TarGz));
+        assert_eq!(ArchiveKind::from_filename("backup.tar.zst"), Some(ArchiveKind::TarZst));
+        assert_eq!(ArchiveKind::from_filename("backup.tar.bz2"), Some(ArchiveKind::TarBz2));
+        assert_eq!(ArchiveKind::from_filename("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_list_zip_archive() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let zip_path = temp_dir.path().join("site.zip");
+        create_test_zip(&zip_path);
+
+        // The archive itself is listed as the root of a virtual directory
+        let root = ArchivePath::parse(&zip_path.to_string_lossy()).expect("Failed to parse archive path");
+        assert!(root.member.is_empty());
+        let listing = archive_wrapper::list_archive(&root).expect("Failed to list archive root");
+        assert_eq!(listing.entries.len(), 2);
+        let www = listing.entries.iter().find(|e| e.filename == "www").expect("Implied www directory not listed");
+        assert!(www.is_dir);
+        let readme = listing.entries.iter().find(|e| e.filename == "readme.txt").expect("readme.txt not listed");
+        assert!(!readme.is_dir);
+        assert_eq!(readme.size, 9);
+
+        // Nested directories inside the archive can be listed as well
+        let nested = ArchivePath::parse(&format!("{}/www", zip_path.to_string_lossy())).expect("Failed to parse nested path");
+        assert_eq!(nested.member, "www");
+        let listing = archive_wrapper::list_archive(&nested).expect("Failed to list nested directory");
+        let names: Vec<&str> = listing.entries.iter().map(|e| e.filename.as_str()).collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"index.html"));
+        assert!(names.contains(&"css"));
+        assert_eq!(listing.parent, Some(zip_path.to_string_lossy().replace('\\', "/")));
+
+        // Missing members are reported as errors
+        let missing = ArchivePath::parse(&format!("{}/missing", zip_path.to_string_lossy())).expect("Failed to parse missing path");
+        assert!(archive_wrapper::list_archive(&missing).is_err());
+    }
+
+    #[test]
+    fn test_list_tar_gz_archive() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let source_path = temp_dir.path().join("notes.txt");
+        std::fs::write(&source_path, b"some notes").expect("Failed to write notes.txt");
+
+        let archive_path = temp_dir.path().join("backup.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(File::create(&archive_path).expect("Failed to create tar.gz"), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        builder.append_path_with_name(&source_path, "docs/notes.txt").expect("Failed to append notes.txt");
+        builder.into_inner().expect("Failed to finish tar").finish().expect("Failed to finish gzip");
+
+        let docs = ArchivePath::parse(&format!("{}/docs", archive_path.to_string_lossy())).expect("Failed to parse tar.gz path");
+        assert_eq!(docs.kind, ArchiveKind::TarGz);
+        let listing = archive_wrapper::list_archive(&docs).expect("Failed to list tar.gz directory");
+        assert_eq!(listing.entries.len(), 1);
+        assert_eq!(listing.entries[0].filename, "notes.txt");
+        assert_eq!(listing.entries[0].size, 10);
+        assert!(listing.entries[0].last_modified.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_stream_archive_member() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let zip_path = temp_dir.path().join("site.zip");
+        create_test_zip(&zip_path);
+
+        let member = ArchivePath::parse(&format!("{}/www/css/site.css", zip_path.to_string_lossy())).expect("Failed to parse member path");
+        let mut receiver = archive_wrapper::stream_archive_member(member, None).await.expect("Failed to stream member");
+        let mut contents = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            contents.extend_from_slice(&chunk.expect("Failed to read chunk"));
+        }
+        assert_eq!(contents, b"body {}");
+
+        let missing = ArchivePath::parse(&format!("{}/www/missing.css", zip_path.to_string_lossy())).expect("Failed to parse member path");
+        assert!(archive_wrapper::stream_archive_member(missing, None).await.is_err());
+    }
+}
+
+#[cfg(test)]
+mod archive_creation_tests {
+    use crate::io::fs::archive_options::{ArchiveFormat, ArchiveOptions, CompressionMethod, is_precompressed};
+    use crate::io::fs::archive_wrapper;
+    use std::io::Read;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_format_from_filename() {
+        assert_eq!(ArchiveFormat::from_filename("logs.zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_filename("logs.tar"), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::from_filename("logs.tar.gz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_filename("logs.tar.zst"), Some(ArchiveFormat::TarZst));
+        assert_eq!(ArchiveFormat::from_filename("logs.7z"), Some(ArchiveFormat::SevenZip));
+        assert_eq!(ArchiveFormat::from_filename("logs.txt"), None);
+    }
+
+    #[test]
+    fn test_precompressed_files_are_stored() {
+        let options =
+            ArchiveOptions { format: Some(ArchiveFormat::Zip), compression: Some(CompressionMethod::Zstd), level: Some(19), password: None };
+        assert!(is_precompressed(Path::new("holiday.JPG")));
+        assert_eq!(options.method_for(Path::new("holiday.jpg")), CompressionMethod::Stored);
+        assert_eq!(options.method_for(Path::new("movie.mp4")), CompressionMethod::Stored);
+        assert_eq!(options.method_for(Path::new("server.log")), CompressionMethod::Zstd);
+        assert_eq!(ArchiveOptions::default().method_for(Path::new("server.log")), CompressionMethod::Deflate);
+    }
+
+    #[test]
+    fn test_archive_options_validation() {
+        assert!(ArchiveOptions::default().validate().is_ok());
+        assert!(ArchiveOptions { format: None, compression: Some(CompressionMethod::Deflate), level: Some(9), password: None }.validate().is_ok());
+        assert!(ArchiveOptions { format: None, compression: Some(CompressionMethod::Deflate), level: Some(12), password: None }.validate().is_err());
+        assert!(ArchiveOptions { format: Some(ArchiveFormat::TarZst), compression: None, level: Some(19), password: None }.validate().is_ok());
+        assert!(
+            ArchiveOptions { format: Some(ArchiveFormat::TarGz), compression: Some(CompressionMethod::Zstd), level: None, password: None }
+                .validate()
+                .is_err()
+        );
+
+        // The format is taken from the extension, which has to agree with a chosen one
+        let mut options = ArchiveOptions::default();
+        options.resolve_format("logs.tar.zst").unwrap();
+        assert_eq!(options.format, Some(ArchiveFormat::TarZst));
+        assert!(ArchiveOptions { format: Some(ArchiveFormat::Zip), ..ArchiveOptions::default() }.resolve_format("logs.7z").is_err());
+        assert!(ArchiveOptions { format: Some(ArchiveFormat::Zip), ..ArchiveOptions::default() }.resolve_format("logs").is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_create_compressed_archives() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let logs_dir = temp_dir.path().join("logs");
+        std::fs::create_dir(&logs_dir).expect("Failed to create logs directory");
+        let log_content = "GET /index.html 200\n".repeat(1000);
+        std::fs::write(logs_dir.join("access.log"), &log_content).expect("Failed to write access.log");
+
+        let (sender, _receiver) = tokio::sync::mpsc::channel(100);
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        // A deflated zip should be much smaller than the repetitive log it contains
+        let zip_path = temp_dir.path().join("logs.zip");
+        let options =
+            ArchiveOptions { format: Some(ArchiveFormat::Zip), compression: Some(CompressionMethod::Deflate), level: Some(9), password: None };
+        archive_wrapper::archive(&zip_path, vec![logs_dir.clone()], &options, &sender, cancelled.clone()).await.expect("Failed to create zip");
+        assert!(std::fs::metadata(&zip_path).unwrap().len() < log_content.len() as u64 / 10);
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).expect("Failed to open zip");
+        let mut contents = String::new();
+        zip.by_name("logs/access.log").expect("Missing logs/access.log").read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, log_content);
+
+        // A tar.zst archive contains the same entries
+        let tar_path = temp_dir.path().join("logs.tar.zst");
+        let options = ArchiveOptions { format: Some(ArchiveFormat::TarZst), compression: None, level: Some(3), password: None };
+        archive_wrapper::archive(&tar_path, vec![logs_dir], &options, &sender, cancelled.clone()).await.expect("Failed to create tar.zst");
+        let decoder = zstd::stream::read::Decoder::new(std::fs::File::open(&tar_path).unwrap()).unwrap();
+        let mut archive = tar::Archive::new(decoder);
+        let names: Vec<String> =
+            archive.entries().unwrap().map(|e| e.unwrap().path().unwrap().to_string_lossy().trim_end_matches('/').to_string()).collect();
+        assert!(names.contains(&"logs".to_string()));
+        assert!(names.contains(&"logs/access.log".to_string()));
+
+        let destination = temp_dir.path().join("extracted");
+        archive_wrapper::extract(&tar_path, &destination, None).await.expect("Failed to extract tar.zst");
+        assert_eq!(std::fs::read_to_string(destination.join("logs/access.log")).unwrap(), log_content);
+    }
+
+    #[actix_web::test]
+    async fn test_password_protected_zip() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let secret = temp_dir.path().join("secret.txt");
+        std::fs::write(&secret, "launch codes").expect("Failed to write secret.txt");
+
+        let (sender, _receiver) = tokio::sync::mpsc::channel(100);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let zip_path = temp_dir.path().join("secret.zip");
+        let options = ArchiveOptions { format: Some(ArchiveFormat::Zip), password: Some("hunter2".to_string()), ..Default::default() };
+        archive_wrapper::archive(&zip_path, vec![secret], &options, &sender, cancelled.clone()).await.expect("Failed to create encrypted zip");
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).expect("Failed to open zip");
+        assert!(zip.by_name("secret.txt").is_err());
+        let mut contents = String::new();
+        zip.by_name_decrypt("secret.txt", b"hunter2").expect("Failed to decrypt secret.txt").read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "launch codes");
+
+        let wrong = archive_wrapper::extract(&zip_path, &temp_dir.path().join("wrong"), Some("hunter3")).await.expect_err("Wrong password accepted");
+        assert!(archive_wrapper::is_password_error(&wrong));
+        let destination = temp_dir.path().join("extracted");
+        archive_wrapper::extract(&zip_path, &destination, Some("hunter2")).await.expect("Failed to extract encrypted zip");
+        assert_eq!(std::fs::read_to_string(destination.join("secret.txt")).unwrap(), "launch codes");
+    }
+}
@@ -0,0 +1,159 @@
+use crate::configuration::configuration_data::Configuration;
+use anyhow::{Context, Result};
+use quick_xml::events::Event;
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+
+/// Files are indexed in chunks of this many lines, so a match can be traced back to the line it is on.
+pub const LINES_PER_CHUNK: usize = 50;
+
+/// Marks the start of a match in the text returned by FTS5's `snippet()` and `highlight()`.
+pub const MATCH_START: char = '\u{1}';
+/// Marks the end of a match in the text returned by FTS5's `snippet()` and `highlight()`.
+pub const MATCH_END: char = '\u{2}';
+
+/// Extensions of files that are indexed as plain text.
+#[rustfmt::skip]
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "rst", "csv", "tsv", "json", "jsonc", "xml", "yaml", "yml", "toml", "ini", "cfg", "conf", "env", "log", "html",
+    "htm", "css", "scss", "less", "js", "jsx", "mjs", "cjs", "ts", "tsx", "vue", "svelte", "rs", "py", "rb", "go", "java", "kt", "kts",
+    "scala", "c", "h", "cpp", "hpp", "cc", "cs", "php", "swift", "lua", "pl", "r", "sql", "sh", "bash", "zsh", "fish", "ps1", "bat", "cmd",
+    "gradle", "dockerfile", "makefile",
+];
+
+/// A run of lines of a file, the unit content is indexed and matched in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentChunk {
+    /// The position of the chunk in the file, counting the blank chunks that are left out.
+    pub index: u64,
+    pub content: String,
+}
+
+/// A place in a file where the content matches a search.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentMatch {
+    pub path: String,
+    pub filename: String,
+    /// The 1-based line of the first match.
+    pub line: u64,
+    /// The text around the match, on a single line.
+    pub snippet: String,
+    /// The matched parts of `snippet`, as `[start, end)` character offsets.
+    pub highlights: Vec<(usize, usize)>,
+}
+
+fn extension(path: &Path) -> String {
+    let filename = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match path.extension() {
+        Some(extension) => extension.to_string_lossy().to_lowercase(),
+        // Files such as `Dockerfile` and `Makefile` are known by their name
+        None => filename,
+    }
+}
+
+/// Whether the content of the file at `path` is indexed, going by its extension and size.
+pub fn is_indexable(path: &Path, size: u64, config: &Configuration) -> bool {
+    if !config.content_indexing_enabled || size > config.content_max_file_size {
+        return false;
+    }
+    let extension = extension(path);
+    let included = config.included_extensions.is_empty()
+        || config.included_extensions.iter().any(|included| included.trim_start_matches('.').eq_ignore_ascii_case(&extension));
+    let extractable = TEXT_EXTENSIONS.contains(&extension.as_str()) || extension == "docx" || (cfg!(feature = "pdf") && extension == "pdf");
+    included && extractable
+}
+
+/// Extracts the text of the file at `path`, or `None` when it turns out not to be text.
+///
+/// Has to be called on a blocking thread, documents are parsed in full.
+pub fn extract_text(path: &Path) -> Result<Option<String>> {
+    match extension(path).as_str() {
+        "docx" => extract_docx(path).map(Some),
+        #[cfg(feature = "pdf")]
+        "pdf" => pdf_extract::extract_text(path).map(Some).context("Failed to extract the text of the PDF"),
+        _ => {
+            let bytes = std::fs::read(path).context("Failed to read file")?;
+            // A NUL byte near the start gives away a binary file that merely has a text extension
+            if bytes.iter().take(8192).any(|byte| *byte == 0) {
+                return Ok(None);
+            }
+            Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+        }
+    }
+}
+
+/// Reads the paragraphs of a Word document from its `word/document.xml`, one per line.
+fn extract_docx(path: &Path) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?).context("Failed to open document")?;
+    let mut xml = String::new();
+    archive.by_name("word/document.xml").context("The document has no body")?.read_to_string(&mut xml)?;
+
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut in_text = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) if element.name().as_ref() == b"w:t" => in_text = true,
+            Event::End(element) if element.name().as_ref() == b"w:t" => in_text = false,
+            Event::End(element) if element.name().as_ref() == b"w:p" => text.push('\n'),
+            Event::Empty(element) if matches!(element.name().as_ref(), b"w:tab" | b"w:br") => text.push(' '),
+            Event::Text(content) if in_text => text.push_str(&content.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+/// Splits `text` into chunks of [`LINES_PER_CHUNK`] lines, leaving out the ones with nothing but whitespace.
+pub fn chunks(text: &str) -> Vec<ContentChunk> {
+    let lines = text.lines().collect::<Vec<_>>();
+    lines
+        .chunks(LINES_PER_CHUNK)
+        .enumerate()
+        .filter(|(_, lines)| lines.iter().any(|line| !line.trim().is_empty()))
+        .map(|(index, lines)| ContentChunk { index: index as u64, content: lines.join("\n") })
+        .collect()
+}
+
+impl ContentChunk {
+    /// The 1-based line the chunk starts at.
+    pub fn start_line(index: u64) -> u64 {
+        index * LINES_PER_CHUNK as u64 + 1
+    }
+}
+
+/// Turns a search into an FTS5 query that matches every word, so characters with a meaning in the FTS5
+/// query syntax are searched for as they are.
+pub fn fts_query(query: &str) -> String {
+    query.split_whitespace().map(|word| format!("\"{}\"", word.replace('"', "\"\""))).collect::<Vec<_>>().join(" ")
+}
+
+impl ContentMatch {
+    /// Builds a match from the `snippet()` and `highlight()` FTS5 returned for a chunk starting at `start_line`.
+    pub fn new(path: String, start_line: u64, snippet: &str, highlighted: &str) -> Self {
+        let line = start_line + highlighted.split(MATCH_START).next().unwrap_or_default().matches('\n').count() as u64;
+
+        let mut text = String::with_capacity(snippet.len());
+        let mut highlights = Vec::new();
+        let mut start = None;
+        for (offset, character) in snippet.chars().filter(|character| !matches!(character, '\r')).scan(0, |offset, character| {
+            let current = *offset;
+            if !matches!(character, MATCH_START | MATCH_END) {
+                *offset += 1;
+            }
+            Some((current, character))
+        }) {
+            match character {
+                MATCH_START => start = Some(offset),
+                MATCH_END => highlights.extend(start.take().map(|start| (start, offset))),
+                '\n' | '\t' => text.push(' '),
+                _ => text.push(character),
+            }
+        }
+
+        let filename = Path::new(&path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        Self { path, filename, line, snippet: text, highlights }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::helpers::db::create_pool;
+use crate::io::fs::indexer::content_data::{ContentChunk, ContentMatch, MATCH_END, MATCH_START, fts_query};
+use crate::io::fs::indexer::indexer_data::index_scope;
+use crate::io::fs::indexer::indexer_db::descendant_range;
+use futures::TryStreamExt;
+use sqlx::{Executor, Row, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Chunks are stored under the rowid `file_id << CHUNK_BITS | chunk index`, so the chunks of a file can be
+/// found and removed by a rowid range instead of a scan of the whole full-text index.
+const CHUNK_BITS: i64 = 20;
+
+pub async fn initialize(pool: &SqlitePool) -> anyhow::Result<()> {
+    // The files whose content is indexed, and the modification time they were indexed at
+    pool.execute(
+        r#"CREATE TABLE IF NOT EXISTS content_files
+(
+    id    INTEGER PRIMARY KEY AUTOINCREMENT,
+    path  TEXT UNIQUE,
+    mtime INTEGER NOT NULL
+);
+        "#,
+    )
+    .await?;
+
+    pool.execute(r#"CREATE VIRTUAL TABLE IF NOT EXISTS content_index USING fts5(content, tokenize = 'unicode61 remove_diacritics 2');"#).await?;
+
+    Ok(())
+}
+
+pub async fn does_table_exist() -> anyhow::Result<bool> {
+    let pool = create_pool().await?;
+    let result = sqlx::query(r#"SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'content_index'"#).fetch_one(&pool).await?;
+
+    Ok(result.get::<i32, _>(0) > 0)
+}
+
+/// The modification time the content of `path` was indexed at, if it is indexed.
+pub async fn indexed_mtime(path: &str, pool: &SqlitePool) -> anyhow::Result<Option<u64>> {
+    let mtime: Option<i64> = sqlx::query_scalar(r#"SELECT mtime FROM content_files WHERE path = ?"#).bind(path).fetch_optional(pool).await?;
+    Ok(mtime.map(|mtime| mtime as u64))
+}
+
+/// Every path with indexed content, and the modification time it was indexed at.
+pub async fn indexed_files(pool: &SqlitePool) -> anyhow::Result<HashMap<String, u64>> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(r#"SELECT path, mtime FROM content_files"#).fetch_all(pool).await?;
+    Ok(rows.into_iter().map(|(path, mtime)| (path, mtime as u64)).collect())
+}
+
+/// Replaces the indexed content of `path` with `chunks`.
+pub async fn replace_with_pool(path: &str, mtime: u64, chunks: &[ContentChunk], pool: &SqlitePool) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    delete_in(path, &mut tx).await?;
+    let id = sqlx::query(r#"INSERT INTO content_files (path, mtime) VALUES (?, ?)"#)
+        .bind(path)
+        .bind(mtime as i64)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    for chunk in chunks {
+        sqlx::query(r#"INSERT INTO content_index (rowid, content) VALUES (?, ?)"#)
+            .bind(id << CHUNK_BITS | chunk.index as i64)
+            .bind(&chunk.content)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_with_pool(path: &str, pool: &SqlitePool) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    delete_in(path, &mut tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Removes the indexed content of `path` as part of a larger transaction.
+pub(crate) async fn delete_in(path: &str, connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    let id: Option<i64> = sqlx::query_scalar(r#"SELECT id FROM content_files WHERE path = ?"#).bind(path).fetch_optional(&mut *connection).await?;
+    if let Some(id) = id {
+        sqlx::query(r#"DELETE FROM content_index WHERE rowid >= ? AND rowid < ?"#)
+            .bind(id << CHUNK_BITS)
+            .bind((id + 1) << CHUNK_BITS)
+            .execute(&mut *connection)
+            .await?;
+        sqlx::query(r#"DELETE FROM content_files WHERE id = ?"#).bind(id).execute(&mut *connection).await?;
+    }
+    Ok(())
+}
+
+/// Removes the indexed content of `path` and everything below it as part of a larger transaction.
+pub(crate) async fn delete_tree_in(path: &str, connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    let (start, end) = descendant_range(path);
+    let ids: Vec<i64> = sqlx::query_scalar(r#"SELECT id FROM content_files WHERE path = ? OR (path >= ? AND path < ?)"#)
+        .bind(path)
+        .bind(&start)
+        .bind(&end)
+        .fetch_all(&mut *connection)
+        .await?;
+    for id in ids {
+        sqlx::query(r#"DELETE FROM content_index WHERE rowid >= ? AND rowid < ?"#)
+            .bind(id << CHUNK_BITS)
+            .bind((id + 1) << CHUNK_BITS)
+            .execute(&mut *connection)
+            .await?;
+        sqlx::query(r#"DELETE FROM content_files WHERE id = ?"#).bind(id).execute(&mut *connection).await?;
+    }
+    Ok(())
+}
+
+/// Moves the indexed content of `from` and everything below it to `to` as part of a larger transaction. The chunks
+/// are stored by file id, so only the paths change.
+pub(crate) async fn rename_tree_in(from: &str, to: &str, connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    let (start, end) = descendant_range(from);
+    sqlx::query(r#"UPDATE content_files SET path = ? || substr(path, ? + 1) WHERE path = ? OR (path >= ? AND path < ?)"#)
+        .bind(to.trim_end_matches('/'))
+        .bind(from.trim_end_matches('/').chars().count() as i64)
+        .bind(from)
+        .bind(&start)
+        .bind(&end)
+        .execute(&mut *connection)
+        .await?;
+    Ok(())
+}
+
+/// Finds the chunks containing every word of `query`, best matches first, by the path clients see.
+pub async fn search(query: &str, limit: u32) -> anyhow::Result<Vec<ContentMatch>> {
+    let query = fts_query(query);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let pool = create_pool().await?;
+    let markers = format!("char({}), char({})", MATCH_START as u32, MATCH_END as u32);
+    let select = format!(
+        r#"SELECT f.path, content_index.rowid AS chunk, snippet(content_index, 0, {markers}, '…', 16) AS snippet,
+                  highlight(content_index, 0, {markers}) AS highlighted
+           FROM content_index
+           JOIN content_files f ON f.id = content_index.rowid >> {CHUNK_BITS}
+           WHERE content_index MATCH ?
+           ORDER BY rank"#
+    );
+    // Files filtered out since they were indexed, or outside of the published tree, are skipped as the matches are
+    // read so they don't take the place of others
+    let scope = index_scope();
+    let mut matches = Vec::new();
+    let mut rows = sqlx::query(&select).bind(&query).fetch(&pool);
+    while matches.len() < limit as usize
+        && let Some(row) = rows.try_next().await?
+    {
+        let Some(path) = scope.visible_path(Path::new(row.get::<&str, _>("path")), false) else {
+            continue;
+        };
+        let chunk: i64 = row.get("chunk");
+        let start_line = ContentChunk::start_line((chunk & ((1 << CHUNK_BITS) - 1)) as u64);
+        matches.push(ContentMatch::new(path, start_line, row.get("snippet"), row.get("highlighted")));
+    }
+    Ok(matches)
+}
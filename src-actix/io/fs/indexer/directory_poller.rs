@@ -0,0 +1,182 @@
+use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind};
+use notify::{Config, Event, EventHandler, EventKind, RecursiveMode, Watcher, WatcherKind};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Every how many scans the files of unchanged directories are compared too, for files changed in place.
+const FULL_SCAN_EVERY: u64 = 10;
+/// How long a directory has to be left alone before its modification time is trusted to tell whether it changed.
+/// Network filesystems often keep it to the second.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Watches directories by scanning them, for filesystems that don't report their changes, such as network shares
+/// changed by other machines.
+///
+/// Rather than stat'ing every file on every scan, a scan only lists the directories whose modification time changed,
+/// which happens when entries are added to, removed from or renamed in them. Files changed in place leave their
+/// directory alone, so every [`FULL_SCAN_EVERY`] scans the files of every directory are compared as well.
+pub struct DirectoryPoller {
+    /// The watched directories, `None` until they are first listed.
+    trees: Arc<Mutex<HashMap<PathBuf, Option<Tree>>>>,
+    /// Wakes the scanning thread up to list new directories. Dropped along with the poller, which stops the thread.
+    wake: mpsc::Sender<()>,
+}
+
+/// What the last scan saw of a watched directory, by directory.
+pub(crate) type Tree = HashMap<PathBuf, Listing>;
+
+pub(crate) struct Listing {
+    modified: Option<SystemTime>,
+    listed_at: SystemTime,
+    entries: HashMap<OsString, Entry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Watcher for DirectoryPoller {
+    fn new<F: EventHandler>(mut event_handler: F, config: Config) -> notify::Result<Self> {
+        let interval = config.poll_interval().unwrap_or(DEFAULT_POLL_INTERVAL);
+        let trees: Arc<Mutex<HashMap<PathBuf, Option<Tree>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let (wake, woken) = mpsc::channel();
+
+        let watched = trees.clone();
+        std::thread::Builder::new().name("directory-poller".to_string()).spawn(move || {
+            let mut scans = 0;
+            let mut next_scan = Instant::now() + interval;
+            loop {
+                let due = match woken.recv_timeout(next_scan.saturating_duration_since(Instant::now())) {
+                    Ok(()) => false,
+                    Err(RecvTimeoutError::Timeout) => true,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if due {
+                    scans += 1;
+                    next_scan = Instant::now() + interval;
+                }
+
+                let mut trees = watched.lock().unwrap();
+                for (root, tree) in trees.iter_mut() {
+                    match tree {
+                        // What is there when a directory starts being watched is left to the index to pick up
+                        None => {
+                            let mut listed = Tree::new();
+                            scan(root, &mut listed, true, &mut |_| {});
+                            *tree = Some(listed);
+                        }
+                        Some(tree) if due => scan(root, tree, scans % FULL_SCAN_EVERY == 0, &mut |event| event_handler.handle_event(Ok(event))),
+                        Some(_) => {}
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self { trees, wake })
+    }
+
+    fn watch(&mut self, path: &Path, _recursive_mode: RecursiveMode) -> notify::Result<()> {
+        if !path.is_dir() {
+            return Err(notify::Error::path_not_found().add_path(path.to_path_buf()));
+        }
+        self.trees.lock().unwrap().insert(path.to_path_buf(), None);
+        // Listing a large tree takes a while, so it happens on the scanning thread
+        let _ = self.wake.send(());
+        Ok(())
+    }
+
+    fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+        self.trees.lock().unwrap().remove(path).map(|_| ()).ok_or_else(notify::Error::watch_not_found)
+    }
+
+    fn kind() -> WatcherKind {
+        WatcherKind::PollWatcher
+    }
+}
+
+/// Compares the directory `root` with `tree`, what the last scan saw of it, reporting the differences to `report` and
+/// updating `tree`. Only the directories whose modification time changed are listed, unless the scan is `full`.
+///
+/// The content of a new directory isn't reported, the creation of the directory covers it.
+pub(crate) fn scan(root: &Path, tree: &mut Tree, full: bool, report: &mut dyn FnMut(Event)) {
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let Ok(metadata) = fs::metadata(&directory) else {
+            // Anything else that is gone is reported by the directory it was in
+            if directory == root && !tree.is_empty() {
+                tree.clear();
+                report(Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(directory));
+            }
+            continue;
+        };
+        let modified = metadata.modified().ok();
+
+        let previous = tree.get(&directory);
+        let unchanged = previous.is_some_and(|listing| {
+            listing.modified == modified
+                && modified.is_some_and(|modified| listing.listed_at.duration_since(modified).is_ok_and(|age| age >= MTIME_GRANULARITY))
+        });
+        if unchanged && !full {
+            let listing = previous.map(|listing| &listing.entries).into_iter().flatten();
+            directories.extend(listing.filter(|(_, entry)| entry.is_dir).map(|(name, _)| directory.join(name)));
+            continue;
+        }
+
+        let listed_at = SystemTime::now();
+        let Ok(read_dir) = fs::read_dir(&directory) else {
+            continue;
+        };
+        let mut entries = HashMap::new();
+        for entry in read_dir.flatten() {
+            // Links are left out, as when the index is reconciled
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_symlink() {
+                continue;
+            }
+            entries.insert(entry.file_name(), Entry { is_dir: metadata.is_dir(), size: metadata.len(), modified: metadata.modified().ok() });
+        }
+
+        if let Some(previous) = tree.remove(&directory) {
+            for (name, old) in &previous.entries {
+                if entries.get(name).is_none_or(|new| new.is_dir != old.is_dir) {
+                    let path = directory.join(name);
+                    let kind = if old.is_dir {
+                        tree.retain(|listed, _| !listed.starts_with(&path));
+                        RemoveKind::Folder
+                    } else {
+                        RemoveKind::File
+                    };
+                    report(Event::new(EventKind::Remove(kind)).add_path(path));
+                }
+            }
+            for (name, new) in &entries {
+                let path = directory.join(name);
+                match previous.entries.get(name) {
+                    Some(old) if old.is_dir == new.is_dir => {
+                        if !new.is_dir && (old.size != new.size || old.modified != new.modified) {
+                            report(Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(path));
+                        }
+                    }
+                    _ => {
+                        let kind = if new.is_dir { CreateKind::Folder } else { CreateKind::File };
+                        report(Event::new(EventKind::Create(kind)).add_path(path));
+                    }
+                }
+            }
+        }
+
+        directories.extend(entries.iter().filter(|(_, entry)| entry.is_dir).map(|(name, _)| directory.join(name)));
+        tree.insert(directory, Listing { modified, listed_at, entries });
+    }
+}
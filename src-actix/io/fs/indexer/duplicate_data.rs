@@ -0,0 +1,313 @@
+use crate::configuration::configuration_data::Configuration;
+use crate::helpers::db::create_pool;
+use crate::io::fs::indexer::duplicate_db;
+use crate::io::fs::indexer::indexer_data::{self, IndexerData, ReconcileReport};
+use crate::io::fs::indexer::indexer_state::{IndexerRun, IndexerStatus, indexer_state};
+use crate::io::fs::normalize_path::{to_virtual_path, virtual_path_in};
+use crate::io::storage::storage_backend;
+use crate::jobs::job_data::{Job, JobHandle, jobs};
+use anyhow::{Context, Result, anyhow, bail};
+use log::{error, info, warn};
+use notify::event::{ModifyKind, RemoveKind};
+use notify::{Event, EventKind};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{FromRow, SqlitePool};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// How many bytes at each end of a file its partial hash covers.
+const PARTIAL_HASH_BYTES: u64 = 64 * 1024;
+/// The name of the job resolving duplicates, which runs once at a time.
+const RESOLVE_JOB: &str = "resolve_duplicates";
+
+/// An indexed file that may have a copy, with the size and modification time it was indexed with.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct HashCandidate {
+    pub path: String,
+    pub size: i64,
+    pub mtime: i64,
+}
+
+/// An indexed file whose full hash is known.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct IndexedCopy {
+    pub path: String,
+    pub hash: String,
+    pub size: i64,
+    pub mtime: i64,
+    /// The device and inode of the file, which its hardlinks share.
+    pub file_id: Option<String>,
+}
+
+/// A BLAKE3 hash of the content of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileHash {
+    pub hash: String,
+    /// Whether the hash covers the whole file, as a partial hash of a small file does.
+    pub complete: bool,
+    pub file_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DuplicateParameters {
+    /// The folder, as clients see it, whose files are grouped.
+    pub prefix: Option<String>,
+    /// The size in bytes below which files are left out, 1 by default.
+    pub min_size: Option<u64>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// A page of groups of files with the same content.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicatePage {
+    pub groups: Vec<DuplicateGroup>,
+    /// The number of groups.
+    pub total: u64,
+    /// The bytes freed by keeping a single copy of every group.
+    pub reclaimable: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    /// The size of each copy.
+    pub size: u64,
+    /// The bytes freed by keeping a single copy.
+    pub reclaimable: u64,
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DuplicateFile {
+    pub path: String,
+    pub mtime: u64,
+}
+
+/// What is done with the duplicates of the copy that is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    Delete,
+    /// Replaces the duplicates with hardlinks to the copy that is kept, which only works on the same filesystem.
+    Hardlink,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResolveDuplicates {
+    pub action: DuplicateAction,
+    pub groups: Vec<DuplicateSelection>,
+}
+
+/// A file to keep and the copies of it to delete or hardlink.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DuplicateSelection {
+    pub keep: String,
+    pub duplicates: Vec<String>,
+}
+
+/// What resolving duplicates did, the result of its job.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResolveReport {
+    pub resolved: usize,
+    /// The bytes freed.
+    pub reclaimed: u64,
+    pub skipped: Vec<SkippedDuplicate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedDuplicate {
+    /// The path of the duplicate, as clients see it.
+    pub path: String,
+    pub reason: String,
+}
+
+/// Hashes the files of the index that may be copies of each other, in three passes so that as little as possible is
+/// read: only files sharing their size with another one are looked at, of those only the first and last
+/// [`PARTIAL_HASH_BYTES`] are hashed, and only the files sharing that partial hash too are hashed in full.
+///
+/// Files keep their hashes until they change. Returns `false` when the run was cancelled.
+pub(crate) async fn hash_files(run: &mut IndexerRun, config: &Configuration, pool: &SqlitePool, report: &mut ReconcileReport) -> Result<bool> {
+    for complete in [false, true] {
+        let candidates = if complete { duplicate_db::full_hash_candidates(pool).await? } else { duplicate_db::partial_hash_candidates(pool).await? };
+        info!("Hashing {} files that may have copies...", candidates.len());
+
+        for candidate in candidates {
+            let progress = |status: &mut IndexerStatus| {
+                status.current_path = virtual_path_in(Path::new(&candidate.path), config);
+                status.errors = report.errors;
+            };
+            if !indexer_state().checkpoint(run, progress).await {
+                info!("Hashing cancelled after {} files", report.hashed);
+                return Ok(false);
+            }
+
+            let path = PathBuf::from(&candidate.path);
+            let expected = candidate.clone();
+            let hashed =
+                tokio::task::spawn_blocking(move || if complete { full_hash(&path, &expected) } else { partial_hash(&path, &expected) }).await?;
+            match hashed {
+                Ok(Some(hashed)) if complete => duplicate_db::set_hash(&candidate, &hashed.hash, pool).await?,
+                Ok(Some(hashed)) => {
+                    duplicate_db::set_partial_hash(&candidate, &hashed.hash, hashed.complete, hashed.file_id.as_deref(), pool).await?
+                }
+                // Changed since it was indexed, it is hashed once the index catches up with it
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Error hashing {}: {:#}", candidate.path, e);
+                    report.errors += 1;
+                    continue;
+                }
+            }
+            report.hashed += 1;
+        }
+    }
+    Ok(true)
+}
+
+/// Hashes the first and last [`PARTIAL_HASH_BYTES`] of the file at `path`, or the whole file when it isn't larger
+/// than that. Returns `None` when the file no longer has the size and modification time of `candidate`.
+pub(crate) fn partial_hash(path: &Path, candidate: &HashCandidate) -> Result<Option<FileHash>> {
+    let Some((mut file, file_id)) = open_unchanged(path, candidate)? else {
+        return Ok(None);
+    };
+    let mut hasher = blake3::Hasher::new();
+    let complete = candidate.size as u64 <= 2 * PARTIAL_HASH_BYTES;
+    if complete {
+        io::copy(&mut file, &mut hasher)?;
+    } else {
+        io::copy(&mut (&mut file).take(PARTIAL_HASH_BYTES), &mut hasher)?;
+        file.seek(SeekFrom::End(-(PARTIAL_HASH_BYTES as i64)))?;
+        io::copy(&mut file, &mut hasher)?;
+    }
+    Ok(Some(FileHash { hash: hasher.finalize().to_hex().to_string(), complete, file_id }))
+}
+
+/// Hashes the whole file at `path`, see [`partial_hash`].
+pub(crate) fn full_hash(path: &Path, candidate: &HashCandidate) -> Result<Option<FileHash>> {
+    let Some((mut file, file_id)) = open_unchanged(path, candidate)? else {
+        return Ok(None);
+    };
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(Some(FileHash { hash: hasher.finalize().to_hex().to_string(), complete: true, file_id }))
+}
+
+/// Opens the file at `path` when it still has the size and modification time of `candidate`, along with what
+/// identifies it on its device.
+fn open_unchanged(path: &Path, candidate: &HashCandidate) -> Result<Option<(File, Option<String>)>> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let mtime = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+    if metadata.len() != candidate.size as u64 || mtime != candidate.mtime as u64 {
+        return Ok(None);
+    }
+    Ok(Some((file, file_id(&metadata))))
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    Some(format!("{}:{}", metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<String> {
+    None
+}
+
+/// Starts deleting or hardlinking the duplicates of `request` as a job, returning `None` when duplicates are being
+/// resolved already. The paths of `request` are OS paths.
+pub fn start_resolve(request: ResolveDuplicates) -> Option<Job> {
+    let (job, handle) = jobs().start(RESOLVE_JOB, None)?;
+    tokio::spawn(async move {
+        let outcome = resolve(request, &handle).await.map(|report| json!(report)).map_err(|e| format!("{:#}", e));
+        if let Err(e) = &outcome {
+            error!("Error resolving duplicates: {}", e);
+        }
+        jobs().finish(handle, outcome);
+    });
+    Some(job)
+}
+
+async fn resolve(request: ResolveDuplicates, handle: &JobHandle) -> Result<ResolveReport> {
+    let pool = create_pool().await?;
+    let mut report = ResolveReport::default();
+    for group in &request.groups {
+        for duplicate in &group.duplicates {
+            if handle.is_cancelled() {
+                return Ok(report);
+            }
+            match resolve_duplicate(&group.keep, duplicate, request.action, &pool).await {
+                Ok(reclaimed) => {
+                    report.resolved += 1;
+                    report.reclaimed += reclaimed;
+                }
+                Err(e) => report.skipped.push(SkippedDuplicate { path: client_path(duplicate), reason: format!("{:#}", e) }),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Deletes `duplicate`, or replaces it with a hardlink to `keep`, once both are known to have the same content and
+/// not to have changed since they were hashed. Returns the bytes freed.
+async fn resolve_duplicate(keep: &str, duplicate: &str, action: DuplicateAction, pool: &SqlitePool) -> Result<u64> {
+    if keep == duplicate {
+        bail!("It is the file to keep");
+    }
+    let kept = duplicate_db::indexed_copy(keep, pool).await?.ok_or_else(|| anyhow!("{} has not been hashed", client_path(keep)))?;
+    let copy = duplicate_db::indexed_copy(duplicate, pool).await?.ok_or_else(|| anyhow!("It has not been hashed"))?;
+    if copy.hash != kept.hash {
+        bail!("It is not a copy of {}", client_path(keep));
+    }
+    for file in [&kept, &copy] {
+        let current = IndexerData::from_path(Path::new(&file.path))?;
+        if current.size != file.size as u64 || current.mtime != file.mtime as u64 {
+            bail!("{} changed since it was hashed", client_path(&file.path));
+        }
+    }
+
+    let duplicate_path = Path::new(duplicate);
+    let backend = storage_backend::backend_for(duplicate_path);
+    if backend.is_read_only() {
+        bail!("It is on a read-only mount");
+    }
+    // Hardlinks of one file take its space once, there is nothing to free
+    let linked = copy.file_id.is_some() && copy.file_id == kept.file_id;
+    match action {
+        DuplicateAction::Delete => {
+            backend.delete(duplicate_path).await?;
+            indexer_data::record_change(Event::new(EventKind::Remove(RemoveKind::File)).add_path(duplicate_path.to_path_buf())).await;
+        }
+        DuplicateAction::Hardlink if linked => {}
+        DuplicateAction::Hardlink => {
+            let source = storage_backend::backend_for(Path::new(keep)).local_path(Path::new(keep));
+            let (Some(source), Some(target)) = (source, backend.local_path(duplicate_path)) else {
+                bail!("Only files on local storage can be hardlinked");
+            };
+            hardlink(&source, &target).await?;
+            indexer_data::record_change(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(duplicate_path.to_path_buf())).await;
+        }
+    }
+    Ok(if linked { 0 } else { copy.size as u64 })
+}
+
+/// Replaces `target` with a hardlink to `source`, through a temporary link so that `target` is never missing.
+async fn hardlink(source: &Path, target: &Path) -> Result<()> {
+    let name = target.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temporary = target.with_file_name(format!(".{}.filer-link", name));
+    tokio::fs::hard_link(source, &temporary).await.context("Failed to link the file to keep")?;
+    if let Err(e) = tokio::fs::rename(&temporary, target).await {
+        let _ = tokio::fs::remove_file(&temporary).await;
+        return Err(e).context("Failed to replace the duplicate");
+    }
+    Ok(())
+}
+
+fn client_path(path: &str) -> String {
+    to_virtual_path(Path::new(path)).unwrap_or_else(|| path.to_string())
+}
@@ -0,0 +1,151 @@
+use crate::helpers::db::create_pool;
+use crate::io::fs::indexer::duplicate_data::{DuplicateFile, DuplicateGroup, DuplicatePage, DuplicateParameters, HashCandidate, IndexedCopy};
+use crate::io::fs::indexer::indexer_data::index_scope;
+use crate::io::fs::indexer::indexer_db::descendant_range;
+use futures::TryStreamExt;
+use sqlx::{Executor, QueryBuilder, Sqlite, SqlitePool};
+use std::collections::HashSet;
+use std::path::Path;
+
+pub async fn initialize(pool: &SqlitePool) -> anyhow::Result<()> {
+    // Files whose partial hashes are compared are looked up by size first, and copies by their full hash
+    pool.execute(r#"CREATE INDEX IF NOT EXISTS idx_indexes_partial_hash ON indexes(size, partial_hash);"#).await?;
+    pool.execute(r#"CREATE INDEX IF NOT EXISTS idx_indexes_hash ON indexes(hash);"#).await?;
+    Ok(())
+}
+
+/// The files without a partial hash that have the size of another file, the only ones that can have a copy.
+pub(crate) async fn partial_hash_candidates(pool: &SqlitePool) -> anyhow::Result<Vec<HashCandidate>> {
+    let candidates = sqlx::query_as(
+        r#"SELECT path, size, mtime FROM indexes
+           WHERE is_dir = 0 AND size > 0 AND partial_hash IS NULL
+             AND size IN (SELECT size FROM indexes WHERE is_dir = 0 AND size > 0 GROUP BY size HAVING COUNT(*) > 1)"#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(candidates)
+}
+
+/// The files without a full hash that share their size and partial hash with another file.
+pub(crate) async fn full_hash_candidates(pool: &SqlitePool) -> anyhow::Result<Vec<HashCandidate>> {
+    let candidates = sqlx::query_as(
+        r#"SELECT path, size, mtime FROM indexes i
+           WHERE is_dir = 0 AND hash IS NULL AND partial_hash IS NOT NULL
+             AND EXISTS (SELECT 1 FROM indexes o WHERE o.size = i.size AND o.partial_hash = i.partial_hash AND o.id != i.id)"#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(candidates)
+}
+
+/// Records the partial hash of `candidate`, and its full hash when the partial hash covers the whole file. Entries
+/// that changed since they were listed are left alone.
+pub(crate) async fn set_partial_hash(
+    candidate: &HashCandidate,
+    partial_hash: &str,
+    complete: bool,
+    file_id: Option<&str>,
+    pool: &SqlitePool,
+) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE indexes SET partial_hash = ?, hash = CASE WHEN ? THEN ? END, file_id = ? WHERE path = ? AND size = ? AND mtime = ?"#)
+        .bind(partial_hash)
+        .bind(complete)
+        .bind(partial_hash)
+        .bind(file_id)
+        .bind(&candidate.path)
+        .bind(candidate.size)
+        .bind(candidate.mtime)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Records the full hash of `candidate`, unless the entry changed since it was listed.
+pub(crate) async fn set_hash(candidate: &HashCandidate, hash: &str, pool: &SqlitePool) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE indexes SET hash = ? WHERE path = ? AND size = ? AND mtime = ?"#)
+        .bind(hash)
+        .bind(&candidate.path)
+        .bind(candidate.size)
+        .bind(candidate.mtime)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The hash and metadata of the file at `path`, when it is indexed and hashed.
+pub(crate) async fn indexed_copy(path: &str, pool: &SqlitePool) -> anyhow::Result<Option<IndexedCopy>> {
+    let copy = sqlx::query_as(r#"SELECT path, hash, size, mtime, file_id FROM indexes WHERE path = ? AND is_dir = 0 AND hash IS NOT NULL"#)
+        .bind(path)
+        .fetch_optional(pool)
+        .await?;
+    Ok(copy)
+}
+
+/// Finds the groups of files with the same content, those wasting the most space first.
+///
+/// `prefix` is the OS path of the folder given as `prefix`, only the files below it are grouped. Files that are
+/// hardlinks of each other take space once, so they count as a single copy. Files are reported by the path clients
+/// see.
+pub async fn find_duplicates(parameters: &DuplicateParameters, prefix: Option<&Path>) -> anyhow::Result<DuplicatePage> {
+    let pool = create_pool().await?;
+    find_duplicates_with_pool(parameters, prefix, &pool).await
+}
+
+pub async fn find_duplicates_with_pool(parameters: &DuplicateParameters, prefix: Option<&Path>, pool: &SqlitePool) -> anyhow::Result<DuplicatePage> {
+    let min_size = parameters.min_size.unwrap_or(1).max(1) as i64;
+    let range = prefix.map(|prefix| descendant_range(&prefix.to_string_lossy().replace('\\', "/")));
+    let limit = parameters.limit.unwrap_or(100).clamp(1, 1000) as usize;
+
+    let mut select = QueryBuilder::<Sqlite>::new(
+        "SELECT hash, size, path, mtime, COALESCE(file_id, path) FROM indexes WHERE is_dir = 0 AND hash IN (SELECT hash FROM (",
+    );
+    push_groups(&mut select, min_size, range.as_ref());
+    select.push("))");
+    if let Some((start, end)) = &range {
+        select.push(" AND path >= ").push_bind(start.clone()).push(" AND path < ").push_bind(end.clone());
+    }
+    select.push(" ORDER BY hash, path");
+
+    // Groups are made of the files clients see, files filtered out since they were indexed or outside of the
+    // published tree are left out before the copies are counted
+    let scope = index_scope();
+    let mut groups = Vec::<(DuplicateGroup, HashSet<String>)>::new();
+    let mut rows = select.build_query_as::<(String, i64, String, i64, String)>().fetch(pool);
+    while let Some((hash, size, path, mtime, copy)) = rows.try_next().await? {
+        let Some(path) = scope.visible_path(Path::new(&path), false) else {
+            continue;
+        };
+        if groups.last().is_none_or(|(group, _)| group.hash != hash) {
+            groups.push((DuplicateGroup { hash, size: size as u64, reclaimable: 0, files: Vec::new() }, HashSet::new()));
+        }
+        if let Some((group, copies)) = groups.last_mut() {
+            group.files.push(DuplicateFile { path, mtime: mtime as u64 });
+            copies.insert(copy);
+        }
+    }
+    drop(rows);
+
+    let mut groups = groups
+        .into_iter()
+        .filter(|(_, copies)| copies.len() > 1)
+        .map(|(group, copies)| DuplicateGroup { reclaimable: group.size * (copies.len() as u64 - 1), ..group })
+        .collect::<Vec<_>>();
+    groups.sort_by(|a, b| b.reclaimable.cmp(&a.reclaimable).then_with(|| a.hash.cmp(&b.hash)));
+    let total = groups.len() as u64;
+    let reclaimable = groups.iter().map(|group| group.reclaimable).sum();
+    let groups = groups.into_iter().skip(parameters.offset.unwrap_or(0) as usize).take(limit).collect();
+
+    Ok(DuplicatePage { groups, total, reclaimable })
+}
+
+/// Selects the hashes shared by more than one file of at least `min_size` bytes, below `range` when given, with their
+/// size and number of copies.
+fn push_groups(builder: &mut QueryBuilder<Sqlite>, min_size: i64, range: Option<&(String, String)>) {
+    builder
+        .push("SELECT hash, size, COUNT(DISTINCT COALESCE(file_id, path)) AS copies FROM indexes WHERE is_dir = 0 AND hash IS NOT NULL AND size >= ")
+        .push_bind(min_size);
+    if let Some((start, end)) = range {
+        builder.push(" AND path >= ").push_bind(start.clone()).push(" AND path < ").push_bind(end.clone());
+    }
+    builder.push(" GROUP BY hash HAVING copies > 1");
+}
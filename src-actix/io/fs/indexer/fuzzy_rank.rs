@@ -0,0 +1,155 @@
+use crate::io::fs::indexer::indexer_data::IndexerData;
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// Results scoring lower than this are left out.
+pub const MIN_SCORE: f64 = 0.3;
+
+/// How much less a match in the directories of a path counts than one in the filename.
+const DIRECTORY_WEIGHT: f64 = 0.6;
+/// How similar a word of the path has to be to a term, as `1 - edit distance / length`, to count as a typo of it.
+const MIN_SIMILARITY: f64 = 0.6;
+/// Added when a term is the extension of the file, as in `invoice pdf`.
+const EXTENSION_BOOST: f64 = 0.2;
+/// Added for a file modified just now, halving every [`RECENCY_HALF_LIFE_DAYS`].
+const RECENCY_BOOST: f64 = 0.1;
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Scores `entries` against the search `terms`, leaving out the ones scoring below [`MIN_SCORE`] and ordering the
+/// rest best first, then by shorter and alphabetically smaller paths so that equal scores always come out the same.
+///
+/// `now` is the time, in seconds since the epoch, recency is measured from.
+pub fn rank(entries: Vec<IndexerData>, terms: &[String], filename_only: bool, now: u64) -> Vec<IndexerData> {
+    let mut ranked = entries
+        .into_iter()
+        .filter_map(|mut entry| {
+            let directory = if filename_only {
+                String::new()
+            } else {
+                Path::new(&entry.path).parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default()
+            };
+            let score = score(terms, &entry.filename, &directory, entry.mtime, now);
+            entry.score = Some(score);
+            (score >= MIN_SCORE).then_some(entry)
+        })
+        .collect::<Vec<_>>();
+    ranked.sort_by(compare);
+    ranked
+}
+
+/// The order of [`rank`], for entries that were scored already.
+pub fn compare(a: &IndexerData, b: &IndexerData) -> Ordering {
+    b.score
+        .unwrap_or_default()
+        .total_cmp(&a.score.unwrap_or_default())
+        .then_with(|| a.path.chars().count().cmp(&b.path.chars().count()))
+        .then_with(|| a.path.cmp(&b.path))
+}
+
+/// How well `terms` match a file, 0 when any of them doesn't match at all. Scores are rounded to three decimals so
+/// that floating point noise can't reorder results.
+pub fn score(terms: &[String], filename: &str, directory: &str, mtime: u64, now: u64) -> f64 {
+    if terms.is_empty() {
+        return 0.0;
+    }
+    let filename = filename.to_lowercase();
+    let directory = directory.to_lowercase();
+    let (stem, extension) = filename.rsplit_once('.').filter(|(stem, _)| !stem.is_empty()).unwrap_or((&filename, ""));
+
+    let mut total = 0.0;
+    let mut extension_matched = false;
+    for term in terms {
+        let term = term.to_lowercase();
+        extension_matched |= !extension.is_empty() && term.trim_start_matches('.') == extension;
+        let best = term_score(&term, &filename, stem).max(term_score(&term, &directory, "") * DIRECTORY_WEIGHT);
+        if best == 0.0 {
+            return 0.0;
+        }
+        total += best;
+    }
+
+    let mut score = total / terms.len() as f64;
+    if extension_matched {
+        score += EXTENSION_BOOST;
+    }
+    let age_days = now.saturating_sub(mtime) as f64 / (24.0 * 60.0 * 60.0);
+    score += RECENCY_BOOST * 0.5f64.powf(age_days / RECENCY_HALF_LIFE_DAYS);
+    (score * 1000.0).round() / 1000.0
+}
+
+/// How well a single lowercase `term` matches `text`: as the whole name, as its start, inside it, as a typo of one
+/// of its words, or as a subsequence of it.
+fn term_score(term: &str, text: &str, stem: &str) -> f64 {
+    if term.is_empty() || text.is_empty() {
+        return 0.0;
+    }
+    if stem == term || text == term {
+        return 1.0;
+    }
+    if text.starts_with(term) {
+        return 0.9;
+    }
+    if text.contains(term) {
+        return 0.8;
+    }
+
+    let similarity = text
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| similarity(term, word))
+        .fold(0.0, f64::max);
+    if similarity >= MIN_SIMILARITY {
+        return 0.7 * similarity;
+    }
+
+    match subsequence_span(term, text) {
+        // The closer together the characters are, the likelier the term is an abbreviation of the text
+        Some(span) if term.chars().count() > 1 => 0.3 + 0.2 * term.chars().count() as f64 / span as f64,
+        _ => 0.0,
+    }
+}
+
+/// `1 - edit distance / length of the longer word`, counting a swap of adjacent characters as a single edit.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    // Optimal string alignment distance, keeping the last three rows
+    let mut before = vec![0; b.len() + 1];
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+/// The number of characters of `text` from the first to the last character of `term`, when the characters of
+/// `term` appear in `text` in order.
+fn subsequence_span(term: &str, text: &str) -> Option<usize> {
+    let mut term = term.chars().peekable();
+    let mut start = None;
+    for (index, character) in text.chars().enumerate() {
+        if term.peek() == Some(&character) {
+            start.get_or_insert(index);
+            term.next();
+            if term.peek().is_none() {
+                return Some(index + 1 - start.unwrap_or_default());
+            }
+        }
+    }
+    None
+}
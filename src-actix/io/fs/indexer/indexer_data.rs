@@ -1,15 +1,25 @@
-use crate::configuration::configuration_data::Configuration;
+use crate::configuration::configuration_data::{Configuration, MountStorage, WatcherMode};
 use crate::helpers::db::create_pool;
-use anyhow::{Context, Result};
+use crate::io::fs::indexer::directory_poller::DirectoryPoller;
+use crate::io::fs::indexer::indexer_state::{IndexerPhase, IndexerRun, IndexerStatus, WatchedRoot, indexer_state};
+use crate::io::fs::indexer::path_filter::PathFilter;
+use crate::io::fs::indexer::watcher_batch::{
+    MAX_BATCH_DELAY, MAX_BATCH_SIZE, QUEUE_CAPACITY, QUIET_PERIOD, WatcherStatus, coalesce, is_rename_source, watcher_stats,
+};
+use crate::io::fs::indexer::{content_data, content_db, duplicate_data, indexer_db};
+use crate::io::fs::normalize_path::{mounted_path, to_virtual_path, virtual_path_in};
+use crate::io::storage::storage_backend;
+use anyhow::{Context, Result, anyhow};
 use log::{debug, error, info, warn};
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use sqlx::{FromRow, SqlitePool};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventHandler, EventKind, RecursiveMode, Watcher};
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
-use std::sync::Arc;
-use std::time::{Duration, UNIX_EPOCH};
-use tokio::sync::Mutex;
-use tokio::time::sleep;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tokio::sync::{OnceCell, mpsc};
 use walkdir::WalkDir;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, FromRow)]
@@ -19,92 +29,279 @@ pub struct IndexerData {
     pub size: u64,
     pub mtime: u64,
     pub ctime: u64,
+    /// Whether the entry is a directory, whose `size` is the total size of the files indexed below it.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub is_dir: bool,
+    /// How many files are indexed below a directory, 0 for a file.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub file_count: u64,
+    /// How well the entry matches a search, higher being better, only set on search results.
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
 }
 
-// Global watcher state
-static mut FILE_WATCHER: Option<Arc<Mutex<FileWatcherState>>> = None;
+/// The running file watcher, set once it is started.
+static FILE_WATCHER: OnceLock<Mutex<FileWatcherState>> = OnceLock::new();
+/// See [`watcher_pool`].
+static WATCHER_POOL: OnceCell<SqlitePool> = OnceCell::const_new();
+
+/// The configuration the index and the file watcher are scoped by, replaced when a saved configuration changes
+/// what is indexed. Falls back to the configuration loaded at startup.
+static INDEX_SCOPE: RwLock<Option<IndexScope>> = RwLock::new(None);
+
+/// The configuration the index is scoped by, with its filter patterns compiled.
+#[derive(Clone)]
+pub struct IndexScope {
+    pub config: Arc<Configuration>,
+    pub filter: Arc<PathFilter>,
+}
+
+impl IndexScope {
+    fn new(config: Configuration) -> Self {
+        let filter = PathFilter::new(&config);
+        Self { config: Arc::new(config), filter: Arc::new(filter) }
+    }
+
+    /// Whether `path` is filtered out, see [`PathFilter::hides`].
+    pub fn hides(&self, path: &Path, is_dir: bool) -> bool {
+        self.filter.hides(path, is_dir, &self.config)
+    }
+
+    /// The path clients see for the indexed `path`, unless it was filtered out since it was indexed or is outside
+    /// of the published tree.
+    pub fn visible_path(&self, path: &Path, is_dir: bool) -> Option<String> {
+        if self.hides(path, is_dir) {
+            return None;
+        }
+        to_virtual_path(path)
+    }
+}
 
 struct FileWatcherState {
-    watcher: RecommendedWatcher,
+    /// One watcher per watched directory, each provided by the storage backend holding it.
+    watchers: Vec<Box<dyn Watcher + Send>>,
+    /// Where the watchers report to, kept so the watched directories can be replaced.
+    sender: mpsc::Sender<notify::Result<Event>>,
+}
+
+/// The configuration the index is scoped by, which listings and searches hide filtered paths by as well.
+pub fn index_scope() -> IndexScope {
+    if let Some(scope) = INDEX_SCOPE.read().unwrap().as_ref() {
+        return scope.clone();
+    }
+    INDEX_SCOPE.write().unwrap().get_or_insert_with(|| IndexScope::new(Configuration::get().clone())).clone()
+}
+
+/// Scopes listings and searches by `config` instead of the loaded configuration, for tests listing temporary
+/// directories the default filter hides.
+#[cfg(test)]
+pub(crate) fn set_index_scope(config: Configuration) {
+    *INDEX_SCOPE.write().unwrap() = Some(IndexScope::new(config));
+}
+
+fn index_configuration() -> Arc<Configuration> {
+    index_scope().config
+}
+
+/// The directories that are indexed and watched: the explicit `index_roots` when there are any, otherwise
+/// `root_path` and every local mount with indexing turned on.
+///
+/// Roots inside another root of the same mount are dropped, as they are covered already.
+pub fn index_roots(config: &Configuration) -> Vec<PathBuf> {
+    let mut roots = if config.index_roots.is_empty() {
+        let mut roots = default_roots(config);
+        roots.extend(
+            config
+                .mounts
+                .iter()
+                .filter(|mount| mount.indexing && matches!(mount.storage, MountStorage::Local(_)))
+                .map(|mount| mount.os_path(&config.root_path)),
+        );
+        roots
+    } else {
+        config
+            .index_roots
+            .iter()
+            .map(|root| mounted_path(root, config).unwrap_or_else(|| PathBuf::from(&config.root_path).join(root.trim_start_matches(['/', '\\']))))
+            .collect()
+    };
+
+    roots.sort();
+    roots.dedup();
+    let mount_name = |path: &Path| config.mount_for(path).map(|mount| mount.name.clone());
+    let all = roots.clone();
+    roots.retain(|root| !all.iter().any(|other| other != root && root.starts_with(other) && mount_name(root) == mount_name(other)));
+    roots
+}
+
+/// `root_path` itself, or every drive when Windows is served from `/`.
+fn default_roots(config: &Configuration) -> Vec<PathBuf> {
+    #[cfg(windows)]
+    if config.root_path == "/" {
+        return sysinfo::Disks::new_with_refreshed_list().iter().map(|disk| disk.mount_point().to_path_buf()).collect();
+    }
+    vec![PathBuf::from(&config.root_path)]
 }
 
-pub async fn index_all_files() -> Result<()> {
-    info!("Starting file indexing...");
+/// What a reconciliation of the index with the files on disk changed.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ReconcileReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Hashes computed to find copies of files, partial and full.
+    pub hashed: usize,
+    pub errors: usize,
+}
+
+/// Brings the index in line with the files on disk: new files are added, files whose size or modification time
+/// changed are updated, and entries of files that no longer exist or fell out of the index scope are removed.
+///
+/// Unchanged files are only looked at, so running this on an up to date index costs little more than the walk.
+/// Fails without doing anything when a run is in progress already, see [`indexer_state`] for following one.
+pub async fn reconcile_index() -> Result<ReconcileReport> {
+    let run = indexer_state().try_start().context("The index is being reconciled already")?;
+    run_reconcile(run).await
+}
+
+/// Starts [`reconcile_index`] in the background, returning `false` when a run is in progress already.
+pub fn start_reconcile() -> bool {
+    let Some(run) = indexer_state().try_start() else {
+        return false;
+    };
+    tokio::spawn(async move {
+        if let Err(e) = run_reconcile(run).await {
+            error!("Error reconciling the index: {}", e);
+        }
+    });
+    true
+}
+
+async fn run_reconcile(mut run: IndexerRun) -> Result<ReconcileReport> {
+    let outcome = reconcile(&mut run).await;
+    let status = match &outcome {
+        Ok(report) => Ok(report.clone()),
+        Err(e) => Err(format!("{:#}", e)),
+    };
+    indexer_state().finish(run, status);
+    outcome
+}
+
+async fn reconcile(run: &mut IndexerRun) -> Result<ReconcileReport> {
+    info!("Reconciling the index with the filesystem...");
     let start_time = std::time::Instant::now();
 
-    let config = Configuration::get();
-    let is_ignored_path_whitelist = config.filter_mode_whitelist;
-    let ignored_paths = &config.filter;
+    let IndexScope { config, filter } = index_scope();
+    let roots = index_roots(&config);
+    let state = indexer_state();
 
     // Create a database connection pool
     let pool = create_pool().await?;
 
-    // Get all disk mount points
-    let disks = sysinfo::Disks::new_with_refreshed_list();
-    let mut indexed_count = 0;
-    let mut error_count = 0;
+    let mut report = ReconcileReport::default();
+    let mut stored = IndexerData::get_all().await?.into_iter().map(|data| (data.path.clone(), data)).collect::<HashMap<_, _>>();
+    let mut stored_content = content_db::indexed_files(&pool).await?;
+    run.expected_files = stored.len();
+    let mut files_scanned = 0;
 
     // Process files in batches for better performance
     let batch_size = 1000;
     let mut batch = Vec::with_capacity(batch_size);
+    let mut batch_added = 0;
+
+    for root in &roots {
+        info!("Indexing directory: {:?}", root);
+
+        // Use WalkDir for efficient directory traversal, leaving mounts inside the root to be walked on their own
+        let root_mount = config.mount_for(root).map(|mount| &mount.name);
+        let walker = WalkDir::new(root)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|entry| config.mount_for(entry.path()).map(|mount| &mount.name) == root_mount);
+        for entry in walker.filter_map(|e| e.ok()) {
+            if !is_indexed_type(&entry) {
+                continue;
+            }
+            let path = entry.path();
+            if !filter.includes(path, &config) {
+                continue;
+            }
 
-    for disk in &disks {
-        let mount_point = disk.mount_point();
-        info!("Indexing disk: {:?}", mount_point);
-
-        // Use WalkDir for efficient directory traversal
-        for entry in WalkDir::new(mount_point).follow_links(false).into_iter().filter_map(|e| e.ok()) {
-            // Skip directories, only index files
-            if entry.file_type().is_file() {
-                let path = entry.path();
-                let path_str = path.to_string_lossy().replace('\\', "/");
-
-                // Check if the path matches any ignored patterns
-                let matches_pattern = ignored_paths.iter().any(|ignored| {
-                    let pattern = glob::Pattern::new(ignored).unwrap_or_default();
-                    pattern.matches(&path_str)
-                });
+            files_scanned += 1;
+            let progress = |status: &mut IndexerStatus| {
+                status.files_scanned = files_scanned;
+                status.files_indexed = report.added + report.updated;
+                status.errors = report.errors;
+                status.current_path = virtual_path_in(path, &config);
+            };
+            if !state.checkpoint(run, progress).await {
+                info!("Reconciling cancelled after {} files", files_scanned);
+                flush_batch(&mut batch, &mut batch_added, &mut report, &pool).await;
+                return Ok(report);
+            }
 
-                // Check if the file is hidden and should be excluded
-                let is_hidden = path.file_name().and_then(|name| name.to_str()).map(|name| name.starts_with(".")).unwrap_or(false);
+            let data = match IndexerData::from_path(path) {
+                Ok(data) => data,
+                Err(e) => {
+                    error!("Error processing file {:?}: {}", path, e);
+                    report.errors += 1;
+                    continue;
+                }
+            };
 
-                if ((!is_ignored_path_whitelist && matches_pattern) || (is_ignored_path_whitelist && !matches_pattern))
-                    || (config.exclude_hidden_files && is_hidden)
+            // Content is only extracted again when the file changed since it was last indexed
+            let content_mtime = stored_content.remove(&data.path);
+            if !data.is_dir && content_data::is_indexable(path, data.size, &config) {
+                if content_mtime != Some(data.mtime)
+                    && let Err(e) = index_content(&data, &config, &pool).await
                 {
-                    continue;
+                    warn!("Error indexing the content of {:?}: {}", path, e);
                 }
+            } else if content_mtime.is_some() {
+                content_db::delete_with_pool(&data.path, &pool).await?;
+            }
 
-                match IndexerData::from_path(path) {
-                    Ok(data) => {
-                        batch.push(data);
-
-                        // When the batch is full, insert into the database
-                        if batch.len() >= batch_size {
-                            if let Err(e) = insert_batch(&batch, &pool).await {
-                                error!("Error inserting batch: {}", e);
-                                error_count += batch.len();
-                            } else {
-                                indexed_count += batch.len();
-                            }
-                            batch.clear();
-                        }
-                    }
-                    Err(e) => {
-                        error!("Error processing file {:?}: {}", path, e);
-                        error_count += 1;
-                    }
+            match stored.remove(&data.path) {
+                Some(previous) if !previous.is_modified_from(&data) => {
+                    report.unchanged += 1;
+                    continue;
                 }
+                Some(_) => {}
+                None => batch_added += 1,
+            }
+            batch.push(data);
+
+            // When the batch is full, insert into the database
+            if batch.len() >= batch_size {
+                flush_batch(&mut batch, &mut batch_added, &mut report, &pool).await;
             }
         }
     }
 
     // Insert any remaining files in the batch
-    if !batch.is_empty() {
-        if let Err(e) = insert_batch(&batch, &pool).await {
-            error!("Error inserting final batch: {}", e);
-            error_count += batch.len();
-        } else {
-            indexed_count += batch.len();
+    flush_batch(&mut batch, &mut batch_added, &mut report, &pool).await;
+
+    state.set_phase(IndexerPhase::Pruning);
+
+    // Whatever wasn't walked is gone, filtered out, or in a directory that couldn't be read. Only the last are kept
+    let removed = stored
+        .into_keys()
+        .filter(|path| {
+            let path = Path::new(path);
+            !path.exists() || !roots.iter().any(|root| path.starts_with(root)) || !filter.includes(path, &config)
+        })
+        .collect::<Vec<_>>();
+    IndexerData::delete_all_with_pool(&removed, &pool).await?;
+    report.removed = removed.len();
+
+    if config.content_hashing_enabled {
+        state.set_phase(IndexerPhase::Hashing);
+        if !duplicate_data::hash_files(run, &config, &pool, &mut report).await? {
+            return Ok(report);
         }
     }
 
@@ -112,9 +309,25 @@ pub async fn index_all_files() -> Result<()> {
     let hours = elapsed.as_secs() / 3600;
     let minutes = (elapsed.as_secs() % 3600) / 60;
     let seconds = elapsed.as_secs() % 60;
-    info!("Indexing completed in {}h {}m {}s. Indexed {} files, {} errors.", hours, minutes, seconds, indexed_count, error_count);
+    info!(
+        "Reconciling completed in {}h {}m {}s. Added {}, updated {}, removed {} and kept {} files, {} errors.",
+        hours, minutes, seconds, report.added, report.updated, report.removed, report.unchanged, report.errors
+    );
 
-    Ok(())
+    Ok(report)
+}
+
+/// Writes `batch` to the index, counting its entries as added or updated in `report`.
+async fn flush_batch(batch: &mut Vec<IndexerData>, batch_added: &mut usize, report: &mut ReconcileReport, pool: &SqlitePool) {
+    if let Err(e) = insert_batch(batch, pool).await {
+        error!("Error inserting batch: {}", e);
+        report.errors += batch.len();
+    } else {
+        report.added += *batch_added;
+        report.updated += batch.len() - *batch_added;
+    }
+    batch.clear();
+    *batch_added = 0;
 }
 
 async fn insert_batch(batch: &[IndexerData], pool: &SqlitePool) -> Result<()> {
@@ -124,171 +337,492 @@ async fn insert_batch(batch: &[IndexerData], pool: &SqlitePool) -> Result<()> {
 
     // Begin transaction for better performance
     let mut tx = pool.begin().await?;
+    insert_batch_in(batch, &mut tx).await?;
 
-    // Use a single query with multiple value sets for better performance
-    let mut query = String::from("INSERT OR REPLACE INTO indexes (path, filename, mtime, ctime, size) VALUES ");
-
-    // Create placeholders for all items in the batch
-    let placeholders: Vec<String> = (0..batch.len())
-        .map(|i| {
-            format!(
-                "(${}, ${}, ${}, ${}, ${})",
-                i * 5 + 1, // Fixed: 5 columns, not 4
-                i * 5 + 2,
-                i * 5 + 3,
-                i * 5 + 4,
-                i * 5 + 5
-            )
-        })
-        .collect();
-
-    query.push_str(&placeholders.join(", "));
-
-    // Prepare the query with all parameters
-    let mut q = sqlx::query(&query);
+    // Commit transaction
+    tx.commit().await?;
+    Ok(())
+}
 
-    // Bind all parameters
+/// [`insert_batch`] as part of a larger transaction.
+async fn insert_batch_in(batch: &[IndexerData], tx: &mut SqliteConnection) -> Result<()> {
+    // One row at a time, as each entry changes the totals of the directories it is in
     for data in batch {
-        q = q.bind(&data.path).bind(&data.filename).bind(data.mtime as i64).bind(data.ctime as i64).bind(data.size as i64);
+        indexer_db::insert_in(data, &mut *tx).await?;
     }
+    Ok(())
+}
 
-    // Execute the query with all parameters
-    q.execute(&mut *tx).await?;
+/// Indexes the text of the file `data` describes for full-text search, or removes it from the full-text index
+/// when the file no longer qualifies.
+async fn index_content(data: &IndexerData, config: &Configuration, pool: &SqlitePool) -> Result<()> {
+    let path = PathBuf::from(&data.path);
+    if !content_data::is_indexable(&path, data.size, config) {
+        return content_db::delete_with_pool(&data.path, pool).await;
+    }
 
-    // Commit transaction
-    tx.commit().await?;
-    Ok(())
+    match tokio::task::spawn_blocking(move || content_data::extract_text(&path)).await?? {
+        Some(text) => content_db::replace_with_pool(&data.path, data.mtime, &content_data::chunks(&text), pool).await,
+        None => content_db::delete_with_pool(&data.path, pool).await,
+    }
 }
 
 pub async fn start_file_watcher() -> Result<()> {
     info!("Starting file watcher...");
 
-    let notify_config = Config::default().with_poll_interval(Duration::from_secs(2)).with_compare_contents(false);
+    let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+    let watcher_state = Mutex::new(FileWatcherState { watchers: Vec::new(), sender: tx });
+    FILE_WATCHER.set(watcher_state).map_err(|_| anyhow!("The file watcher is running already"))?;
+    if let Some(watcher_state) = FILE_WATCHER.get() {
+        watch_roots(&mut watcher_state.lock().unwrap(), &index_configuration());
+    }
 
-    let (tx, rx) = std::sync::mpsc::channel();
-    let watcher = RecommendedWatcher::new(tx, notify_config)?;
+    tokio::spawn(process_watcher_events(rx));
 
-    let watcher_state = Arc::new(Mutex::new(FileWatcherState { watcher }));
+    info!("File watcher started successfully");
+    Ok(())
+}
 
-    unsafe {
-        FILE_WATCHER = Some(watcher_state.clone());
-    }
+/// Applies the events reported by the watchers in batches.
+///
+/// A batch is applied once no event came in for [`QUIET_PERIOD`], or after [`MAX_BATCH_DELAY`] or [`MAX_BATCH_SIZE`]
+/// events, so that a burst of changes such as a `git checkout` is applied in one transaction.
+async fn process_watcher_events(mut receiver: mpsc::Receiver<notify::Result<Event>>) {
+    let stats = watcher_stats();
+    while let Some(first) = receiver.recv().await {
+        let deadline = Instant::now() + MAX_BATCH_DELAY;
+        let mut events = Vec::new();
+        let mut rescan = false;
+        let mut next = Some(first);
+        while let Some(event) = next {
+            match event {
+                // The watcher lost track of changes, such as when the inotify queue overflowed
+                Ok(event) if event.need_rescan() => rescan = true,
+                Ok(event) => events.push(event),
+                Err(e) => error!("File watcher error: {}", e),
+            }
 
-    let disks = sysinfo::Disks::new_with_refreshed_list();
-    let mut state = watcher_state.lock().await;
+            // The source of a rename is kept in the same batch as its destination, which follows right after it
+            let holding_rename = events.last().is_some_and(is_rename_source);
+            if events.len() >= MAX_BATCH_SIZE && !holding_rename {
+                break;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let wait = if holding_rename { QUIET_PERIOD } else { QUIET_PERIOD.min(remaining) };
+            next = tokio::time::timeout(wait, receiver.recv()).await.ok().flatten();
+        }
 
-    for disk in &disks {
-        let mount_point = disk.mount_point();
-        info!("Watching directory: {:?}", mount_point);
-        if let Err(e) = state.watcher.watch(mount_point, RecursiveMode::Recursive) {
-            warn!("Error watching {:?}: {}", mount_point, e);
+        let collected = events.len();
+        let events = coalesce(events);
+        let processed = events.len();
+        let failed = match watcher_pool().await {
+            Ok(pool) => {
+                let IndexScope { config, filter } = index_scope();
+                apply_events(events, &config, &filter, pool).await.unwrap_or_else(|e| {
+                    error!("Error applying file events: {}", e);
+                    processed
+                })
+            }
+            Err(e) => {
+                error!("Error opening the database to apply file events: {}", e);
+                processed
+            }
+        };
+        stats.batch(collected, processed, failed);
+        debug!("Applied {} file events, coalesced from {}", processed, collected);
+
+        if stats.take_overflowed() || rescan {
+            warn!("The file watcher missed changes, reconciling the index...");
+            if index_configuration().indexing_enabled && !start_reconcile() {
+                warn!("The index is being reconciled already, it may miss some of the changes");
+            }
         }
     }
+}
 
-    tokio::spawn(async move {
-        loop {
-            match rx.recv() {
-                Ok(event) => {
-                    if let Err(e) = process_file_event(event).await {
-                        error!("Error processing file event: {}", e);
-                    }
-                }
-                Err(e) => {
-                    error!("File watcher error: {}", e);
-                    sleep(Duration::from_secs(5)).await;
-                }
+/// The state of the file watcher and its counters.
+pub fn watcher_status() -> WatcherStatus {
+    let watcher_state = FILE_WATCHER.get().map(|state| state.lock().unwrap());
+    WatcherStatus {
+        running: watcher_state.is_some(),
+        watched_directories: watcher_state.as_ref().map_or(0, |state| state.watchers.len()),
+        queued: watcher_state.as_ref().map_or(0, |state| state.sender.max_capacity() - state.sender.capacity()),
+        counters: watcher_stats().counters(),
+    }
+}
+
+/// The pool the file watcher applies changes with, opened once rather than for every batch.
+async fn watcher_pool() -> Result<&'static SqlitePool> {
+    WATCHER_POOL.get_or_try_init(create_pool).await
+}
+
+/// Replaces the watchers of `state` with one for every index root of `config`, each in the mode set for it.
+fn watch_roots(state: &mut FileWatcherState, config: &Configuration) {
+    state.watchers.clear();
+    let mut watched = Vec::new();
+    for root in index_roots(config) {
+        let (mode, poll_interval) = config.watcher_for(&root);
+        let sender = state.sender.clone();
+        let handler = move |event: notify::Result<Event>| {
+            watcher_stats().received();
+            if sender.try_send(event).is_err() {
+                watcher_stats().dropped();
             }
+        };
+        let watcher = match mode {
+            WatcherMode::Native => storage_backend::backend_for(&root).watch(&root, Box::new(handler)),
+            WatcherMode::Poll => poll_directory(&root, poll_interval, handler),
+            WatcherMode::Off => Ok(None),
+        };
+
+        let active_mode = match watcher {
+            Ok(Some(watcher)) => {
+                info!("Watching directory: {:?} ({:?})", root, mode);
+                state.watchers.push(watcher);
+                mode
+            }
+            Ok(None) if mode == WatcherMode::Off => {
+                info!("Not watching directory: {:?}", root);
+                mode
+            }
+            Ok(None) => {
+                warn!("The storage of {:?} can not be watched", root);
+                WatcherMode::Off
+            }
+            Err(e) => {
+                warn!("Error watching {:?}: {}", root, e);
+                WatcherMode::Off
+            }
+        };
+        watched.push(WatchedRoot {
+            path: virtual_path_in(&root, config).unwrap_or_else(|| root.to_string_lossy().into_owned()),
+            mode: active_mode,
+            poll_interval: (active_mode == WatcherMode::Poll).then_some(poll_interval.as_secs()),
+        });
+    }
+    indexer_state().set_watchers(watched);
+}
+
+/// Watches `root` by scanning it every `interval`, which works for storage kept on the local filesystem, network
+/// shares included.
+fn poll_directory(root: &Path, interval: Duration, handler: impl EventHandler) -> std::io::Result<Option<Box<dyn Watcher + Send>>> {
+    let Some(path) = storage_backend::backend_for(root).local_path(root) else {
+        return Ok(None);
+    };
+    let mut watcher = DirectoryPoller::new(handler, notify::Config::default().with_poll_interval(interval)).map_err(std::io::Error::other)?;
+    watcher.watch(&path, RecursiveMode::Recursive).map_err(std::io::Error::other)?;
+    Ok(Some(Box::new(watcher)))
+}
+
+/// Re-scopes the index and the file watcher when a saved configuration changes what is indexed.
+///
+/// Entries that fall out of the new scope are removed and the new scope is indexed in the background.
+pub fn handle_config_change(new_config: &Configuration) {
+    let old_config = index_configuration();
+    let scope_changed = index_roots(&old_config) != index_roots(new_config)
+        || old_config.filter != new_config.filter
+        || old_config.filter_mode_whitelist != new_config.filter_mode_whitelist
+        || old_config.exclude_hidden_files != new_config.exclude_hidden_files
+        || old_config.mounts != new_config.mounts
+        || old_config.included_extensions != new_config.included_extensions
+        || old_config.content_indexing_enabled != new_config.content_indexing_enabled
+        || old_config.content_max_file_size != new_config.content_max_file_size
+        || old_config.content_hashing_enabled != new_config.content_hashing_enabled;
+    let watchers_changed = old_config.watcher_mode != new_config.watcher_mode || old_config.watcher_poll_interval != new_config.watcher_poll_interval;
+    if !scope_changed && !watchers_changed {
+        return;
+    }
+
+    let scope = IndexScope::new(new_config.clone());
+    *INDEX_SCOPE.write().unwrap() = Some(scope.clone());
+    let config = scope.config;
+    tokio::spawn(async move {
+        // Only a running watcher is moved, a disabled one stays off until the next start
+        if let Some(watcher_state) = FILE_WATCHER.get() {
+            watch_roots(&mut watcher_state.lock().unwrap(), &config);
+        }
+        if !scope_changed || !config.indexing_enabled {
+            return;
+        }
+        // A run in progress walks the old scope, so it is replaced by one over the new scope
+        let state = indexer_state();
+        state.cancel();
+        state.wait_idle().await;
+        // Entries that fell out of the new scope are removed along the way
+        if !start_reconcile() {
+            warn!("The index is being reconciled already, it may not reflect the new configuration");
         }
     });
+}
 
-    info!("File watcher started successfully");
-    Ok(())
+/// Updates the index for a change Filer made itself, such as an SFTP upload.
+///
+/// The event goes through the same processing as the ones reported by the file watcher, so the index
+/// stays up to date even when the watcher is disabled.
+pub async fn record_change(event: Event) {
+    if !index_configuration().indexing_enabled {
+        return;
+    }
+    let applied = match watcher_pool().await {
+        Ok(pool) => {
+            let IndexScope { config, filter } = index_scope();
+            apply_events(vec![event], &config, &filter, pool).await.map(|_| ())
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = applied {
+        error!("Error processing file event: {}", e);
+    }
+}
+
+/// Applies changes reported by the file watcher, or made by Filer itself, to the index in one transaction. Returns
+/// how many of the events couldn't be applied.
+///
+/// A rename moves the entries of the renamed path and everything below it in place, keeping their indexed content.
+/// Watchers that report the two sides of a rename as separate events have the source held until the destination
+/// comes in. A source followed by anything else, or by nothing, was moved out of the watched directories, and its
+/// entries are removed.
+///
+/// The content of new and changed files is indexed once the transaction is committed, so that extracting it doesn't
+/// hold up other writers.
+pub(crate) async fn apply_events(events: Vec<Event>, config: &Configuration, filter: &PathFilter, pool: &SqlitePool) -> Result<usize> {
+    let mut batch = EventBatch::default();
+    let mut failed = 0;
+    let mut tx = pool.begin().await?;
+    for event in events {
+        if let Err(e) = apply_event(event, config, filter, &mut tx, &mut batch).await {
+            error!("Error processing file event: {}", e);
+            failed += 1;
+        }
+    }
+    if let Some(path) = batch.rename_source.take() {
+        remove_tree(&path, &mut tx, &mut batch).await?;
+    }
+    tx.commit().await?;
+
+    for data in batch.content {
+        if let Err(e) = index_content(&data, config, pool).await {
+            warn!("Error indexing the content of {}: {}", data.path, e);
+        }
+    }
+    Ok(failed)
 }
 
-async fn process_file_event(event: Result<Event, notify::Error>) -> Result<()> {
-    let config = Configuration::get();
-    let is_ignored_path_whitelist = config.filter_mode_whitelist;
-    let ignored_paths = &config.filter;
+/// What is carried from one event of a batch to the next, see [`apply_events`].
+#[derive(Default)]
+struct EventBatch {
+    /// The source of a rename whose destination wasn't reported yet.
+    rename_source: Option<PathBuf>,
+    /// The last rename applied from separate source and destination events.
+    applied_rename: Option<(PathBuf, PathBuf)>,
+    /// The files whose content is indexed once the batch is committed.
+    content: Vec<IndexerData>,
+    /// New or changed files that were gone by the time the batch was applied, found again if a later rename of the
+    /// batch moved them.
+    missing: Vec<PathBuf>,
+}
 
-    let event = event?;
+impl EventBatch {
+    /// Follows the files waiting for their content to be indexed to where `from` was moved to.
+    fn moved(&mut self, from: &str, to: &str) {
+        for data in &mut self.content {
+            if let Some(rest) = data.path.strip_prefix(from).filter(|rest| rest.is_empty() || rest.starts_with('/')) {
+                data.path = format!("{}{}", to, rest);
+            }
+        }
+    }
+
+    /// Takes the missing files below `from`, returning where they are now that it was moved to `to`.
+    fn arrived(&mut self, from: &Path, to: &Path) -> Vec<PathBuf> {
+        let (arrived, missing): (Vec<_>, Vec<_>) = std::mem::take(&mut self.missing).into_iter().partition(|path| path.starts_with(from));
+        self.missing = missing;
+        arrived.into_iter().filter_map(|path| Some(to.join(path.strip_prefix(from).ok()?))).collect()
+    }
+
+    /// Drops the files below `path` from the ones waiting for their content to be indexed.
+    fn removed(&mut self, path: &str) {
+        self.content.retain(|data| !data.path.strip_prefix(path).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')));
+    }
+}
+
+async fn apply_event(event: Event, config: &Configuration, filter: &PathFilter, tx: &mut SqliteConnection, batch: &mut EventBatch) -> Result<()> {
+    let is_rename_destination = matches!(event.kind, EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Both)));
+    if !is_rename_destination {
+        batch.applied_rename = None;
+        if let Some(path) = batch.rename_source.take() {
+            remove_tree(&path, tx, batch).await?;
+        }
+    }
 
     match event.kind {
-        EventKind::Create(_) | EventKind::Modify(_) => {
-            for path in event.paths {
-                // Check if the path matches any ignored patterns
-                let path_str = path.to_string_lossy().replace('\\', "/");
-                let matches_pattern = ignored_paths.iter().any(|ignored| {
-                    let pattern = glob::Pattern::new(ignored).unwrap_or_default();
-                    pattern.matches(&path_str)
-                });
-
-                // Check if the file is hidden and should be excluded
-                let is_hidden = path.file_name().and_then(|name| name.to_str()).map(|name| name.starts_with(".")).unwrap_or(false);
-
-                if ((!is_ignored_path_whitelist && matches_pattern) || (is_ignored_path_whitelist && !matches_pattern))
-                    || (config.exclude_hidden_files && is_hidden)
-                {
-                    continue;
-                }
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            batch.rename_source = event.paths.into_iter().next();
+        }
 
-                if path.is_file() {
-                    match IndexerData::from_path(&path) {
-                        Ok(data) => {
-                            debug!("Updating index for modified file: {}", data.path);
-
-                            if let Ok(Some(_)) = IndexerData::get_by_path(&data.path).await {
-                                data.update().await?;
-                            } else {
-                                data.insert().await?;
-                            }
-                        }
-                        Err(e) => {
-                            error!("Error processing modified file {:?}: {}", path, e);
-                        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            for to in event.paths {
+                match batch.rename_source.take() {
+                    Some(from) => {
+                        rename_tree(&from, &to, config, filter, tx, batch).await?;
+                        // inotify follows up with both sides in one event, which has nothing left to do
+                        batch.applied_rename = Some((from, to));
                     }
+                    // Moved in from outside the watched directories
+                    None => index_tree(&to, config, filter, tx, batch).await?,
                 }
             }
         }
 
-        EventKind::Remove(_) => {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let [from, to] = &event.paths[..] else {
+                return Ok(());
+            };
+            batch.rename_source = None;
+            let already_applied = batch.applied_rename.take().is_some_and(|(applied_from, applied_to)| &applied_from == from && &applied_to == to);
+            if !already_applied {
+                rename_tree(from, to, config, filter, tx, batch).await?;
+            }
+        }
+
+        // Watchers that can't tell the sides of a rename apart report each side on its own
+        EventKind::Modify(ModifyKind::Name(_)) => {
             for path in event.paths {
-                // Check if the path matches any ignored patterns
-                let path_str = path.to_string_lossy().replace('\\', "/");
-                let matches_pattern = ignored_paths.iter().any(|ignored| {
-                    let pattern = glob::Pattern::new(ignored).unwrap_or_default();
-                    pattern.matches(&path_str)
-                });
-
-                // Check if the file is hidden and should be excluded
-                let is_hidden = path.file_name().and_then(|name| name.to_str()).map(|name| name.starts_with(".")).unwrap_or(false);
-
-                if ((!is_ignored_path_whitelist && matches_pattern) || (is_ignored_path_whitelist && !matches_pattern))
-                    || (config.exclude_hidden_files && is_hidden)
-                {
-                    continue;
+                if path.exists() {
+                    index_tree(&path, config, filter, tx, batch).await?;
+                } else {
+                    remove_tree(&path, tx, batch).await?;
                 }
+            }
+        }
 
-                let path_str = path.to_string_lossy().to_string().replace('\\', "/");
-                debug!("Removing index for deleted file: {}", path_str);
-
-                if let Err(e) = IndexerData::delete(&path_str).await {
-                    error!("Error removing index for {:?}: {}", path, e);
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            for path in event.paths {
+                // A new directory may already have files in it, as when it was created with them or copied in
+                if path.is_dir() && event.kind.is_create() {
+                    index_tree(&path, config, filter, tx, batch).await?;
+                } else if path.symlink_metadata().is_err() {
+                    batch.missing.push(path);
+                } else {
+                    index_file(&path, config, filter, tx, batch).await?;
                 }
             }
         }
 
+        EventKind::Remove(_) => {
+            for path in event.paths {
+                remove_tree(&path, tx, batch).await?;
+            }
+        }
+
         _ => {}
     }
 
     Ok(())
 }
 
+/// Moves the entries of `from` and everything below it to `to`, dropping the ones the new paths leave out of the
+/// index. When nothing was indexed at `from`, such as when it was filtered out, `to` is indexed from scratch.
+async fn rename_tree(
+    from: &Path,
+    to: &Path,
+    config: &Configuration,
+    filter: &PathFilter,
+    tx: &mut SqliteConnection,
+    batch: &mut EventBatch,
+) -> Result<()> {
+    let from_str = from.to_string_lossy().replace('\\', "/");
+    let to_str = to.to_string_lossy().replace('\\', "/");
+    let moved = indexer_db::rename_tree_in(&from_str, &to_str, tx).await?;
+    debug!("Moved {} entries from {} to {}", moved.len(), from_str, to_str);
+    batch.removed(&to_str);
+    batch.moved(&from_str, &to_str);
+    let arrived = batch.arrived(from, to);
+    if moved.is_empty() {
+        return index_tree(to, config, filter, tx, batch).await;
+    }
+    for path in arrived {
+        index_file(&path, config, filter, tx, batch).await?;
+    }
+
+    let filtered = moved.into_iter().map(|data| data.path).filter(|path| !filter.includes(Path::new(path), config)).collect::<Vec<_>>();
+    for path in &filtered {
+        batch.removed(path);
+    }
+    indexer_db::delete_all_in(&filtered, tx).await
+}
+
+/// Indexes the file at `path` unless it is filtered out, and has its content indexed with the batch.
+async fn index_file(path: &Path, config: &Configuration, filter: &PathFilter, tx: &mut SqliteConnection, batch: &mut EventBatch) -> Result<()> {
+    if !path.is_file() || !filter.includes(path, config) {
+        return Ok(());
+    }
+    match IndexerData::from_path(path) {
+        Ok(data) => {
+            debug!("Updating index for modified file: {}", data.path);
+            insert_batch_in(std::slice::from_ref(&data), tx).await?;
+            batch.content.push(data);
+        }
+        Err(e) => {
+            error!("Error processing modified file {:?}: {}", path, e);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the entries of `path` and everything below it.
+async fn remove_tree(path: &Path, tx: &mut SqliteConnection, batch: &mut EventBatch) -> Result<()> {
+    let path_str = path.to_string_lossy().replace('\\', "/");
+    let removed = indexer_db::delete_tree_in(&path_str, tx).await?;
+    batch.removed(&path_str);
+    debug!("Removed {} entries for deleted path: {}", removed, path_str);
+    Ok(())
+}
+
+/// Indexes `root` and every file and directory below it, such as a directory that was created or moved in.
+///
+/// The tree is walked on a blocking thread before any of it is written, so the walk doesn't hold up the transaction.
+async fn index_tree(root: &Path, config: &Configuration, filter: &PathFilter, tx: &mut SqliteConnection, batch: &mut EventBatch) -> Result<()> {
+    let (root, walk_config, walk_filter) = (root.to_path_buf(), config.clone(), filter.clone());
+    let entries = tokio::task::spawn_blocking(move || walk_tree(&root, &walk_config, &walk_filter)).await?;
+    insert_batch_in(&entries, tx).await?;
+    batch.content.extend(entries.into_iter().filter(|data| !data.is_dir));
+    Ok(())
+}
+
+/// The entries of `root` and everything below it that the index holds.
+fn walk_tree(root: &Path, config: &Configuration, filter: &PathFilter) -> Vec<IndexerData> {
+    // Mounts inside the tree are left to be indexed by their own settings, as when reconciling
+    let root_mount = config.mount_for(root).map(|mount| &mount.name);
+    let walker = WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|entry| config.mount_for(entry.path()).map(|mount| &mount.name) == root_mount);
+    walker
+        .filter_map(|e| e.ok())
+        .filter(|entry| is_indexed_type(entry) && filter.includes(entry.path(), config))
+        .filter_map(|entry| match IndexerData::from_path(entry.path()) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                error!("Error processing file {:?}: {}", entry.path(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Whether a walked entry is one the index holds: files, and directories other than the top of the filesystem. Links
+/// are left out.
+fn is_indexed_type(entry: &walkdir::DirEntry) -> bool {
+    entry.file_type().is_file() || (entry.file_type().is_dir() && entry.path().file_name().is_some())
+}
+
 impl IndexerData {
     pub fn from_path(path: &Path) -> Result<Self> {
         let metadata = fs::metadata(path).context("Failed to get file metadata")?;
 
-        // Get file size
-        let size = metadata.len();
+        // Get file size, that of a directory being the total of its files once it is indexed
+        let is_dir = metadata.is_dir();
+        let size = if is_dir { 0 } else { metadata.len() };
         let filename = path.file_name().unwrap().to_string_lossy().to_string();
 
         // Get modification time
@@ -297,7 +831,7 @@ impl IndexerData {
         // Get creation time
         let ctime = metadata.created().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()).unwrap_or(0);
 
-        Ok(Self { path: path.to_string_lossy().to_string().replace('\\', "/"), filename, size, mtime, ctime })
+        Ok(Self { path: path.to_string_lossy().to_string().replace('\\', "/"), filename, size, mtime, ctime, is_dir, file_count: 0, score: None })
     }
 
     // Utility method to check if a file has been modified since it was last indexed
@@ -308,7 +842,13 @@ impl IndexerData {
         }
 
         let current = Self::from_path(path)?;
-        Ok(current.size != self.size || current.mtime != self.mtime)
+        Ok(self.is_modified_from(&current))
+    }
+
+    /// Whether `current`, read from disk, differs from this indexed entry of the same file. The size of a directory
+    /// is kept up to date with its files, so only its modification time is compared.
+    pub fn is_modified_from(&self, current: &Self) -> bool {
+        current.is_dir != self.is_dir || (!current.is_dir && current.size != self.size) || current.mtime != self.mtime
     }
 
     // Utility method to get all indexed files
@@ -323,13 +863,13 @@ impl IndexerData {
         let pool = create_pool().await?;
 
         // Get total count
-        let count: (i64,) = sqlx::query_as(r#"select count(*) from indexes"#).fetch_one(&pool).await?;
+        let count: (i64,) = sqlx::query_as(r#"select count(*) from indexes where is_dir = 0"#).fetch_one(&pool).await?;
 
         // Get total size
-        let size: (i64,) = sqlx::query_as(r#"select sum(size) from indexes"#).fetch_one(&pool).await?;
+        let size: (i64,) = sqlx::query_as(r#"select sum(size) from indexes where is_dir = 0"#).fetch_one(&pool).await?;
 
         // Get average size
-        let avg_size: (i64,) = sqlx::query_as(r#"select avg(size) from indexes"#).fetch_one(&pool).await?;
+        let avg_size: (i64,) = sqlx::query_as(r#"select avg(size) from indexes where is_dir = 0"#).fetch_one(&pool).await?;
 
         Ok((count.0 as u64, size.0 as u64, avg_size.0 as u64))
     }
@@ -1,26 +1,63 @@
+use crate::configuration::configuration_data::Configuration;
 use crate::helpers::db::create_pool;
-use crate::io::fs::indexer::indexer_data::IndexerData;
-use sqlx::{Executor, Row, SqlitePool};
+use crate::io::fs::indexer::content_db;
+use crate::io::fs::indexer::duplicate_db;
+use crate::io::fs::indexer::fuzzy_rank;
+use crate::io::fs::indexer::indexer_data::{IndexerData, index_scope};
+use crate::io::fs::indexer::pattern_search::{MAX_PATTERN_MATCHES, PATTERN_SEARCH_TIMEOUT, PathPattern};
+use crate::io::fs::indexer::search_query::{EntryKind, SearchPage, SearchParameters, SearchQuery, SearchSort, SortOrder, encode_cursor};
+use futures::TryStreamExt;
+use serde_json::Value;
+use sqlx::{Executor, QueryBuilder, Row, Sqlite, SqliteConnection, SqlitePool};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The most entries sharing trigrams with a search that are ranked by how well they match it.
+const FUZZY_CANDIDATES: usize = 2000;
 
 pub async fn initialize() -> anyhow::Result<()> {
     let pool = create_pool().await?;
+    initialize_with_pool(&pool).await
+}
 
+pub async fn initialize_with_pool(pool: &SqlitePool) -> anyhow::Result<()> {
     // Create the main indexes table if it doesn't exist
     pool.execute(
         r#"CREATE TABLE IF NOT EXISTS indexes
 (
-    id       INTEGER PRIMARY KEY AUTOINCREMENT,
-    path     TEXT UNIQUE,
-    filename TEXT    NOT NULL,
-    mtime    INTEGER NOT NULL,
-    ctime    INTEGER NOT NULL,
-    size     INTEGER NOT NULL
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    path         TEXT UNIQUE,
+    filename     TEXT    NOT NULL,
+    mtime        INTEGER NOT NULL,
+    ctime        INTEGER NOT NULL,
+    size         INTEGER NOT NULL,
+    is_dir       INTEGER NOT NULL DEFAULT 0,
+    file_count   INTEGER NOT NULL DEFAULT 0,
+    partial_hash TEXT,
+    hash         TEXT,
+    file_id      TEXT
 );
         "#,
     )
     .await?;
 
+    // Tables created before directories were indexed and files hashed lack their columns. The directory entries and
+    // hashes themselves are added when the index is next reconciled
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('indexes')").fetch_all(pool).await?;
+    for (column, definition) in [
+        ("is_dir", "is_dir INTEGER NOT NULL DEFAULT 0"),
+        ("file_count", "file_count INTEGER NOT NULL DEFAULT 0"),
+        ("partial_hash", "partial_hash TEXT"),
+        ("hash", "hash TEXT"),
+        ("file_id", "file_id TEXT"),
+    ] {
+        if !columns.iter().any(|existing| existing == column) {
+            pool.execute(format!("ALTER TABLE indexes ADD COLUMN {}", definition).as_str()).await?;
+        }
+    }
+
     // Create the trigram table if it doesn't exist
     pool.execute(
         r#"CREATE TABLE IF NOT EXISTS path_trigrams
@@ -36,62 +73,29 @@ pub async fn initialize() -> anyhow::Result<()> {
 
     // Create an index on trigrams for faster searching
     pool.execute(r#"CREATE INDEX IF NOT EXISTS idx_trigram ON path_trigrams(trigram);"#).await?;
-
-    // Check if the trigram table is empty
-    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM path_trigrams").fetch_one(&pool).await?;
-
-    // If the trigram table is empty, but we have data in the index table,
-    // rebuild the trigram index
-    if count == 0 {
-        let index_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM indexes").fetch_one(&pool).await?;
-
-        if index_count > 0 {
-            log::info!("Rebuilding trigram index for {} existing records...", index_count);
-            rebuild_trigram_index().await?;
-            log::info!("Trigram index rebuilt successfully.");
-        }
+    pool.execute(r#"CREATE INDEX IF NOT EXISTS idx_trigram_path ON path_trigrams(path_id);"#).await?;
+
+    // Create the full-text content index if it doesn't exist
+    content_db::initialize(pool).await?;
+    duplicate_db::initialize(pool).await?;
+
+    // Entries indexed in bulk before their trigrams were indexed along with them have none, rebuild the
+    // trigram index when any are missing
+    let missing: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM indexes WHERE id NOT IN (SELECT DISTINCT path_id FROM path_trigrams)").fetch_one(pool).await?;
+    if missing > 0 {
+        log::info!("Rebuilding trigram index for {} records without trigrams...", missing);
+        rebuild_trigram_index(pool).await?;
+        log::info!("Trigram index rebuilt successfully.");
     }
 
     Ok(())
 }
 impl IndexerData {
     pub async fn insert_with_pool(&self, pool: &SqlitePool) -> anyhow::Result<()> {
-        // Start a transaction
         let mut tx = pool.begin().await?;
-
-        // Insert into the main indexes table
-        let result = sqlx::query(r#"INSERT INTO indexes (path, filename, mtime, ctime, size) VALUES (?, ?, ?, ?, ?)"#)
-            .bind(&self.path)
-            .bind(&self.filename)
-            .bind(self.mtime as i64)
-            .bind(self.ctime as i64)
-            .bind(self.size as i64)
-            .execute(&mut *tx)
-            .await?;
-
-        // Get the last inserted ID
-        let id = result.last_insert_rowid();
-
-        // Generate trigrams for path and filename (for better search)
-        let path_trigrams = generate_trigrams(&self.path);
-        let filename_trigrams = generate_trigrams(&self.filename);
-
-        // Combine trigrams, remove duplicates
-        let mut all_trigrams = path_trigrams;
-        for trigram in filename_trigrams {
-            if !all_trigrams.contains(&trigram) {
-                all_trigrams.push(trigram);
-            }
-        }
-
-        // Insert trigrams
-        for trigram in all_trigrams {
-            sqlx::query(r#"INSERT INTO path_trigrams (path_id, trigram) VALUES (?, ?)"#).bind(id).bind(&trigram).execute(&mut *tx).await?;
-        }
-
-        // Commit the transaction
+        insert_in(self, &mut tx).await?;
         tx.commit().await?;
-
         Ok(())
     }
 
@@ -101,47 +105,8 @@ impl IndexerData {
     }
 
     pub async fn update_with_pool(&self, pool: &SqlitePool) -> anyhow::Result<()> {
-        // Start a transaction
-        let mut tx = pool.begin().await?;
-
-        // Update the main record
-        sqlx::query(r#"UPDATE indexes SET mtime = ?, ctime = ?, size = ? WHERE path = ?"#)
-            .bind(self.mtime as i64)
-            .bind(self.ctime as i64)
-            .bind(self.size as i64)
-            .bind(&self.path)
-            .execute(&mut *tx)
-            .await?;
-
-        // Get the ID for this path
-        let row = sqlx::query(r#"SELECT id FROM indexes WHERE path = ?"#).bind(&self.path).fetch_one(&mut *tx).await?;
-
-        let id: i64 = row.get(0);
-
-        // Delete existing trigrams
-        sqlx::query(r#"DELETE FROM path_trigrams WHERE path_id = ?"#).bind(id).execute(&mut *tx).await?;
-
-        // Generate trigrams for path and filename (for better search)
-        let path_trigrams = generate_trigrams(&self.path);
-        let filename_trigrams = generate_trigrams(&self.filename);
-
-        // Combine trigrams, remove duplicates
-        let mut all_trigrams = path_trigrams;
-        for trigram in filename_trigrams {
-            if !all_trigrams.contains(&trigram) {
-                all_trigrams.push(trigram);
-            }
-        }
-
-        // Insert trigrams
-        for trigram in all_trigrams {
-            sqlx::query(r#"INSERT INTO path_trigrams (path_id, trigram) VALUES (?, ?)"#).bind(id).bind(&trigram).execute(&mut *tx).await?;
-        }
-
-        // Commit the transaction
-        tx.commit().await?;
-
-        Ok(())
+        // Replacing the entry keeps the totals of the directories it is in right
+        self.insert_with_pool(pool).await
     }
 
     pub async fn update(&self) -> anyhow::Result<()> {
@@ -150,26 +115,33 @@ impl IndexerData {
     }
 
     pub async fn delete_with_pool(path: &str, pool: &SqlitePool) -> anyhow::Result<()> {
-        // Start a transaction
-        let mut tx = pool.begin().await?;
-
-        // Get the ID for this path
-        let row_opt = sqlx::query(r#"SELECT id FROM indexes WHERE path = ?"#).bind(path).fetch_optional(&mut *tx).await?;
-
-        if let Some(row) = row_opt {
-            let id: i64 = row.get(0);
-
-            // Delete trigrams first (due to foreign key constraint)
-            sqlx::query(r#"DELETE FROM path_trigrams WHERE path_id = ?"#).bind(id).execute(&mut *tx).await?;
-        }
+        Self::delete_all_with_pool(&[path.to_string()], pool).await
+    }
 
-        // Then delete the main record
-        sqlx::query(r#"DELETE FROM indexes WHERE path = ?"#).bind(path).execute(&mut *tx).await?;
+    /// Removes the entries of `paths` in a single transaction.
+    pub async fn delete_all_with_pool(paths: &[String], pool: &SqlitePool) -> anyhow::Result<()> {
+        let mut tx = pool.begin().await?;
+        delete_all_in(paths, &mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        // Commit the transaction
+    /// Removes the entry of `path` and, when it is a directory, the entries of everything below it. Returns the
+    /// number of entries removed.
+    pub async fn delete_tree_with_pool(path: &str, pool: &SqlitePool) -> anyhow::Result<u64> {
+        let mut tx = pool.begin().await?;
+        let removed = delete_tree_in(path, &mut tx).await?;
         tx.commit().await?;
+        Ok(removed)
+    }
 
-        Ok(())
+    /// Moves the entries of `from`, and of everything below it when it is a directory, to `to`, along with their
+    /// trigrams and indexed content. Entries that were at `to` are replaced. Returns the moved entries.
+    pub async fn rename_tree_with_pool(from: &str, to: &str, pool: &SqlitePool) -> anyhow::Result<Vec<Self>> {
+        let mut tx = pool.begin().await?;
+        let moved = rename_tree_in(from, to, &mut tx).await?;
+        tx.commit().await?;
+        Ok(moved)
     }
 
     pub async fn delete(path: &str) -> anyhow::Result<()> {
@@ -209,124 +181,550 @@ impl IndexerData {
         Ok(in_dir)
     }
 
+    /// The total size of the files indexed below the directory `path`.
     pub async fn get_directory_size(path: impl AsRef<Path>) -> anyhow::Result<u64> {
-        let path = path.as_ref();
-        let path_str = path.to_string_lossy().to_string();
-        let path_str = path_str.replace('\\', "/");
+        let path = path.as_ref().to_string_lossy().replace('\\', "/");
         let pool = create_pool().await?;
-        let query = format!("{}%", path_str);
-        let result = sqlx::query_as::<_, IndexerData>(r#"select * from indexes where path like ?"#).bind(&query).fetch_all(&pool).await?;
-
-        let size: u64 = result.iter().map(|item| item.size).sum();
+        let indexed: Option<i64> =
+            sqlx::query_scalar(r#"SELECT size FROM indexes WHERE path = ? AND is_dir = 1"#).bind(&path).fetch_optional(&pool).await?;
+        let size = match indexed {
+            Some(size) => size,
+            // Directories that are filtered out have no entry, but the files below them may
+            None => directory_totals_in(&path, &mut *pool.acquire().await?).await?.0,
+        };
+        Ok(size as u64)
+    }
 
-        Ok(size)
+    /// The total sizes of those of the directories `paths` that are indexed, by path.
+    pub async fn get_directory_sizes(paths: &[String]) -> anyhow::Result<HashMap<String, u64>> {
+        let pool = create_pool().await?;
+        let mut sizes = HashMap::new();
+        // Kept well below the number of parameters SQLite allows in a query
+        for chunk in paths.chunks(500) {
+            let mut select = QueryBuilder::<Sqlite>::new("SELECT path, size FROM indexes WHERE is_dir = 1 AND path IN (");
+            let mut separated = select.separated(", ");
+            for path in chunk {
+                separated.push_bind(path);
+            }
+            select.push(")");
+            let rows: Vec<(String, i64)> = select.build_query_as().fetch_all(&pool).await?;
+            sizes.extend(rows.into_iter().map(|(path, size)| (path, size as u64)));
+        }
+        Ok(sizes)
     }
 
+    /// Finds the entries best matching the words of `query`, typos included, see [`Self::find`].
     pub async fn search(query: impl AsRef<str>, filename_only: bool) -> anyhow::Result<Vec<Self>> {
-        if query.as_ref().is_empty() {
+        let query = SearchQuery { words: query.as_ref().split_whitespace().map(String::from).collect(), ..SearchQuery::default() };
+        if query.is_empty() {
             return Ok(Vec::new());
         }
+        let parameters = SearchParameters { filename_only, ..SearchParameters::default() };
+        Ok(Self::find(&query, None, &parameters, None).await?.results)
+    }
+}
 
+impl IndexerData {
+    /// Finds the entries matching `query`, a page at a time.
+    ///
+    /// `folder` is the OS path of the folder given with `in:`, and `cursor` the decoded cursor of the previous page.
+    /// Results are reported by the path clients see.
+    /// Pages are cut by the sort keys of the last result when there is a cursor, so entries added or removed
+    /// meanwhile don't shift later pages.
+    pub async fn find(
+        query: &SearchQuery,
+        folder: Option<&Path>,
+        parameters: &SearchParameters,
+        cursor: Option<Vec<Value>>,
+    ) -> anyhow::Result<SearchPage> {
         let pool = create_pool().await?;
-        let search_term = query.as_ref();
-
-        // Generate trigrams from the search term
-        let search_trigrams = generate_trigrams(search_term);
-
-        if search_trigrams.is_empty() {
-            return Ok(Vec::new());
+        if parameters.sort == SearchSort::Relevance && !query.words.is_empty() {
+            return Self::find_fuzzy(query, folder, parameters, cursor, &pool).await;
         }
 
-        // First, try exact search for very short queries
-        if search_term.len() <= 3 {
-            let exact_pattern = format!("%{}%", search_term);
+        let field = if parameters.filename_only { "filename" } else { "path" };
+        let limit = parameters.limit.unwrap_or(100).clamp(1, 1000) as usize;
+        let keys = parameters.sort.keys();
+
+        // Entries filtered out since they were indexed, or outside of the published tree, are left out as the rows
+        // are read, so they neither count nor leave pages short
+        let scope = index_scope();
+        let mut count = QueryBuilder::<Sqlite>::new("SELECT i.path, i.is_dir FROM indexes i WHERE 1");
+        push_words(&mut count, query, field);
+        push_conditions(&mut count, query, folder);
+        let mut total = 0u64;
+        let mut rows = count.build_query_as::<(String, bool)>().fetch(&pool);
+        while let Some((path, is_dir)) = rows.try_next().await? {
+            if scope.visible_path(Path::new(&path), is_dir).is_some() {
+                total += 1;
+            }
+        }
+        drop(rows);
+
+        let key_columns = keys.iter().enumerate().map(|(index, key)| format!("{} AS key{}", key, index)).collect::<Vec<_>>().join(", ");
+        let mut select = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT *, {} FROM (SELECT i.path, i.filename, i.mtime, i.ctime, i.size, i.is_dir, i.file_count, ",
+            key_columns
+        ));
+        // Relevance only orders results with words to rank by, which are ranked in `find_fuzzy`
+        select.push("0 AS rank FROM indexes i WHERE 1");
+        push_words(&mut select, query, field);
+        push_conditions(&mut select, query, folder);
+        select.push(") WHERE 1");
+
+        let (direction, comparison) = match parameters.order {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+        // Pages cut by a cursor start right after it, others skip `offset` visible entries
+        let mut skipped = if cursor.is_some() { 0 } else { parameters.offset.unwrap_or(0) as usize };
+        if let Some(values) = cursor {
+            select.push(format!(" AND ({}) {} (", keys.join(", "), comparison));
+            let mut separated = select.separated(", ");
+            for value in values {
+                match value {
+                    Value::String(value) => separated.push_bind(value),
+                    value => separated.push_bind(value.as_i64()),
+                };
+            }
+            select.push(")");
+        }
+        select.push(format!(" ORDER BY {}", keys.iter().map(|key| format!("{} {}", key, direction)).collect::<Vec<_>>().join(", ")));
+
+        let mut results = Vec::with_capacity(limit);
+        let mut next_cursor = None;
+        let mut rows = select.build().fetch(&pool);
+        while let Some(row) = rows.try_next().await? {
+            let Some(path) = scope.visible_path(Path::new(row.get::<&str, _>("path")), row.get("is_dir")) else {
+                continue;
+            };
+            if skipped > 0 {
+                skipped -= 1;
+                continue;
+            }
+            results.push(IndexerData {
+                path,
+                filename: row.get("filename"),
+                mtime: row.get::<i64, _>("mtime") as u64,
+                ctime: row.get::<i64, _>("ctime") as u64,
+                size: row.get::<i64, _>("size") as u64,
+                is_dir: row.get("is_dir"),
+                file_count: row.get::<i64, _>("file_count") as u64,
+                score: None,
+            });
+            if results.len() == limit {
+                let values = (0..keys.len())
+                    .map(|index| {
+                        let column = format!("key{}", index);
+                        row.try_get::<i64, _>(column.as_str())
+                            .map(Value::from)
+                            .or_else(|_| row.try_get::<String, _>(column.as_str()).map(Value::from))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                next_cursor = Some(encode_cursor(&values));
+                break;
+            }
+        }
 
-            let field = if filename_only { "filename" } else { "path" };
-            let exact_results = sqlx::query_as::<_, IndexerData>(&format!(r#"SELECT * FROM indexes WHERE {} LIKE ? LIMIT 100"#, field))
-                .bind(&exact_pattern)
-                .fetch_all(&pool)
-                .await?;
+        Ok(SearchPage { results, total, next_cursor, truncated: false })
+    }
 
-            if !exact_results.is_empty() {
-                return Ok(exact_results);
+    /// Ranks the entries sharing trigrams with the words of `query`, or containing them, by how well they match,
+    /// so that misspelled searches still find what was meant.
+    ///
+    /// Only the [`FUZZY_CANDIDATES`] entries sharing the most trigrams are ranked, a page is marked as truncated
+    /// when there were more.
+    async fn find_fuzzy(
+        query: &SearchQuery,
+        folder: Option<&Path>,
+        parameters: &SearchParameters,
+        cursor: Option<Vec<Value>>,
+        pool: &SqlitePool,
+    ) -> anyhow::Result<SearchPage> {
+        let field = if parameters.filename_only { "filename" } else { "path" };
+        let limit = parameters.limit.unwrap_or(100).clamp(1, 1000) as usize;
+        let mut trigrams = query.words.iter().flat_map(|word| generate_trigrams(word)).collect::<Vec<_>>();
+        trigrams.sort();
+        trigrams.dedup();
+
+        let mut similar = QueryBuilder::<Sqlite>::new(
+            "SELECT i.path, i.filename, i.mtime, i.ctime, i.size, i.is_dir, i.file_count FROM path_trigrams pt JOIN indexes i ON i.id = pt.path_id WHERE pt.trigram IN (",
+        );
+        let mut separated = similar.separated(", ");
+        for trigram in &trigrams {
+            separated.push_bind(trigram);
+        }
+        similar.push(")");
+        push_conditions(&mut similar, query, folder);
+        similar.push(" GROUP BY i.id ORDER BY COUNT(DISTINCT pt.trigram) DESC LIMIT ").push_bind(FUZZY_CANDIDATES as i64);
+        let mut candidates = similar.build_query_as::<IndexerData>().fetch_all(pool).await?;
+        let truncated = candidates.len() >= FUZZY_CANDIDATES;
+
+        // Words too short to have trigrams only match where they appear as they are
+        let mut containing =
+            QueryBuilder::<Sqlite>::new("SELECT i.path, i.filename, i.mtime, i.ctime, i.size, i.is_dir, i.file_count FROM indexes i WHERE 1");
+        push_words(&mut containing, query, field);
+        push_conditions(&mut containing, query, folder);
+        containing.push(" LIMIT ").push_bind(FUZZY_CANDIDATES as i64);
+        for entry in containing.build_query_as::<IndexerData>().fetch_all(pool).await? {
+            if !candidates.iter().any(|candidate| candidate.path == entry.path) {
+                candidates.push(entry);
             }
         }
 
-        // Create a query that finds paths with the most matching trigrams
-        let sql_condition = if filename_only {
-            // Join with indexes to filter by filename trigrams
-            "JOIN indexes i ON pt.path_id = i.id"
-        } else {
-            // Join with indexes without additional filtering
-            "JOIN indexes i ON pt.path_id = i.id"
+        // Candidates are ranked by the path clients see, entries filtered out since they were indexed or outside of
+        // the published tree aren't ranked at all
+        let scope = index_scope();
+        let candidates = candidates
+            .into_iter()
+            .filter_map(|mut candidate| {
+                candidate.path = scope.visible_path(Path::new(&candidate.path), candidate.is_dir)?;
+                Some(candidate)
+            })
+            .collect::<Vec<_>>();
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default();
+        let mut ranked = fuzzy_rank::rank(candidates, &query.words, parameters.filename_only, now);
+        if parameters.order == SortOrder::Desc {
+            ranked.reverse();
+        }
+
+        // The keys match those of `SearchSort::Relevance`, so cursors keep working the same way
+        let key = |entry: &IndexerData| {
+            vec![
+                Value::from(-(entry.score.unwrap_or_default() * 1000.0).round() as i64),
+                Value::from(entry.path.chars().count() as i64),
+                Value::from(entry.path.clone()),
+            ]
         };
+        let start = match cursor {
+            Some(cursor) => ranked
+                .iter()
+                .position(|entry| {
+                    let ordering = compare_keys(&key(entry), &cursor);
+                    if parameters.order == SortOrder::Desc { ordering.is_lt() } else { ordering.is_gt() }
+                })
+                .unwrap_or(ranked.len()),
+            None => (parameters.offset.unwrap_or(0) as usize).min(ranked.len()),
+        };
+        let total = ranked.len();
+        let results = ranked.into_iter().skip(start).take(limit).collect::<Vec<_>>();
+        let next_cursor = results.last().filter(|_| start + results.len() < total).map(|last| encode_cursor(&key(last)));
 
-        // Create placeholders for the IN clause
-        let placeholders = vec!["?"; search_trigrams.len()].join(",");
-
-        let query_str = format!(
-            r#"SELECT i.path, i.filename, i.mtime, i.ctime, i.size, COUNT(DISTINCT pt.trigram) as match_count
-            FROM path_trigrams pt
-            {}
-            WHERE pt.trigram IN ({})
-            GROUP BY pt.path_id
-            ORDER BY match_count DESC, 
-                     CASE WHEN i.{} LIKE ? THEN 1 ELSE 0 END DESC,
-                     LENGTH(i.{})
-            LIMIT 100"#,
-            sql_condition,
-            placeholders,
-            if filename_only { "filename" } else { "path" },
-            if filename_only { "filename" } else { "path" }
-        );
+        Ok(SearchPage { results, total: total as u64, next_cursor, truncated })
+    }
+}
 
-        // Build the query with all trigram parameters
-        let mut query = sqlx::query(&query_str);
+/// Compares the sort keys of two results, numbers and strings alike.
+fn compare_keys(a: &[Value], b: &[Value]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| match (a, b) {
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (a, b) => a.as_i64().cmp(&b.as_i64()),
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
 
-        // Bind all trigrams as parameters
-        for trigram in &search_trigrams {
-            query = query.bind(trigram);
+impl IndexerData {
+    /// Finds the entries whose path, as clients see it, or filename matches `pattern`.
+    ///
+    /// Candidates are narrowed down to the entries holding every trigram of the literal parts of the pattern
+    /// before the pattern is applied. The scan gives up after [`PATTERN_SEARCH_TIMEOUT`] or
+    /// [`MAX_PATTERN_MATCHES`], returning the matches found so far as a truncated page.
+    pub async fn find_matching(pattern: &PathPattern, parameters: &SearchParameters) -> anyhow::Result<SearchPage> {
+        let pool = create_pool().await?;
+        let limit = parameters.limit.unwrap_or(100).clamp(1, 1000) as usize;
+        let offset = parameters.offset.unwrap_or(0) as usize;
+
+        let mount_names = Configuration::get().mounts.iter().map(|mount| mount.name.clone()).collect::<Vec<_>>();
+        let mut trigrams = pattern.required_literals(&mount_names).iter().flat_map(|literal| generate_trigrams(literal)).collect::<Vec<_>>();
+        trigrams.sort();
+        trigrams.dedup();
+
+        let mut select = QueryBuilder::<Sqlite>::new("SELECT path, filename, mtime, ctime, size, is_dir, file_count FROM indexes");
+        if !trigrams.is_empty() {
+            select.push(" WHERE id IN (SELECT path_id FROM path_trigrams WHERE trigram IN (");
+            let mut separated = select.separated(", ");
+            for trigram in &trigrams {
+                separated.push_bind(trigram);
+            }
+            select.push(") GROUP BY path_id HAVING COUNT(DISTINCT trigram) = ").push_bind(trigrams.len() as i64).push(")");
         }
+        // Patterns have no rank, so the sort keys are used without it: sorting by relevance orders by path length
+        // then path, the other sorts by their own keys
+        let direction = if parameters.order == SortOrder::Desc { "DESC" } else { "ASC" };
+        let keys = parameters.sort.keys().iter().filter(|key| **key != "rank").map(|key| format!("{} {}", key, direction)).collect::<Vec<_>>();
+        select.push(format!(" ORDER BY {}", keys.join(", ")));
+
+        let mut results = Vec::with_capacity(limit);
+        let mut total = 0;
+        let scope = index_scope();
+        let scan = async {
+            let mut rows = select.build_query_as::<IndexerData>().fetch(&pool);
+            while let Some(mut entry) = rows.try_next().await? {
+                let Some(path) = scope.visible_path(Path::new(&entry.path), entry.is_dir) else {
+                    continue;
+                };
+                if !pattern.matches(if parameters.filename_only { &entry.filename } else { &path }) {
+                    continue;
+                }
+                if total >= offset && results.len() < limit {
+                    entry.path = path;
+                    results.push(entry);
+                }
+                total += 1;
+                if total >= MAX_PATTERN_MATCHES {
+                    return anyhow::Ok(true);
+                }
+            }
+            anyhow::Ok(false)
+        };
+        let truncated = tokio::time::timeout(PATTERN_SEARCH_TIMEOUT, scan).await.unwrap_or(Ok(true))?;
 
-        // Bind the LIKE pattern for prioritizing direct matches
-        query = query.bind(format!("%{}%", search_term));
+        Ok(SearchPage { results, total: total as u64, next_cursor: None, truncated })
+    }
+}
 
-        // Execute the query
-        let rows = query.fetch_all(&pool).await?;
+fn push_words(builder: &mut QueryBuilder<Sqlite>, query: &SearchQuery, field: &str) {
+    for word in &query.words {
+        builder.push(format!(" AND i.{} LIKE ", field)).push_bind(format!("%{}%", escape_like(word))).push(r#" ESCAPE '\'"#);
+    }
+}
 
-        // Convert the results to IndexerData structs
-        let mut results = Vec::with_capacity(rows.len());
-        for row in rows {
-            let indexer_data = IndexerData {
-                path: row.get("path"),
-                filename: row.get("filename"),
-                mtime: row.get::<i64, _>("mtime") as u64,
-                ctime: row.get::<i64, _>("ctime") as u64,
-                size: row.get::<i64, _>("size") as u64,
-            };
-            results.push(indexer_data);
+/// Adds the filters of `query`, everything but its words.
+fn push_conditions(builder: &mut QueryBuilder<Sqlite>, query: &SearchQuery, folder: Option<&Path>) {
+    if !query.extensions.is_empty() {
+        builder.push(" AND i.is_dir = 0 AND (0");
+        for extension in &query.extensions {
+            builder.push(" OR i.filename LIKE ").push_bind(format!("%.{}", escape_like(extension))).push(r#" ESCAPE '\'"#);
+        }
+        builder.push(")");
+    }
+    for (column, bounds) in [("size", query.size), ("mtime", query.modified), ("ctime", query.created)] {
+        if let Some(min) = bounds.min {
+            builder.push(format!(" AND i.{} >= ", column)).push_bind(min as i64);
+        }
+        if let Some(max) = bounds.max {
+            builder.push(format!(" AND i.{} < ", column)).push_bind(max as i64);
+        }
+    }
+    if let Some(folder) = folder {
+        let folder = folder.to_string_lossy().replace('\\', "/");
+        builder.push(" AND i.path LIKE ").push_bind(format!("{}/%", escape_like(folder.trim_end_matches('/')))).push(r#" ESCAPE '\'"#);
+    }
+    match query.kind {
+        Some(EntryKind::File) => {
+            builder.push(" AND i.is_dir = 0");
+        }
+        Some(EntryKind::Directory) => {
+            builder.push(" AND i.is_dir = 1");
         }
+        None => {}
+    }
+}
 
-        // If we didn't get many results from trigram search, fallback to LIKE
-        if results.len() < 5 {
-            let like_pattern = format!("%{}%", search_term);
-            let field = if filename_only { "filename" } else { "path" };
+/// The bounds of the paths below the directory `path`, as `path >= start AND path < end`. Unlike `LIKE`, the range
+/// is case-sensitive and can use the index on `path`.
+pub(crate) fn descendant_range(path: &str) -> (String, String) {
+    let path = path.trim_end_matches('/');
+    // `0` is the character after `/`
+    (format!("{}/", path), format!("{}0", path))
+}
 
-            let like_results = sqlx::query_as::<_, IndexerData>(&format!(r#"SELECT * FROM indexes WHERE {} LIKE ? LIMIT 100"#, field))
-                .bind(&like_pattern)
-                .fetch_all(&pool)
-                .await?;
+/// [`IndexerData::rename_tree_with_pool`] as part of a larger transaction.
+pub(crate) async fn rename_tree_in(from: &str, to: &str, connection: &mut SqliteConnection) -> anyhow::Result<Vec<IndexerData>> {
+    delete_tree_in(to, &mut *connection).await?;
 
-            // Combine results, removing duplicates
-            for item in like_results {
-                if !results.iter().any(|x| x.path == item.path) {
-                    results.push(item);
-                }
-            }
+    // The files move from the totals of the directories `from` is in to those of the directories `to` is in
+    let (size, files) = tree_totals_in(from, &mut *connection).await?;
+    add_to_directories_in(from, -size, -files, &mut *connection).await?;
+
+    let (start, end) = descendant_range(from);
+    let prefix_length = from.trim_end_matches('/').chars().count() as i64;
+    let filename = Path::new(to).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    sqlx::query(
+        r#"UPDATE indexes SET path = ? || substr(path, ? + 1), filename = CASE WHEN path = ? THEN ? ELSE filename END
+           WHERE path = ? OR (path >= ? AND path < ?)"#,
+    )
+    .bind(to.trim_end_matches('/'))
+    .bind(prefix_length)
+    .bind(from)
+    .bind(&filename)
+    .bind(from)
+    .bind(&start)
+    .bind(&end)
+    .execute(&mut *connection)
+    .await?;
+    content_db::rename_tree_in(from, to, &mut *connection).await?;
+    add_to_directories_in(to, size, files, &mut *connection).await?;
+
+    // The trigrams of the old paths would still find the entries under them
+    let (start, end) = descendant_range(to);
+    sqlx::query(r#"DELETE FROM path_trigrams WHERE path_id IN (SELECT id FROM indexes WHERE path = ? OR (path >= ? AND path < ?))"#)
+        .bind(to)
+        .bind(&start)
+        .bind(&end)
+        .execute(&mut *connection)
+        .await?;
+    let moved = sqlx::query_as::<_, IndexerData>(r#"SELECT * FROM indexes WHERE path = ? OR (path >= ? AND path < ?)"#)
+        .bind(to)
+        .bind(&start)
+        .bind(&end)
+        .fetch_all(&mut *connection)
+        .await?;
+    for data in &moved {
+        insert_trigrams(data, &mut *connection).await?;
+    }
+    Ok(moved)
+}
+
+/// Removes the entries of `paths` as part of a larger transaction.
+pub(crate) async fn delete_all_in(paths: &[String], connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    for path in paths {
+        // The files below a directory are left, along with the totals they add to
+        let file_size: Option<i64> =
+            sqlx::query_scalar(r#"SELECT size FROM indexes WHERE path = ? AND is_dir = 0"#).bind(path).fetch_optional(&mut *connection).await?;
+        sqlx::query(r#"DELETE FROM path_trigrams WHERE path_id IN (SELECT id FROM indexes WHERE path = ?)"#)
+            .bind(path)
+            .execute(&mut *connection)
+            .await?;
+        sqlx::query(r#"DELETE FROM indexes WHERE path = ?"#).bind(path).execute(&mut *connection).await?;
+        content_db::delete_in(path, &mut *connection).await?;
+        if let Some(size) = file_size {
+            add_to_directories_in(path, -size, -1, &mut *connection).await?;
         }
+    }
+    Ok(())
+}
+
+/// Removes the entries of `path` and everything below it as part of a larger transaction, returning how many there
+/// were.
+pub(crate) async fn delete_tree_in(path: &str, connection: &mut SqliteConnection) -> anyhow::Result<u64> {
+    let (size, files) = tree_totals_in(path, &mut *connection).await?;
+    add_to_directories_in(path, -size, -files, &mut *connection).await?;
+
+    let (start, end) = descendant_range(path);
+    sqlx::query(r#"DELETE FROM path_trigrams WHERE path_id IN (SELECT id FROM indexes WHERE path = ? OR (path >= ? AND path < ?))"#)
+        .bind(path)
+        .bind(&start)
+        .bind(&end)
+        .execute(&mut *connection)
+        .await?;
+    let removed = sqlx::query(r#"DELETE FROM indexes WHERE path = ? OR (path >= ? AND path < ?)"#)
+        .bind(path)
+        .bind(&start)
+        .bind(&end)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+    content_db::delete_tree_in(path, &mut *connection).await?;
+    Ok(removed)
+}
+
+/// Indexes `data` as part of a larger transaction, replacing the entry at its path, and adds it to the totals of the
+/// indexed directories it is in. A directory keeps the totals it had, or gets those of the files indexed below it when
+/// it is new.
+pub(crate) async fn insert_in(data: &IndexerData, connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    let previous: Option<(i64, i64, bool, i64)> = sqlx::query_as(r#"SELECT id, size, is_dir, file_count FROM indexes WHERE path = ?"#)
+        .bind(&data.path)
+        .fetch_optional(&mut *connection)
+        .await?;
+    // Replaced rows get a new id, so their trigrams would be left pointing at nothing
+    if let Some((id, ..)) = previous {
+        sqlx::query(r#"DELETE FROM path_trigrams WHERE path_id = ?"#).bind(id).execute(&mut *connection).await?;
+    }
 
-        Ok(results)
+    let (size, file_count) = match previous {
+        _ if !data.is_dir => (data.size as i64, 0),
+        Some((_, size, true, file_count)) => (size, file_count),
+        _ => directory_totals_in(&data.path, &mut *connection).await?,
+    };
+    sqlx::query(r#"INSERT OR REPLACE INTO indexes (path, filename, mtime, ctime, size, is_dir, file_count) VALUES (?, ?, ?, ?, ?, ?, ?)"#)
+        .bind(&data.path)
+        .bind(&data.filename)
+        .bind(data.mtime as i64)
+        .bind(data.ctime as i64)
+        .bind(size)
+        .bind(data.is_dir)
+        .bind(file_count)
+        .execute(&mut *connection)
+        .await?;
+    insert_trigrams(data, &mut *connection).await?;
+
+    let previous_file = previous.filter(|(_, _, is_dir, _)| !is_dir).map(|(_, size, ..)| size);
+    let (size, files) = match (data.is_dir, previous_file) {
+        (false, Some(previous)) => (size - previous, 0),
+        (false, None) => (size, 1),
+        (true, Some(previous)) => (-previous, -1),
+        (true, None) => (0, 0),
+    };
+    add_to_directories_in(&data.path, size, files, connection).await
+}
+
+/// The total size and number of the files indexed below the directory `path`.
+async fn directory_totals_in(path: &str, connection: &mut SqliteConnection) -> anyhow::Result<(i64, i64)> {
+    let (start, end) = descendant_range(path);
+    let totals = sqlx::query_as(r#"SELECT COALESCE(SUM(size), 0), COUNT(*) FROM indexes WHERE is_dir = 0 AND path >= ? AND path < ?"#)
+        .bind(&start)
+        .bind(&end)
+        .fetch_one(&mut *connection)
+        .await?;
+    Ok(totals)
+}
+
+/// The total size and number of the files indexed at `path` and below it.
+async fn tree_totals_in(path: &str, connection: &mut SqliteConnection) -> anyhow::Result<(i64, i64)> {
+    let (start, end) = descendant_range(path);
+    let totals =
+        sqlx::query_as(r#"SELECT COALESCE(SUM(size), 0), COUNT(*) FROM indexes WHERE is_dir = 0 AND (path = ? OR (path >= ? AND path < ?))"#)
+            .bind(path)
+            .bind(&start)
+            .bind(&end)
+            .fetch_one(&mut *connection)
+            .await?;
+    Ok(totals)
+}
+
+/// Adds `size` and `files` to the totals of the indexed directories `path` is in.
+async fn add_to_directories_in(path: &str, size: i64, files: i64, connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    let directories = Path::new(path).ancestors().skip(1).filter(|directory| directory.file_name().is_some()).collect::<Vec<_>>();
+    if (size == 0 && files == 0) || directories.is_empty() {
+        return Ok(());
     }
+
+    let mut update = QueryBuilder::<Sqlite>::new("UPDATE indexes SET size = size + ");
+    update.push_bind(size).push(", file_count = file_count + ").push_bind(files).push(" WHERE is_dir = 1 AND path IN (");
+    let mut separated = update.separated(", ");
+    for directory in directories {
+        separated.push_bind(directory.to_string_lossy().replace('\\', "/"));
+    }
+    update.push(")");
+    update.build().execute(&mut *connection).await?;
+    Ok(())
+}
+
+/// Escapes the wildcards of a `LIKE` pattern, to be used with `ESCAPE '\'`.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Indexes the trigrams of the path and filename of `data`, which has to be in the `indexes` table already.
+pub(crate) async fn insert_trigrams(data: &IndexerData, connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    let id: i64 = sqlx::query_scalar(r#"SELECT id FROM indexes WHERE path = ?"#).bind(&data.path).fetch_one(&mut *connection).await?;
+
+    let mut trigrams = generate_trigrams(&data.path);
+    trigrams.extend(generate_trigrams(&data.filename));
+    trigrams.sort();
+    trigrams.dedup();
+
+    let mut insert = QueryBuilder::<Sqlite>::new("INSERT INTO path_trigrams (path_id, trigram) ");
+    insert.push_values(&trigrams, |mut values, trigram| {
+        values.push_bind(id).push_bind(trigram);
+    });
+    insert.build().execute(&mut *connection).await?;
+    Ok(())
 }
 
 // Function to generate trigrams from a string
@@ -348,14 +746,12 @@ fn generate_trigrams(text: &str) -> Vec<String> {
 }
 
 // Function to rebuild the trigram index for existing data
-pub async fn rebuild_trigram_index() -> anyhow::Result<()> {
-    let pool = create_pool().await?;
-
+pub async fn rebuild_trigram_index(pool: &SqlitePool) -> anyhow::Result<()> {
     // Clear existing trigrams
-    sqlx::query("DELETE FROM path_trigrams").execute(&pool).await?;
+    sqlx::query("DELETE FROM path_trigrams").execute(pool).await?;
 
     // Get all indexes data
-    let rows = sqlx::query("SELECT id, path, filename FROM indexes").fetch_all(&pool).await?;
+    let rows = sqlx::query("SELECT id, path, filename FROM indexes").fetch_all(pool).await?;
 
     // Process in batches of 1000 to avoid excessive memory usage
     let batch_size = 1000;
@@ -0,0 +1,219 @@
+use crate::configuration::configuration_data::WatcherMode;
+use crate::io::fs::indexer::indexer_data::ReconcileReport;
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+static INDEXER_STATE: OnceLock<IndexerState> = OnceLock::new();
+
+/// The shared state of the indexer, which runs one reconciliation at a time.
+pub fn indexer_state() -> &'static IndexerState {
+    INDEXER_STATE.get_or_init(|| IndexerState {
+        status: watch::Sender::new(IndexerStatus::default()),
+        running: AtomicBool::new(false),
+        cancelled: AtomicBool::new(false),
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexerPhase {
+    #[default]
+    Idle,
+    /// Walking the index roots, adding and updating entries.
+    Scanning,
+    /// Removing the entries of files that are gone.
+    Pruning,
+    /// Hashing the files that may have copies, when content hashing is enabled.
+    Hashing,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// The progress of the current indexer run, or the outcome of the last one.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexerStatus {
+    pub phase: IndexerPhase,
+    pub paused: bool,
+    pub files_scanned: usize,
+    /// Files added to or updated in the index.
+    pub files_indexed: usize,
+    pub errors: usize,
+    /// The path, as clients see it, of the file being looked at.
+    pub current_path: Option<String>,
+    /// Files scanned per second, not counting the time spent paused.
+    pub rate: f64,
+    /// The seconds left, estimated from the number of entries the index held when the run started.
+    pub eta_seconds: Option<u64>,
+    /// When the run started, in seconds since the epoch.
+    pub started_at: Option<u64>,
+    /// What the last completed run changed.
+    pub report: Option<ReconcileReport>,
+    pub error: Option<String>,
+    /// The directories the file watcher follows.
+    pub watchers: Vec<WatchedRoot>,
+}
+
+/// A directory the file watcher follows, and how.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WatchedRoot {
+    /// The path of the directory, as clients see it.
+    pub path: String,
+    /// The mode the directory is watched in, `off` when it couldn't be watched.
+    pub mode: WatcherMode,
+    /// The seconds between two scans, in `poll` mode.
+    pub poll_interval: Option<u64>,
+}
+
+pub struct IndexerState {
+    status: watch::Sender<IndexerStatus>,
+    running: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+/// Held for the duration of an indexer run, letting the next one start when dropped.
+pub struct IndexerRun {
+    started: Instant,
+    /// Time spent paused, left out of the rate.
+    paused_for: Duration,
+    /// The number of files the index held at the start, which the ETA is estimated from.
+    pub expected_files: usize,
+}
+
+impl IndexerState {
+    /// Starts a run, or returns `None` when one is running already.
+    pub fn try_start(&self) -> Option<IndexerRun> {
+        if self.running.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            return None;
+        }
+        self.cancelled.store(false, Ordering::SeqCst);
+        let started_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).ok();
+        self.status.send_modify(|status| {
+            *status = IndexerStatus {
+                phase: IndexerPhase::Scanning,
+                started_at,
+                report: status.report.clone(),
+                watchers: status.watchers.clone(),
+                ..IndexerStatus::default()
+            };
+        });
+        Some(IndexerRun { started: Instant::now(), paused_for: Duration::ZERO, expected_files: 0 })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> IndexerStatus {
+        self.status.borrow().clone()
+    }
+
+    /// A receiver that is notified of every change of the status.
+    pub fn subscribe(&self) -> watch::Receiver<IndexerStatus> {
+        self.status.subscribe()
+    }
+
+    /// Pauses the running run at the next file, returning whether one is running.
+    pub fn pause(&self) -> bool {
+        self.set_paused(true)
+    }
+
+    pub fn resume(&self) -> bool {
+        self.set_paused(false)
+    }
+
+    fn set_paused(&self, paused: bool) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        self.status.send_modify(|status| status.paused = paused);
+        true
+    }
+
+    /// Stops the running run at the next file, returning whether one is running.
+    pub fn cancel(&self) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        self.cancelled.store(true, Ordering::SeqCst);
+        // Wakes a paused run up, so it notices
+        self.status.send_modify(|status| status.paused = false);
+        true
+    }
+
+    /// Waits until no run is in progress.
+    pub async fn wait_idle(&self) {
+        let mut receiver = self.subscribe();
+        while self.is_running() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Records the progress of `run`, waiting while it is paused. Returns `false` when the run was cancelled and has
+    /// to stop.
+    pub async fn checkpoint(&self, run: &mut IndexerRun, update: impl FnOnce(&mut IndexerStatus)) -> bool {
+        self.status.send_modify(|status| {
+            update(status);
+            let elapsed = run.started.elapsed().saturating_sub(run.paused_for).as_secs_f64();
+            status.rate = if elapsed > 0.0 { status.files_scanned as f64 / elapsed } else { 0.0 };
+            status.eta_seconds = (status.rate > 0.0 && run.expected_files > status.files_scanned)
+                .then(|| ((run.expected_files - status.files_scanned) as f64 / status.rate) as u64);
+        });
+
+        if self.status.borrow().paused {
+            let paused_at = Instant::now();
+            let mut receiver = self.subscribe();
+            while receiver.borrow_and_update().paused {
+                if receiver.changed().await.is_err() {
+                    break;
+                }
+            }
+            run.paused_for += paused_at.elapsed();
+        }
+        !self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn set_watchers(&self, watchers: Vec<WatchedRoot>) {
+        self.status.send_modify(|status| status.watchers = watchers);
+    }
+
+    pub fn set_phase(&self, phase: IndexerPhase) {
+        self.status.send_modify(|status| status.phase = phase);
+    }
+
+    /// Ends `run` with `outcome`, recording the report of a completed run.
+    pub fn finish(&self, run: IndexerRun, outcome: Result<ReconcileReport, String>) {
+        let cancelled = self.cancelled.swap(false, Ordering::SeqCst);
+        self.status.send_modify(|status| {
+            status.paused = false;
+            status.current_path = None;
+            status.eta_seconds = None;
+            match outcome {
+                Ok(_) if cancelled => status.phase = IndexerPhase::Cancelled,
+                Ok(report) => {
+                    status.phase = IndexerPhase::Completed;
+                    status.report = Some(report);
+                }
+                Err(error) => {
+                    status.phase = IndexerPhase::Failed;
+                    status.error = Some(error);
+                }
+            }
+        });
+        drop(run);
+    }
+}
+
+impl Drop for IndexerRun {
+    fn drop(&mut self) {
+        let state = indexer_state();
+        state.running.store(false, Ordering::SeqCst);
+        // Wakes up whoever waits for the run to end
+        state.status.send_modify(|_| {});
+    }
+}
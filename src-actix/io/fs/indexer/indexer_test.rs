@@ -0,0 +1,495 @@
+#[cfg(test)]
+mod tests {
+    use crate::configuration::configuration_data::Configuration;
+    use crate::io::fs::indexer::path_filter::PathFilter;
+    use crate::io::fs::indexer::{indexer_data, indexer_db};
+    use notify::{Event, EventKind};
+    use sqlx::SqlitePool;
+    use std::path::Path;
+    use tempfile::{TempDir, tempdir};
+
+    /// A directory with an empty index in memory, for the tests applying watcher events to it.
+    struct WatchedDirectory {
+        dir: TempDir,
+        root: String,
+        config: Configuration,
+        filter: PathFilter,
+        pool: SqlitePool,
+    }
+
+    impl WatchedDirectory {
+        async fn new() -> Self {
+            let dir = tempdir().unwrap();
+            let root = dir.path().to_string_lossy().replace('\\', "/");
+            let config = Configuration {
+                root_path: root.clone(),
+                filter: vec![],
+                exclude_hidden_files: false,
+                content_indexing_enabled: true,
+                ..Configuration::default()
+            };
+            let filter = PathFilter::compile(&config).unwrap();
+            let pool = SqlitePool::connect("sqlite::memory:").await.expect("Failed to open database");
+            indexer_db::initialize_with_pool(&pool).await.unwrap();
+            Self { dir, root, config, filter, pool }
+        }
+
+        /// An event of `kind` for `paths`, relative to the directory.
+        fn event(&self, kind: EventKind, paths: &[&str]) -> Event {
+            paths.iter().fold(Event::new(kind), |event, path| event.add_path(self.dir.path().join(path)))
+        }
+
+        async fn apply(&self, events: Vec<Event>) -> anyhow::Result<usize> {
+            indexer_data::apply_events(events, &self.config, &self.filter, &self.pool).await
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_indexer_controls() {
+        use crate::io::fs::indexer::indexer_state::{IndexerPhase, indexer_state};
+
+        let state = indexer_state();
+        assert!(!state.pause() && !state.cancel());
+        let mut run = state.try_start().unwrap();
+        // Only one run at a time
+        assert!(state.try_start().is_none());
+
+        assert!(state.checkpoint(&mut run, |status| status.files_scanned = 1).await);
+        assert!(state.pause());
+        {
+            let checkpoint = state.checkpoint(&mut run, |status| status.files_scanned = 2);
+            tokio::pin!(checkpoint);
+            // A paused run waits at its next checkpoint until it is resumed or cancelled
+            assert!(tokio::time::timeout(std::time::Duration::from_millis(50), &mut checkpoint).await.is_err());
+            assert!(state.cancel());
+            assert!(!checkpoint.await);
+        }
+
+        state.finish(run, Ok(Default::default()));
+        assert_eq!(state.status().phase, IndexerPhase::Cancelled);
+        assert_eq!(state.status().files_scanned, 2);
+        state.try_start().map(|run| state.finish(run, Ok(Default::default()))).unwrap();
+        assert_eq!(state.status().phase, IndexerPhase::Completed);
+        assert!(state.status().report.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_watcher_renames_and_directories() {
+        use notify::EventKind;
+        use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+
+        let watched = WatchedDirectory::new().await;
+        let (dir, root, pool) = (&watched.dir, &watched.root, &watched.pool);
+        let count = |table: &'static str| {
+            let pool = pool.clone();
+            async move { sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {}", table)).fetch_one(&pool).await.unwrap() }
+        };
+        let paths = || {
+            let pool = pool.clone();
+            async move {
+                let mut paths: Vec<(String, String)> =
+                    sqlx::query_as("SELECT path, filename FROM indexes WHERE is_dir = 0").fetch_all(&pool).await.unwrap();
+                paths.sort();
+                paths
+            }
+        };
+
+        // A new directory is indexed along with the files already in it
+        std::fs::create_dir_all(dir.path().join("notes/drafts")).unwrap();
+        std::fs::write(dir.path().join("notes/todo.txt"), "water the plants").unwrap();
+        std::fs::write(dir.path().join("notes/drafts/letter.txt"), "dear reader").unwrap();
+        watched.apply(vec![watched.event(EventKind::Create(CreateKind::Folder), &["notes"])]).await.unwrap();
+        assert_eq!(paths().await.len(), 2);
+        assert_eq!(count("content_files").await, 2);
+
+        // The two sides of a rename move the whole tree, followed by both at once as inotify does. The content of a
+        // file created earlier in the batch is indexed at its new path.
+        std::fs::write(dir.path().join("notes/ideas.txt"), "plant more trees").unwrap();
+        std::fs::rename(dir.path().join("notes"), dir.path().join("journal")).unwrap();
+        watched
+            .apply(vec![
+                watched.event(EventKind::Create(CreateKind::File), &["notes/ideas.txt"]),
+                watched.event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), &["notes"]),
+                watched.event(EventKind::Modify(ModifyKind::Name(RenameMode::To)), &["journal"]),
+                watched.event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["notes", "journal"]),
+            ])
+            .await
+            .unwrap();
+        let moved = [
+            (format!("{}/journal/drafts/letter.txt", root), "letter.txt".to_string()),
+            (format!("{}/journal/ideas.txt", root), "ideas.txt".to_string()),
+            (format!("{}/journal/todo.txt", root), "todo.txt".to_string()),
+        ];
+        assert_eq!(paths().await, moved);
+        let content_paths: Vec<String> = sqlx::query_scalar("SELECT path FROM content_files ORDER BY path").fetch_all(pool).await.unwrap();
+        assert_eq!(content_paths, moved.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>());
+        // The trigrams follow the new paths, of the files and of the two directories
+        let trigram_paths: i64 =
+            sqlx::query_scalar("SELECT COUNT(DISTINCT path_id) FROM path_trigrams WHERE trigram = 'jou'").fetch_one(pool).await.unwrap();
+        assert_eq!(trigram_paths, 5);
+
+        // A renamed file gets its new name
+        std::fs::rename(dir.path().join("journal/todo.txt"), dir.path().join("journal/done.txt")).unwrap();
+        watched
+            .apply(vec![watched.event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["journal/todo.txt", "journal/done.txt"])])
+            .await
+            .unwrap();
+        assert!(paths().await.contains(&(format!("{}/journal/done.txt", root), "done.txt".to_string())));
+
+        // A source without a destination was moved out of the watched directories
+        watched
+            .apply(vec![
+                watched.event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), &["journal/drafts"]),
+                watched.event(EventKind::Modify(ModifyKind::Any), &["journal/done.txt"]),
+            ])
+            .await
+            .unwrap();
+        watched.apply(vec![watched.event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), &["journal/ideas.txt"])]).await.unwrap();
+        assert_eq!(paths().await, [(format!("{}/journal/done.txt", root), "done.txt".to_string())]);
+
+        // A removed directory takes everything below it along
+        std::fs::remove_dir_all(dir.path().join("journal")).unwrap();
+        watched.apply(vec![watched.event(EventKind::Remove(RemoveKind::Folder), &["journal"])]).await.unwrap();
+        assert!(paths().await.is_empty());
+        assert_eq!(count("path_trigrams").await, 0);
+        assert_eq!(count("content_files").await, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_directory_totals() {
+        use notify::EventKind;
+        use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode};
+
+        let watched = WatchedDirectory::new().await;
+        let (dir, root, pool) = (&watched.dir, &watched.root, &watched.pool);
+        let totals = |path: &'static str| {
+            let pool = pool.clone();
+            let path = format!("{}/{}", root, path);
+            async move {
+                sqlx::query_as::<_, (i64, i64)>("SELECT size, file_count FROM indexes WHERE path = ? AND is_dir = 1")
+                    .bind(path)
+                    .fetch_optional(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        // A new directory is indexed with the files already in it, directories adding up everything below them
+        std::fs::create_dir_all(dir.path().join("photos/2024")).unwrap();
+        std::fs::write(dir.path().join("photos/cover.jpg"), [0; 100]).unwrap();
+        std::fs::write(dir.path().join("photos/2024/beach.jpg"), [0; 1000]).unwrap();
+        watched.apply(vec![watched.event(EventKind::Create(CreateKind::Folder), &["photos"])]).await.unwrap();
+        assert_eq!(totals("photos").await, Some((1100, 2)));
+        assert_eq!(totals("photos/2024").await, Some((1000, 1)));
+
+        // A changed file changes the totals by the difference
+        std::fs::write(dir.path().join("photos/2024/beach.jpg"), [0; 400]).unwrap();
+        watched.apply(vec![watched.event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &["photos/2024/beach.jpg"])]).await.unwrap();
+        assert_eq!(totals("photos").await, Some((500, 2)));
+
+        // A moved directory takes its totals from its old parents to its new ones
+        std::fs::create_dir(dir.path().join("archive")).unwrap();
+        watched.apply(vec![watched.event(EventKind::Create(CreateKind::Folder), &["archive"])]).await.unwrap();
+        std::fs::rename(dir.path().join("photos/2024"), dir.path().join("archive/2024")).unwrap();
+        watched.apply(vec![watched.event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["photos/2024", "archive/2024"])]).await.unwrap();
+        assert_eq!(totals("photos").await, Some((100, 1)));
+        assert_eq!(totals("archive").await, Some((400, 1)));
+        assert_eq!(totals("archive/2024").await, Some((400, 1)));
+
+        // Removed files and directories are taken off
+        std::fs::remove_file(dir.path().join("photos/cover.jpg")).unwrap();
+        std::fs::remove_dir_all(dir.path().join("archive/2024")).unwrap();
+        watched
+            .apply(vec![
+                watched.event(EventKind::Remove(RemoveKind::File), &["photos/cover.jpg"]),
+                watched.event(EventKind::Remove(RemoveKind::Folder), &["archive/2024"]),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(totals("photos").await, Some((0, 0)));
+        assert_eq!(totals("archive").await, Some((0, 0)));
+        assert_eq!(totals("archive/2024").await, None);
+    }
+
+    #[actix_web::test]
+    async fn test_duplicate_files() {
+        use crate::io::fs::indexer::duplicate_data::{DuplicateParameters, HashCandidate, full_hash, partial_hash};
+        use crate::io::fs::indexer::indexer_data::IndexerData;
+        use crate::io::fs::indexer::{duplicate_db, indexer_db};
+        use sqlx::SqlitePool;
+
+        let dir = tempdir().unwrap();
+        let candidate = |name: &str| {
+            let data = IndexerData::from_path(&dir.path().join(name)).unwrap();
+            HashCandidate { path: data.path, size: data.size as i64, mtime: data.mtime as i64 }
+        };
+        let hash = |name: &str, complete: bool| {
+            let candidate = candidate(name);
+            let path = dir.path().join(name);
+            if complete { full_hash(&path, &candidate) } else { partial_hash(&path, &candidate) }.unwrap().unwrap()
+        };
+
+        // A small file is hashed whole the first time
+        std::fs::write(dir.path().join("small.txt"), "the same words").unwrap();
+        let small = hash("small.txt", false);
+        assert!(small.complete);
+        assert_eq!(small.hash, hash("small.txt", true).hash);
+
+        // Large files differing in the middle only share their partial hash
+        let mut content = vec![7u8; 512 * 1024];
+        std::fs::write(dir.path().join("a.bin"), &content).unwrap();
+        std::fs::write(dir.path().join("b.bin"), &content).unwrap();
+        content[256 * 1024] = 8;
+        std::fs::write(dir.path().join("c.bin"), &content).unwrap();
+        assert!(!hash("a.bin", false).complete);
+        assert_eq!(hash("a.bin", false).hash, hash("c.bin", false).hash);
+        assert_eq!(hash("a.bin", true).hash, hash("b.bin", true).hash);
+        assert_ne!(hash("a.bin", true).hash, hash("c.bin", true).hash);
+
+        // A file that changed since it was indexed isn't hashed
+        let stale = HashCandidate { size: 1, ..candidate("a.bin") };
+        assert!(full_hash(&dir.path().join("a.bin"), &stale).unwrap().is_none());
+
+        // Groups are sorted by the space they waste, hardlinks of a file counting once
+        let pool = SqlitePool::connect("sqlite::memory:").await.expect("Failed to open database");
+        indexer_db::initialize_with_pool(&pool).await.unwrap();
+        for (path, size, hash, file_id) in [
+            ("/media/a.mp4", 100, "aaa", "1:1"),
+            ("/media/b.mp4", 100, "aaa", "1:2"),
+            ("/backup/a.mp4", 100, "aaa", "1:3"),
+            ("/media/c.jpg", 10, "ccc", "1:4"),
+            ("/media/c-link.jpg", 10, "ccc", "1:4"),
+            ("/media/d.jpg", 10, "ddd", "1:5"),
+            ("/backup/d.jpg", 10, "ddd", "1:6"),
+            ("/media/e.part", 50, "eee", "1:7"),
+            ("/backup/e.part", 50, "eee", "1:8"),
+            ("/media/f.mp4", 50, "fff", "1:9"),
+            ("/media/f.part", 50, "fff", "1:10"),
+        ] {
+            sqlx::query("INSERT INTO indexes (path, filename, mtime, ctime, size, hash, file_id) VALUES (?, '', 0, 0, ?, ?, ?)")
+                .bind(path)
+                .bind(size)
+                .bind(hash)
+                .bind(file_id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        // Filtered files are no copies, the same scope as the listing tests so they don't race
+        indexer_data::set_index_scope(Configuration { filter: vec!["**/*.part".to_string()], ..Configuration::default() });
+        let page = duplicate_db::find_duplicates_with_pool(&DuplicateParameters::default(), None, &pool).await.unwrap();
+        assert_eq!((page.total, page.reclaimable), (2, 210));
+        assert_eq!(page.groups.iter().map(|group| (group.hash.as_str(), group.reclaimable)).collect::<Vec<_>>(), [("aaa", 200), ("ddd", 10)]);
+        assert_eq!(page.groups[0].files.len(), 3);
+
+        // Pages are cut once the filtered files are left out
+        let parameters = DuplicateParameters { limit: Some(1), offset: Some(1), ..DuplicateParameters::default() };
+        let page = duplicate_db::find_duplicates_with_pool(&parameters, None, &pool).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.groups.iter().map(|group| group.hash.as_str()).collect::<Vec<_>>(), ["ddd"]);
+
+        // Only files inside the prefix and above the minimum size are grouped
+        let parameters = DuplicateParameters { min_size: Some(50), ..DuplicateParameters::default() };
+        let page = duplicate_db::find_duplicates_with_pool(&parameters, Some(Path::new("/media")), &pool).await.unwrap();
+        assert_eq!((page.total, page.reclaimable), (1, 100));
+        assert_eq!(page.groups[0].files.iter().map(|file| file.path.as_str()).collect::<Vec<_>>(), ["/media/a.mp4", "/media/b.mp4"]);
+    }
+
+    #[test]
+    fn test_watcher_event_coalescing() {
+        use crate::io::fs::indexer::watcher_batch::coalesce;
+        use notify::event::{AccessKind, CreateKind, ModifyKind, RemoveKind, RenameMode};
+        use notify::{Event, EventKind};
+
+        let event = |kind: EventKind, paths: &[&str]| paths.iter().fold(Event::new(kind), |event, path| event.add_path(path.into()));
+        let modify = EventKind::Modify(ModifyKind::Any);
+        let coalesced = coalesce(vec![
+            event(EventKind::Create(CreateKind::File), &["/a.txt"]),
+            event(modify, &["/a.txt"]),
+            event(modify, &["/b.txt"]),
+            event(EventKind::Access(AccessKind::Any), &["/b.txt"]),
+            event(modify, &["/b.txt", "/c.txt"]),
+            event(modify, &["/d.txt"]),
+            event(EventKind::Remove(RemoveKind::File), &["/d.txt"]),
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["/b.txt", "/e.txt"]),
+            event(modify, &["/e.txt"]),
+            event(modify, &["/e.txt"]),
+        ]);
+        let summary =
+            coalesced.iter().map(|event| (event.kind, event.paths.iter().map(|path| path.to_str().unwrap()).collect::<Vec<_>>())).collect::<Vec<_>>();
+
+        // One event per path and change, keeping the last position of each, with renames in between left in place
+        assert_eq!(
+            summary,
+            [
+                (EventKind::Create(CreateKind::File), vec!["/a.txt"]),
+                (modify, vec!["/b.txt"]),
+                (modify, vec!["/c.txt"]),
+                (EventKind::Remove(RemoveKind::File), vec!["/d.txt"]),
+                (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), vec!["/b.txt", "/e.txt"]),
+                (modify, vec!["/e.txt"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_poller_scan() {
+        use crate::io::fs::indexer::directory_poller::{Tree, scan};
+
+        let dir = tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("notes/drafts")).unwrap();
+        std::fs::write(dir.path().join("notes/todo.txt"), "water").unwrap();
+        let mut tree = Tree::new();
+        let mut scan_events = || {
+            let mut events = Vec::new();
+            scan(dir.path(), &mut tree, false, &mut |event| {
+                events.push(format!("{:?} {}", event.kind, event.paths[0].strip_prefix(dir.path()).unwrap().display()))
+            });
+            events.sort();
+            events
+        };
+
+        // The first listing reports nothing
+        assert!(scan_events().is_empty());
+
+        // A new directory is reported without its content, a changed file by its size or modification time
+        std::fs::write(dir.path().join("notes/todo.txt"), "water the plants").unwrap();
+        std::fs::create_dir_all(dir.path().join("photos/2024")).unwrap();
+        std::fs::write(dir.path().join("photos/2024/beach.jpg"), "jpeg").unwrap();
+        assert_eq!(scan_events(), ["Create(Folder) photos", "Modify(Data(Any)) notes/todo.txt"]);
+
+        std::fs::remove_dir_all(dir.path().join("notes")).unwrap();
+        assert_eq!(scan_events(), ["Remove(Folder) notes"]);
+        assert!(scan_events().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use std::path::Path;
+
+    #[test]
+    fn test_content_search_helpers() {
+        use crate::configuration::configuration_data::Configuration;
+        use crate::io::fs::indexer::content_data::{ContentChunk, ContentMatch, chunks, fts_query, is_indexable};
+
+        let config = Configuration { included_extensions: vec![".md".to_string(), ".jpg".to_string()], ..Configuration::default() };
+        assert!(is_indexable(Path::new("/docs/README.MD"), 1024, &config));
+        assert!(!is_indexable(Path::new("/docs/notes.txt"), 1024, &config));
+        assert!(!is_indexable(Path::new("/photos/cat.jpg"), 1024, &config));
+        assert!(!is_indexable(Path::new("/docs/huge.md"), config.content_max_file_size + 1, &config));
+
+        // Blank chunks are left out but keep their place, so line numbers stay right
+        let text = format!("{}{}needle\n", "first\n".repeat(50), "\n".repeat(50));
+        let chunks = chunks(&text);
+        assert_eq!(chunks.iter().map(|chunk| chunk.index).collect::<Vec<_>>(), [0, 2]);
+        assert_eq!(ContentChunk::start_line(chunks[1].index), 101);
+
+        assert_eq!(fts_query(r#"foo "bar OR"#), r#""foo" """bar" "OR""#);
+
+        let found = ContentMatch::new("/docs/a.md".to_string(), 101, "…say\n\u{1}hello\u{2} world", "line one\nsay\n\u{1}hello\u{2} world");
+        assert_eq!(found.filename, "a.md");
+        assert_eq!(found.line, 103);
+        assert_eq!(found.snippet, "…say hello world");
+        assert_eq!(found.highlights, [(5, 10)]);
+    }
+
+    #[test]
+    fn test_search_query() {
+        use crate::io::fs::indexer::search_query::{Bounds, EntryKind, SearchQuery, SearchSort, decode_cursor, encode_cursor};
+
+        let query =
+            SearchQuery::parse(r#"report ext:PDF,.docx size:>10MB size:<=1GB modified:2025-01-01 in:"/My Projects" type:file "a:b" c:d"#).unwrap();
+        assert_eq!(query.words, ["report", "a:b", "c:d"]);
+        assert_eq!(query.extensions, ["pdf", "docx"]);
+        assert_eq!(query.size, Bounds { min: Some(10 * 1024 * 1024 + 1), max: Some(1024 * 1024 * 1024 + 1) });
+        assert_eq!(query.modified, Bounds { min: Some(1735689600), max: Some(1735689600 + 86400) });
+        assert_eq!(query.folder.as_deref(), Some("/My Projects"));
+        assert_eq!(query.kind, Some(EntryKind::File));
+
+        let query = SearchQuery::parse("created:2024-01-01..2024-12-31 size:1.5kb..").unwrap();
+        assert_eq!(query.created, Bounds { min: Some(1704067200), max: Some(1735689600) });
+        assert_eq!(query.size, Bounds { min: Some(1536), max: None });
+
+        assert!(SearchQuery::parse("size:big").is_err());
+        assert!(SearchQuery::parse("modified:<yesterday").is_err());
+        assert!(SearchQuery::parse("type:link").is_err());
+
+        let cursor = encode_cursor(&[serde_json::json!(42), serde_json::json!("/docs/a.txt")]);
+        assert!(decode_cursor(&cursor, SearchSort::Size).is_ok());
+        assert!(decode_cursor(&cursor, SearchSort::Relevance).is_err());
+        assert!(decode_cursor("not a cursor", SearchSort::Size).is_err());
+    }
+
+    #[test]
+    fn test_pattern_search() {
+        use crate::io::fs::indexer::pattern_search::PathPattern;
+        use crate::io::fs::indexer::search_query::SearchMode;
+
+        let mounts = ["Media".to_string()];
+        let glob = PathPattern::new("**/invoice-*.pdf", SearchMode::Glob).unwrap();
+        assert!(glob.matches("/billing/2025/INVOICE-17.pdf"));
+        assert!(!glob.matches("/billing/invoice-17.pdf.bak"));
+        assert_eq!(glob.required_literals(&mounts), ["invoice-", ".pdf"]);
+        // The mount name is not part of the indexed OS path, so it can't narrow down the candidates
+        let glob = PathPattern::new("/Media/movies/*.mkv", SearchMode::Glob).unwrap();
+        assert_eq!(glob.required_literals(&mounts), ["movies", ".mkv"]);
+
+        let regex = PathPattern::new(r"inv(oice)?-\d+\.pdf$", SearchMode::Regex).unwrap();
+        assert!(regex.matches("/billing/inv-17.pdf"));
+        assert_eq!(regex.required_literals(&mounts), ["inv", ".pdf"]);
+        assert!(PathPattern::new(r"^/(docs|notes)/", SearchMode::Regex).unwrap().required_literals(&mounts).is_empty());
+
+        assert!(PathPattern::new("(unclosed", SearchMode::Regex).is_err());
+        assert!(PathPattern::new("[", SearchMode::Glob).is_err());
+    }
+
+    #[test]
+    fn test_fuzzy_ranking() {
+        use crate::io::fs::indexer::fuzzy_rank::rank;
+        use crate::io::fs::indexer::indexer_data::IndexerData;
+
+        let now = 1_750_000_000;
+        let year_ago = now - 365 * 24 * 60 * 60;
+        let entry = |path: &str, mtime: u64| IndexerData {
+            path: path.to_string(),
+            filename: path.rsplit('/').next().unwrap().to_string(),
+            size: 0,
+            mtime,
+            ctime: mtime,
+            is_dir: false,
+            file_count: 0,
+            score: None,
+        };
+        let entries = vec![
+            entry("/receipts/scan.jpg", year_ago),
+            entry("/docs/report.pdf", year_ago),
+            entry("/docs/receipts.txt", year_ago),
+            entry("/docs/new-receipts.pdf", year_ago),
+            entry("/docs/receipts.pdf", year_ago),
+        ];
+        let terms = |query: &str| query.split_whitespace().map(String::from).collect::<Vec<_>>();
+        let paths = |ranked: Vec<IndexerData>| ranked.into_iter().map(|entry| entry.path).collect::<Vec<_>>();
+
+        // Exact names first, then names containing the term, then directories holding it, equal scores by path
+        let ranked = rank(entries.clone(), &terms("receipts"), false, now);
+        assert_eq!(paths(ranked.clone()), ["/docs/receipts.pdf", "/docs/receipts.txt", "/docs/new-receipts.pdf", "/receipts/scan.jpg"]);
+        assert!(ranked.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        // The order doesn't depend on the order the candidates came in
+        let mut reversed = entries.clone();
+        reversed.reverse();
+        assert_eq!(paths(rank(reversed, &terms("receipts"), false, now)), paths(ranked));
+
+        // Typos still find the file, an exact extension and a recent change move it up
+        let ranked = paths(rank(entries.clone(), &terms("recieptes"), false, now));
+        assert_eq!(ranked.first().map(String::as_str), Some("/docs/receipts.pdf"));
+        assert_eq!(paths(rank(entries.clone(), &terms("receipts pdf"), false, now)), ["/docs/receipts.pdf", "/docs/new-receipts.pdf"]);
+        let mut recent = entries.clone();
+        recent[2].mtime = now;
+        assert_eq!(paths(rank(recent, &terms("receipts"), false, now))[0], "/docs/receipts.txt");
+
+        // Directories don't count when searching filenames only
+        assert!(!paths(rank(entries, &terms("receipts"), true, now)).contains(&"/receipts/scan.jpg".to_string()));
+    }
+}
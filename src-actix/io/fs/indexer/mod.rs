@@ -1,2 +1,15 @@
+pub mod content_data;
+pub mod content_db;
+pub mod directory_poller;
+pub mod duplicate_data;
+pub mod duplicate_db;
+pub mod fuzzy_rank;
 pub mod indexer_data;
 pub mod indexer_db;
+pub mod indexer_state;
+#[cfg(test)]
+mod indexer_test;
+pub mod path_filter;
+pub mod pattern_search;
+pub mod search_query;
+pub mod watcher_batch;
@@ -0,0 +1,103 @@
+use crate::configuration::configuration_data::{Configuration, MountConfiguration};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::warn;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The `filter` patterns of a configuration and of its mounts, compiled once so that a path is checked against all
+/// of them in a single pass.
+///
+/// The configuration is passed along to the checks, it has to be the one the filter was compiled from.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    global: GlobSet,
+    /// The patterns of each mount by its name, matched against paths relative to the mount.
+    mounts: HashMap<String, GlobSet>,
+}
+
+/// A pattern of the configuration that isn't a valid glob.
+#[derive(Debug)]
+pub struct InvalidPattern {
+    /// The configuration field holding the pattern, `filter` or `mounts`.
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl PathFilter {
+    /// Compiles the patterns of `config`, failing on the first one that isn't a valid glob.
+    pub fn compile(config: &Configuration) -> Result<Self, InvalidPattern> {
+        Self::build(config, false)
+    }
+
+    /// Compiles the patterns of `config`, leaving out the ones that aren't valid globs with a warning, as a
+    /// configuration file edited by hand may have them.
+    pub fn new(config: &Configuration) -> Self {
+        Self::build(config, true).unwrap_or_else(|e| {
+            warn!("Ignoring the filter patterns: {}", e.message);
+            Self { global: GlobSet::empty(), mounts: HashMap::new() }
+        })
+    }
+
+    fn build(config: &Configuration, skip_invalid: bool) -> Result<Self, InvalidPattern> {
+        let global = compile_set(&config.filter, skip_invalid).map_err(|message| InvalidPattern { field: "filter", message })?;
+        let mut mounts = HashMap::with_capacity(config.mounts.len());
+        for mount in &config.mounts {
+            let set = compile_set(&mount.filter, skip_invalid)
+                .map_err(|message| InvalidPattern { field: "mounts", message: format!("{} in the filter of {}", message, mount.name) })?;
+            mounts.insert(mount.name.clone(), set);
+        }
+        Ok(Self { global, mounts })
+    }
+
+    /// Whether the file or directory at `path` belongs in the index.
+    ///
+    /// Paths on a mount are checked against the mount's own settings, everything else against the global filter.
+    pub fn includes(&self, path: &Path, config: &Configuration) -> bool {
+        // Check if the file is hidden and should be excluded
+        let is_hidden = path.file_name().and_then(|name| name.to_str()).map(|name| name.starts_with(".")).unwrap_or(false);
+        if config.exclude_hidden_files && is_hidden {
+            return false;
+        }
+
+        if let Some(mount) = config.mount_for(path) {
+            return mount.indexing && !self.matches_mount(path, mount, config);
+        }
+
+        // A whitelist keeps the matching paths, a blacklist drops them
+        self.global.is_match(path.to_string_lossy().replace('\\', "/")) == config.filter_mode_whitelist
+    }
+
+    /// Whether `path` is filtered out, and left out of listings and search results along with the index.
+    ///
+    /// Hidden files and mounts that aren't indexed are only kept out of the index, so they are not hidden here. A
+    /// whitelist doesn't hide directories either, as they may hold the files it keeps.
+    pub fn hides(&self, path: &Path, is_dir: bool, config: &Configuration) -> bool {
+        if let Some(mount) = config.mount_for(path) {
+            return self.matches_mount(path, mount, config);
+        }
+        let matches_pattern = self.global.is_match(path.to_string_lossy().replace('\\', "/"));
+        if config.filter_mode_whitelist { !matches_pattern && !is_dir } else { matches_pattern }
+    }
+
+    fn matches_mount(&self, path: &Path, mount: &MountConfiguration, config: &Configuration) -> bool {
+        let Some(patterns) = self.mounts.get(&mount.name) else {
+            return false;
+        };
+        let relative = path.strip_prefix(mount.os_path(&config.root_path)).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        patterns.is_match(relative)
+    }
+}
+
+fn compile_set(patterns: &[String], skip_invalid: bool) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) if skip_invalid => warn!("Ignoring the invalid filter pattern {:?}: {}", pattern, e),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    builder.build().map_err(|e| e.to_string())
+}
@@ -0,0 +1,110 @@
+use crate::helpers::http_error::{Error, Result};
+use crate::io::fs::indexer::search_query::SearchMode;
+use glob::MatchOptions;
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Hir, HirKind};
+use std::time::Duration;
+
+/// How long a glob or regex search may scan the index before it returns what it found so far.
+pub const PATTERN_SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most matches a glob or regex search counts before it stops looking.
+pub const MAX_PATTERN_MATCHES: usize = 10_000;
+
+/// The largest a compiled regex may get, so a pattern can't make the server allocate without bounds.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions { case_sensitive: false, require_literal_separator: true, require_literal_leading_dot: false };
+
+/// A glob or regex matched against paths as clients see them, or against filenames.
+pub enum PathPattern {
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str, mode: SearchMode) -> Result<Self> {
+        let invalid = |error: String| Error::validation_error(format!("Invalid pattern: {}", error), Some("q"));
+        match mode {
+            SearchMode::Regex => {
+                RegexBuilder::new(pattern).size_limit(REGEX_SIZE_LIMIT).build().map(Self::Regex).map_err(|error| invalid(error.to_string()))
+            }
+            _ => glob::Pattern::new(pattern).map(Self::Glob).map_err(|error| invalid(error.to_string())),
+        }
+    }
+
+    /// Whether all of `text` matches a glob, or any part of it matches a regex.
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Glob(pattern) => pattern.matches_with(text, GLOB_OPTIONS),
+            Self::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    /// Substrings of at least three characters that every match contains, for narrowing down candidates with the
+    /// trigram index before the pattern is applied.
+    ///
+    /// The index holds OS paths, which share everything but the first segment with the paths clients see when
+    /// that segment is a mount. Parts that could stand for a mount name are left out, as the OS path may not
+    /// contain them.
+    pub fn required_literals(&self, mount_names: &[String]) -> Vec<String> {
+        let literals = match self {
+            Self::Glob(pattern) => glob_literals(pattern.as_str()),
+            Self::Regex(regex) => {
+                let mut literals = Vec::new();
+                if let Ok(hir) = regex_syntax::parse(regex.as_str()) {
+                    let mut current = String::new();
+                    hir_literals(&hir, &mut literals, &mut current);
+                    literals.push(current);
+                }
+                literals
+            }
+        };
+
+        let mount_names = mount_names.iter().map(|name| name.to_lowercase()).collect::<Vec<_>>();
+        literals
+            .iter()
+            .flat_map(|literal| literal.split('/'))
+            .filter(|part| part.chars().count() >= 3)
+            .filter(|part| !mount_names.iter().any(|name| name.contains(&part.to_lowercase())))
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// The runs of literal characters between the wildcards of a glob.
+fn glob_literals(pattern: &str) -> Vec<String> {
+    let mut literals = vec![String::new()];
+    let mut in_class = false;
+    for character in pattern.chars() {
+        match character {
+            '[' => in_class = true,
+            ']' if in_class => in_class = false,
+            _ if in_class => continue,
+            '*' | '?' => {}
+            character => {
+                literals.last_mut().unwrap().push(character);
+                continue;
+            }
+        }
+        literals.push(String::new());
+    }
+    literals
+}
+
+/// Collects the runs of literal text a match of `hir` has to contain, `current` being the run in progress.
+fn hir_literals(hir: &Hir, literals: &mut Vec<String>, current: &mut String) {
+    match hir.kind() {
+        HirKind::Literal(literal) => current.push_str(&String::from_utf8_lossy(&literal.0)),
+        // Anchors and empty matches take no room, so the text on either side is adjacent
+        HirKind::Look(_) | HirKind::Empty => {}
+        HirKind::Capture(capture) => hir_literals(&capture.sub, literals, current),
+        HirKind::Concat(subs) => subs.iter().for_each(|sub| hir_literals(sub, literals, current)),
+        // What is repeated at least once is there, but what precedes and follows it may not be adjacent to it
+        HirKind::Repetition(repetition) if repetition.min > 0 => {
+            literals.push(std::mem::take(current));
+            hir_literals(&repetition.sub, literals, current);
+            literals.push(std::mem::take(current));
+        }
+        _ => literals.push(std::mem::take(current)),
+    }
+}
@@ -0,0 +1,254 @@
+use crate::helpers::http_error::{Error, Result};
+use crate::io::fs::indexer::indexer_data::IndexerData;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The query parameters of the search endpoint.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchParameters {
+    /// The search, in the syntax parsed by [`SearchQuery::parse`].
+    pub q: Option<String>,
+    #[serde(default)]
+    pub filename_only: bool,
+    #[serde(default)]
+    pub mode: SearchMode,
+    #[serde(default)]
+    pub sort: SearchSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    /// The `next_cursor` of the previous page, which takes precedence over `offset`.
+    pub cursor: Option<String>,
+}
+
+/// How `q` is read: as words and filters, or as a single glob or regex matched against the path or filename.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    #[default]
+    Text,
+    Glob,
+    Regex,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Name,
+    Size,
+    Modified,
+    Created,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// A `[min, max)` range, open on the sides that are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+/// A search split into the words to look for and the filters results have to pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// Words, or quoted phrases, that all have to appear in the path or the filename.
+    pub words: Vec<String>,
+    /// Lowercase extensions without the dot, any of which results have to have.
+    pub extensions: Vec<String>,
+    pub size: Bounds,
+    /// The range of modification times, in seconds since the epoch.
+    pub modified: Bounds,
+    /// The range of creation times, in seconds since the epoch.
+    pub created: Bounds,
+    /// The folder results have to be in, as clients see it.
+    pub folder: Option<String>,
+    pub kind: Option<EntryKind>,
+}
+
+/// A page of search results.
+#[derive(Debug, Serialize)]
+pub struct SearchPage {
+    pub results: Vec<IndexerData>,
+    /// The number of results across all pages.
+    pub total: u64,
+    /// Passed as `cursor` to get the next page, missing on the last one.
+    pub next_cursor: Option<String>,
+    /// Whether the search stopped before looking at every entry, making `total` a lower bound.
+    pub truncated: bool,
+}
+
+impl SearchQuery {
+    /// Parses a search such as `report ext:pdf,docx size:>10MB modified:<2025-01-01 in:/projects type:file`.
+    ///
+    /// Sizes take a `B`, `KB`, `MB`, `GB` or `TB` unit and dates are `YYYY-MM-DD` in UTC. Both can be compared with
+    /// `>`, `>=`, `<` or `<=`, given as a range such as `size:1MB..5MB`, or matched exactly, a date covering the
+    /// whole day. Quote words with spaces, as in `in:"/My Documents"`. Words with an unknown `key:` prefix, or that
+    /// are quoted entirely, are searched for as they are.
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut search = Self::default();
+        for (token, quoted) in tokenize(query) {
+            let Some((key, value)) = token.split_once(':').filter(|_| !quoted) else {
+                search.words.push(token);
+                continue;
+            };
+            match key.to_lowercase().as_str() {
+                "ext" => search.extensions.extend(
+                    value
+                        .split(',')
+                        .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+                        .filter(|extension| !extension.is_empty()),
+                ),
+                "size" => search.size = search.size.intersect(parse_bounds(value, parse_size).ok_or_else(|| invalid("size", value))?),
+                "modified" => search.modified = search.modified.intersect(parse_bounds(value, parse_date).ok_or_else(|| invalid("modified", value))?),
+                "created" => search.created = search.created.intersect(parse_bounds(value, parse_date).ok_or_else(|| invalid("created", value))?),
+                "in" if !value.is_empty() => search.folder = Some(value.to_string()),
+                "in" => return Err(invalid("in", value)),
+                "type" => {
+                    search.kind = Some(match value.to_lowercase().as_str() {
+                        "file" => EntryKind::File,
+                        "dir" | "directory" | "folder" => EntryKind::Directory,
+                        _ => return Err(invalid("type", value)),
+                    })
+                }
+                _ => search.words.push(token),
+            }
+        }
+        Ok(search)
+    }
+
+    /// Whether the query has no words and no filters, matching everything.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl SearchSort {
+    /// The columns results are ordered by, ending with the path so that the order is total.
+    pub fn keys(self) -> &'static [&'static str] {
+        match self {
+            Self::Relevance => &["rank", "length(path)", "path"],
+            Self::Name => &["lower(filename)", "path"],
+            Self::Size => &["size", "path"],
+            Self::Modified => &["mtime", "path"],
+            Self::Created => &["ctime", "path"],
+        }
+    }
+}
+
+/// Encodes the sort keys of the last result of a page into an opaque cursor.
+pub fn encode_cursor(values: &[Value]) -> String {
+    BASE64.encode(serde_json::to_vec(values).unwrap_or_default())
+}
+
+/// Decodes a cursor made by [`encode_cursor`] for results ordered by `sort`.
+pub fn decode_cursor(cursor: &str, sort: SearchSort) -> Result<Vec<Value>> {
+    let values: Vec<Value> = BASE64
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .filter(|values: &Vec<Value>| values.len() == sort.keys().len() && values.iter().all(|value| value.is_string() || value.is_i64()))
+        .ok_or_else(|| Error::validation_error("Invalid cursor, it may be from a search with another sort", Some("cursor")))?;
+    Ok(values)
+}
+
+impl Bounds {
+    /// The range covered by both `self` and `other`.
+    fn intersect(self, other: Self) -> Self {
+        Self { min: self.min.max(other.min), max: [self.max, other.max].into_iter().flatten().min() }
+    }
+}
+
+fn invalid(field: &str, value: &str) -> Error {
+    Error::validation_error(format!("Invalid {} filter: {:?}", field, value), Some(field))
+}
+
+/// Splits `query` on whitespace outside of double quotes, dropping the quotes and telling whether a token
+/// started with one.
+fn tokenize(query: &str) -> Vec<(String, bool)> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    for character in query.chars() {
+        match character {
+            '"' => {
+                quoted |= token.is_empty();
+                in_quotes = !in_quotes;
+            }
+            character if character.is_whitespace() && !in_quotes => {
+                if !token.is_empty() {
+                    tokens.push((std::mem::take(&mut token), quoted));
+                }
+                quoted = false;
+            }
+            character => token.push(character),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push((token, quoted));
+    }
+    tokens
+}
+
+/// Parses a comparison or range of values into [`Bounds`], where `parse` gives the `[start, end)` span a single
+/// value covers.
+fn parse_bounds(value: &str, parse: fn(&str) -> Option<(u64, u64)>) -> Option<Bounds> {
+    if let Some((from, to)) = value.split_once("..") {
+        let min = if from.is_empty() { None } else { Some(parse(from)?.0) };
+        let max = if to.is_empty() { None } else { Some(parse(to)?.1) };
+        return Some(Bounds { min, max });
+    }
+
+    let (operator, value) =
+        [">=", "<=", ">", "<"].into_iter().find_map(|operator| value.strip_prefix(operator).map(|value| (operator, value))).unwrap_or(("", value));
+    let (start, end) = parse(value)?;
+    Some(match operator {
+        ">" => Bounds { min: Some(end), max: None },
+        ">=" => Bounds { min: Some(start), max: None },
+        "<" => Bounds { min: None, max: Some(start) },
+        "<=" => Bounds { min: None, max: Some(end) },
+        _ => Bounds { min: Some(start), max: Some(end) },
+    })
+}
+
+/// Parses a size such as `10MB` or `1.5 GiB`.
+fn parse_size(value: &str) -> Option<(u64, u64)> {
+    let value = value.trim();
+    let split = value.find(|character: char| !character.is_ascii_digit() && character != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return None,
+    };
+    let size = (number.parse::<f64>().ok()? * multiplier as f64) as u64;
+    Some((size, size + 1))
+}
+
+/// Parses a `YYYY-MM-DD` date into the seconds of the day it covers.
+fn parse_date(value: &str) -> Option<(u64, u64)> {
+    let start = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0)?.and_utc().timestamp().max(0) as u64;
+    Some((start, start + 24 * 60 * 60))
+}
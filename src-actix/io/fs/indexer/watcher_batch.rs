@@ -0,0 +1,163 @@
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How long the file watcher waits for more events after the last one before it applies a batch.
+pub const QUIET_PERIOD: Duration = Duration::from_millis(250);
+/// The longest a batch is collected for, so that a steady stream of changes is still applied as it comes.
+pub const MAX_BATCH_DELAY: Duration = Duration::from_secs(2);
+/// The most events collected into one batch.
+pub const MAX_BATCH_SIZE: usize = 10_000;
+/// How many events can wait to be batched before new ones are dropped.
+pub const QUEUE_CAPACITY: usize = 100_000;
+
+static WATCHER_STATS: WatcherStats = WatcherStats {
+    received: AtomicU64::new(0),
+    processed: AtomicU64::new(0),
+    coalesced: AtomicU64::new(0),
+    dropped: AtomicU64::new(0),
+    failed: AtomicU64::new(0),
+    batches: AtomicU64::new(0),
+    overflowed: AtomicBool::new(false),
+};
+
+/// The counters of the file watcher since the server started.
+pub fn watcher_stats() -> &'static WatcherStats {
+    &WATCHER_STATS
+}
+
+pub struct WatcherStats {
+    received: AtomicU64,
+    processed: AtomicU64,
+    coalesced: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+    batches: AtomicU64,
+    /// Whether events were dropped since the last batch, which leaves the index to be reconciled.
+    overflowed: AtomicBool,
+}
+
+/// The counters of [`WatcherStats`] at one point in time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WatcherCounters {
+    /// Events reported by the watchers.
+    pub received: u64,
+    /// Events applied to the index, after coalescing.
+    pub processed: u64,
+    /// Events that were collapsed into another one for the same path, or that don't affect the index.
+    pub coalesced: u64,
+    /// Events lost because the queue was full.
+    pub dropped: u64,
+    /// Events that couldn't be applied.
+    pub failed: u64,
+    pub batches: u64,
+}
+
+/// The state of the file watcher, as returned by the watcher endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatcherStatus {
+    pub running: bool,
+    pub watched_directories: usize,
+    /// Events waiting to be batched.
+    pub queued: usize,
+    #[serde(flatten)]
+    pub counters: WatcherCounters,
+}
+
+impl WatcherStats {
+    pub fn received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.overflowed.store(true, Ordering::SeqCst);
+    }
+
+    /// Records a batch of `collected` events, coalesced into `processed` events of which `failed` couldn't be applied.
+    pub fn batch(&self, collected: usize, processed: usize, failed: usize) {
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.processed.fetch_add(processed as u64, Ordering::Relaxed);
+        self.coalesced.fetch_add(collected.saturating_sub(processed) as u64, Ordering::Relaxed);
+        self.failed.fetch_add(failed as u64, Ordering::Relaxed);
+    }
+
+    /// Whether events were dropped since the last time this was called.
+    pub fn take_overflowed(&self) -> bool {
+        self.overflowed.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn counters(&self) -> WatcherCounters {
+        WatcherCounters {
+            received: self.received.load(Ordering::Relaxed),
+            processed: self.processed.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            batches: self.batches.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// What an event does to its path, as far as the index is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Change {
+    Create,
+    Modify,
+    Remove,
+}
+
+/// Collapses the events of a batch to one per path and change, in the order they have to be applied in.
+///
+/// Files are read from disk when the batch is applied, so of repeated changes to a path only the last one is kept,
+/// and changes to a path that is removed later in the batch are dropped. Events of several paths are split into one
+/// event per path. Renames change what the paths of the events after them refer to, so events are only collapsed
+/// with the ones since the last rename.
+pub fn coalesce(events: Vec<Event>) -> Vec<Event> {
+    let mut coalesced: Vec<Option<Event>> = Vec::with_capacity(events.len());
+    // Where the last change of each path since the last rename is in `coalesced`
+    let mut pending: HashMap<(PathBuf, Change), usize> = HashMap::new();
+    for event in events {
+        let change = match event.kind {
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                pending.clear();
+                coalesced.push(Some(event));
+                continue;
+            }
+            EventKind::Create(_) => Change::Create,
+            EventKind::Modify(_) => Change::Modify,
+            EventKind::Remove(_) => Change::Remove,
+            // Accesses and events of unknown kind leave the index as it is
+            _ => continue,
+        };
+
+        for path in event.paths {
+            match change {
+                // A created file is indexed as it is once the batch is applied, changes after that add nothing
+                Change::Modify if pending.contains_key(&(path.clone(), Change::Create)) => continue,
+                Change::Remove => {
+                    for earlier in [Change::Create, Change::Modify] {
+                        if let Some(index) = pending.remove(&(path.clone(), earlier)) {
+                            coalesced[index] = None;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if let Some(index) = pending.insert((path.clone(), change), coalesced.len()) {
+                coalesced[index] = None;
+            }
+            coalesced.push(Some(Event { kind: event.kind, paths: vec![path], attrs: event.attrs.clone() }));
+        }
+    }
+    coalesced.into_iter().flatten().collect()
+}
+
+/// Whether `event` is the source of a rename, whose destination is reported as a separate event.
+pub fn is_rename_source(event: &Event) -> bool {
+    matches!(event.kind, EventKind::Modify(ModifyKind::Name(RenameMode::From)))
+}
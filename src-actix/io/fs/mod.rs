@@ -1,9 +1,12 @@
 mod filesystem_data;
 pub(crate) mod filesystem_endpoint;
 
+pub mod archive_options;
 pub mod archive_wrapper;
 mod download_parameters;
 #[cfg(test)]
 mod filesystem_test;
 pub mod indexer;
+#[cfg(test)]
+mod mounts_test;
 pub mod normalize_path;
@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use crate::configuration::configuration_data::{Configuration, LocalMountConfiguration, MountConfiguration, MountStorage, WatcherMode};
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    /// An indexed mount of the local directory `path`, with the global settings.
+    fn local_mount(name: &str, path: &str) -> MountConfiguration {
+        MountConfiguration {
+            name: name.to_string(),
+            read_only: false,
+            indexing: true,
+            filter: Vec::new(),
+            watcher_mode: None,
+            watcher_poll_interval: None,
+            storage: MountStorage::Local(LocalMountConfiguration { path: path.to_string() }),
+        }
+    }
+
+    #[test]
+    fn test_mount_paths() {
+        use crate::io::fs::normalize_path::{mounted_path, resolve_path, virtual_path_in};
+
+        let config = Configuration {
+            root_path: "/srv/files".to_string(),
+            mounts: vec![MountConfiguration { read_only: true, ..local_mount("Media", "/mnt/media") }],
+            ..Configuration::default()
+        };
+
+        assert_eq!(mounted_path("/Media/movies/a.mkv", &config), Some(PathBuf::from("/mnt/media/movies/a.mkv")));
+        assert_eq!(mounted_path("/Media", &config), Some(PathBuf::from("/mnt/media")));
+        assert_eq!(mounted_path("/Media/../etc/passwd", &config), None);
+        assert_eq!(mounted_path("/Other/a.txt", &config), None);
+        assert_eq!(resolve_path("Media/movies/a.mkv", &config), Some(PathBuf::from("/mnt/media/movies/a.mkv")));
+        assert_eq!(resolve_path("/Other/./a.txt", &config), Some(PathBuf::from("/srv/files/Other/a.txt")));
+        assert_eq!(resolve_path("/Other/../../etc/passwd", &config), None);
+
+        // Symlinks inside a mount can't lead out of it
+        #[cfg(unix)]
+        {
+            let mount_root = tempdir().expect("Failed to create temp dir");
+            let outside = tempdir().expect("Failed to create temp dir");
+            std::fs::create_dir(mount_root.path().join("docs")).unwrap();
+            std::os::unix::fs::symlink(outside.path(), mount_root.path().join("escape")).unwrap();
+            std::os::unix::fs::symlink(outside.path().join("missing"), mount_root.path().join("dangling")).unwrap();
+            let mut config = config.clone();
+            config.mounts[0].storage = MountStorage::Local(LocalMountConfiguration { path: mount_root.path().to_string_lossy().to_string() });
+            assert_eq!(mounted_path("/Media/docs/new.txt", &config), Some(mount_root.path().join("docs/new.txt")));
+            assert_eq!(mounted_path("/Media/escape", &config), None);
+            assert_eq!(mounted_path("/Media/escape/new/file.txt", &config), None);
+            assert_eq!(mounted_path("/Media/dangling", &config), None);
+        }
+
+        assert_eq!(virtual_path_in(Path::new("/mnt/media/movies/a.mkv"), &config).as_deref(), Some("/Media/movies/a.mkv"));
+        assert_eq!(virtual_path_in(Path::new("/mnt/media"), &config).as_deref(), Some("/Media"));
+        assert_eq!(virtual_path_in(Path::new("/srv/files/docs/a.txt"), &config).as_deref(), Some("/docs/a.txt"));
+        assert_eq!(virtual_path_in(Path::new("/etc/passwd"), &config), None);
+    }
+
+    #[test]
+    fn test_index_roots() {
+        use crate::io::fs::indexer::indexer_data::index_roots;
+        use std::time::Duration;
+
+        let mut config = Configuration {
+            root_path: "/srv/files".to_string(),
+            mounts: vec![local_mount("Media", "/srv/files/media"), MountConfiguration { indexing: false, ..local_mount("Backup", "/mnt/backup") }],
+            ..Configuration::default()
+        };
+        // A mount inside the root is still walked on its own, as its settings differ from the root's
+        assert_eq!(index_roots(&config), [PathBuf::from("/srv/files"), PathBuf::from("/srv/files/media")]);
+
+        config.index_roots = vec!["/docs".to_string(), "/docs/reports".to_string(), "/Media/movies".to_string()];
+        assert_eq!(index_roots(&config), [PathBuf::from("/srv/files/docs"), PathBuf::from("/srv/files/media/movies")]);
+
+        // A mount can watch its directories its own way
+        config.mounts[0].watcher_mode = Some(WatcherMode::Poll);
+        config.watcher_poll_interval = 30;
+        assert_eq!(config.watcher_for(Path::new("/srv/files/media/movies")), (WatcherMode::Poll, Duration::from_secs(30)));
+        assert_eq!(config.watcher_for(Path::new("/srv/files/docs")), (WatcherMode::Native, Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_path_filter() {
+        use crate::io::fs::indexer::path_filter::PathFilter;
+
+        let mut config = Configuration {
+            root_path: "/srv/files".to_string(),
+            filter: vec!["**/*.tmp".to_string(), "/srv/files/cache/**".to_string()],
+            exclude_hidden_files: true,
+            mounts: vec![MountConfiguration { filter: vec!["*.part".to_string()], ..local_mount("Media", "/mnt/media") }],
+            ..Configuration::default()
+        };
+        let filter = PathFilter::compile(&config).unwrap();
+        assert!(filter.includes(Path::new("/srv/files/docs/report.pdf"), &config));
+        assert!(!filter.includes(Path::new("/srv/files/docs/report.tmp"), &config));
+        assert!(!filter.includes(Path::new("/srv/files/cache/thumbs/a.jpg"), &config));
+        assert!(!filter.includes(Path::new("/srv/files/.git"), &config));
+        // A mount is checked against its own patterns only, relative to the mount
+        assert!(filter.includes(Path::new("/mnt/media/movie.tmp"), &config));
+        assert!(!filter.includes(Path::new("/mnt/media/movies/movie.part"), &config));
+
+        // Hidden files are only left out of the index, filtered paths are hidden everywhere
+        assert!(!filter.hides(Path::new("/srv/files/.git"), true, &config));
+        assert!(filter.hides(Path::new("/srv/files/docs/report.tmp"), false, &config));
+        assert!(filter.hides(Path::new("/mnt/media/movies/movie.part"), false, &config));
+
+        // A whitelist hides the files it doesn't match, but not the directories that may hold the ones it does
+        config.filter_mode_whitelist = true;
+        config.filter = vec!["**/*.pdf".to_string()];
+        let filter = PathFilter::compile(&config).unwrap();
+        assert!(filter.includes(Path::new("/srv/files/docs/report.pdf"), &config));
+        assert!(filter.hides(Path::new("/srv/files/docs/notes.txt"), false, &config));
+        assert!(!filter.hides(Path::new("/srv/files/docs"), true, &config));
+
+        // Invalid patterns are rejected by the field holding them, and skipped in a configuration that has them already
+        config.mounts[0].filter = vec!["[unclosed".to_string(), "*.part".to_string()];
+        assert_eq!(PathFilter::compile(&config).unwrap_err().field, "mounts");
+        assert!(PathFilter::new(&config).hides(Path::new("/mnt/media/movie.part"), false, &config));
+        config.filter = vec!["{unclosed".to_string()];
+        assert_eq!(PathFilter::compile(&config).unwrap_err().field, "filter");
+    }
+}
@@ -1,5 +1,5 @@
-use crate::configuration::configuration_data::Configuration;
-use std::path::PathBuf;
+use crate::configuration::configuration_data::{Configuration, MountStorage};
+use std::path::{Path, PathBuf};
 
 /// The `NormalizePath` trait defines a method for converting a type into an
 /// operating-system-compatible path representation (`std::path::PathBuf`).
@@ -69,6 +69,11 @@ impl NormalizePath for String {
             return root_path_buf;
         }
 
+        // Paths starting with the name of a mount are resolved against the mount instead of `root_path`
+        if let Some(mounted_path) = mounted_path(self, config) {
+            return mounted_path;
+        }
+
         #[cfg(target_os = "windows")]
         {
             // On Windows, we need to handle paths differently
@@ -156,6 +161,80 @@ impl NormalizePath for String {
         }
     }
 }
+/// Maps `path` into the mount named by its first segment, if any. Paths that try to leave the mount with `..`
+/// or through a symlink are not mapped.
+pub(crate) fn mounted_path(path: &str, config: &Configuration) -> Option<PathBuf> {
+    let path = path.replace('\\', "/");
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    let name = segments.next()?;
+    let mount = config.mounts.iter().find(|mount| mount.name == name)?;
+    let relative = segments.collect::<Vec<_>>();
+    if relative.contains(&"..") {
+        return None;
+    }
+    let mount_root = mount.os_path(&config.root_path);
+    let mapped = relative.into_iter().fold(mount_root.clone(), |path, segment| path.join(segment));
+    // Buckets have no symlinks, only directories of this machine need to be checked
+    if matches!(mount.storage, MountStorage::Local(_)) && !stays_within(&mapped, &mount_root) {
+        return None;
+    }
+    Some(mapped)
+}
+
+/// Maps `path` like [`NormalizePath::to_os_path`], but against `config` and without falling back to the root path.
+/// Paths that lead outside the root path or a mount, with `..` or through a symlink, are not mapped.
+pub(crate) fn resolve_path(path: &str, config: &Configuration) -> Option<PathBuf> {
+    let path = path.replace('\\', "/");
+    let segments = path.split('/').filter(|segment| !segment.is_empty() && *segment != ".").collect::<Vec<_>>();
+    if segments.contains(&"..") {
+        return None;
+    }
+    if segments.first().is_some_and(|name| config.mounts.iter().any(|mount| mount.name == *name)) {
+        return mounted_path(&path, config);
+    }
+    let root = PathBuf::from(&config.root_path);
+    let mapped = segments.into_iter().fold(root.clone(), |path, segment| path.join(segment));
+    stays_within(&mapped, &root).then_some(mapped)
+}
+
+/// Whether `path` stays inside `root` once symlinks are followed. Paths that don't exist yet are checked through
+/// their closest existing ancestor.
+fn stays_within(path: &Path, root: &Path) -> bool {
+    let Ok(canonical_root) = root.canonicalize() else {
+        // Nothing exists below a missing root, so there is no symlink to follow out of it
+        return true;
+    };
+    let mut existing = path;
+    // A dangling symlink counts as existing, so that it is refused rather than created through
+    while existing.symlink_metadata().is_err() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return false,
+        }
+    }
+    existing.canonicalize().is_ok_and(|canonical| canonical.starts_with(&canonical_root))
+}
+
+/// Maps an OS path back to the path clients see, the reverse of [`NormalizePath::to_os_path`].
+///
+/// Returns `None` for paths outside of `root_path` and every mount.
+pub fn to_virtual_path(path: &Path) -> Option<String> {
+    virtual_path_in(path, Configuration::get())
+}
+
+pub(crate) fn virtual_path_in(path: &Path, config: &Configuration) -> Option<String> {
+    let relative_to = |base: &Path| path.strip_prefix(base).ok().map(|relative| relative.to_string_lossy().replace('\\', "/"));
+    if let Some(mount) = config.mount_for(path) {
+        let relative = relative_to(&mount.os_path(&config.root_path))?;
+        return Some(if relative.is_empty() { mount.virtual_path() } else { format!("{}/{}", mount.virtual_path(), relative) });
+    }
+    // Served from the root of the filesystem, OS paths are already what clients see
+    if config.root_path == "/" {
+        return Some(path.to_string_lossy().into_owned());
+    }
+    relative_to(Path::new(&config.root_path)).map(|relative| format!("/{}", relative))
+}
+
 impl NormalizePath for PathBuf {
     fn to_os_path(&self) -> PathBuf {
         self.to_string_lossy().to_string().to_os_path()
@@ -0,0 +1,157 @@
+use crate::io::storage::storage_backend::{ByteReader, ByteWriter, StorageBackend, StorageEntry};
+use async_trait::async_trait;
+use log::warn;
+use notify::{Config, EventHandler, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs::Metadata;
+use std::io;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Stores files on the local filesystem, where every path is used as-is.
+pub struct LocalStorage;
+
+fn to_entry(path: &Path, metadata: &Metadata) -> StorageEntry {
+    StorageEntry {
+        // Roots such as `/` or `C:\` have no filename, so they are named after the whole path
+        name: path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned(),
+        path: path.to_path_buf(),
+        size: metadata.len(),
+        last_modified: metadata.modified().ok(),
+        created: metadata.created().ok(),
+        is_dir: metadata.is_dir(),
+    }
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&src_path, &dst_path)?;
+        } else {
+            std::fs::copy(&src_path, &dst_path)?;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn list(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        let mut readdir = fs::read_dir(path).await?;
+        let mut entries = Vec::new();
+        while let Some(entry) = readdir.next_entry().await? {
+            let path = entry.path();
+            if is_special_file(&path) {
+                continue;
+            }
+            if let Ok(metadata) = fs::metadata(&path).await {
+                entries.push(to_entry(&path, &metadata));
+            }
+        }
+        Ok(entries)
+    }
+
+    async fn stat(&self, path: &Path) -> io::Result<StorageEntry> {
+        Ok(to_entry(path, &fs::metadata(path).await?))
+    }
+
+    async fn walk(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        let root = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            std::fs::metadata(&root)?;
+            let mut entries = Vec::new();
+            // Symlinks are not followed, so a link to one of its own parents can't be walked forever
+            for dir_entry in walkdir::WalkDir::new(&root) {
+                match dir_entry.and_then(|dir_entry| dir_entry.metadata().map(|metadata| (dir_entry, metadata))) {
+                    Ok((dir_entry, metadata)) => entries.push(to_entry(dir_entry.path(), &metadata)),
+                    Err(e) => warn!("Error walking directory {}: {}", root.display(), e),
+                }
+            }
+            Ok(entries)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    async fn read(&self, path: &Path, range: Option<Range<u64>>) -> io::Result<ByteReader> {
+        let mut file = fs::File::open(path).await?;
+        match range {
+            Some(range) => {
+                file.seek(SeekFrom::Start(range.start)).await?;
+                Ok(Box::pin(file.take(range.end.saturating_sub(range.start))))
+            }
+            None => Ok(Box::pin(file)),
+        }
+    }
+
+    async fn write(&self, path: &Path) -> io::Result<ByteWriter> {
+        Ok(Box::pin(fs::File::create(path).await?))
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if fs::metadata(from).await?.is_dir() {
+            let (from, to) = (from.to_path_buf(), to.to_path_buf());
+            return tokio::task::spawn_blocking(move || copy_dir_all(&from, &to)).await.map_err(io::Error::other)?;
+        }
+        fs::copy(from, to).await.map(|_| ())
+    }
+
+    async fn delete(&self, path: &Path) -> io::Result<()> {
+        if fs::metadata(path).await?.is_dir() { fs::remove_dir_all(path).await } else { fs::remove_file(path).await }
+    }
+
+    async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path).await
+    }
+
+    fn watch(&self, path: &Path, mut handler: Box<dyn EventHandler>) -> io::Result<Option<Box<dyn Watcher + Send>>> {
+        // Directories the operating system can't report the changes of are scanned by the indexer's poll mode instead
+        let mut watcher = RecommendedWatcher::new(move |event| handler.handle_event(event), Config::default()).map_err(io::Error::other)?;
+        watcher.watch(path, RecursiveMode::Recursive).map_err(io::Error::other)?;
+        Ok(Some(Box::new(watcher)))
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        Some(path.to_path_buf())
+    }
+}
+
+pub fn is_special_file(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        if let Ok(metadata) = path.metadata() {
+            let file_type = metadata.file_type();
+            use std::os::unix::fs::FileTypeExt;
+            // Check for Unix special files
+            return file_type.is_char_device()
+                || file_type.is_block_device()
+                || file_type.is_fifo()
+                || file_type.is_socket()
+                || file_type.is_symlink();
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        // Check for Windows special files/directories
+        if let Some(file_name) = path.file_name() {
+            if let Some(name) = file_name.to_str() {
+                return name.eq_ignore_ascii_case("desktop.ini")
+                    || name.eq_ignore_ascii_case("thumbs.db")
+                    || name.starts_with("$")
+                    || name.starts_with("~$");
+            }
+        }
+    }
+    false
+}
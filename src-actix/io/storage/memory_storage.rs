@@ -0,0 +1,165 @@
+use crate::io::storage::storage_backend::{ByteReader, ByteWriter, StorageBackend, StorageEntry, transfer};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::io::AsyncWrite;
+
+/// Keeps files in memory, so tests can exercise the storage code without touching the disk.
+///
+/// The root `/` always exists, every other directory has to be created before files are written into it.
+#[derive(Default)]
+pub struct MemoryStorage {
+    nodes: Arc<Mutex<BTreeMap<PathBuf, MemoryNode>>>,
+}
+
+struct MemoryNode {
+    /// The contents of a file, or `None` for a directory.
+    data: Option<Vec<u8>>,
+    modified: SystemTime,
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display()))
+}
+
+fn is_root(path: &Path) -> bool {
+    path.parent().is_none()
+}
+
+impl MemoryStorage {
+    fn is_dir(nodes: &BTreeMap<PathBuf, MemoryNode>, path: &Path) -> bool {
+        is_root(path) || nodes.get(path).is_some_and(|node| node.data.is_none())
+    }
+
+    fn to_entry(path: &Path, node: &MemoryNode) -> StorageEntry {
+        StorageEntry {
+            name: path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned(),
+            path: path.to_path_buf(),
+            size: node.data.as_ref().map_or(0, |data| data.len() as u64),
+            last_modified: Some(node.modified),
+            created: None,
+            is_dir: node.data.is_none(),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn list(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        let nodes = self.nodes.lock().unwrap();
+        if !Self::is_dir(&nodes, path) {
+            return Err(not_found(path));
+        }
+        Ok(nodes.iter().filter(|(child, _)| child.parent() == Some(path)).map(|(child, node)| Self::to_entry(child, node)).collect())
+    }
+
+    async fn stat(&self, path: &Path) -> io::Result<StorageEntry> {
+        if is_root(path) {
+            return Ok(StorageEntry {
+                name: path.to_string_lossy().into_owned(),
+                path: path.to_path_buf(),
+                size: 0,
+                last_modified: None,
+                created: None,
+                is_dir: true,
+            });
+        }
+        let nodes = self.nodes.lock().unwrap();
+        nodes.get(path).map(|node| Self::to_entry(path, node)).ok_or_else(|| not_found(path))
+    }
+
+    async fn read(&self, path: &Path, range: Option<Range<u64>>) -> io::Result<ByteReader> {
+        let nodes = self.nodes.lock().unwrap();
+        let data = nodes.get(path).and_then(|node| node.data.as_ref()).ok_or_else(|| not_found(path))?;
+        let range = range.unwrap_or(0..data.len() as u64);
+        let end = (range.end as usize).min(data.len());
+        let start = (range.start as usize).min(end);
+        Ok(Box::pin(io::Cursor::new(data[start..end].to_vec())))
+    }
+
+    async fn write(&self, path: &Path) -> io::Result<ByteWriter> {
+        let mut nodes = self.nodes.lock().unwrap();
+        if !path.parent().is_some_and(|parent| Self::is_dir(&nodes, parent)) {
+            return Err(not_found(path));
+        }
+        nodes.insert(path.to_path_buf(), MemoryNode { data: Some(Vec::new()), modified: SystemTime::now() });
+        Ok(Box::pin(MemoryWriter { nodes: self.nodes.clone(), path: path.to_path_buf() }))
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        if !nodes.contains_key(from) {
+            return Err(not_found(from));
+        }
+        let moved = nodes.keys().filter(|path| path.starts_with(from)).cloned().collect::<Vec<_>>();
+        for path in moved {
+            let node = nodes.remove(&path).expect("the key was just listed");
+            let relative = path.strip_prefix(from).expect("the key starts with the source");
+            let target = if relative.as_os_str().is_empty() { to.to_path_buf() } else { to.join(relative) };
+            nodes.insert(target, node);
+        }
+        Ok(())
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        transfer(self, from, self, to).await
+    }
+
+    async fn delete(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        if !nodes.contains_key(path) {
+            return Err(not_found(path));
+        }
+        nodes.retain(|child, _| !child.starts_with(path));
+        Ok(())
+    }
+
+    async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        for ancestor in path.ancestors().filter(|ancestor| !is_root(ancestor)) {
+            match nodes.get(ancestor) {
+                Some(node) if node.data.is_some() => {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is a file", ancestor.display())));
+                }
+                Some(_) => {}
+                None => {
+                    nodes.insert(ancestor.to_path_buf(), MemoryNode { data: None, modified: SystemTime::now() });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Appends everything written to it to the file it was opened for.
+struct MemoryWriter {
+    nodes: Arc<Mutex<BTreeMap<PathBuf, MemoryNode>>>,
+    path: PathBuf,
+}
+
+impl AsyncWrite for MemoryWriter {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get_mut(&self.path).and_then(|node| node.data.as_mut()) {
+            Some(data) => {
+                data.extend_from_slice(buf);
+                Poll::Ready(Ok(buf.len()))
+            }
+            None => Poll::Ready(Err(not_found(&self.path))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
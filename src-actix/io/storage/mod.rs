@@ -0,0 +1,8 @@
+pub mod local_storage;
+#[cfg(test)]
+mod memory_storage;
+pub mod read_only_storage;
+pub mod s3_storage;
+pub mod storage_backend;
+#[cfg(test)]
+mod storage_test;
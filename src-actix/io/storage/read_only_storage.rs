@@ -0,0 +1,65 @@
+use crate::io::storage::storage_backend::{ByteReader, ByteWriter, StorageBackend, StorageEntry};
+use async_trait::async_trait;
+use notify::{EventHandler, Watcher};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Serves another backend without allowing any changes, for mounts configured as read-only.
+pub struct ReadOnlyStorage(pub Arc<dyn StorageBackend>);
+
+fn read_only(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is on a read-only mount", path.display()))
+}
+
+#[async_trait]
+impl StorageBackend for ReadOnlyStorage {
+    async fn list(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        self.0.list(path).await
+    }
+
+    async fn stat(&self, path: &Path) -> io::Result<StorageEntry> {
+        self.0.stat(path).await
+    }
+
+    async fn walk(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        self.0.walk(path).await
+    }
+
+    async fn read(&self, path: &Path, range: Option<Range<u64>>) -> io::Result<ByteReader> {
+        self.0.read(path, range).await
+    }
+
+    async fn write(&self, path: &Path) -> io::Result<ByteWriter> {
+        Err(read_only(path))
+    }
+
+    async fn rename(&self, from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only(from))
+    }
+
+    async fn copy(&self, _from: &Path, to: &Path) -> io::Result<()> {
+        Err(read_only(to))
+    }
+
+    async fn delete(&self, path: &Path) -> io::Result<()> {
+        Err(read_only(path))
+    }
+
+    async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        Err(read_only(path))
+    }
+
+    fn watch(&self, path: &Path, handler: Box<dyn EventHandler>) -> io::Result<Option<Box<dyn Watcher + Send>>> {
+        self.0.watch(path, handler)
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        self.0.local_path(path)
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,491 @@
+use crate::configuration::configuration_data::S3MountConfiguration;
+use crate::io::storage::storage_backend::{ByteReader, ByteWriter, StorageBackend, StorageEntry};
+use crate::s3::s3_signature::{ALGORITHM, UNSIGNED_PAYLOAD, canonical_request, sign, signing_key, string_to_sign, uri_encode};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use reqwest::header::{CONTENT_LENGTH, ETAG, HeaderName, LAST_MODIFIED};
+use reqwest::{Body, Client, Method, Response, StatusCode, Url};
+use serde::Deserialize;
+use std::future::Future;
+use std::io;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, DuplexStream};
+use tokio::task::JoinHandle;
+use tokio_util::io::StreamReader;
+
+/// Uploads are sent in parts of this size, anything smaller is sent in a single request.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Serves a prefix of an S3 compatible bucket, such as AWS S3 or MinIO, as a directory tree.
+///
+/// Directories are emulated with key prefixes: a directory exists as long as any key starts with
+/// `<directory>/`, and creating an empty one stores a `<directory>/` marker object.
+pub struct S3Storage {
+    /// The OS path the mount is served at.
+    root: PathBuf,
+    client: Arc<S3Client>,
+}
+
+/// Sends SigV4 signed requests for the objects of one bucket.
+struct S3Client {
+    http: Client,
+    config: S3MountConfiguration,
+    /// The scheme, host and port requests are sent to.
+    origin: String,
+    /// The value of the signed `host` header.
+    host: String,
+    /// `/<bucket>` for path style addressing, empty when the bucket is part of the host.
+    bucket_path: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub(crate) struct ListBucketResponse {
+    pub contents: Vec<ListedObject>,
+    pub common_prefixes: Vec<ListedPrefix>,
+    pub is_truncated: bool,
+    pub next_continuation_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub(crate) struct ListedObject {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub(crate) struct ListedPrefix {
+    pub prefix: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InitiateMultipartUploadResponse {
+    upload_id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct ErrorResponse {
+    code: String,
+    message: String,
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display()))
+}
+
+/// The prefix every key inside the directory `key` starts with.
+fn directory_prefix(key: &str) -> String {
+    if key.is_empty() { String::new() } else { format!("{}/", key) }
+}
+
+/// Turns the error response of a request into an `io::Error` of the matching kind.
+async fn error_from(response: Response) -> io::Error {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = match quick_xml::de::from_str::<ErrorResponse>(&body) {
+        Ok(error) if !error.code.is_empty() => format!("{}: {}", error.code, error.message),
+        _ => status.to_string(),
+    };
+    let kind = match status {
+        StatusCode::NOT_FOUND => io::ErrorKind::NotFound,
+        StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, message)
+}
+
+/// Reads the body of a response that may report an error despite its `200 OK`, as CopyObject and
+/// CompleteMultipartUpload do.
+async fn checked_body(response: Response) -> io::Result<String> {
+    let body = response.text().await.map_err(io::Error::other)?;
+    match quick_xml::de::from_str::<ErrorResponse>(&body) {
+        Ok(error) if !error.code.is_empty() => Err(io::Error::other(format!("{}: {}", error.code, error.message))),
+        _ => Ok(body),
+    }
+}
+
+impl S3Client {
+    fn new(config: S3MountConfiguration) -> io::Result<Self> {
+        let mut url = Url::parse(&config.endpoint).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let bucket_path = if config.path_style {
+            format!("/{}", uri_encode(&config.bucket, true))
+        } else {
+            let host = format!("{}.{}", config.bucket, url.host_str().unwrap_or_default());
+            url.set_host(Some(&host)).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            String::new()
+        };
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no host", config.endpoint))),
+        };
+        Ok(Self { http: Client::new(), origin: url.origin().ascii_serialization(), host, bucket_path, config })
+    }
+
+    /// Signs and sends a request for the object at `key`, failing unless the response is successful.
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        headers: &[(&str, String)],
+        body: Option<Body>,
+    ) -> io::Result<Response> {
+        let path = format!("{}/{}", self.bucket_path, uri_encode(key, false));
+        let mut query = query.iter().map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true))).collect::<Vec<_>>();
+        query.sort();
+        let query = query.join("&");
+        let mut url = Url::parse(&format!("{}{}", self.origin, path)).map_err(io::Error::other)?;
+        if !query.is_empty() {
+            url.set_query(Some(&query));
+        }
+
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        let mut signed_headers = vec![
+            ("host".to_string(), self.host.clone()),
+            ("x-amz-content-sha256".to_string(), UNSIGNED_PAYLOAD.to_string()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        signed_headers.extend(headers.iter().filter(|(name, _)| name.starts_with("x-amz-")).map(|(name, value)| (name.to_string(), value.clone())));
+        signed_headers.sort();
+
+        let canonical_request = canonical_request(method.as_str(), &path, &query, &signed_headers, UNSIGNED_PAYLOAD);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let key = signing_key(&self.config.secret_access_key, date, &self.config.region, "s3");
+        let signature = sign(&key, &string_to_sign(&amz_date, &scope, &canonical_request));
+        let authorization = format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM,
+            self.config.access_key_id,
+            scope,
+            signed_headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";"),
+            signature
+        );
+
+        // The host header is added by the client itself
+        let mut request = self.http.request(method, url).header("authorization", authorization);
+        for (name, value) in signed_headers.into_iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        for (name, value) in headers.iter().filter(|(name, _)| !name.starts_with("x-amz-")) {
+            request = request.header(*name, value);
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+
+        let response = request.send().await.map_err(io::Error::other)?;
+        if response.status().is_success() { Ok(response) } else { Err(error_from(response).await) }
+    }
+
+    /// Lists the keys starting with `prefix`, grouped at the next `/` when `delimited`.
+    ///
+    /// Stops after the first page when `max_keys` is given.
+    async fn list(&self, prefix: &str, delimited: bool, max_keys: Option<u32>) -> io::Result<ListBucketResponse> {
+        let mut listing = ListBucketResponse::default();
+        let mut continuation_token = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_string()), ("prefix", prefix.to_string())];
+            if delimited {
+                query.push(("delimiter", "/".to_string()));
+            }
+            if let Some(max_keys) = max_keys {
+                query.push(("max-keys", max_keys.to_string()));
+            }
+            if let Some(token) = continuation_token.take() {
+                query.push(("continuation-token", token));
+            }
+            let body = self.send(Method::GET, "", &query, &[], None).await?.text().await.map_err(io::Error::other)?;
+            let page = quick_xml::de::from_str::<ListBucketResponse>(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            listing.contents.extend(page.contents);
+            listing.common_prefixes.extend(page.common_prefixes);
+            match page.next_continuation_token {
+                Some(token) if page.is_truncated && max_keys.is_none() => continuation_token = Some(token),
+                _ => return Ok(listing),
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> io::Result<()> {
+        self.send(Method::PUT, key, &[], &[], Some(Body::from(data))).await.map(|_| ())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> io::Result<()> {
+        let source = format!("/{}/{}", self.config.bucket, uri_encode(from, false));
+        let response = self.send(Method::PUT, to, &[], &[("x-amz-copy-source", source)], None).await?;
+        checked_body(response).await.map(|_| ())
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.send(Method::DELETE, key, &[], &[], None).await.map(|_| ())
+    }
+
+    async fn create_multipart_upload(&self, key: &str) -> io::Result<String> {
+        let body = self.send(Method::POST, key, &[("uploads", String::new())], &[], None).await?.text().await.map_err(io::Error::other)?;
+        let upload = quick_xml::de::from_str::<InitiateMultipartUploadResponse>(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(upload.upload_id)
+    }
+
+    /// Uploads one part, returning its ETag.
+    async fn upload_part(&self, key: &str, upload_id: &str, part_number: u32, data: Vec<u8>) -> io::Result<String> {
+        let query = [("partNumber", part_number.to_string()), ("uploadId", upload_id.to_string())];
+        let response = self.send(Method::PUT, key, &query, &[], Some(Body::from(data))).await?;
+        response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "The uploaded part has no ETag"))
+    }
+
+    async fn complete_multipart_upload(&self, key: &str, upload_id: &str, parts: &[(u32, String)]) -> io::Result<()> {
+        let parts: String = parts
+            .iter()
+            .map(|(part_number, etag)| {
+                format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", part_number, quick_xml::escape::escape(etag))
+            })
+            .collect();
+        let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+        let response = self.send(Method::POST, key, &[("uploadId", upload_id.to_string())], &[], Some(Body::from(body))).await?;
+        checked_body(response).await.map(|_| ())
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> io::Result<()> {
+        self.send(Method::DELETE, key, &[("uploadId", upload_id.to_string())], &[], None).await.map(|_| ())
+    }
+
+    /// Uploads everything read from `reader` to `key`, in parts once it outgrows a single request.
+    async fn upload(&self, key: &str, mut reader: impl AsyncRead + Unpin) -> io::Result<()> {
+        let mut part = read_part(&mut reader).await?;
+        if part.len() < PART_SIZE {
+            return self.put(key, part).await;
+        }
+
+        let upload_id = self.create_multipart_upload(key).await?;
+        let result = async {
+            let mut parts = Vec::new();
+            while !part.is_empty() {
+                let part_number = parts.len() as u32 + 1;
+                parts.push((part_number, self.upload_part(key, &upload_id, part_number, part).await?));
+                part = read_part(&mut reader).await?;
+            }
+            self.complete_multipart_upload(key, &upload_id, &parts).await
+        }
+        .await;
+        if result.is_err() {
+            // Abandoned parts are billed until the upload is aborted
+            let _ = self.abort_multipart_upload(key, &upload_id).await;
+        }
+        result
+    }
+}
+
+async fn read_part(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let mut part = Vec::with_capacity(PART_SIZE);
+    reader.take(PART_SIZE as u64).read_to_end(&mut part).await?;
+    Ok(part)
+}
+
+fn parse_last_modified(value: &str) -> Option<SystemTime> {
+    DateTime::parse_from_rfc3339(value).or_else(|_| DateTime::parse_from_rfc2822(value)).ok().map(SystemTime::from)
+}
+
+/// Turns a delimited listing of `prefix` into the entries of the directory at `path`.
+pub(crate) fn listing_entries(path: &Path, prefix: &str, listing: ListBucketResponse) -> Vec<StorageEntry> {
+    let directories = listing.common_prefixes.into_iter().filter_map(|common_prefix| {
+        let name = common_prefix.prefix.strip_prefix(prefix)?.trim_end_matches('/').to_string();
+        (!name.is_empty()).then(|| StorageEntry { path: path.join(&name), name, size: 0, last_modified: None, created: None, is_dir: true })
+    });
+    let files = listing.contents.into_iter().filter_map(|object| {
+        // The marker object of the directory itself is not one of its children
+        let name = object.key.strip_prefix(prefix)?.to_string();
+        (!name.is_empty() && !name.contains('/')).then(|| StorageEntry {
+            path: path.join(&name),
+            name,
+            size: object.size,
+            last_modified: parse_last_modified(&object.last_modified),
+            created: None,
+            is_dir: false,
+        })
+    });
+    directories.chain(files).collect()
+}
+
+impl S3Storage {
+    pub fn new(root: PathBuf, config: S3MountConfiguration) -> io::Result<Self> {
+        Ok(Self { root, client: Arc::new(S3Client::new(config)?) })
+    }
+
+    /// The object key of `path`, below the configured prefix.
+    pub(crate) fn key(&self, path: &Path) -> String {
+        let relative = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let prefix = self.client.config.prefix.trim_matches('/');
+        match (prefix.is_empty(), relative.is_empty()) {
+            (true, _) => relative,
+            (false, true) => prefix.to_string(),
+            (false, false) => format!("{}/{}", prefix, relative),
+        }
+    }
+
+    /// Every key stored at or below the directory at `path`.
+    async fn keys_below(&self, path: &Path) -> io::Result<Vec<String>> {
+        let listing = self.client.list(&directory_prefix(&self.key(path)), false, None).await?;
+        Ok(listing.contents.into_iter().map(|object| object.key).collect())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    async fn list(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        let prefix = directory_prefix(&self.key(path));
+        let listing = self.client.list(&prefix, true, None).await?;
+        Ok(listing_entries(path, &prefix, listing))
+    }
+
+    async fn stat(&self, path: &Path) -> io::Result<StorageEntry> {
+        let mut entry = StorageEntry {
+            name: path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned(),
+            path: path.to_path_buf(),
+            size: 0,
+            last_modified: None,
+            created: None,
+            is_dir: true,
+        };
+        if path == self.root {
+            return Ok(entry);
+        }
+
+        let key = self.key(path);
+        match self.client.send(Method::HEAD, &key, &[], &[], None).await {
+            Ok(response) => {
+                let header = |name: HeaderName| response.headers().get(name).and_then(|value| value.to_str().ok());
+                entry.size = header(CONTENT_LENGTH).and_then(|length| length.parse().ok()).unwrap_or(0);
+                entry.last_modified = header(LAST_MODIFIED).and_then(parse_last_modified);
+                entry.is_dir = false;
+                Ok(entry)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // Directories only exist as the prefix of other keys
+                let listing = self.client.list(&directory_prefix(&key), false, Some(1)).await?;
+                if listing.contents.is_empty() && listing.common_prefixes.is_empty() { Err(not_found(path)) } else { Ok(entry) }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn read(&self, path: &Path, range: Option<Range<u64>>) -> io::Result<ByteReader> {
+        let headers = match range {
+            Some(range) if range.end <= range.start => return Ok(Box::pin(io::Cursor::new(Vec::new()))),
+            Some(range) => vec![("range", format!("bytes={}-{}", range.start, range.end - 1))],
+            None => Vec::new(),
+        };
+        let response = match self.client.send(Method::GET, &self.key(path), &[], &headers, None).await {
+            Ok(response) => response,
+            // A range starting past the end of the object reads nothing, like a local file would
+            Err(e) if e.to_string().starts_with("InvalidRange") => return Ok(Box::pin(io::Cursor::new(Vec::new()))),
+            Err(e) => return Err(e),
+        };
+        Ok(Box::pin(StreamReader::new(response.bytes_stream().map_err(io::Error::other))))
+    }
+
+    async fn write(&self, path: &Path) -> io::Result<ByteWriter> {
+        let (pipe, reader) = tokio::io::duplex(64 * 1024);
+        let (client, key) = (self.client.clone(), self.key(path));
+        let upload = tokio::spawn(async move { client.upload(&key, reader).await });
+        Ok(Box::pin(S3Writer { pipe, upload: Some(upload) }))
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.copy(from, to).await?;
+        self.delete(from).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from_key, to_key) = (self.key(from), self.key(to));
+        if !self.stat(from).await?.is_dir {
+            return self.client.copy(&from_key, &to_key).await;
+        }
+        let (source_prefix, target_prefix) = (directory_prefix(&from_key), directory_prefix(&to_key));
+        for key in self.keys_below(from).await? {
+            let target = format!("{}{}", target_prefix, &key[source_prefix.len()..]);
+            self.client.copy(&key, &target).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, path: &Path) -> io::Result<()> {
+        if !self.stat(path).await?.is_dir {
+            return self.client.delete(&self.key(path)).await;
+        }
+        for key in self.keys_below(path).await? {
+            self.client.delete(&key).await?;
+        }
+        Ok(())
+    }
+
+    async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let key = self.key(path);
+        if key.is_empty() {
+            return Ok(());
+        }
+        self.client.put(&directory_prefix(&key), Vec::new()).await
+    }
+}
+
+/// Streams what is written into an upload task, which finishes the object once the writer is shut down.
+///
+/// Dropping the writer without shutting it down abandons the upload.
+struct S3Writer {
+    pipe: DuplexStream,
+    upload: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl AsyncWrite for S3Writer {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().pipe).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().pipe).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(Pin::new(&mut this.pipe).poll_shutdown(cx))?;
+        let Some(upload) = this.upload.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(Pin::new(upload).poll(cx)).map_err(io::Error::other).and_then(|result| result);
+        this.upload = None;
+        Poll::Ready(result)
+    }
+}
+
+impl Drop for S3Writer {
+    fn drop(&mut self) {
+        if let Some(upload) = self.upload.take() {
+            upload.abort();
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::configuration::configuration_data::{Configuration, MountStorage};
+use crate::io::storage::local_storage::LocalStorage;
+use crate::io::storage::read_only_storage::ReadOnlyStorage;
+use crate::io::storage::s3_storage::S3Storage;
+use async_trait::async_trait;
+use log::{error, warn};
+use notify::{EventHandler, Watcher};
+use std::io;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::runtime::Handle;
+use tokio_util::io::SyncIoBridge;
+
+pub type ByteReader = Pin<Box<dyn AsyncRead + Send>>;
+pub type ByteWriter = Pin<Box<dyn AsyncWrite + Send>>;
+
+/// A file or directory stored by a [`StorageBackend`].
+#[derive(Debug, Clone)]
+pub struct StorageEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_modified: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    pub is_dir: bool,
+}
+
+/// Where the files of the Filer tree are stored.
+///
+/// Every handler reaches files through a backend instead of `std::fs`, so remote and in-memory
+/// storage can be served without touching the handlers. Paths are the OS paths produced by
+/// [`NormalizePath`](crate::io::fs::normalize_path::NormalizePath).
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Lists the direct children of the directory at `path`.
+    async fn list(&self, path: &Path) -> io::Result<Vec<StorageEntry>>;
+
+    async fn stat(&self, path: &Path) -> io::Result<StorageEntry>;
+
+    /// Lists `path` and everything below it, every directory before its children.
+    async fn walk(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        let mut entries = Vec::new();
+        let mut pending = vec![self.stat(path).await?];
+        while let Some(entry) = pending.pop() {
+            if entry.is_dir {
+                match self.list(&entry.path).await {
+                    Ok(mut children) => {
+                        children.sort_by(|a, b| b.name.cmp(&a.name));
+                        pending.extend(children);
+                    }
+                    Err(e) => warn!("Error walking directory {}: {}", entry.path.display(), e),
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Opens the file at `path` for reading, limited to `range` when one is given.
+    async fn read(&self, path: &Path, range: Option<Range<u64>>) -> io::Result<ByteReader>;
+
+    /// Creates or truncates the file at `path`. The data is complete once the writer is shut down.
+    async fn write(&self, path: &Path) -> io::Result<ByteWriter>;
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies the file or directory at `from` to `to`, recursively.
+    async fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Deletes the file or directory at `path`, recursively.
+    async fn delete(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates the directory at `path` along with any missing parents.
+    async fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Reports changes below `path` to `handler` for as long as the returned watcher is kept alive.
+    ///
+    /// Returns `None` for backends that can't be watched.
+    fn watch(&self, _path: &Path, _handler: Box<dyn EventHandler>) -> io::Result<Option<Box<dyn Watcher + Send>>> {
+        Ok(None)
+    }
+
+    /// The location of `path` on the local filesystem, for backends that keep their files on disk.
+    ///
+    /// Archive formats that need to seek, such as zip and 7z, are read and written through it.
+    fn local_path(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Whether every change is rejected, so callers can refuse before doing any work.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+}
+
+static LOCAL_STORAGE: OnceLock<Arc<dyn StorageBackend>> = OnceLock::new();
+/// The tree of the configuration Filer started with, resolved once at startup like the path mapping, so saving
+/// other mounts is refused.
+static TREE: OnceLock<Arc<StorageTree>> = OnceLock::new();
+
+/// A configured mount, resolved to the OS path it is served at.
+struct Mount {
+    name: String,
+    path: PathBuf,
+    backend: Arc<dyn StorageBackend>,
+}
+
+/// The files Filer serves: the local filesystem under `root_path`, and the mounts of a configuration along with
+/// the backends that store them.
+pub struct StorageTree {
+    config: Configuration,
+    mounts: Vec<Mount>,
+}
+
+fn local_storage() -> Arc<dyn StorageBackend> {
+    LOCAL_STORAGE.get_or_init(|| Arc::new(LocalStorage)).clone()
+}
+
+/// The tree of the running configuration.
+pub fn tree() -> &'static Arc<StorageTree> {
+    TREE.get_or_init(|| Arc::new(StorageTree::new(Configuration::get().clone())))
+}
+
+impl StorageTree {
+    pub fn new(config: Configuration) -> Self {
+        let mounts = config
+            .mounts
+            .iter()
+            .filter_map(|mount| {
+                let path = mount.os_path(&config.root_path);
+                let backend: Arc<dyn StorageBackend> = match &mount.storage {
+                    MountStorage::Local(_) => local_storage(),
+                    MountStorage::S3(s3) => match S3Storage::new(path.clone(), s3.clone()) {
+                        Ok(storage) => Arc::new(storage),
+                        Err(e) => {
+                            error!("Failed to mount {}: {}", mount.name, e);
+                            return None;
+                        }
+                    },
+                };
+                let backend = if mount.read_only { Arc::new(ReadOnlyStorage(backend)) } else { backend };
+                Some(Mount { name: mount.name.clone(), path, backend })
+            })
+            .collect();
+        Self { config, mounts }
+    }
+
+    /// The configuration the tree was built from.
+    pub fn config(&self) -> &Configuration {
+        &self.config
+    }
+
+    /// The backend that stores `path`: the mount it falls into, or the local filesystem.
+    pub fn backend_for(&self, path: &Path) -> Arc<dyn StorageBackend> {
+        match self.mounts.iter().find(|mount| path.starts_with(&mount.path)) {
+            Some(mount) => mount.backend.clone(),
+            None => local_storage(),
+        }
+    }
+
+    /// Whether `path` is where a mount is served, which can't be removed or renamed like the folder it appears as.
+    pub fn is_mount(&self, path: &Path) -> bool {
+        self.mounts.iter().any(|mount| mount.path == path)
+    }
+
+    /// The mounts that appear as folders of the directory at `parent`, which is every mount for the top of the
+    /// tree and none for any other directory.
+    pub fn mount_entries(&self, parent: &Path) -> Vec<StorageEntry> {
+        if parent != Path::new(&self.config.root_path) {
+            return Vec::new();
+        }
+        self.mounts
+            .iter()
+            .map(|mount| StorageEntry {
+                name: mount.name.clone(),
+                path: mount.path.clone(),
+                size: 0,
+                last_modified: None,
+                created: None,
+                is_dir: true,
+            })
+            .collect()
+    }
+
+    /// Lists the directory at `path` through the backend that stores it, along with the mounts that show up in it.
+    ///
+    /// Mounts replace anything stored in the directory under the same name.
+    pub async fn list(&self, path: &Path) -> io::Result<Vec<StorageEntry>> {
+        let mounts = self.mount_entries(path);
+        let mut entries = self.backend_for(path).list(path).await?;
+        entries.retain(|entry| !mounts.iter().any(|mount| mount.name == entry.name));
+        entries.extend(mounts);
+        Ok(entries)
+    }
+
+    /// Copies `from` to `to`, which may be stored by different backends.
+    pub async fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (source, target) = (self.backend_for(from), self.backend_for(to));
+        if Arc::ptr_eq(&source, &target) {
+            return source.copy(from, to).await;
+        }
+        transfer(source.as_ref(), from, target.as_ref(), to).await
+    }
+
+    /// Moves `from` to `to`, which may be stored by different backends.
+    pub async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (source, target) = (self.backend_for(from), self.backend_for(to));
+        if Arc::ptr_eq(&source, &target) {
+            return source.rename(from, to).await;
+        }
+        // Refused up front, as the copy would otherwise be made before the original turns out to be undeletable
+        if source.is_read_only() {
+            return source.delete(from).await;
+        }
+        transfer(source.as_ref(), from, target.as_ref(), to).await?;
+        source.delete(from).await
+    }
+}
+
+/// The backend that stores `path` in the running configuration, see [`StorageTree::backend_for`].
+pub fn backend_for(path: &Path) -> Arc<dyn StorageBackend> {
+    tree().backend_for(path)
+}
+
+/// See [`StorageTree::mount_entries`].
+pub fn mount_entries(parent: &Path) -> Vec<StorageEntry> {
+    tree().mount_entries(parent)
+}
+
+/// See [`StorageTree::list`].
+pub async fn list(path: &Path) -> io::Result<Vec<StorageEntry>> {
+    tree().list(path).await
+}
+
+/// See [`StorageTree::copy`].
+pub async fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    tree().copy(from, to).await
+}
+
+/// See [`StorageTree::rename`].
+pub async fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    tree().rename(from, to).await
+}
+
+/// Copies a file or directory tree by streaming every file from `source` into `target`.
+pub async fn transfer(source: &dyn StorageBackend, from: &Path, target: &dyn StorageBackend, to: &Path) -> io::Result<()> {
+    for entry in source.walk(from).await? {
+        let destination = match entry.path.strip_prefix(from) {
+            Ok(relative) if !relative.as_os_str().is_empty() => to.join(relative),
+            _ => to.to_path_buf(),
+        };
+        if entry.is_dir {
+            target.create_dir(&destination).await?;
+            continue;
+        }
+        let mut reader = source.read(&entry.path, None).await?;
+        let mut writer = target.write(&destination).await?;
+        tokio::io::copy(&mut reader, &mut writer).await?;
+        writer.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// Opens `path` for reading from a blocking thread, such as one building an archive.
+///
+/// Has to be called from a thread of the runtime, e.g. inside `spawn_blocking`.
+pub fn blocking_reader(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let backend = backend_for(path);
+    if let Some(local) = backend.local_path(path) {
+        return Ok(Box::new(std::fs::File::open(local)?));
+    }
+    let handle = Handle::current();
+    let reader = handle.block_on(backend.read(path, None))?;
+    Ok(Box::new(SyncIoBridge::new_with_handle(reader, handle)))
+}
+
+/// A file available on the local filesystem, either directly or as a temporary copy.
+pub enum LocalFile {
+    Direct(PathBuf),
+    Temporary(tempfile::TempPath),
+}
+
+impl AsRef<Path> for LocalFile {
+    fn as_ref(&self) -> &Path {
+        match self {
+            LocalFile::Direct(path) => path,
+            LocalFile::Temporary(path) => path,
+        }
+    }
+}
+
+/// Makes the file at `path` available on the local filesystem, downloading it to a temporary
+/// file when its backend doesn't keep it on disk.
+pub async fn local_file(path: &Path) -> io::Result<LocalFile> {
+    let backend = backend_for(path);
+    if let Some(local) = backend.local_path(path) {
+        return Ok(LocalFile::Direct(local));
+    }
+    let temporary = tempfile::NamedTempFile::new()?;
+    let mut writer = tokio::fs::File::from_std(temporary.reopen()?);
+    tokio::io::copy(&mut backend.read(path, None).await?, &mut writer).await?;
+    writer.flush().await?;
+    Ok(LocalFile::Temporary(temporary.into_temp_path()))
+}
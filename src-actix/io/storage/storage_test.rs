@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+    use crate::configuration::configuration_data::S3MountConfiguration;
+    use crate::io::storage::local_storage::LocalStorage;
+    use crate::io::storage::memory_storage::MemoryStorage;
+    use crate::io::storage::read_only_storage::ReadOnlyStorage;
+    use crate::io::storage::s3_storage::{ListBucketResponse, S3Storage, listing_entries};
+    use crate::io::storage::storage_backend::{StorageBackend, transfer};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn write(backend: &dyn StorageBackend, path: &str, content: &[u8]) {
+        let mut writer = backend.write(Path::new(path)).await.expect("Failed to open file for writing");
+        writer.write_all(content).await.expect("Failed to write file");
+        writer.shutdown().await.expect("Failed to close file");
+    }
+
+    async fn read(backend: &dyn StorageBackend, path: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        backend.read(Path::new(path), None).await.expect("Failed to open file").read_to_end(&mut content).await.expect("Failed to read file");
+        content
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_read_write() {
+        let storage = MemoryStorage::default();
+        storage.create_dir(Path::new("/docs/notes")).await.unwrap();
+        write(&storage, "/docs/notes/todo.txt", b"hello world").await;
+
+        assert_eq!(read(&storage, "/docs/notes/todo.txt").await, b"hello world");
+        let mut range = String::new();
+        storage.read(Path::new("/docs/notes/todo.txt"), Some(6..100)).await.unwrap().read_to_string(&mut range).await.unwrap();
+        assert_eq!(range, "world");
+
+        let entry = storage.stat(Path::new("/docs/notes/todo.txt")).await.unwrap();
+        assert_eq!((entry.name.as_str(), entry.size, entry.is_dir), ("todo.txt", 11, false));
+        assert!(storage.stat(Path::new("/docs")).await.unwrap().is_dir);
+
+        let listing = storage.list(Path::new("/docs")).await.unwrap();
+        assert_eq!(listing.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>(), ["notes"]);
+
+        // Like a filesystem, files can only be written into existing directories
+        assert!(storage.write(Path::new("/missing/file.txt")).await.is_err());
+        assert!(storage.read(Path::new("/docs/notes"), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_copy_rename_delete() {
+        let storage = MemoryStorage::default();
+        storage.create_dir(Path::new("/a/b")).await.unwrap();
+        write(&storage, "/a/b/file.txt", b"content").await;
+
+        storage.copy(Path::new("/a"), Path::new("/copy")).await.unwrap();
+        assert_eq!(read(&storage, "/copy/b/file.txt").await, b"content");
+
+        storage.rename(Path::new("/a"), Path::new("/moved")).await.unwrap();
+        assert!(storage.stat(Path::new("/a/b/file.txt")).await.is_err());
+        assert_eq!(read(&storage, "/moved/b/file.txt").await, b"content");
+
+        let walked = storage.walk(Path::new("/moved")).await.unwrap();
+        assert_eq!(walked.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>(), ["moved", "b", "file.txt"]);
+
+        storage.delete(Path::new("/moved")).await.unwrap();
+        assert!(storage.stat(Path::new("/moved/b")).await.is_err());
+        assert_eq!(read(&storage, "/copy/b/file.txt").await, b"content");
+    }
+
+    #[tokio::test]
+    async fn test_local_storage() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let storage = LocalStorage;
+        storage.create_dir(&root.join("logs/old")).await.unwrap();
+        let file = root.join("logs/old/app.log");
+        write(&storage, file.to_str().unwrap(), b"0123456789").await;
+
+        let mut range = Vec::new();
+        storage.read(&file, Some(2..5)).await.unwrap().read_to_end(&mut range).await.unwrap();
+        assert_eq!(range, b"234");
+
+        let walked = storage.walk(&root.join("logs")).await.unwrap();
+        assert_eq!(walked.len(), 3);
+        assert!(walked[0].is_dir && walked[2].path == file);
+
+        storage.copy(&root.join("logs"), &root.join("backup")).await.unwrap();
+        assert_eq!(std::fs::read(root.join("backup/old/app.log")).unwrap(), b"0123456789");
+        storage.delete(&root.join("logs")).await.unwrap();
+        assert!(!root.join("logs").exists());
+    }
+
+    #[tokio::test]
+    async fn test_transfer_between_backends() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let memory = MemoryStorage::default();
+        memory.create_dir(Path::new("/photos/2024")).await.unwrap();
+        write(&memory, "/photos/2024/beach.jpg", b"jpeg").await;
+
+        let target = temp_dir.path().join("photos");
+        transfer(&memory, Path::new("/photos"), &LocalStorage, &target).await.unwrap();
+        assert_eq!(std::fs::read(target.join("2024/beach.jpg")).unwrap(), b"jpeg");
+    }
+
+    #[tokio::test]
+    async fn test_read_only_storage() {
+        let memory = MemoryStorage::default();
+        memory.create_dir(Path::new("/media")).await.unwrap();
+        write(&memory, "/media/song.mp3", b"mp3").await;
+
+        let storage = ReadOnlyStorage(Arc::new(memory));
+        assert_eq!(read(&storage, "/media/song.mp3").await, b"mp3");
+        assert_eq!(storage.list(Path::new("/media")).await.unwrap().len(), 1);
+        for error in [
+            storage.write(Path::new("/media/new.mp3")).await.err().unwrap(),
+            storage.delete(Path::new("/media/song.mp3")).await.unwrap_err(),
+            storage.rename(Path::new("/media/song.mp3"), Path::new("/media/renamed.mp3")).await.unwrap_err(),
+            storage.create_dir(Path::new("/media/albums")).await.unwrap_err(),
+        ] {
+            assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        }
+        assert!(storage.stat(Path::new("/media/song.mp3")).await.is_ok());
+    }
+
+    fn s3_config(endpoint: &str, bucket: &str, prefix: &str) -> S3MountConfiguration {
+        S3MountConfiguration {
+            endpoint: endpoint.to_string(),
+            region: "us-east-1".to_string(),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            access_key_id: std::env::var("FILER_TEST_S3_ACCESS_KEY").unwrap_or_default(),
+            secret_access_key: std::env::var("FILER_TEST_S3_SECRET_KEY").unwrap_or_default(),
+            path_style: true,
+        }
+    }
+
+    #[test]
+    fn test_s3_keys() {
+        let storage = S3Storage::new(PathBuf::from("/srv/files/cloud"), s3_config("http://localhost:9000", "filer", "/team/")).unwrap();
+        assert_eq!(storage.key(Path::new("/srv/files/cloud")), "team");
+        assert_eq!(storage.key(Path::new("/srv/files/cloud/My Docs/a.txt")), "team/My Docs/a.txt");
+
+        let storage = S3Storage::new(PathBuf::from("/srv/files/cloud"), s3_config("http://localhost:9000", "filer", "")).unwrap();
+        assert_eq!(storage.key(Path::new("/srv/files/cloud")), "");
+        assert_eq!(storage.key(Path::new("/srv/files/cloud/photos/")), "photos");
+        assert!(S3Storage::new(PathBuf::from("/cloud"), s3_config("not a url", "filer", "")).is_err());
+    }
+
+    #[test]
+    fn test_s3_listing_entries() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+                <Name>filer</Name><Prefix>team/docs/</Prefix><KeyCount>3</KeyCount><IsTruncated>false</IsTruncated>
+                <Contents><Key>team/docs/</Key><LastModified>2024-05-01T10:00:00.000Z</LastModified><Size>0</Size></Contents>
+                <Contents><Key>team/docs/report.pdf</Key><LastModified>2024-05-01T10:00:00.000Z</LastModified><Size>2048</Size></Contents>
+                <CommonPrefixes><Prefix>team/docs/drafts/</Prefix></CommonPrefixes>
+            </ListBucketResult>"#;
+        let listing = quick_xml::de::from_str::<ListBucketResponse>(xml).unwrap();
+        assert!(!listing.is_truncated);
+
+        let entries = listing_entries(Path::new("/cloud/docs"), "team/docs/", listing);
+        let summary = entries.iter().map(|entry| (entry.name.as_str(), entry.size, entry.is_dir)).collect::<Vec<_>>();
+        assert_eq!(summary, [("drafts", 0, true), ("report.pdf", 2048, false)]);
+        assert_eq!(entries[1].path, Path::new("/cloud/docs/report.pdf"));
+        assert!(entries[1].last_modified.is_some());
+    }
+
+    /// Runs against a real bucket, e.g. a local MinIO, when `FILER_TEST_S3_ENDPOINT`, `FILER_TEST_S3_BUCKET`,
+    /// `FILER_TEST_S3_ACCESS_KEY` and `FILER_TEST_S3_SECRET_KEY` are set.
+    #[tokio::test]
+    async fn test_s3_storage_against_bucket() {
+        let (Ok(endpoint), Ok(bucket)) = (std::env::var("FILER_TEST_S3_ENDPOINT"), std::env::var("FILER_TEST_S3_BUCKET")) else {
+            return;
+        };
+        let prefix = format!("filer-test-{}", uuid::Uuid::new_v4());
+        let storage = S3Storage::new(PathBuf::from("/cloud"), s3_config(&endpoint, &bucket, &prefix)).unwrap();
+
+        storage.create_dir(Path::new("/cloud/docs/empty")).await.unwrap();
+        write(&storage, "/cloud/docs/note.txt", b"hello world").await;
+        let large = vec![7u8; 9 * 1024 * 1024];
+        write(&storage, "/cloud/docs/large.bin", &large).await;
+
+        assert_eq!(read(&storage, "/cloud/docs/note.txt").await, b"hello world");
+        assert_eq!(read(&storage, "/cloud/docs/large.bin").await.len(), large.len());
+        let mut range = String::new();
+        storage.read(Path::new("/cloud/docs/note.txt"), Some(6..11)).await.unwrap().read_to_string(&mut range).await.unwrap();
+        assert_eq!(range, "world");
+
+        let mut names = storage.list(Path::new("/cloud/docs")).await.unwrap().into_iter().map(|entry| entry.name).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["empty", "large.bin", "note.txt"]);
+        assert!(storage.stat(Path::new("/cloud/docs/empty")).await.unwrap().is_dir);
+
+        storage.rename(Path::new("/cloud/docs"), Path::new("/cloud/moved")).await.unwrap();
+        assert!(storage.stat(Path::new("/cloud/docs")).await.is_err());
+        assert_eq!(read(&storage, "/cloud/moved/note.txt").await, b"hello world");
+
+        storage.delete(Path::new("/cloud/moved")).await.unwrap();
+        assert!(storage.list(Path::new("/cloud")).await.unwrap().is_empty());
+    }
+}
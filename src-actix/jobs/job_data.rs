@@ -0,0 +1,149 @@
+use crate::shares::share_data::unix_now;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// How many finished jobs are kept for the job list.
+const MAX_FINISHED_JOBS: usize = 100;
+
+static JOBS: OnceLock<JobRegistry> = OnceLock::new();
+
+/// The jobs that are running and the last ones that finished.
+pub fn jobs() -> &'static JobRegistry {
+    JOBS.get_or_init(|| JobRegistry { next_id: AtomicU64::new(1), jobs: Mutex::new(VecDeque::new()) })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// A piece of background work, such as a scheduled task.
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub name: String,
+    pub status: JobStatus,
+    /// In seconds since the epoch.
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// What the job did, when it completed.
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    cancelled: Arc<AtomicBool>,
+    /// Called when the job is cancelled, for work that has a way of its own to be stopped.
+    #[serde(skip)]
+    on_cancel: Option<fn()>,
+}
+
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<VecDeque<Job>>,
+}
+
+/// Given to the work a job does, to check for cancellation and to finish the job.
+pub struct JobHandle {
+    pub id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobRegistry {
+    /// Registers a running job named `name`, or returns `None` when a job of that name is running already.
+    pub fn start(&self, name: &str, on_cancel: Option<fn()>) -> Option<(Job, JobHandle)> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.iter().any(|job| job.name == name && job.status == JobStatus::Running) {
+            return None;
+        }
+        let job = Job {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            name: name.to_string(),
+            status: JobStatus::Running,
+            started_at: unix_now(),
+            finished_at: None,
+            result: None,
+            error: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            on_cancel,
+        };
+        let handle = JobHandle { id: job.id, cancelled: job.cancelled.clone() };
+        jobs.push_back(job.clone());
+        Some((job, handle))
+    }
+
+    /// Records the outcome of the job of `handle`. A job that was cancelled counts as cancelled, whatever the outcome.
+    pub fn finish(&self, handle: JobHandle, outcome: Result<Value, String>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == handle.id) {
+            job.finished_at = Some(unix_now());
+            match outcome {
+                _ if handle.is_cancelled() => job.status = JobStatus::Cancelled,
+                Ok(result) => {
+                    job.status = JobStatus::Completed;
+                    job.result = Some(result);
+                }
+                Err(error) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(error);
+                }
+            }
+        }
+
+        // Running jobs are never dropped, only the oldest finished ones
+        let finished = jobs.iter().filter(|job| job.status != JobStatus::Running).count();
+        for _ in MAX_FINISHED_JOBS..finished {
+            if let Some(index) = jobs.iter().position(|job| job.status != JobStatus::Running) {
+                jobs.remove(index);
+            }
+        }
+    }
+
+    /// Asks the job `id` to stop. Returns the job, or `None` when there is no such job.
+    pub fn cancel(&self, id: u64) -> Option<Job> {
+        let job = self.get(id)?;
+        if job.status == JobStatus::Running {
+            job.cancelled.store(true, Ordering::SeqCst);
+            if let Some(on_cancel) = job.on_cancel {
+                on_cancel();
+            }
+        }
+        Some(job)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.jobs.lock().unwrap().iter().find(|job| job.id == id).cloned()
+    }
+
+    /// The running job named `name`, if any.
+    pub fn running(&self, name: &str) -> Option<Job> {
+        self.jobs.lock().unwrap().iter().find(|job| job.name == name && job.status == JobStatus::Running).cloned()
+    }
+
+    /// Every job, newest first.
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+impl JobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,53 @@
+use crate::auth::auth_middleware::Authentication;
+use crate::helpers::http_error::{Error, Result};
+use crate::jobs::job_data::{JobStatus, jobs};
+use crate::jobs::schedule_data::ScheduledTask;
+use crate::jobs::scheduler;
+use actix_web::{HttpResponse, get, post, web};
+
+#[get("")]
+async fn list_jobs() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(jobs().list()))
+}
+
+#[get("/schedule")]
+async fn list_schedule() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(scheduler::task_schedules().await?))
+}
+
+/// Runs a task now, whether it is scheduled or not.
+#[post("/schedule/{task}/run")]
+async fn run_task(task: web::Path<String>) -> Result<HttpResponse> {
+    let task = ScheduledTask::from_name(&task).ok_or_else(|| Error::not_found(format!("There is no task named {}", task)))?;
+    let job = scheduler::run_task(task).ok_or_else(|| Error::conflict(format!("{} is running already", task.as_str())))?;
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+#[get("/{id}")]
+async fn get_job(id: web::Path<u64>) -> Result<HttpResponse> {
+    let job = jobs().get(*id).ok_or_else(|| Error::not_found(format!("Job {} does not exist", id)))?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+/// Asks a running job to stop, which it does at the next point it can.
+#[post("/{id}/cancel")]
+async fn cancel_job(id: web::Path<u64>) -> Result<HttpResponse> {
+    let job = jobs().cancel(*id).ok_or_else(|| Error::not_found(format!("Job {} does not exist", id)))?;
+    if job.status != JobStatus::Running {
+        return Err(Error::conflict(format!("Job {} is not running", id)));
+    }
+    Ok(HttpResponse::Ok().json(job))
+}
+
+/// Registers the job and schedule routes, under `/api/jobs`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/jobs")
+            .wrap(Authentication::new())
+            .service(list_jobs)
+            .service(list_schedule)
+            .service(run_task)
+            .service(get_job)
+            .service(cancel_job),
+    );
+}
@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use crate::jobs::job_data::{JobStatus, jobs};
+    use crate::jobs::schedule_data::{CronSchedule, ScheduleConfiguration, ScheduledTask, TaskRun};
+    use crate::jobs::schedule_db;
+    use chrono::NaiveDateTime;
+    use serde_json::json;
+    use sqlx::SqlitePool;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn next(expression: &str, after: &str) -> String {
+        CronSchedule::parse(expression).unwrap().next_after(time(after)).unwrap().format("%Y-%m-%d %H:%M").to_string()
+    }
+
+    #[test]
+    fn test_cron_schedule() {
+        // 2025-06-02 is a Monday
+        assert_eq!(next("0 3 * * *", "2025-06-02 02:59"), "2025-06-02 03:00");
+        assert_eq!(next("0 3 * * *", "2025-06-02 03:00"), "2025-06-03 03:00");
+        assert_eq!(next("*/15 * * * *", "2025-06-02 10:07"), "2025-06-02 10:15");
+        assert_eq!(next("30 4 * * sun", "2025-06-02 12:00"), "2025-06-08 04:30");
+        assert_eq!(next("30 4 * * 7", "2025-06-02 12:00"), "2025-06-08 04:30");
+        assert_eq!(next("0 9 * * mon-fri", "2025-06-06 10:00"), "2025-06-09 09:00");
+        assert_eq!(next("0 0 1,15 * *", "2025-06-02 00:00"), "2025-06-15 00:00");
+        assert_eq!(next("@monthly", "2025-12-31 23:59"), "2026-01-01 00:00");
+        assert_eq!(next("0 12 29 feb *", "2025-03-01 00:00"), "2028-02-29 12:00");
+        // Either day field matches when both are restricted
+        assert_eq!(next("0 0 13 * fri", "2025-06-02 00:00"), "2025-06-06 00:00");
+
+        assert!(CronSchedule::parse("0 3 * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("0 5-2 * * *").is_err());
+        assert!(CronSchedule::parse("0 0 * * someday").is_err());
+        assert!(ScheduleConfiguration::defaults().iter().all(|schedule| CronSchedule::parse(&schedule.cron).is_ok()));
+        assert_eq!(ScheduledTask::from_name("share_cleanup"), Some(ScheduledTask::ShareCleanup));
+    }
+
+    #[test]
+    fn test_job_registry() {
+        let (job, handle) = jobs().start("test_job_registry", None).unwrap();
+        // A job runs once at a time
+        assert!(jobs().start("test_job_registry", None).is_none());
+        assert_eq!(jobs().running("test_job_registry").map(|job| job.id), Some(job.id));
+
+        assert_eq!(jobs().cancel(job.id).map(|job| job.status), Some(JobStatus::Running));
+        assert!(handle.is_cancelled());
+        jobs().finish(handle, Ok(json!({})));
+        assert_eq!(jobs().get(job.id).map(|job| job.status), Some(JobStatus::Cancelled));
+        assert!(jobs().running("test_job_registry").is_none());
+
+        let (job, handle) = jobs().start("test_job_registry", None).unwrap();
+        jobs().finish(handle, Err("failed".to_string()));
+        let job = jobs().get(job.id).unwrap();
+        assert_eq!((job.status, job.error.as_deref()), (JobStatus::Failed, Some("failed")));
+        assert!(jobs().cancel(u64::MAX).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_task_runs() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.expect("Failed to open database");
+        schedule_db::initialize_with_pool(&pool).await.unwrap();
+        let run = |started_at, status: &str| TaskRun {
+            task: ScheduledTask::Reindex.as_str().to_string(),
+            started_at,
+            finished_at: started_at + 10,
+            status: status.to_string(),
+            error: None,
+        };
+        run(100, "failed").save_with_pool(&pool).await.unwrap();
+        run(200, "completed").save_with_pool(&pool).await.unwrap();
+
+        // Only the last run of a task is kept
+        let runs = TaskRun::list_with_pool(&pool).await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!((runs[0].started_at, runs[0].status.as_str()), (200, "completed"));
+    }
+}
@@ -0,0 +1,8 @@
+pub(crate) mod job_data;
+pub(crate) mod job_endpoint;
+pub(crate) mod schedule_data;
+pub(crate) mod schedule_db;
+pub(crate) mod scheduler;
+
+#[cfg(test)]
+mod job_test;
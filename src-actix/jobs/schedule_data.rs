@@ -0,0 +1,233 @@
+use crate::jobs::job_data::Job;
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// How many days ahead [`CronSchedule::next_after`] looks, enough for a schedule that only matches on February 29th.
+const MAX_LOOKAHEAD_DAYS: u32 = 8 * 366;
+
+/// The periodic maintenance work the scheduler runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledTask {
+    /// Reconciles the index with the files on disk, picking up changes the file watcher missed.
+    Reindex,
+    /// Deletes share links that have expired.
+    ShareCleanup,
+    /// Runs `VACUUM` and `ANALYZE` on the database.
+    DatabaseMaintenance,
+}
+
+impl ScheduledTask {
+    pub const ALL: [Self; 3] = [Self::Reindex, Self::ShareCleanup, Self::DatabaseMaintenance];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Reindex => "reindex",
+            Self::ShareCleanup => "share_cleanup",
+            Self::DatabaseMaintenance => "database_maintenance",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|task| task.as_str() == name)
+    }
+}
+
+/// When a task runs on its own, as saved in the configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScheduleConfiguration {
+    pub task: ScheduledTask,
+    /// A cron expression, see [`CronSchedule::parse`].
+    pub cron: String,
+    #[serde(default = "default_schedule_enabled")]
+    pub enabled: bool,
+}
+
+fn default_schedule_enabled() -> bool {
+    true
+}
+
+impl ScheduleConfiguration {
+    /// The schedules a new configuration starts with: a nightly reindex, an hourly share cleanup and a weekly
+    /// database maintenance.
+    pub fn defaults() -> Vec<Self> {
+        let schedule = |task, cron: &str| Self { task, cron: cron.to_string(), enabled: true };
+        vec![
+            schedule(ScheduledTask::Reindex, "0 3 * * *"),
+            schedule(ScheduledTask::ShareCleanup, "0 * * * *"),
+            schedule(ScheduledTask::DatabaseMaintenance, "30 4 * * sun"),
+        ]
+    }
+}
+
+/// A parsed cron expression, matched against the local time of the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month field is something other than `*`. When both day fields are, a day matching
+    /// either of them matches, as in every cron.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronSchedule {
+    /// Parses a five field `minute hour day-of-month month day-of-week` expression, such as `30 4 * * sun`.
+    ///
+    /// Fields take `*`, numbers, ranges such as `1-5`, steps such as `*/15` or `0-30/10`, and lists of these separated
+    /// by commas. Months and days of the week can also be given by their three letter English names, and Sunday is
+    /// both `0` and `7`. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` stand for the usual expressions.
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        };
+
+        const MONTHS: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+        const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+        let mut weekdays_field = parse_field(weekdays, 0, 7, WEEKDAYS, 0)?;
+        // Sunday can be written as 7
+        if weekdays_field & (1 << 7) != 0 {
+            weekdays_field = (weekdays_field | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59, &[], 0)?,
+            hours: parse_field(hours, 0, 23, &[], 0)?,
+            days: parse_field(days, 1, 31, &[], 0)?,
+            months: parse_field(months, 1, 12, MONTHS, 1)?,
+            weekdays: weekdays_field,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        })
+    }
+
+    /// The first minute strictly after `after` that the schedule matches, `None` if it never does.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + chrono::TimeDelta::minutes(1);
+        let mut date = start.date();
+        for _ in 0..MAX_LOOKAHEAD_DAYS {
+            if self.matches_date(date) {
+                let first_day = date == start.date();
+                let first_hour = if first_day { start.hour() } else { 0 };
+                for hour in first_hour..24 {
+                    if self.hours & (1 << hour) == 0 {
+                        continue;
+                    }
+                    let first_minute = if first_day && hour == start.hour() { start.minute() } else { 0 };
+                    if let Some(minute) = (first_minute..60).find(|minute| self.minutes & (1 << minute) != 0) {
+                        return date.and_hms_opt(hour, minute, 0);
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    /// [`Self::next_after`] for a time in seconds since the epoch, skipping local times that don't exist because of a
+    /// daylight saving change.
+    pub fn next_timestamp_after(&self, after: i64) -> Option<i64> {
+        let mut next = Local.timestamp_opt(after, 0).single()?.naive_local();
+        loop {
+            next = self.next_after(next)?;
+            if let Some(time) = Local.from_local_datetime(&next).earliest() {
+                // Leaving a daylight saving time, the local times of the repeated hour come before `after` once
+                if time.timestamp() > after {
+                    return Some(time.timestamp());
+                }
+            }
+        }
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        }
+    }
+}
+
+/// Parses a cron field into a bit set of the values it matches, where `names` are the names of the values from
+/// `first_name` on.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], first_name: u32) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let value = match names.iter().position(|name| name.eq_ignore_ascii_case(text)) {
+            Some(index) => index as u32 + first_name,
+            None => text.parse().map_err(|_| format!("invalid value {:?}", text))?,
+        };
+        if !(min..=max).contains(&value) {
+            return Err(format!("{} is out of range {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(|| format!("invalid step {:?}", step))?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 to the end of the range
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("invalid range {:?}", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// The last run of a scheduled task, kept across restarts so that runs missed while the server was stopped are caught
+/// up on.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TaskRun {
+    pub task: String,
+    /// In seconds since the epoch.
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// `completed`, `failed` or `cancelled`.
+    pub status: String,
+    pub error: Option<String>,
+}
+
+/// How a task is scheduled, as listed by the schedule endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskSchedule {
+    pub task: ScheduledTask,
+    /// The cron expression of the task, `None` when it only runs when triggered.
+    pub cron: Option<String>,
+    pub enabled: bool,
+    /// In seconds since the epoch, `None` when the task isn't scheduled.
+    pub next_run: Option<i64>,
+    pub last_run: Option<TaskRun>,
+    /// The job running the task right now.
+    pub job: Option<Job>,
+}
@@ -0,0 +1,60 @@
+use crate::helpers::db::create_pool;
+use crate::jobs::schedule_data::TaskRun;
+use anyhow::Result;
+use sqlx::{Executor, SqlitePool};
+
+pub async fn initialize() -> Result<()> {
+    let pool = create_pool().await?;
+    initialize_with_pool(&pool).await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+pub async fn initialize_with_pool(pool: &SqlitePool) -> Result<()> {
+    pool.execute(
+        r#"
+CREATE TABLE IF NOT EXISTS scheduled_task_runs
+(
+    task        TEXT    NOT NULL PRIMARY KEY,
+    started_at  INTEGER NOT NULL,
+    finished_at INTEGER NOT NULL,
+    status      TEXT    NOT NULL,
+    error       TEXT             DEFAULT NULL
+)
+"#,
+    )
+    .await?;
+    Ok(())
+}
+
+impl TaskRun {
+    /// The last run of every task that ran before.
+    pub async fn list() -> Result<Vec<Self>> {
+        let pool = create_pool().await?;
+        Self::list_with_pool(&pool).await
+    }
+
+    pub async fn list_with_pool(pool: &SqlitePool) -> Result<Vec<Self>> {
+        let runs = sqlx::query_as::<_, Self>("select * from scheduled_task_runs").fetch_all(pool).await?;
+        Ok(runs)
+    }
+
+    /// Stores the run as the last one of its task.
+    pub async fn save(&self) -> Result<()> {
+        let pool = create_pool().await?;
+        self.save_with_pool(&pool).await
+    }
+
+    pub async fn save_with_pool(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query("insert or replace into scheduled_task_runs (task, started_at, finished_at, status, error) values (?, ?, ?, ?, ?)")
+            .bind(&self.task)
+            .bind(self.started_at)
+            .bind(self.finished_at)
+            .bind(&self.status)
+            .bind(&self.error)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
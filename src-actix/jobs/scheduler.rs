@@ -0,0 +1,175 @@
+use crate::configuration::configuration_data::Configuration;
+use crate::helpers::db::{DATABASE_PATH, create_pool};
+use crate::io::fs::indexer::indexer_data;
+use crate::io::fs::indexer::indexer_state::indexer_state;
+use crate::jobs::job_data::{Job, JobHandle, jobs};
+use crate::jobs::schedule_data::{CronSchedule, ScheduleConfiguration, ScheduledTask, TaskRun, TaskSchedule};
+use crate::shares::share_data::{Share, unix_now};
+use anyhow::Result;
+use log::{error, info, warn};
+use serde_json::{Value, json};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// The longest the scheduler sleeps before it looks at the schedules again.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// The configuration the scheduler runs by, replaced when a saved configuration changes. Falls back to the
+/// configuration loaded at startup.
+static SCHEDULER_CONFIGURATION: RwLock<Option<Arc<Configuration>>> = RwLock::new(None);
+/// Wakes the scheduler up when the schedules change.
+static SCHEDULES_CHANGED: OnceLock<Notify> = OnceLock::new();
+/// When the scheduler started, which tasks that never ran are scheduled from.
+static STARTED_AT: OnceLock<i64> = OnceLock::new();
+
+fn scheduler_configuration() -> Arc<Configuration> {
+    if let Some(config) = SCHEDULER_CONFIGURATION.read().unwrap().as_ref() {
+        return config.clone();
+    }
+    SCHEDULER_CONFIGURATION.write().unwrap().get_or_insert_with(|| Arc::new(Configuration::get().clone())).clone()
+}
+
+fn schedules_changed() -> &'static Notify {
+    SCHEDULES_CHANGED.get_or_init(Notify::new)
+}
+
+/// Runs the scheduled tasks in the background as their schedules come due.
+///
+/// A task whose run was missed while the server was stopped runs once as soon as it starts.
+pub fn start_scheduler() {
+    STARTED_AT.get_or_init(unix_now);
+    tokio::spawn(async {
+        info!("Starting scheduler...");
+        loop {
+            let mut sleep = MAX_SLEEP;
+            match next_runs().await {
+                Ok(next_runs) => {
+                    let now = unix_now();
+                    for (task, next_run) in next_runs {
+                        if next_run <= now {
+                            run_task(task);
+                        } else {
+                            sleep = sleep.min(Duration::from_secs((next_run - now) as u64));
+                        }
+                    }
+                }
+                Err(e) => error!("Error reading the scheduled task runs: {}", e),
+            }
+            // Either way the schedules are looked at again, the notification only cuts the wait short
+            let _ = tokio::time::timeout(sleep, schedules_changed().notified()).await;
+        }
+    });
+}
+
+/// Applies the schedules of a saved configuration.
+pub fn handle_config_change(new_config: &Configuration) {
+    *SCHEDULER_CONFIGURATION.write().unwrap() = Some(Arc::new(new_config.clone()));
+    schedules_changed().notify_one();
+}
+
+/// The next run of every enabled task, in seconds since the epoch.
+async fn next_runs() -> Result<Vec<(ScheduledTask, i64)>> {
+    let runs = TaskRun::list().await?;
+    let config = scheduler_configuration();
+    Ok(config.schedules.iter().filter(|schedule| schedule.enabled).filter_map(|schedule| Some((schedule.task, next_run(schedule, &runs)?))).collect())
+}
+
+fn next_run(schedule: &ScheduleConfiguration, runs: &[TaskRun]) -> Option<i64> {
+    let cron = CronSchedule::parse(&schedule.cron)
+        .inspect_err(|e| warn!("Invalid schedule {:?} for {}: {}", schedule.cron, schedule.task.as_str(), e))
+        .ok()?;
+    let last_run = runs.iter().find(|run| run.task == schedule.task.as_str()).map(|run| run.started_at);
+    cron.next_timestamp_after(last_run.unwrap_or_else(|| *STARTED_AT.get_or_init(unix_now)))
+}
+
+/// The schedule, last run and running job of every task, whether it is scheduled or not.
+pub async fn task_schedules() -> Result<Vec<TaskSchedule>> {
+    let runs = TaskRun::list().await?;
+    let config = scheduler_configuration();
+    Ok(ScheduledTask::ALL
+        .into_iter()
+        .map(|task| {
+            let schedule = config.schedules.iter().find(|schedule| schedule.task == task);
+            TaskSchedule {
+                task,
+                cron: schedule.map(|schedule| schedule.cron.clone()),
+                enabled: schedule.is_some_and(|schedule| schedule.enabled),
+                next_run: schedule.filter(|schedule| schedule.enabled).and_then(|schedule| next_run(schedule, &runs)),
+                last_run: runs.iter().find(|run| run.task == task.as_str()).cloned(),
+                job: jobs().running(task.as_str()),
+            }
+        })
+        .collect())
+}
+
+/// Starts `task` as a job, returning `None` when it is running already.
+pub fn run_task(task: ScheduledTask) -> Option<Job> {
+    let on_cancel: Option<fn()> = match task {
+        ScheduledTask::Reindex => Some(|| {
+            indexer_state().cancel();
+        }),
+        _ => None,
+    };
+    let (job, handle) = jobs().start(task.as_str(), on_cancel)?;
+    let started_at = job.started_at;
+    tokio::spawn(async move {
+        info!("Running {}", task.as_str());
+        let outcome = execute(task, &handle).await.map_err(|e| format!("{:#}", e));
+        if let Err(e) = &outcome {
+            error!("Error running {}: {}", task.as_str(), e);
+        }
+        let id = handle.id;
+        jobs().finish(handle, outcome);
+
+        let Some(job) = jobs().get(id) else {
+            return;
+        };
+        let run = TaskRun {
+            task: task.as_str().to_string(),
+            started_at,
+            finished_at: job.finished_at.unwrap_or_else(unix_now),
+            status: job.status.as_str().to_string(),
+            error: job.error,
+        };
+        if let Err(e) = run.save().await {
+            error!("Error saving the run of {}: {}", task.as_str(), e);
+        }
+    });
+    Some(job)
+}
+
+async fn execute(task: ScheduledTask, handle: &JobHandle) -> Result<Value> {
+    match task {
+        ScheduledTask::Reindex => {
+            if !scheduler_configuration().indexing_enabled {
+                return Ok(json!({ "skipped": "Indexing is disabled" }));
+            }
+            // A run started elsewhere, such as at startup, is let finish first
+            indexer_state().wait_idle().await;
+            if handle.is_cancelled() {
+                return Ok(Value::Null);
+            }
+            Ok(json!(indexer_data::reconcile_index().await?))
+        }
+        ScheduledTask::ShareCleanup => {
+            let removed = Share::delete_expired(unix_now()).await?;
+            Ok(json!({ "removed": removed }))
+        }
+        ScheduledTask::DatabaseMaintenance => {
+            let database_size = || std::fs::metadata(DATABASE_PATH).map(|metadata| metadata.len()).unwrap_or_default();
+            let size_before = database_size();
+            let pool = create_pool().await?;
+            sqlx::query("ANALYZE").execute(&pool).await?;
+            // VACUUM can't be interrupted, so this is the last chance to stop
+            if handle.is_cancelled() {
+                return Ok(Value::Null);
+            }
+            sqlx::query("VACUUM").execute(&pool).await?;
+            // The vacuumed database is written to the WAL first, the file only shrinks once it is checkpointed
+            sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&pool).await?;
+            pool.close().await;
+            Ok(json!({ "size_before": size_before, "size_after": database_size() }))
+        }
+    }
+}
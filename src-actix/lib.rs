@@ -3,12 +3,17 @@ use crate::auth::{auth_db, auth_endpoint};
 use crate::configuration::configuration_data::Configuration;
 use crate::configuration::configuration_endpoint;
 use crate::configuration::upnp;
+use crate::dav::dav_endpoint;
 use crate::helpers::asset_endpoint::AssetsAppConfig;
 use crate::helpers::constants::DEBUG;
 use crate::internal_configuration::{ic_db, ic_endpoint};
 use crate::io::fs::indexer::indexer_data::IndexerData;
-use crate::io::fs::indexer::{indexer_data, indexer_db};
+use crate::io::fs::indexer::{content_db, indexer_data, indexer_db};
+use crate::jobs::{job_endpoint, schedule_db, scheduler};
 use crate::middleware::network::NetworkMiddleware;
+use crate::s3::{s3_db, s3_endpoint};
+use crate::sftp::{sftp_db, sftp_endpoint, sftp_server};
+use crate::shares::{share_db, share_endpoint};
 use actix_web::{App, HttpResponse, HttpServer, middleware as actix_middleware, web};
 use anyhow::Result;
 use clap::Parser;
@@ -24,10 +29,15 @@ use vite_actix::start_vite_server;
 pub mod arguments;
 pub mod auth;
 pub mod configuration;
+pub mod dav;
 pub mod helpers;
 pub mod internal_configuration;
 pub mod io;
+pub mod jobs;
 pub mod middleware;
+pub mod s3;
+pub mod sftp;
+pub mod shares;
 
 pub async fn run() -> Result<()> {
     pretty_env_logger::env_logger::builder().filter_level(LevelFilter::Debug).format_timestamp(None).init();
@@ -73,16 +83,27 @@ pub async fn run() -> Result<()> {
 
     auth_db::initialize().await?;
     ic_db::initialize().await?;
+    share_db::initialize().await?;
+    sftp_db::initialize().await?;
+    s3_db::initialize().await?;
+    schedule_db::initialize().await?;
+
+    if config.sftp_enabled {
+        let sftp_port = config.sftp_port;
+        tokio::spawn(async move {
+            if let Err(e) = sftp_server::start(sftp_port).await {
+                error!("Error starting SFTP server: {}", e);
+            }
+        });
+    }
 
     // Start file indexing and watcher in a separate task to avoid blocking server startup
     if !args.disable_indexing && config.indexing_enabled {
-        if !IndexerData::does_table_exist().await? {
-            indexer_db::initialize().await?;
-            tokio::spawn(async {
-                if let Err(e) = indexer_data::index_all_files().await {
-                    error!("Error starting file indexer: {}", e);
-                }
-            });
+        // A missing table means a new database or one from before content indexing, either way it needs filling
+        let needs_indexing = !IndexerData::does_table_exist().await? || !content_db::does_table_exist().await?;
+        indexer_db::initialize().await?;
+        if needs_indexing {
+            indexer_data::start_reconcile();
         }
         if !args.disable_filewatchers && config.file_watcher_enabled {
             tokio::spawn(async {
@@ -93,6 +114,8 @@ pub async fn run() -> Result<()> {
             });
         }
     }
+    scheduler::start_scheduler();
+
     let server = HttpServer::new(move || {
         App::new()
             .wrap(actix_middleware::Logger::default())
@@ -107,9 +130,16 @@ pub async fn run() -> Result<()> {
                     .configure(filesystem_endpoint::configure)
                     .configure(configuration_endpoint::configure)
                     .configure(ic_endpoint::configure)
+                    .configure(share_endpoint::configure)
+                    .configure(sftp_endpoint::configure)
+                    .configure(s3_endpoint::configure)
+                    .configure(job_endpoint::configure)
                     // Handle unmatched API endpoints
                     .default_service(web::to(|| async { HttpResponse::NotFound().json(json!({"error": "API endpoint not found"})) })),
             )
+            .configure(share_endpoint::configure_public)
+            .configure(dav_endpoint::configure)
+            .configure(s3_endpoint::configure_gateway)
             .configure_frontend_routes()
     })
     .workers(4)
@@ -0,0 +1,7 @@
+pub(crate) mod s3_data;
+pub(crate) mod s3_db;
+pub(crate) mod s3_endpoint;
+pub(crate) mod s3_signature;
+
+#[cfg(test)]
+mod s3_test;
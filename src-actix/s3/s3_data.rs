@@ -0,0 +1,381 @@
+use crate::auth::permission_flags::PermissionFlags;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use enumflags2::BitFlags;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::time::SystemTime;
+
+/// The namespace of every S3 response document.
+pub(crate) const S3_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// An access key a user created to sign S3 requests with.
+///
+/// SigV4 signatures are an HMAC of the secret, so unlike passwords the secret is stored as is. It is only
+/// returned once, when the key is created.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct S3AccessKey {
+    pub id: i64,
+    pub access_key_id: String,
+    #[serde(skip_serializing)]
+    pub secret_access_key: String,
+    pub username: String,
+    pub created_at: i64,
+}
+
+impl S3AccessKey {
+    /// Generates a new key pair, e.g. `FLR1A2B3C4D5E6F7A8B9` and a 40 character secret.
+    pub fn generate(username: impl AsRef<str>) -> Self {
+        let access_key_id = format!("FLR{}", &uuid::Uuid::new_v4().simple().to_string()[..17]).to_uppercase();
+        let secret_access_key = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())[..40].to_string();
+        Self { id: 0, access_key_id, secret_access_key, username: username.as_ref().to_string(), created_at: 0 }
+    }
+}
+
+/// An error in the XML format S3 clients expect.
+#[derive(thiserror::Error, Debug)]
+#[error("{code}: {message}")]
+pub struct S3Error {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl S3Error {
+    pub fn new<S: Into<String>>(status: StatusCode, code: &'static str, message: S) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    pub fn access_denied() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied")
+    }
+
+    pub fn signature_mismatch() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "SignatureDoesNotMatch", "The request signature we calculated does not match the signature you provided")
+    }
+
+    pub fn no_such_bucket() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NoSuchBucket", "The specified bucket does not exist")
+    }
+
+    pub fn no_such_key() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NoSuchKey", "The specified key does not exist")
+    }
+
+    pub fn no_such_upload() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NoSuchUpload", "The specified multipart upload does not exist")
+    }
+
+    pub fn invalid_argument<S: Into<String>>(message: S) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+    }
+
+    pub fn not_implemented() -> Self {
+        Self::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", "A header or operation you provided is not implemented")
+    }
+}
+
+impl ResponseError for S3Error {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = ErrorDocument { code: self.code, message: &self.message };
+        HttpResponse::build(self.status).content_type("application/xml").body(to_xml(&body))
+    }
+}
+
+impl From<std::io::Error> for S3Error {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => Self::no_such_key(),
+            std::io::ErrorKind::PermissionDenied => Self::access_denied(),
+            _ => Self::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", err.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for S3Error {
+    fn from(err: anyhow::Error) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", err.to_string())
+    }
+}
+
+pub type S3Result<T> = Result<T, S3Error>;
+
+/// The query parameters of ListObjects and ListObjectsV2.
+const LIST_PARAMETERS: [&str; 9] =
+    ["list-type", "prefix", "delimiter", "max-keys", "continuation-token", "start-after", "marker", "encoding-type", "fetch-owner"];
+
+/// The S3 operations Filer implements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operation {
+    ListBuckets,
+    HeadBucket,
+    CreateBucket,
+    GetBucketLocation,
+    /// Both versions of ListObjects, clients such as rclone still use the first one.
+    ListObjects,
+    GetObject,
+    HeadObject,
+    PutObject,
+    DeleteObject,
+    CreateMultipartUpload,
+    UploadPart,
+    CompleteMultipartUpload,
+    AbortMultipartUpload,
+}
+
+impl Operation {
+    /// Works out the operation from the method, whether a bucket and key were given, and the query parameter names.
+    pub fn parse(method: &str, bucket: Option<&str>, key: Option<&str>, query: &[(String, String)]) -> Option<Self> {
+        let has = |name: &str| query.iter().any(|(key, _)| key == name);
+        let operation = match (method, bucket, key) {
+            ("GET", None, None) => Self::ListBuckets,
+            ("HEAD", Some(_), None) => Self::HeadBucket,
+            ("PUT", Some(_), None) => Self::CreateBucket,
+            ("GET", Some(_), None) if has("location") => Self::GetBucketLocation,
+            // Bucket level sub-resources such as ?versioning or ?policy aren't supported
+            ("GET", Some(_), None) if query.iter().all(|(key, _)| LIST_PARAMETERS.contains(&key.as_str())) => Self::ListObjects,
+            ("GET", Some(_), Some(_)) => Self::GetObject,
+            ("HEAD", Some(_), Some(_)) => Self::HeadObject,
+            ("PUT", Some(_), Some(_)) if has("uploadId") && has("partNumber") => Self::UploadPart,
+            ("PUT", Some(_), Some(_)) => Self::PutObject,
+            ("DELETE", Some(_), Some(_)) if has("uploadId") => Self::AbortMultipartUpload,
+            ("DELETE", Some(_), Some(_)) => Self::DeleteObject,
+            ("POST", Some(_), Some(_)) if has("uploads") => Self::CreateMultipartUpload,
+            ("POST", Some(_), Some(_)) if has("uploadId") => Self::CompleteMultipartUpload,
+            _ => return None,
+        };
+        Some(operation)
+    }
+
+    /// The permissions a user needs for the operation.
+    ///
+    /// `target_exists` tells whether the object is already on disk, so that uploads can tell overwriting
+    /// a file apart from adding a new one.
+    pub fn required_permissions(&self, target_exists: bool) -> BitFlags<PermissionFlags> {
+        match self {
+            Self::ListBuckets | Self::HeadBucket | Self::GetBucketLocation | Self::ListObjects => PermissionFlags::Read.into(),
+            Self::GetObject | Self::HeadObject => PermissionFlags::Read | PermissionFlags::Download,
+            Self::CreateBucket => PermissionFlags::Create.into(),
+            Self::PutObject | Self::CreateMultipartUpload | Self::CompleteMultipartUpload if target_exists => PermissionFlags::Write.into(),
+            Self::PutObject | Self::CreateMultipartUpload | Self::CompleteMultipartUpload => PermissionFlags::Upload.into(),
+            // Parts can only be sent to an upload the same user started
+            Self::UploadPart | Self::AbortMultipartUpload => BitFlags::empty(),
+            Self::DeleteObject => PermissionFlags::Delete.into(),
+        }
+    }
+
+    /// Whether the operation changes the files of a bucket, which read-only mounts refuse.
+    pub fn is_change(&self) -> bool {
+        matches!(self, Self::CreateBucket | Self::PutObject | Self::DeleteObject | Self::CreateMultipartUpload | Self::CompleteMultipartUpload)
+    }
+}
+
+/// A file found while listing a bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ListedEntry {
+    pub key: String,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// The page of a listing that is returned to the client.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ListingPage {
+    pub objects: Vec<ListedEntry>,
+    pub common_prefixes: Vec<String>,
+    /// The last key or prefix of the page, set when more keys follow.
+    pub next_marker: Option<String>,
+}
+
+/// Applies the ListObjects parameters to `entries`, which have to be sorted by key.
+///
+/// Keys containing `delimiter` after the prefix are rolled up into a single common prefix. Only keys after
+/// `start_after` are returned, and at most `max_keys` objects and prefixes together.
+pub(crate) fn paginate(entries: &[ListedEntry], prefix: &str, delimiter: Option<&str>, start_after: Option<&str>, max_keys: usize) -> ListingPage {
+    let mut page = ListingPage::default();
+    let mut returned = 0;
+    for entry in entries {
+        if !entry.key.starts_with(prefix) {
+            continue;
+        }
+        let common_prefix = delimiter.filter(|delimiter| !delimiter.is_empty()).and_then(|delimiter| {
+            entry.key[prefix.len()..].find(delimiter).map(|index| entry.key[..prefix.len() + index + delimiter.len()].to_string())
+        });
+        let marker = common_prefix.as_deref().unwrap_or(&entry.key);
+        if start_after.is_some_and(|start_after| marker <= start_after) || page.common_prefixes.last().is_some_and(|last| last == marker) {
+            continue;
+        }
+        if returned == max_keys {
+            page.next_marker = page.objects.last().map(|entry| entry.key.clone()).max(page.common_prefixes.last().cloned());
+            break;
+        }
+        returned += 1;
+        match common_prefix {
+            Some(common_prefix) => page.common_prefixes.push(common_prefix),
+            None => page.objects.push(entry.clone()),
+        }
+    }
+    page
+}
+
+/// An ETag for a file that doesn't need it to be read, which is enough for clients to notice changes.
+pub(crate) fn quick_etag(size: u64, modified: SystemTime) -> String {
+    let modified = modified.duration_since(SystemTime::UNIX_EPOCH).map(|duration| duration.as_nanos()).unwrap_or_default();
+    format!("\"{:x}-{:x}\"", modified, size)
+}
+
+/// Formats a time the way S3 listings do, e.g. `2009-10-12T17:50:30.000Z`.
+pub(crate) fn iso8601(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// Serializes an S3 response document, including the XML declaration.
+pub(crate) fn to_xml<T: Serialize>(document: &T) -> String {
+    let body = quick_xml::se::to_string(document).unwrap_or_default();
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", body)
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Error", rename_all = "PascalCase")]
+struct ErrorDocument<'a> {
+    code: &'a str,
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Owner {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub display_name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "ListAllMyBucketsResult", rename_all = "PascalCase")]
+pub(crate) struct ListAllMyBucketsResult {
+    #[serde(rename = "@xmlns")]
+    xmlns: &'static str,
+    pub owner: Owner,
+    pub buckets: Buckets,
+}
+
+impl ListAllMyBucketsResult {
+    pub fn new(owner: Owner, buckets: Vec<Bucket>) -> Self {
+        Self { xmlns: S3_NAMESPACE, owner, buckets: Buckets { bucket: buckets } }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Buckets {
+    #[serde(rename = "Bucket")]
+    pub bucket: Vec<Bucket>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Bucket {
+    pub name: String,
+    pub creation_date: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "LocationConstraint")]
+pub(crate) struct LocationConstraint {
+    #[serde(rename = "@xmlns")]
+    xmlns: &'static str,
+    /// Empty stands for `us-east-1`, Filer accepts requests signed for any region.
+    #[serde(rename = "$text")]
+    region: &'static str,
+}
+
+impl Default for LocationConstraint {
+    fn default() -> Self {
+        Self { xmlns: S3_NAMESPACE, region: "" }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "ListBucketResult", rename_all = "PascalCase")]
+pub(crate) struct ListBucketResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_marker: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_after: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_continuation_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_count: Option<usize>,
+    pub max_keys: usize,
+    pub is_truncated: bool,
+    pub contents: Vec<ObjectEntry>,
+    pub common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ObjectEntry {
+    pub key: String,
+    pub last_modified: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    pub size: u64,
+    pub storage_class: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct CommonPrefix {
+    pub prefix: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "InitiateMultipartUploadResult", rename_all = "PascalCase")]
+pub(crate) struct InitiateMultipartUploadResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub bucket: String,
+    pub key: String,
+    pub upload_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "CompleteMultipartUploadResult", rename_all = "PascalCase")]
+pub(crate) struct CompleteMultipartUploadResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub bucket: String,
+    pub key: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+}
+
+/// The body of a CompleteMultipartUpload request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CompleteMultipartUpload {
+    #[serde(rename = "Part", default)]
+    pub parts: Vec<CompletedPart>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CompletedPart {
+    #[serde(rename = "PartNumber")]
+    pub part_number: u32,
+    #[serde(rename = "ETag")]
+    pub etag: Option<String>,
+}
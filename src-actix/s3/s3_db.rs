@@ -0,0 +1,82 @@
+use crate::helpers::db::create_pool;
+use crate::s3::s3_data::S3AccessKey;
+use crate::shares::share_data::unix_now;
+use anyhow::Result;
+use sqlx::{Executor, SqlitePool};
+
+pub async fn initialize() -> Result<()> {
+    let pool = create_pool().await?;
+    initialize_with_pool(&pool).await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+pub async fn initialize_with_pool(pool: &SqlitePool) -> Result<()> {
+    pool.execute(
+        r#"
+CREATE TABLE IF NOT EXISTS s3_access_keys
+(
+    id                INTEGER PRIMARY KEY,
+    access_key_id     TEXT    NOT NULL UNIQUE,
+    secret_access_key TEXT    NOT NULL,
+    username          TEXT    NOT NULL,
+    created_at        INTEGER NOT NULL
+)
+"#,
+    )
+    .await?;
+    Ok(())
+}
+
+impl S3AccessKey {
+    /// Generates and stores a new access key for `username`.
+    pub async fn create(username: impl AsRef<str>) -> Result<Self> {
+        let pool = create_pool().await?;
+        Self::create_with_pool(username, &pool).await
+    }
+
+    pub async fn create_with_pool(username: impl AsRef<str>, pool: &SqlitePool) -> Result<Self> {
+        let mut key = Self::generate(username);
+        key.created_at = unix_now();
+        let result = sqlx::query("insert into s3_access_keys (access_key_id, secret_access_key, username, created_at) values (?, ?, ?, ?)")
+            .bind(&key.access_key_id)
+            .bind(&key.secret_access_key)
+            .bind(&key.username)
+            .bind(key.created_at)
+            .execute(pool)
+            .await?;
+        key.id = result.last_insert_rowid();
+        Ok(key)
+    }
+
+    pub async fn get_by_access_key_id(access_key_id: impl AsRef<str>) -> Result<Option<Self>> {
+        let pool = create_pool().await?;
+        Self::get_by_access_key_id_with_pool(access_key_id, &pool).await
+    }
+
+    pub async fn get_by_access_key_id_with_pool(access_key_id: impl AsRef<str>, pool: &SqlitePool) -> Result<Option<Self>> {
+        let key = sqlx::query_as::<_, Self>("select * from s3_access_keys where access_key_id = ? limit 1")
+            .bind(access_key_id.as_ref())
+            .fetch_optional(pool)
+            .await?;
+        Ok(key)
+    }
+
+    pub async fn list_by_username(username: impl AsRef<str>) -> Result<Vec<Self>> {
+        let pool = create_pool().await?;
+        Self::list_by_username_with_pool(username, &pool).await
+    }
+
+    pub async fn list_by_username_with_pool(username: impl AsRef<str>, pool: &SqlitePool) -> Result<Vec<Self>> {
+        let keys = sqlx::query_as::<_, Self>("select * from s3_access_keys where username = ?").bind(username.as_ref()).fetch_all(pool).await?;
+        Ok(keys)
+    }
+
+    /// Deletes a key of `username`, returning `false` if the user has no key with that id.
+    pub async fn delete(username: impl AsRef<str>, id: i64) -> Result<bool> {
+        let pool = create_pool().await?;
+        let result = sqlx::query("delete from s3_access_keys where username = ? and id = ?").bind(username.as_ref()).bind(id).execute(&pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
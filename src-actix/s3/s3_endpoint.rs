@@ -0,0 +1,771 @@
+use crate::auth::auth_data::User;
+use crate::auth::auth_middleware::{Authentication, authenticated_user};
+use crate::auth::permission_flags::PermissionFlags;
+use crate::configuration::configuration_data::Configuration;
+use crate::helpers::http_error;
+use crate::io::fs::indexer::indexer_data;
+use crate::io::fs::normalize_path::mounted_path;
+use crate::io::storage::storage_backend;
+use crate::io::storage::storage_backend::{ByteWriter, StorageBackend, StorageEntry};
+use crate::s3::s3_data::*;
+use crate::s3::s3_signature::{
+    ChunkedDecoder, SignatureHeader, UNSIGNED_PAYLOAD, canonical_request, parse_query, signing_key, string_to_sign, uri_decode, uri_encode, verify,
+};
+use actix_files::{HttpRange, NamedFile};
+use actix_web::http::header::{self, HeaderValue, HttpDate};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, delete, get, post, web};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::StreamExt;
+use log::*;
+use md5::Md5;
+use notify::event::{CreateKind, ModifyKind, RemoveKind};
+use notify::{Event, EventKind};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio_util::io::ReaderStream;
+
+/// The url prefix the S3 gateway is mounted under.
+pub(crate) const S3_PREFIX: &str = "/s3";
+
+/// How far the `x-amz-date` of a request may be from the server's clock, the same window AWS allows.
+const MAX_CLOCK_SKEW: chrono::TimeDelta = chrono::TimeDelta::minutes(15);
+
+/// The most keys a single listing returns.
+const MAX_KEYS: usize = 1000;
+
+/// The largest CompleteMultipartUpload document accepted, which fits the 10,000 parts S3 allows.
+const MAX_COMPLETE_BODY: usize = 2 * 1024 * 1024;
+
+/// The suffix of files that are still being uploaded, they are renamed once complete.
+const PARTIAL_UPLOAD_SUFFIX: &str = ".s3upload";
+
+/// How the body of a request is covered by its signature, from the `x-amz-content-sha256` header.
+enum PayloadHash {
+    Unsigned,
+    /// The body is sent with `aws-chunked` encoding.
+    Streaming,
+    Sha256(String),
+}
+
+impl PayloadHash {
+    fn parse(value: &str) -> Self {
+        if value == UNSIGNED_PAYLOAD {
+            Self::Unsigned
+        } else if value.starts_with("STREAMING-") {
+            Self::Streaming
+        } else {
+            Self::Sha256(value.to_lowercase())
+        }
+    }
+}
+
+/// A multipart upload that was started but not completed or aborted yet.
+struct MultipartUpload {
+    username: String,
+    target: PathBuf,
+    /// Where the parts are kept until the upload is completed.
+    directory: PathBuf,
+}
+
+static MULTIPART_UPLOADS: OnceLock<Mutex<HashMap<String, MultipartUpload>>> = OnceLock::new();
+
+fn multipart_directory() -> PathBuf {
+    std::env::temp_dir().join("filer-s3-uploads")
+}
+
+fn get_multipart_uploads() -> &'static Mutex<HashMap<String, MultipartUpload>> {
+    MULTIPART_UPLOADS.get_or_init(|| {
+        // Parts left behind by a previous run can never be completed
+        std::fs::remove_dir_all(multipart_directory()).ok();
+        Mutex::new(HashMap::new())
+    })
+}
+
+fn header_value<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+fn query_parameter<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+/// Verifies the SigV4 signature of a request, returning the user of the access key it was signed with.
+async fn authenticate(req: &HttpRequest) -> S3Result<(User, PayloadHash)> {
+    let authorization = header_value(req, header::AUTHORIZATION.as_str()).ok_or_else(S3Error::access_denied)?;
+    let signature = SignatureHeader::parse(authorization)
+        .ok_or_else(|| S3Error::invalid_argument("Only AWS4-HMAC-SHA256 authorization headers are supported"))?;
+
+    let amz_date = header_value(req, "x-amz-date").ok_or_else(S3Error::access_denied)?;
+    let signed_at = chrono::NaiveDateTime::parse_from_str(amz_date, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| S3Error::invalid_argument("Invalid x-amz-date header"))?
+        .and_utc();
+    if (chrono::Utc::now() - signed_at).abs() > MAX_CLOCK_SKEW {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "RequestTimeTooSkewed",
+            "The difference between the request time and the server's time is too large",
+        ));
+    }
+    if !amz_date.starts_with(&signature.date) || !signature.signed_headers.iter().any(|name| name == "host") {
+        return Err(S3Error::signature_mismatch());
+    }
+
+    let key = S3AccessKey::get_by_access_key_id(&signature.access_key_id)
+        .await?
+        .ok_or_else(|| S3Error::new(StatusCode::FORBIDDEN, "InvalidAccessKeyId", "The access key ID you provided does not exist in our records"))?;
+    let user = User::get_by_username(&key.username).await?.ok_or_else(S3Error::access_denied)?;
+
+    let payload_hash = header_value(req, "x-amz-content-sha256").unwrap_or(UNSIGNED_PAYLOAD);
+    let connection_info = req.connection_info();
+    let headers = signature
+        .signed_headers
+        .iter()
+        .map(|name| {
+            let values = req.headers().get_all(name.as_str()).filter_map(|value| value.to_str().ok()).map(str::trim).collect::<Vec<_>>();
+            // HTTP/2 requests carry the host in the :authority pseudo header instead
+            let value = if values.is_empty() && name == "host" { connection_info.host().to_string() } else { values.join(",") };
+            (name.clone(), value)
+        })
+        .collect::<Vec<_>>();
+    let canonical_request = canonical_request(req.method().as_str(), req.path(), req.query_string(), &headers, payload_hash);
+    let string_to_sign = string_to_sign(amz_date, &signature.scope(), &canonical_request);
+    if !verify(&signing_key(&key.secret_access_key, &signature.date, &signature.region, &signature.service), &string_to_sign, &signature.signature) {
+        debug!("S3 signature mismatch for {}, canonical request:\n{}", key.username, canonical_request);
+        return Err(S3Error::signature_mismatch());
+    }
+
+    Ok((user, PayloadHash::parse(payload_hash)))
+}
+
+/// Splits a url path under [`S3_PREFIX`] into the decoded bucket and key.
+pub(crate) fn split_path(path: &str) -> (Option<String>, Option<String>) {
+    let path = uri_decode(path.strip_prefix(S3_PREFIX).unwrap_or(path));
+    let path = path.strip_prefix('/').unwrap_or(&path);
+    match path.split_once('/') {
+        _ if path.is_empty() => (None, None),
+        None | Some((_, "")) => (Some(path.trim_end_matches('/').to_string()), None),
+        Some((bucket, key)) => (Some(bucket.to_string()), Some(key.to_string())),
+    }
+}
+
+/// Maps a bucket and key to a path under `root`.
+///
+/// Buckets are the directories directly under the root path. Returns `None` for names that can't be
+/// mapped to a path safely, such as keys containing `..` segments.
+pub(crate) fn object_path(root: &Path, bucket: &str, key: Option<&str>) -> Option<PathBuf> {
+    if bucket.is_empty() || bucket == "." || bucket == ".." || bucket.contains(['/', '\\']) {
+        return None;
+    }
+    let mut path = root.join(bucket);
+    if let Some(key) = key {
+        for segment in key.strip_suffix('/').unwrap_or(key).split('/') {
+            if segment.is_empty() || segment == "." || segment == ".." || segment.contains('\\') {
+                return None;
+            }
+            path.push(segment);
+        }
+    }
+    Some(path)
+}
+
+/// Refuses paths that lead outside `root` through a symlink, checking paths that don't exist yet through
+/// their closest existing ancestor.
+fn confine(root: &Path, path: PathBuf) -> S3Result<PathBuf> {
+    let mut existing = path.as_path();
+    while !existing.exists() {
+        existing = existing.parent().ok_or_else(S3Error::access_denied)?;
+    }
+    if existing.canonicalize()?.starts_with(root.canonicalize()?) { Ok(path) } else { Err(S3Error::access_denied()) }
+}
+
+/// Maps a bucket and key to the OS path that stores it, like [`object_path`], except that buckets named after a
+/// mount are served from that mount.
+pub(crate) fn resolve_object(config: &Configuration, bucket: &str, key: Option<&str>) -> S3Result<PathBuf> {
+    let root = Path::new(&config.root_path);
+    let path = object_path(root, bucket, key)
+        .ok_or_else(|| S3Error::invalid_argument(if key.is_some() { "Invalid object key" } else { "Invalid bucket name" }))?;
+    if config.mounts.iter().any(|mount| mount.name == bucket) {
+        return mounted_path(&format!("/{}/{}", bucket, key.unwrap_or_default()), config).ok_or_else(S3Error::access_denied);
+    }
+    confine(root, path)
+}
+
+async fn record_change(kind: EventKind, path: &Path) {
+    indexer_data::record_change(Event::new(kind).add_path(path.to_path_buf())).await
+}
+
+/// Handles every request under [`S3_PREFIX`].
+async fn handle_s3(req: HttpRequest, payload: web::Payload) -> S3Result<HttpResponse> {
+    let method = req.method().as_str().to_uppercase();
+    let query = parse_query(req.query_string());
+    let (bucket, key) = split_path(req.path());
+    let operation = Operation::parse(&method, bucket.as_deref(), key.as_deref(), &query).ok_or_else(S3Error::not_implemented)?;
+    let (user, payload_hash) = authenticate(&req).await?;
+
+    let config = Configuration::get();
+    let root = PathBuf::from(&config.root_path);
+    let Some(bucket) = bucket.as_deref() else {
+        if !user.permissions.contains(operation.required_permissions(true)) {
+            return Err(S3Error::access_denied());
+        }
+        return list_buckets(&user, &root).await;
+    };
+    let bucket_path = resolve_object(config, bucket, None)?;
+    let target = match key.as_deref() {
+        Some(key) => resolve_object(config, bucket, Some(key))?,
+        None => bucket_path.clone(),
+    };
+    let backend = storage_backend::backend_for(&target);
+    if operation != Operation::CreateBucket && !backend.stat(&bucket_path).await.is_ok_and(|entry| entry.is_dir) {
+        return Err(S3Error::no_such_bucket());
+    }
+    // Refused up front, as multipart uploads would otherwise only fail once every part is sent
+    if operation.is_change() && backend.is_read_only() {
+        return Err(S3Error::access_denied());
+    }
+
+    let mut required = operation.required_permissions(backend.stat(&target).await.is_ok());
+    // Uploading into folders that don't exist yet creates them
+    if matches!(operation, Operation::PutObject | Operation::CompleteMultipartUpload)
+        && let Some(parent) = target.parent()
+        && backend.stat(parent).await.is_err()
+    {
+        required |= PermissionFlags::Create;
+    }
+    if !user.permissions.contains(required) {
+        debug!("{} is missing {:?} for S3 {:?} on {}", user.username, required, operation, req.path());
+        return Err(S3Error::access_denied());
+    }
+
+    let key = key.unwrap_or_default();
+    let upload_id = query_parameter(&query, "uploadId");
+    let backend = backend.as_ref();
+    match operation {
+        Operation::ListBuckets => list_buckets(&user, &root).await,
+        Operation::HeadBucket => Ok(HttpResponse::Ok().finish()),
+        Operation::CreateBucket => create_bucket(backend, &target).await,
+        Operation::GetBucketLocation => Ok(HttpResponse::Ok().content_type("application/xml").body(to_xml(&LocationConstraint::default()))),
+        Operation::ListObjects => list_objects(backend, bucket, &bucket_path, &query).await,
+        Operation::GetObject | Operation::HeadObject => get_object(backend, &req, &key, &target).await,
+        Operation::PutObject => put_object(backend, &req, payload, payload_hash, &key, &target).await,
+        Operation::DeleteObject => delete_object(backend, &key, &target).await,
+        Operation::CreateMultipartUpload => create_multipart_upload(backend, &user, bucket, &key, target).await,
+        Operation::UploadPart => upload_part(&user, payload, payload_hash, upload_id, query_parameter(&query, "partNumber")).await,
+        Operation::CompleteMultipartUpload => complete_multipart_upload(&user, payload, payload_hash, bucket, &key, &target, upload_id).await,
+        Operation::AbortMultipartUpload => abort_multipart_upload(&user, upload_id).await,
+    }
+}
+
+/// Lists the folders at the top of the tree as buckets, mounts included.
+async fn list_buckets(user: &User, root: &Path) -> S3Result<HttpResponse> {
+    let mut buckets = storage_backend::list(root)
+        .await?
+        .into_iter()
+        .filter(|entry| entry.is_dir && object_path(root, &entry.name, None).is_some())
+        .map(|entry| {
+            let created = entry.created.or(entry.last_modified).unwrap_or(SystemTime::UNIX_EPOCH);
+            Bucket { name: entry.name, creation_date: iso8601(created) }
+        })
+        .collect::<Vec<_>>();
+    buckets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let owner = Owner { id: user.username.clone(), display_name: user.username.clone() };
+    Ok(HttpResponse::Ok().content_type("application/xml").body(to_xml(&ListAllMyBucketsResult::new(owner, buckets))))
+}
+
+async fn create_bucket(backend: &dyn StorageBackend, bucket_path: &Path) -> S3Result<HttpResponse> {
+    if backend.stat(bucket_path).await.is_ok() {
+        return Err(S3Error::new(StatusCode::CONFLICT, "BucketAlreadyOwnedByYou", "Your previous request to create the named bucket succeeded"));
+    }
+    backend.create_dir(bucket_path).await?;
+    record_change(EventKind::Create(CreateKind::Folder), bucket_path).await;
+    let location = format!("/{}", bucket_path.file_name().unwrap_or_default().to_string_lossy());
+    Ok(HttpResponse::Ok().insert_header((header::LOCATION, location)).finish())
+}
+
+/// Collects the files under `bucket_path` whose keys may start with `prefix`, sorted by key.
+///
+/// With a `/` delimiter only the folder the prefix points into is read, and its subfolders are listed as
+/// `<name>/` so that they show up as common prefixes, even when empty.
+///
+/// The bucket is walked in key order, so folders holding only keys up to `start_after` are skipped and, when
+/// every key is a page entry of its own, the walk stops once `max_keys` keys after it and one more are found.
+pub(crate) async fn collect_entries(
+    backend: &dyn StorageBackend,
+    bucket_path: &Path,
+    prefix: &str,
+    delimiter: Option<&str>,
+    start_after: Option<&str>,
+    max_keys: usize,
+) -> Vec<ListedEntry> {
+    let folder = &prefix[..prefix.rfind('/').map(|index| index + 1).unwrap_or(0)];
+    let shallow = delimiter == Some("/");
+    // Other delimiters roll any number of keys up into one common prefix, so the page size says nothing about
+    // how many keys are needed
+    let limit = if shallow || delimiter.is_none() { max_keys.saturating_add(1) } else { usize::MAX };
+    // Sorting folders as `<name>/` walks the bucket in the order of the keys
+    let sort_name = |entry: &StorageEntry| if entry.is_dir { format!("{}/", entry.name) } else { entry.name.clone() };
+    let key_of = |entry: &StorageEntry| {
+        let relative = entry.path.strip_prefix(bucket_path).ok()?.to_string_lossy().replace('\\', "/");
+        Some(if entry.is_dir { format!("{}/", relative) } else { relative })
+    };
+
+    // The bucket is entered from the top rather than at the folder the prefix points into, so that a symlinked
+    // folder can't expose files from outside of it, as backends leave symlinks out of their listings
+    let mut entries = Vec::new();
+    let mut pending = backend.list(bucket_path).await.unwrap_or_default();
+    pending.sort_by_key(|entry| Reverse(sort_name(entry)));
+    while let Some(entry) = pending.pop() {
+        let Some(key) = key_of(&entry) else {
+            continue;
+        };
+        // A folder is kept while keys below it may still be listed
+        let in_prefix = key.starts_with(prefix) || (entry.is_dir && prefix.starts_with(&key));
+        let after_start = start_after.is_none_or(|start_after| key.as_str() > start_after || (entry.is_dir && start_after.starts_with(&key)));
+        if !(in_prefix && after_start) {
+            continue;
+        }
+        // With a `/` delimiter, the folders inside the one the prefix points into are listed rather than entered
+        if entry.is_dir && !(shallow && key.len() > folder.len()) {
+            let mut children = backend.list(&entry.path).await.unwrap_or_default();
+            children.sort_by_key(|child| Reverse(sort_name(child)));
+            pending.extend(children);
+            continue;
+        }
+        if entry.name.ends_with(PARTIAL_UPLOAD_SUFFIX)
+            || !key.starts_with(prefix)
+            || start_after.is_some_and(|start_after| key.as_str() <= start_after)
+        {
+            continue;
+        }
+        let size = if entry.is_dir { 0 } else { entry.size };
+        entries.push(ListedEntry { key, size, modified: entry.last_modified.unwrap_or(SystemTime::UNIX_EPOCH) });
+        if entries.len() == limit {
+            break;
+        }
+    }
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    entries
+}
+
+async fn list_objects(backend: &dyn StorageBackend, bucket: &str, bucket_path: &Path, query: &[(String, String)]) -> S3Result<HttpResponse> {
+    let parameter = |name: &str| query_parameter(query, name);
+    let version_2 = parameter("list-type") == Some("2");
+    let prefix = parameter("prefix").unwrap_or_default().to_string();
+    let delimiter = parameter("delimiter").filter(|delimiter| !delimiter.is_empty()).map(str::to_string);
+    let max_keys = parameter("max-keys").and_then(|max_keys| max_keys.parse::<usize>().ok()).unwrap_or(MAX_KEYS).min(MAX_KEYS);
+    let url_encoded = parameter("encoding-type") == Some("url");
+    let continuation_token = parameter("continuation-token").map(str::to_string);
+    let start_after = match (&continuation_token, version_2) {
+        (Some(token), true) => Some(
+            BASE64
+                .decode(token)
+                .ok()
+                .and_then(|key| String::from_utf8(key).ok())
+                .ok_or_else(|| S3Error::invalid_argument("Invalid continuation token"))?,
+        ),
+        (None, true) => parameter("start-after").map(str::to_string),
+        (_, false) => parameter("marker").map(str::to_string),
+    };
+
+    let entries = collect_entries(backend, bucket_path, &prefix, delimiter.as_deref(), start_after.as_deref(), max_keys).await;
+    let page = paginate(&entries, &prefix, delimiter.as_deref(), start_after.as_deref(), max_keys);
+
+    let encode = |key: &str| if url_encoded { uri_encode(key, false) } else { key.to_string() };
+    let key_count = page.objects.len() + page.common_prefixes.len();
+    let result = ListBucketResult {
+        xmlns: S3_NAMESPACE,
+        name: bucket.to_string(),
+        prefix: encode(&prefix),
+        delimiter: delimiter.as_deref().map(encode),
+        marker: (!version_2).then(|| start_after.as_deref().map(encode).unwrap_or_default()),
+        next_marker: page.next_marker.as_deref().filter(|_| !version_2).map(encode),
+        start_after: parameter("start-after").filter(|_| version_2).map(encode),
+        continuation_token: continuation_token.filter(|_| version_2),
+        next_continuation_token: page.next_marker.as_ref().filter(|_| version_2).map(|marker| BASE64.encode(marker)),
+        encoding_type: url_encoded.then(|| "url".to_string()),
+        key_count: version_2.then_some(key_count),
+        max_keys,
+        is_truncated: page.next_marker.is_some(),
+        contents: page
+            .objects
+            .iter()
+            .map(|entry| ObjectEntry {
+                key: encode(&entry.key),
+                last_modified: iso8601(entry.modified),
+                etag: quick_etag(entry.size, entry.modified),
+                size: entry.size,
+                storage_class: "STANDARD",
+            })
+            .collect(),
+        common_prefixes: page.common_prefixes.iter().map(|prefix| CommonPrefix { prefix: encode(prefix) }).collect(),
+    };
+    Ok(HttpResponse::Ok().content_type("application/xml").body(to_xml(&result)))
+}
+
+/// Serves GetObject and HeadObject, including `Range` and conditional requests.
+async fn get_object(backend: &dyn StorageBackend, req: &HttpRequest, key: &str, path: &Path) -> S3Result<HttpResponse> {
+    let entry = backend.stat(path).await.map_err(|_| S3Error::no_such_key())?;
+    let modified = entry.last_modified.unwrap_or(SystemTime::UNIX_EPOCH);
+    let etag = quick_etag(entry.size, modified);
+    // Keys ending in a slash are the folder markers clients use to show empty folders
+    if key.ends_with('/') || entry.is_dir {
+        if !(key.ends_with('/') && entry.is_dir) {
+            return Err(S3Error::no_such_key());
+        }
+        return Ok(HttpResponse::Ok().insert_header((header::ETAG, etag)).insert_header((header::CONTENT_LENGTH, 0)).finish());
+    }
+
+    // Files on disk get the full `Range` and conditional request handling of actix-files
+    if let Some(local) = backend.local_path(path) {
+        let file = NamedFile::open_async(local).await?.use_etag(false).disable_content_disposition();
+        let mut response = file.into_response(req);
+        if let Ok(etag) = HeaderValue::from_str(&etag) {
+            response.headers_mut().insert(header::ETAG, etag);
+        }
+        return Ok(response);
+    }
+
+    if header_value(req, header::IF_NONE_MATCH.as_str()).is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*")) {
+        return Ok(HttpResponse::NotModified().insert_header((header::ETAG, etag)).finish());
+    }
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::LAST_MODIFIED, HttpDate::from(modified)))
+        .insert_header((header::ACCEPT_RANGES, "bytes"));
+    let range = match header_value(req, header::RANGE.as_str()).map(|range| HttpRange::parse(range, entry.size)) {
+        // Only a single range is served, as S3 does
+        Some(Ok(ranges)) if ranges.len() == 1 => {
+            let range = ranges[0].start..ranges[0].start + ranges[0].length;
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, range.end - 1, entry.size)));
+            Some(range)
+        }
+        Some(_) => {
+            return Err(S3Error::new(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange", "The requested range is not satisfiable"));
+        }
+        None => None,
+    };
+    let length = range.as_ref().map_or(entry.size, |range| range.end - range.start);
+    if req.method() == Method::HEAD {
+        return Ok(response.no_chunking(length).finish());
+    }
+    let reader = backend.read(path, range).await?;
+    Ok(response.no_chunking(length).streaming(ReaderStream::new(reader)))
+}
+
+/// A path next to `target` to write an upload to before moving it in place, so that readers never see
+/// half written objects.
+///
+/// Backends that don't keep their files on disk only store an object once it is written completely, so uploads
+/// are written to them in place and this returns `None`.
+fn partial_path(backend: &dyn StorageBackend, target: &Path) -> Option<PathBuf> {
+    backend.local_path(target)?;
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    Some(target.with_file_name(format!(".{}.{}{}", name, uuid::Uuid::new_v4().simple(), PARTIAL_UPLOAD_SUFFIX)))
+}
+
+/// Stores an upload at `target` by handing a writer to `write`, going through a [`partial_path`] where there is
+/// one, and returns what `write` returns.
+async fn store_upload<T>(backend: &dyn StorageBackend, target: &Path, write: impl AsyncFnOnce(&mut ByteWriter) -> S3Result<T>) -> S3Result<T> {
+    let partial = partial_path(backend, target);
+    let destination = partial.as_deref().unwrap_or(target);
+    let mut writer = backend.write(destination).await?;
+    // Writers of remote backends drop everything written so far unless they are shut down
+    let result = match write(&mut writer).await {
+        Ok(result) => writer.shutdown().await.map(|_| result).map_err(S3Error::from),
+        Err(e) => Err(e),
+    };
+    drop(writer);
+    if let Some(partial) = &partial {
+        match &result {
+            Ok(_) => backend.rename(partial, target).await?,
+            Err(_) => {
+                backend.delete(partial).await.ok();
+            }
+        }
+    }
+    result
+}
+
+/// Writes the request body to `file`, returning its MD5 digest.
+async fn receive_body(mut payload: web::Payload, payload_hash: &PayloadHash, file: &mut (impl AsyncWrite + Unpin)) -> S3Result<Vec<u8>> {
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut decoder = matches!(payload_hash, PayloadHash::Streaming).then(ChunkedDecoder::default);
+    let incomplete_body = || S3Error::new(StatusCode::BAD_REQUEST, "IncompleteBody", "You did not provide the number of bytes specified");
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|_| incomplete_body())?;
+        let data = match &mut decoder {
+            Some(decoder) => decoder.push(&chunk).ok_or_else(|| S3Error::invalid_argument("Malformed aws-chunked body"))?,
+            None => chunk.to_vec(),
+        };
+        md5.update(&data);
+        sha256.update(&data);
+        file.write_all(&data).await?;
+    }
+    file.flush().await?;
+
+    if decoder.is_some_and(|decoder| !decoder.is_done()) {
+        return Err(incomplete_body());
+    }
+    if let PayloadHash::Sha256(expected) = payload_hash
+        && hex::encode(sha256.finalize()) != *expected
+    {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "The provided 'x-amz-content-sha256' header does not match what was computed",
+        ));
+    }
+    Ok(md5.finalize().to_vec())
+}
+
+async fn put_object(
+    backend: &dyn StorageBackend,
+    req: &HttpRequest,
+    payload: web::Payload,
+    payload_hash: PayloadHash,
+    key: &str,
+    target: &Path,
+) -> S3Result<HttpResponse> {
+    if req.headers().contains_key("x-amz-copy-source") {
+        return Err(S3Error::not_implemented());
+    }
+    if key.ends_with('/') {
+        backend.create_dir(target).await?;
+        record_change(EventKind::Create(CreateKind::Folder), target).await;
+        return Ok(HttpResponse::Ok().insert_header((header::ETAG, format!("\"{}\"", hex::encode(Md5::digest(b""))))).finish());
+    }
+    let existing = backend.stat(target).await.ok();
+    if existing.as_ref().is_some_and(|entry| entry.is_dir) {
+        return Err(S3Error::invalid_argument("A folder already exists with this key"));
+    }
+
+    create_parent(backend, target).await?;
+    let md5 = store_upload(backend, target, async |writer| receive_body(payload, &payload_hash, writer).await).await?;
+
+    let kind = if existing.is_some() { EventKind::Modify(ModifyKind::Any) } else { EventKind::Create(CreateKind::File) };
+    record_change(kind, target).await;
+    Ok(HttpResponse::Ok().insert_header((header::ETAG, format!("\"{}\"", hex::encode(md5)))).finish())
+}
+
+/// Creates the folder an upload to `target` goes into, unless it exists already.
+async fn create_parent(backend: &dyn StorageBackend, target: &Path) -> S3Result<()> {
+    if let Some(parent) = target.parent()
+        && backend.stat(parent).await.is_err()
+    {
+        backend.create_dir(parent).await?;
+    }
+    Ok(())
+}
+
+async fn delete_object(backend: &dyn StorageBackend, key: &str, target: &Path) -> S3Result<HttpResponse> {
+    let Ok(entry) = backend.stat(target).await else {
+        // S3 reports success for keys that don't exist
+        return Ok(HttpResponse::NoContent().finish());
+    };
+    // Folders are only removed through their marker key, and only once they are empty
+    if key.ends_with('/') && entry.is_dir {
+        if backend.list(target).await.is_ok_and(|entries| entries.is_empty()) && backend.delete(target).await.is_ok() {
+            record_change(EventKind::Remove(RemoveKind::Folder), target).await;
+        }
+    } else if !entry.is_dir {
+        backend.delete(target).await?;
+        record_change(EventKind::Remove(RemoveKind::File), target).await;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn create_multipart_upload(backend: &dyn StorageBackend, user: &User, bucket: &str, key: &str, target: PathBuf) -> S3Result<HttpResponse> {
+    if key.ends_with('/') || backend.stat(&target).await.is_ok_and(|entry| entry.is_dir) {
+        return Err(S3Error::invalid_argument("A folder already exists with this key"));
+    }
+    let upload_id = uuid::Uuid::new_v4().simple().to_string();
+    let directory = multipart_directory().join(&upload_id);
+    let mut uploads = get_multipart_uploads().lock().await;
+    fs::create_dir_all(&directory).await?;
+    uploads.insert(upload_id.clone(), MultipartUpload { username: user.username.clone(), target, directory });
+
+    let result = InitiateMultipartUploadResult { xmlns: S3_NAMESPACE, bucket: bucket.to_string(), key: key.to_string(), upload_id };
+    Ok(HttpResponse::Ok().content_type("application/xml").body(to_xml(&result)))
+}
+
+/// Looks up the parts folder of an upload started by `user`.
+async fn multipart_upload_directory(user: &User, upload_id: Option<&str>) -> S3Result<PathBuf> {
+    let uploads = get_multipart_uploads().lock().await;
+    match upload_id.and_then(|upload_id| uploads.get(upload_id)) {
+        Some(upload) if upload.username == user.username => Ok(upload.directory.clone()),
+        _ => Err(S3Error::no_such_upload()),
+    }
+}
+
+async fn upload_part(
+    user: &User,
+    payload: web::Payload,
+    payload_hash: PayloadHash,
+    upload_id: Option<&str>,
+    part_number: Option<&str>,
+) -> S3Result<HttpResponse> {
+    let directory = multipart_upload_directory(user, upload_id).await?;
+    let part_number = part_number
+        .and_then(|part_number| part_number.parse::<u32>().ok())
+        .filter(|part_number| (1..=10_000).contains(part_number))
+        .ok_or_else(|| S3Error::invalid_argument("Part number must be an integer between 1 and 10000, inclusive"))?;
+
+    // Parts are kept on this machine until the upload is completed, whatever backend stores the object
+    let part = directory.join(part_number.to_string());
+    let mut file = File::create(&part).await?;
+    let md5 = match receive_body(payload, &payload_hash, &mut file).await {
+        Ok(md5) => md5,
+        Err(e) => {
+            fs::remove_file(&part).await.ok();
+            return Err(e);
+        }
+    };
+    Ok(HttpResponse::Ok().insert_header((header::ETAG, format!("\"{}\"", hex::encode(md5)))).finish())
+}
+
+/// Reads the CompleteMultipartUpload document from the request body.
+async fn read_complete_body(mut payload: web::Payload, payload_hash: &PayloadHash) -> S3Result<CompleteMultipartUpload> {
+    let mut body = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| S3Error::invalid_argument(e.to_string()))?;
+        if body.len() + chunk.len() > MAX_COMPLETE_BODY {
+            return Err(S3Error::invalid_argument("The multipart upload document is too large"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    if let PayloadHash::Sha256(expected) = payload_hash
+        && hex::encode(Sha256::digest(&body)) != *expected
+    {
+        return Err(S3Error::signature_mismatch());
+    }
+    let body = String::from_utf8(body).map_err(|_| S3Error::invalid_argument("The multipart upload document is not valid UTF-8"))?;
+    quick_xml::de::from_str(&body).map_err(|_| S3Error::new(StatusCode::BAD_REQUEST, "MalformedXML", "The XML you provided was not well-formed"))
+}
+
+/// Joins the listed parts into `output`, returning the multipart ETag, e.g. `"<md5 of the part md5s>-3"`.
+async fn join_parts(directory: &Path, parts: &[CompletedPart], output: &mut (impl AsyncWrite + Unpin)) -> S3Result<String> {
+    let invalid_part = || S3Error::new(StatusCode::BAD_REQUEST, "InvalidPart", "One or more of the specified parts could not be found");
+    let mut part_digests = Vec::with_capacity(parts.len() * 16);
+    let mut buffer = vec![0u8; 256 * 1024];
+    for part in parts {
+        let mut input = File::open(directory.join(part.part_number.to_string())).await.map_err(|_| invalid_part())?;
+        let mut md5 = Md5::new();
+        loop {
+            let read = input.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            md5.update(&buffer[..read]);
+            output.write_all(&buffer[..read]).await?;
+        }
+        let digest = md5.finalize();
+        if part.etag.as_deref().is_some_and(|etag| etag.trim_matches('"') != hex::encode(digest)) {
+            return Err(invalid_part());
+        }
+        part_digests.extend_from_slice(&digest);
+    }
+    output.flush().await?;
+    Ok(format!("\"{}-{}\"", hex::encode(Md5::digest(&part_digests)), parts.len()))
+}
+
+async fn complete_multipart_upload(
+    user: &User,
+    payload: web::Payload,
+    payload_hash: PayloadHash,
+    bucket: &str,
+    key: &str,
+    target: &Path,
+    upload_id: Option<&str>,
+) -> S3Result<HttpResponse> {
+    let directory = multipart_upload_directory(user, upload_id).await?;
+    let document = read_complete_body(payload, &payload_hash).await?;
+    if document.parts.is_empty() {
+        return Err(S3Error::new(StatusCode::BAD_REQUEST, "MalformedXML", "You must specify at least one part"));
+    }
+    if document.parts.windows(2).any(|pair| pair[0].part_number >= pair[1].part_number) {
+        return Err(S3Error::new(StatusCode::BAD_REQUEST, "InvalidPartOrder", "The list of parts was not in ascending order"));
+    }
+
+    let upload_id = upload_id.unwrap_or_default();
+    // The upload has to be completed under the key it was started for
+    if get_multipart_uploads().lock().await.get(upload_id).is_none_or(|upload| upload.target != target) {
+        return Err(S3Error::no_such_upload());
+    }
+    let backend = storage_backend::backend_for(target);
+    let backend = backend.as_ref();
+    create_parent(backend, target).await?;
+    let existed = backend.stat(target).await.is_ok();
+    let etag = store_upload(backend, target, async |writer| join_parts(&directory, &document.parts, writer).await).await?;
+    get_multipart_uploads().lock().await.remove(upload_id);
+    fs::remove_dir_all(&directory).await.ok();
+
+    let kind = if existed { EventKind::Modify(ModifyKind::Any) } else { EventKind::Create(CreateKind::File) };
+    record_change(kind, target).await;
+    let result = CompleteMultipartUploadResult { xmlns: S3_NAMESPACE, bucket: bucket.to_string(), key: key.to_string(), etag };
+    Ok(HttpResponse::Ok().content_type("application/xml").body(to_xml(&result)))
+}
+
+async fn abort_multipart_upload(user: &User, upload_id: Option<&str>) -> S3Result<HttpResponse> {
+    let directory = multipart_upload_directory(user, upload_id).await?;
+    get_multipart_uploads().lock().await.remove(upload_id.unwrap_or_default());
+    fs::remove_dir_all(&directory).await.ok();
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/keys")]
+async fn list_keys(req: HttpRequest) -> http_error::Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    Ok(HttpResponse::Ok().json(S3AccessKey::list_by_username(&user.username).await?))
+}
+
+#[post("/keys")]
+async fn create_key(req: HttpRequest) -> http_error::Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    let key = S3AccessKey::create(&user.username).await?;
+    // The secret is only ever shown here
+    Ok(HttpResponse::Created().json(json!({
+        "id": key.id,
+        "access_key_id": key.access_key_id,
+        "secret_access_key": key.secret_access_key,
+        "username": key.username,
+        "created_at": key.created_at
+    })))
+}
+
+#[delete("/keys/{id}")]
+async fn delete_key(req: HttpRequest, id: web::Path<i64>) -> http_error::Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    if !S3AccessKey::delete(&user.username, *id).await? {
+        return Err(http_error::Error::not_found(format!("Key {} does not exist", id)));
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "deleted",
+        "id": *id
+    })))
+}
+
+/// Registers the access key routes, under `/api/s3`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/s3").wrap(Authentication::new()).service(list_keys).service(create_key).service(delete_key));
+}
+
+/// Registers the S3 gateway itself, under [`S3_PREFIX`].
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope(S3_PREFIX).default_service(web::to(handle_s3)));
+}
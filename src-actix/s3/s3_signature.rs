@@ -0,0 +1,221 @@
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The only signing algorithm Filer accepts.
+pub(crate) const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// The payload hash clients send when the body is not part of the signature.
+pub(crate) const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// The parsed `Authorization` header of a SigV4 signed request.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SignatureHeader {
+    pub access_key_id: String,
+    /// The day the signing key is derived for, as `yyyymmdd`.
+    pub date: String,
+    pub region: String,
+    pub service: String,
+    pub signed_headers: Vec<String>,
+    pub signature: String,
+}
+
+impl SignatureHeader {
+    /// Parses `AWS4-HMAC-SHA256 Credential=<key>/<date>/<region>/<service>/aws4_request, SignedHeaders=<..>, Signature=<..>`.
+    pub fn parse(authorization: &str) -> Option<Self> {
+        let parameters = authorization.strip_prefix(ALGORITHM)?.trim();
+        let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+        for parameter in parameters.split(',') {
+            match parameter.trim().split_once('=')? {
+                ("Credential", value) => credential = Some(value),
+                ("SignedHeaders", value) => signed_headers = Some(value),
+                ("Signature", value) => signature = Some(value),
+                _ => {}
+            }
+        }
+
+        let mut scope = credential?.split('/');
+        let (access_key_id, date, region, service, terminator) = (scope.next()?, scope.next()?, scope.next()?, scope.next()?, scope.next()?);
+        if terminator != "aws4_request" || scope.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            access_key_id: access_key_id.to_string(),
+            date: date.to_string(),
+            region: region.to_string(),
+            service: service.to_string(),
+            signed_headers: signed_headers?.split(';').map(|header| header.to_lowercase()).collect(),
+            signature: signature?.to_lowercase(),
+        })
+    }
+
+    /// The credential scope, `<date>/<region>/<service>/aws4_request`.
+    pub fn scope(&self) -> String {
+        format!("{}/{}/{}/aws4_request", self.date, self.region, self.service)
+    }
+}
+
+/// Percent-encodes everything except the unreserved characters, the way SigV4 expects.
+pub(crate) fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+pub(crate) fn uri_decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().to_string()
+}
+
+/// Parses a raw query string into decoded key/value pairs.
+pub(crate) fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (uri_decode(key), uri_decode(value))
+        })
+        .collect()
+}
+
+/// Builds the canonical request that the signature is computed over.
+///
+/// `path` and `query` are taken as sent by the client and re-encoded, so clients that encode a few
+/// characters differently still produce the same canonical form. `headers` holds the lowercase name and
+/// value of every signed header.
+pub(crate) fn canonical_request(method: &str, path: &str, query: &str, headers: &[(String, String)], payload_hash: &str) -> String {
+    let canonical_uri = uri_encode(&uri_decode(path), false);
+
+    let mut query = parse_query(query).into_iter().map(|(key, value)| (uri_encode(&key, true), uri_encode(&value, true))).collect::<Vec<_>>();
+    query.sort();
+    let canonical_query = query.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join("&");
+
+    let canonical_headers: String =
+        headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.split_whitespace().collect::<Vec<_>>().join(" "))).collect();
+    let signed_headers = headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+
+    format!("{}\n{}\n{}\n{}\n{}\n{}", method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash)
+}
+
+pub(crate) fn string_to_sign(amz_date: &str, scope: &str, canonical_request: &str) -> String {
+    format!("{}\n{}\n{}\n{}", ALGORITHM, amz_date, scope, hex::encode(Sha256::digest(canonical_request.as_bytes())))
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derives the key of `secret_access_key` for the credential scope `<date>/<region>/<service>/aws4_request`.
+pub(crate) fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let date_key = hmac(format!("AWS4{}", secret_access_key).as_bytes(), date);
+    let region_key = hmac(&date_key, region);
+    let service_key = hmac(&region_key, service);
+    hmac(&service_key, "aws4_request")
+}
+
+/// Signs `string_to_sign`, returning the hex encoded signature.
+pub(crate) fn sign(signing_key: &[u8], string_to_sign: &str) -> String {
+    hex::encode(hmac(signing_key, string_to_sign))
+}
+
+/// Checks `signature` against `string_to_sign` in constant time.
+pub(crate) fn verify(signing_key: &[u8], string_to_sign: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(signing_key).expect("HMAC accepts keys of any length");
+    mac.update(string_to_sign.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Decodes a body sent with `Content-Encoding: aws-chunked`, as used by the `STREAMING-*` payload modes.
+///
+/// Every chunk is prefixed with its size in hex and an optional signature, e.g. `400;chunk-signature=<..>\r\n`.
+/// A zero sized chunk ends the body and may be followed by trailing checksum headers. Chunk signatures and
+/// trailing checksums are not verified, the headers of the request are still covered by its signature.
+#[derive(Default)]
+pub(crate) struct ChunkedDecoder {
+    buffer: Vec<u8>,
+    remaining: usize,
+    state: ChunkState,
+}
+
+#[derive(Default, PartialEq, Eq)]
+enum ChunkState {
+    #[default]
+    Size,
+    Data,
+    DataEnd,
+    Done,
+}
+
+/// The longest chunk header line accepted, which is plenty for a size and a signature.
+const MAX_CHUNK_HEADER: usize = 4096;
+
+impl ChunkedDecoder {
+    /// Feeds the next part of the body, returning the decoded data it completes or `None` if the body is malformed.
+    pub fn push(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        self.buffer.extend_from_slice(data);
+        let mut decoded = Vec::new();
+        loop {
+            match self.state {
+                ChunkState::Size => {
+                    let Some(end) = self.buffer.windows(2).position(|window| window == b"\r\n") else {
+                        if self.buffer.len() > MAX_CHUNK_HEADER {
+                            return None;
+                        }
+                        break;
+                    };
+                    let line = std::str::from_utf8(&self.buffer[..end]).ok()?;
+                    let size = usize::from_str_radix(line.split(';').next()?.trim(), 16).ok()?;
+                    self.buffer.drain(..end + 2);
+                    if size == 0 {
+                        self.state = ChunkState::Done;
+                    } else {
+                        self.remaining = size;
+                        self.state = ChunkState::Data;
+                    }
+                }
+                ChunkState::Data => {
+                    let length = self.remaining.min(self.buffer.len());
+                    decoded.extend(self.buffer.drain(..length));
+                    self.remaining -= length;
+                    if self.remaining > 0 {
+                        break;
+                    }
+                    self.state = ChunkState::DataEnd;
+                }
+                ChunkState::DataEnd => {
+                    if self.buffer.len() < 2 {
+                        break;
+                    }
+                    if &self.buffer[..2] != b"\r\n" {
+                        return None;
+                    }
+                    self.buffer.drain(..2);
+                    self.state = ChunkState::Size;
+                }
+                ChunkState::Done => {
+                    self.buffer.clear();
+                    break;
+                }
+            }
+        }
+        Some(decoded)
+    }
+
+    /// Whether the final, zero sized, chunk has been received.
+    pub fn is_done(&self) -> bool {
+        self.state == ChunkState::Done
+    }
+}
@@ -0,0 +1,7 @@
+pub(crate) mod sftp_data;
+pub(crate) mod sftp_db;
+pub(crate) mod sftp_endpoint;
+pub(crate) mod sftp_server;
+
+#[cfg(test)]
+mod sftp_test;
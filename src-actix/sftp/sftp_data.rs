@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Deserialize)]
+pub struct AddPublicKeyRequest {
+    /// The key in OpenSSH format, e.g. the contents of `~/.ssh/id_ed25519.pub`.
+    pub key: String,
+}
+
+/// A public key a user registered to sign in over SFTP.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SftpPublicKey {
+    pub id: i64,
+    pub username: String,
+    pub key: String,
+    pub fingerprint: String,
+    pub created_at: i64,
+}
+
+impl SftpPublicKey {
+    /// Parses an OpenSSH public key, returning it without its comment along with its SHA-256 fingerprint.
+    pub fn parse(key: &str) -> anyhow::Result<(russh::keys::PublicKey, String)> {
+        let key = russh::keys::PublicKey::from_openssh(key.trim()).map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+        let fingerprint = key.fingerprint(russh::keys::ssh_key::HashAlg::Sha256).to_string();
+        Ok((key, fingerprint))
+    }
+
+    /// Whether this is the same key as `offered`, ignoring comments.
+    pub fn matches(&self, offered: &russh::keys::PublicKey) -> bool {
+        Self::parse(&self.key).is_ok_and(|(key, _)| key.key_data() == offered.key_data())
+    }
+}
@@ -0,0 +1,70 @@
+use crate::helpers::db::create_pool;
+use crate::sftp::sftp_data::SftpPublicKey;
+use crate::shares::share_data::unix_now;
+use anyhow::Result;
+use sqlx::{Executor, SqlitePool};
+
+pub async fn initialize() -> Result<()> {
+    let pool = create_pool().await?;
+    initialize_with_pool(&pool).await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+pub async fn initialize_with_pool(pool: &SqlitePool) -> Result<()> {
+    pool.execute(
+        r#"
+CREATE TABLE IF NOT EXISTS sftp_public_keys
+(
+    id          INTEGER PRIMARY KEY,
+    username    TEXT    NOT NULL,
+    key         TEXT    NOT NULL,
+    fingerprint TEXT    NOT NULL,
+    created_at  INTEGER NOT NULL,
+    UNIQUE (username, fingerprint)
+)
+"#,
+    )
+    .await?;
+    Ok(())
+}
+
+impl SftpPublicKey {
+    pub async fn create(username: impl AsRef<str>, key: impl AsRef<str>) -> Result<Self> {
+        let pool = create_pool().await?;
+        Self::create_with_pool(username, key, &pool).await
+    }
+
+    pub async fn create_with_pool(username: impl AsRef<str>, key: impl AsRef<str>, pool: &SqlitePool) -> Result<Self> {
+        let (public_key, fingerprint) = Self::parse(key.as_ref())?;
+        let key = public_key.to_openssh()?;
+        let created_at = unix_now();
+        let result = sqlx::query("insert into sftp_public_keys (username, key, fingerprint, created_at) values (?, ?, ?, ?)")
+            .bind(username.as_ref())
+            .bind(&key)
+            .bind(&fingerprint)
+            .bind(created_at)
+            .execute(pool)
+            .await?;
+        Ok(Self { id: result.last_insert_rowid(), username: username.as_ref().to_string(), key, fingerprint, created_at })
+    }
+
+    pub async fn list_by_username(username: impl AsRef<str>) -> Result<Vec<Self>> {
+        let pool = create_pool().await?;
+        Self::list_by_username_with_pool(username, &pool).await
+    }
+
+    pub async fn list_by_username_with_pool(username: impl AsRef<str>, pool: &SqlitePool) -> Result<Vec<Self>> {
+        let keys = sqlx::query_as::<_, Self>("select * from sftp_public_keys where username = ?").bind(username.as_ref()).fetch_all(pool).await?;
+        Ok(keys)
+    }
+
+    /// Deletes a key of `username`, returning `false` if the user has no key with that id.
+    pub async fn delete(username: impl AsRef<str>, id: i64) -> Result<bool> {
+        let pool = create_pool().await?;
+        let result =
+            sqlx::query("delete from sftp_public_keys where username = ? and id = ?").bind(username.as_ref()).bind(id).execute(&pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
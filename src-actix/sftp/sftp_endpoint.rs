@@ -0,0 +1,38 @@
+use crate::auth::auth_middleware::{Authentication, authenticated_user};
+use crate::helpers::http_error::{Error, Result};
+use crate::sftp::sftp_data::{AddPublicKeyRequest, SftpPublicKey};
+use actix_web::{HttpRequest, HttpResponse, delete, get, post, web};
+use serde_json::json;
+
+#[get("/keys")]
+async fn list_keys(req: HttpRequest) -> Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    Ok(HttpResponse::Ok().json(SftpPublicKey::list_by_username(&user.username).await?))
+}
+
+#[post("/keys")]
+async fn add_key(req: HttpRequest, body: web::Json<AddPublicKeyRequest>) -> Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    let (_, fingerprint) = SftpPublicKey::parse(&body.key).map_err(|e| Error::validation_error(e.to_string(), Some("key")))?;
+    if SftpPublicKey::list_by_username(&user.username).await?.iter().any(|key| key.fingerprint == fingerprint) {
+        return Err(Error::validation_error("This key is already registered", Some("key")));
+    }
+    let key = SftpPublicKey::create(&user.username, &body.key).await?;
+    Ok(HttpResponse::Created().json(key))
+}
+
+#[delete("/keys/{id}")]
+async fn delete_key(req: HttpRequest, id: web::Path<i64>) -> Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    if !SftpPublicKey::delete(&user.username, *id).await? {
+        return Err(Error::not_found(format!("Key {} does not exist", id)));
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "deleted",
+        "id": *id
+    })))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/sftp").wrap(Authentication::new()).service(list_keys).service(add_key).service(delete_key));
+}
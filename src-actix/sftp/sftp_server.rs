@@ -0,0 +1,523 @@
+use crate::auth::auth_data::User;
+use crate::auth::permission_flags::PermissionFlags;
+use crate::configuration::configuration_data::Configuration;
+use crate::io::fs::indexer::indexer_data;
+use crate::io::fs::normalize_path::mounted_path;
+use crate::io::storage::storage_backend;
+use crate::io::storage::storage_backend::{ByteReader, ByteWriter, StorageEntry, StorageTree};
+use crate::sftp::sftp_data::SftpPublicKey;
+use anyhow::Result;
+use enumflags2::BitFlags;
+use log::*;
+use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify::{Event, EventKind};
+use russh::keys::PrivateKey;
+use russh::keys::ssh_key::LineEnding;
+use russh::keys::ssh_key::rand_core::OsRng;
+use russh::server::{Auth, Msg, Server as _, Session};
+use russh::{Channel, ChannelId};
+use russh_sftp::protocol::{Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode, Version};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+/// The file the server's host key is kept in, in the directory of the configuration file.
+const HOST_KEY_FILE: &str = "sftp_host_key";
+
+/// Starts the SFTP server on `port`, running until the process exits.
+pub async fn start(port: u16) -> Result<()> {
+    let config = russh::server::Config {
+        auth_rejection_time: Duration::from_secs(3),
+        auth_rejection_time_initial: Some(Duration::from_secs(0)),
+        inactivity_timeout: Some(Duration::from_secs(30 * 60)),
+        keys: vec![load_host_key()?],
+        ..Default::default()
+    };
+    info!("Starting SFTP server on port {}", port);
+    SftpServer.run_on_address(Arc::new(config), ("0.0.0.0", port)).await?;
+    Ok(())
+}
+
+/// Loads the host key, generating one on first start so clients see the same key across restarts.
+fn load_host_key() -> Result<PrivateKey> {
+    let path = host_key_path(Configuration::get_path().as_deref());
+    if path.exists() {
+        return Ok(PrivateKey::read_openssh_file(&path)?);
+    }
+    info!("Generating a new SFTP host key at {}", path.display());
+    let key = PrivateKey::random(&mut OsRng, russh::keys::Algorithm::Ed25519)?;
+    key.write_openssh_file(&path, LineEnding::LF)?;
+    Ok(key)
+}
+
+/// Where the host key is kept for the configuration file at `config_path`, so that it moves along with the
+/// configuration rather than depending on the directory Filer is started from.
+pub(crate) fn host_key_path(config_path: Option<&str>) -> PathBuf {
+    let directory = config_path.and_then(|path| Path::new(path).parent()).unwrap_or(Path::new(""));
+    directory.join(HOST_KEY_FILE)
+}
+
+/// The directory a user is confined to over SFTP.
+fn user_root(user: &User) -> Result<PathBuf> {
+    let config = Configuration::get();
+    let root = PathBuf::from(&config.root_path);
+    if !config.sftp_home_directories {
+        return Ok(root);
+    }
+    let home = root.join(&user.username);
+    std::fs::create_dir_all(&home)?;
+    Ok(home)
+}
+
+/// Normalizes an SFTP path to an absolute path with forward slashes, resolving `.` and `..`.
+///
+/// Relative paths are relative to the user's root, and `..` never goes above it.
+pub(crate) fn normalize_virtual_path(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+/// The permission needed to open a file with `flags`.
+pub(crate) fn open_permission(flags: OpenFlags, exists: bool) -> PermissionFlags {
+    if !flags.intersects(OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE | OpenFlags::TRUNCATE) {
+        PermissionFlags::Read
+    } else if exists {
+        PermissionFlags::Write
+    } else {
+        PermissionFlags::Create
+    }
+}
+
+#[derive(Clone)]
+struct SftpServer;
+
+impl russh::server::Server for SftpServer {
+    type Handler = SshSession;
+
+    fn new_client(&mut self, peer: Option<SocketAddr>) -> Self::Handler {
+        debug!("New SFTP connection from {:?}", peer);
+        SshSession { user: None, channels: HashMap::new() }
+    }
+}
+
+struct SshSession {
+    user: Option<User>,
+    channels: HashMap<ChannelId, Channel<Msg>>,
+}
+
+fn reject() -> Auth {
+    Auth::Reject { proceed_with_methods: None, partial_success: false }
+}
+
+impl russh::server::Handler for SshSession {
+    type Error = anyhow::Error;
+
+    async fn auth_password(&mut self, username: &str, password: &str) -> Result<Auth, Self::Error> {
+        let Some(user) = User::get_by_username(username).await? else {
+            return Ok(reject());
+        };
+        if !bcrypt::verify(password, &user.password).unwrap_or(false) {
+            warn!("Failed SFTP password login for {}", username);
+            return Ok(reject());
+        }
+        info!("{} signed in over SFTP with a password", username);
+        self.user = Some(user);
+        Ok(Auth::Accept)
+    }
+
+    async fn auth_publickey(&mut self, username: &str, public_key: &russh::keys::PublicKey) -> Result<Auth, Self::Error> {
+        let Some(user) = User::get_by_username(username).await? else {
+            return Ok(reject());
+        };
+        if !SftpPublicKey::list_by_username(username).await?.iter().any(|key| key.matches(public_key)) {
+            return Ok(reject());
+        }
+        info!("{} signed in over SFTP with a public key", username);
+        self.user = Some(user);
+        Ok(Auth::Accept)
+    }
+
+    async fn channel_open_session(&mut self, channel: Channel<Msg>, _session: &mut Session) -> Result<bool, Self::Error> {
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
+
+    async fn channel_eof(&mut self, channel: ChannelId, session: &mut Session) -> Result<(), Self::Error> {
+        session.close(channel)?;
+        Ok(())
+    }
+
+    async fn subsystem_request(&mut self, channel_id: ChannelId, name: &str, session: &mut Session) -> Result<(), Self::Error> {
+        let (Some(user), Some(channel)) = (self.user.clone(), self.channels.remove(&channel_id)) else {
+            session.channel_failure(channel_id)?;
+            return Ok(());
+        };
+        if name != "sftp" {
+            session.channel_failure(channel_id)?;
+            return Ok(());
+        }
+        let root = user_root(&user)?;
+        session.channel_success(channel_id)?;
+        russh_sftp::server::run(channel.into_stream(), SftpSession::new(user, root, storage_backend::tree().clone())).await;
+        Ok(())
+    }
+}
+
+enum OpenHandle {
+    /// A file opened for reading, served from one stream for as long as the client reads it in order.
+    Reader {
+        path: PathBuf,
+        size: u64,
+        stream: Option<(u64, Mutex<ByteReader>)>,
+    },
+    /// A file opened for writing, which backends take as one stream written from the start.
+    Writer {
+        path: PathBuf,
+        stream: Mutex<ByteWriter>,
+        position: u64,
+    },
+    Directory {
+        path: PathBuf,
+        listed: bool,
+    },
+}
+
+/// The SFTP subsystem of a signed in user, confined to `root` in `tree`.
+pub(crate) struct SftpSession {
+    user: User,
+    root: PathBuf,
+    tree: Arc<StorageTree>,
+    handles: HashMap<String, OpenHandle>,
+    next_handle: u64,
+}
+
+fn status(id: u32, status_code: StatusCode) -> Status {
+    Status { id, status_code, error_message: format!("{:?}", status_code), language_tag: "en-US".to_string() }
+}
+
+fn io_status(error: std::io::Error) -> StatusCode {
+    match error.kind() {
+        ErrorKind::NotFound => StatusCode::NoSuchFile,
+        ErrorKind::PermissionDenied => StatusCode::PermissionDenied,
+        _ => StatusCode::Failure,
+    }
+}
+
+fn change(kind: EventKind, path: &Path) -> Event {
+    Event::new(kind).add_path(path.to_path_buf())
+}
+
+/// The attributes of a stored file or directory. Filer doesn't manage ownership, so the permissions only tell
+/// whether it can be changed.
+fn attributes(entry: &StorageEntry, read_only: bool) -> FileAttributes {
+    let mode = match (entry.is_dir, read_only) {
+        (true, false) => 0o755,
+        (true, true) => 0o555,
+        (false, false) => 0o644,
+        (false, true) => 0o444,
+    };
+    let mtime = entry.last_modified.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok()).map(|since| since.as_secs() as u32);
+    let mut attrs = FileAttributes { size: Some(entry.size), permissions: Some(mode), atime: mtime, mtime, ..Default::default() };
+    attrs.set_dir(entry.is_dir);
+    attrs.set_regular(!entry.is_dir);
+    attrs
+}
+
+impl SftpSession {
+    pub(crate) fn new(user: User, root: PathBuf, tree: Arc<StorageTree>) -> Self {
+        Self { user, root, tree, handles: HashMap::new(), next_handle: 0 }
+    }
+
+    fn require(&self, permission: impl Into<BitFlags<PermissionFlags>>) -> Result<(), StatusCode> {
+        let permission = permission.into();
+        if self.user.permissions.contains(permission) {
+            Ok(())
+        } else {
+            debug!("{} is missing {:?} over SFTP", self.user.username, permission);
+            Err(StatusCode::PermissionDenied)
+        }
+    }
+
+    /// Maps an SFTP path to the OS path that stores it.
+    ///
+    /// Mounts show up as folders of the top of the tree, as they do over HTTP, unless the user is confined to a home
+    /// directory. Paths that lead outside the user's root or a mount through a symlink are refused.
+    fn resolve(&self, path: &str) -> Result<PathBuf, StatusCode> {
+        let virtual_path = normalize_virtual_path(path);
+        let config = self.tree.config();
+        if self.root == Path::new(&config.root_path)
+            && let Some(name) = virtual_path.split('/').nth(1)
+            && config.mounts.iter().any(|mount| mount.name == name)
+        {
+            return mounted_path(&virtual_path, config).ok_or(StatusCode::PermissionDenied);
+        }
+        let target = self.root.join(virtual_path.trim_start_matches('/'));
+        let mut existing = target.as_path();
+        while !existing.exists() {
+            existing = existing.parent().ok_or(StatusCode::NoSuchFile)?;
+        }
+        let canonical_root = self.root.canonicalize().map_err(io_status)?;
+        let canonical = existing.canonicalize().map_err(io_status)?;
+        if canonical.starts_with(&canonical_root) { Ok(target) } else { Err(StatusCode::PermissionDenied) }
+    }
+
+    async fn entry(&self, path: &Path) -> Result<StorageEntry, StatusCode> {
+        self.tree.backend_for(path).stat(path).await.map_err(io_status)
+    }
+
+    async fn attributes(&self, path: &Path) -> Result<FileAttributes, StatusCode> {
+        let backend = self.tree.backend_for(path);
+        let entry = backend.stat(path).await.map_err(io_status)?;
+        Ok(attributes(&entry, backend.is_read_only()))
+    }
+
+    /// Changes the size of the file at `path`. Backends can only write files anew, so files can be emptied but not
+    /// cut short or extended.
+    async fn set_size(&self, path: &Path, size: u64) -> Result<(), StatusCode> {
+        if self.entry(path).await?.size == size {
+            return Ok(());
+        }
+        if size != 0 {
+            return Err(StatusCode::OpUnsupported);
+        }
+        let mut stream = self.tree.backend_for(path).write(path).await.map_err(io_status)?;
+        stream.shutdown().await.map_err(io_status)
+    }
+
+    fn insert_handle(&mut self, handle: OpenHandle) -> String {
+        self.next_handle += 1;
+        let key = self.next_handle.to_string();
+        self.handles.insert(key.clone(), handle);
+        key
+    }
+
+    fn handle_path(&self, handle: &str) -> Result<&Path, StatusCode> {
+        match self.handles.get(handle) {
+            Some(OpenHandle::Reader { path, .. }) | Some(OpenHandle::Writer { path, .. }) | Some(OpenHandle::Directory { path, .. }) => Ok(path),
+            None => Err(StatusCode::Failure),
+        }
+    }
+}
+
+impl russh_sftp::server::Handler for SftpSession {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(&mut self, _version: u32, _extensions: HashMap<String, String>) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(&mut self, id: u32, filename: String, pflags: OpenFlags, _attrs: FileAttributes) -> Result<Handle, Self::Error> {
+        let path = self.resolve(&filename)?;
+        let backend = self.tree.backend_for(&path);
+        let existing = backend.stat(&path).await.ok();
+        self.require(open_permission(pflags, existing.is_some()))?;
+        if existing.as_ref().is_some_and(|entry| entry.is_dir) {
+            return Err(StatusCode::Failure);
+        }
+        if open_permission(pflags, true) == PermissionFlags::Read {
+            let size = existing.ok_or(StatusCode::NoSuchFile)?.size;
+            return Ok(Handle { id, handle: self.insert_handle(OpenHandle::Reader { path, size, stream: None }) });
+        }
+
+        match &existing {
+            Some(_) if pflags.contains(OpenFlags::EXCLUDE) => return Err(StatusCode::Failure),
+            None if !pflags.contains(OpenFlags::CREATE) => return Err(StatusCode::NoSuchFile),
+            _ => {}
+        }
+        // Files are written anew from the start, which is how clients upload, so they can't be added to or changed
+        // in place
+        if pflags.contains(OpenFlags::APPEND) || (existing.is_some_and(|entry| entry.size > 0) && !pflags.contains(OpenFlags::TRUNCATE)) {
+            return Err(StatusCode::OpUnsupported);
+        }
+        let stream = backend.write(&path).await.map_err(io_status)?;
+        Ok(Handle { id, handle: self.insert_handle(OpenHandle::Writer { path, stream: Mutex::new(stream), position: 0 }) })
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        if let Some(OpenHandle::Writer { mut stream, path, .. }) = self.handles.remove(&handle) {
+            // Backends only store the file once its stream is shut down
+            stream.get_mut().shutdown().await.map_err(io_status)?;
+            drop(stream);
+            indexer_data::record_change(change(EventKind::Modify(ModifyKind::Any), &path)).await;
+        }
+        Ok(status(id, StatusCode::Ok))
+    }
+
+    async fn read(&mut self, id: u32, handle: String, offset: u64, len: u32) -> Result<Data, Self::Error> {
+        let Some(OpenHandle::Reader { path, size, stream }) = self.handles.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        if offset >= *size {
+            return Err(StatusCode::Eof);
+        }
+        let (position, mut reader) = match stream.take() {
+            Some((position, reader)) if position == offset => (position, reader),
+            _ => (offset, Mutex::new(self.tree.backend_for(path).read(path, Some(offset..*size)).await.map_err(io_status)?)),
+        };
+        let mut data = vec![0u8; len.min(256 * 1024) as usize];
+        let read = reader.get_mut().read(&mut data).await.map_err(io_status)?;
+        if read == 0 {
+            return Err(StatusCode::Eof);
+        }
+        data.truncate(read);
+        *stream = Some((position + read as u64, reader));
+        Ok(Data { id, data })
+    }
+
+    async fn write(&mut self, id: u32, handle: String, offset: u64, data: Vec<u8>) -> Result<Status, Self::Error> {
+        let Some(OpenHandle::Writer { stream, position, .. }) = self.handles.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        if offset != *position {
+            return Err(StatusCode::OpUnsupported);
+        }
+        stream.get_mut().write_all(&data).await.map_err(io_status)?;
+        *position += data.len() as u64;
+        Ok(status(id, StatusCode::Ok))
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        self.require(PermissionFlags::Read)?;
+        // Symlinks are never listed, so they are described by what they point to
+        Ok(Attrs { id, attrs: self.attributes(&self.resolve(&path)?).await? })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        self.require(PermissionFlags::Read)?;
+        Ok(Attrs { id, attrs: self.attributes(&self.resolve(&path)?).await? })
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs { id, attrs: self.attributes(self.handle_path(&handle)?).await? })
+    }
+
+    async fn setstat(&mut self, id: u32, path: String, attrs: FileAttributes) -> Result<Status, Self::Error> {
+        self.require(PermissionFlags::Write)?;
+        let path = self.resolve(&path)?;
+        if let Some(size) = attrs.size {
+            self.set_size(&path, size).await?;
+        }
+        // Ownership, permissions and times are left alone, Filer doesn't manage them
+        Ok(status(id, StatusCode::Ok))
+    }
+
+    async fn fsetstat(&mut self, id: u32, handle: String, attrs: FileAttributes) -> Result<Status, Self::Error> {
+        self.require(PermissionFlags::Write)?;
+        match (attrs.size, self.handles.get(&handle)) {
+            (Some(size), Some(OpenHandle::Writer { position, .. })) if size != *position => return Err(StatusCode::OpUnsupported),
+            (Some(size), Some(OpenHandle::Reader { path, .. })) => self.set_size(&path.clone(), size).await?,
+            _ => {}
+        }
+        Ok(status(id, StatusCode::Ok))
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        self.require(PermissionFlags::Read)?;
+        let path = self.resolve(&path)?;
+        if !self.entry(&path).await.is_ok_and(|entry| entry.is_dir) {
+            return Err(StatusCode::NoSuchFile);
+        }
+        Ok(Handle { id, handle: self.insert_handle(OpenHandle::Directory { path, listed: false }) })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        let Some(OpenHandle::Directory { path, listed }) = self.handles.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        // Everything is sent in the first reply, the next one tells the client the listing is done
+        if *listed {
+            return Err(StatusCode::Eof);
+        }
+        *listed = true;
+        // Mounts are only listed at the top of the tree, which home directories are never at
+        let files = self
+            .tree
+            .list(path)
+            .await
+            .map_err(io_status)?
+            .iter()
+            .map(|entry| File::new(&entry.name, attributes(entry, self.tree.backend_for(&entry.path).is_read_only())))
+            .collect();
+        Ok(Name { id, files })
+    }
+
+    async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+        self.require(PermissionFlags::Delete)?;
+        let path = self.resolve(&filename)?;
+        if self.entry(&path).await?.is_dir {
+            return Err(StatusCode::Failure);
+        }
+        self.tree.backend_for(&path).delete(&path).await.map_err(io_status)?;
+        indexer_data::record_change(change(EventKind::Remove(RemoveKind::File), &path)).await;
+        Ok(status(id, StatusCode::Ok))
+    }
+
+    async fn mkdir(&mut self, id: u32, path: String, _attrs: FileAttributes) -> Result<Status, Self::Error> {
+        self.require(PermissionFlags::Create)?;
+        let path = self.resolve(&path)?;
+        if self.entry(&path).await.is_ok() {
+            return Err(StatusCode::Failure);
+        }
+        if let Some(parent) = path.parent() {
+            self.entry(parent).await?;
+        }
+        self.tree.backend_for(&path).create_dir(&path).await.map_err(io_status)?;
+        indexer_data::record_change(change(EventKind::Create(CreateKind::Folder), &path)).await;
+        Ok(status(id, StatusCode::Ok))
+    }
+
+    async fn rmdir(&mut self, id: u32, path: String) -> Result<Status, Self::Error> {
+        self.require(PermissionFlags::Delete)?;
+        let path = self.resolve(&path)?;
+        if path == self.root || self.tree.is_mount(&path) {
+            return Err(StatusCode::PermissionDenied);
+        }
+        let backend = self.tree.backend_for(&path);
+        if !backend.stat(&path).await.map_err(io_status)?.is_dir || !backend.list(&path).await.map_err(io_status)?.is_empty() {
+            return Err(StatusCode::Failure);
+        }
+        backend.delete(&path).await.map_err(io_status)?;
+        indexer_data::record_change(change(EventKind::Remove(RemoveKind::Folder), &path)).await;
+        Ok(status(id, StatusCode::Ok))
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        Ok(Name { id, files: vec![File::dummy(normalize_virtual_path(&path))] })
+    }
+
+    async fn rename(&mut self, id: u32, oldpath: String, newpath: String) -> Result<Status, Self::Error> {
+        self.require(PermissionFlags::moving())?;
+        let old_path = self.resolve(&oldpath)?;
+        let new_path = self.resolve(&newpath)?;
+        if self.tree.is_mount(&old_path) {
+            return Err(StatusCode::PermissionDenied);
+        }
+        if self.entry(&new_path).await.is_ok() {
+            return Err(StatusCode::Failure);
+        }
+        // Moves between mounts are copied over and removed, like moves over HTTP
+        self.tree.rename(&old_path, &new_path).await.map_err(io_status)?;
+        // Moved in place, so a renamed directory keeps its indexed content
+        let rename = change(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &old_path).add_path(new_path);
+        indexer_data::record_change(rename).await;
+        Ok(status(id, StatusCode::Ok))
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use crate::auth::auth_data::User;
+    use crate::auth::permission_flags::PermissionFlags;
+    use crate::configuration::configuration_data::{Configuration, LocalMountConfiguration, MountConfiguration, MountStorage};
+    use crate::io::storage::storage_backend::StorageTree;
+    use crate::sftp::sftp_data::SftpPublicKey;
+    use crate::sftp::sftp_server::{SftpSession, host_key_path, normalize_virtual_path, open_permission};
+    use russh_sftp::client::error::Error as SftpError;
+    use russh_sftp::protocol::{OpenFlags, StatusCode};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tokio::io::AsyncWriteExt;
+
+    fn tree(root: &Path, mounts: Vec<MountConfiguration>) -> Arc<StorageTree> {
+        Arc::new(StorageTree::new(Configuration { root_path: root.to_string_lossy().to_string(), mounts, ..Configuration::default() }))
+    }
+
+    fn mount(name: &str, path: &Path, read_only: bool) -> MountConfiguration {
+        MountConfiguration {
+            name: name.to_string(),
+            read_only,
+            indexing: false,
+            filter: Vec::new(),
+            watcher_mode: None,
+            watcher_poll_interval: None,
+            storage: MountStorage::Local(LocalMountConfiguration { path: path.to_string_lossy().to_string() }),
+        }
+    }
+
+    fn status_code<T>(result: Result<T, SftpError>) -> Option<StatusCode> {
+        match result {
+            Err(SftpError::Status(status)) => Some(status.status_code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_normalize_virtual_path() {
+        assert_eq!(normalize_virtual_path(""), "/");
+        assert_eq!(normalize_virtual_path("."), "/");
+        assert_eq!(normalize_virtual_path("Documents/./report.pdf"), "/Documents/report.pdf");
+        assert_eq!(normalize_virtual_path("/Documents//Photos/"), "/Documents/Photos");
+        assert_eq!(normalize_virtual_path("/Documents/../Music"), "/Music");
+        assert_eq!(normalize_virtual_path("../../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalize_virtual_path("..\\..\\Windows"), "/Windows");
+    }
+
+    #[test]
+    fn test_open_permission() {
+        assert_eq!(open_permission(OpenFlags::READ, true), PermissionFlags::Read);
+        assert_eq!(open_permission(OpenFlags::WRITE | OpenFlags::TRUNCATE, true), PermissionFlags::Write);
+        assert_eq!(open_permission(OpenFlags::WRITE | OpenFlags::CREATE, false), PermissionFlags::Create);
+        assert_eq!(open_permission(OpenFlags::APPEND, true), PermissionFlags::Write);
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIIOUnJD67unipPqCigVVRaaFFNF5LK/78mlAqOHuMCxh test@example";
+        let (public_key, fingerprint) = SftpPublicKey::parse(key).unwrap();
+        assert!(fingerprint.starts_with("SHA256:"));
+        let stored = SftpPublicKey { id: 1, username: "test".to_string(), key: key.to_string(), fingerprint, created_at: 0 };
+        assert!(stored.matches(&public_key));
+        assert!(SftpPublicKey::parse("ssh-ed25519 not-a-key").is_err());
+    }
+
+    #[test]
+    fn test_host_key_path() {
+        assert_eq!(host_key_path(Some("/etc/filer/app-config.json")), PathBuf::from("/etc/filer/sftp_host_key"));
+        assert_eq!(host_key_path(Some("app-config.json")), PathBuf::from("sftp_host_key"));
+        assert_eq!(host_key_path(None), PathBuf::from("sftp_host_key"));
+    }
+
+    #[actix_web::test]
+    async fn test_sftp_session() {
+        let root = tempdir().expect("Failed to create temp dir");
+        std::fs::create_dir(root.path().join("docs")).unwrap();
+        std::fs::write(root.path().join("docs/readme.txt"), b"hello").unwrap();
+        let user =
+            User { id: 0, username: "sftpuser".to_string(), password: String::new(), permissions: PermissionFlags::Read | PermissionFlags::Write };
+
+        // The client and the server talk the SFTP protocol over an in-memory stream instead of an SSH channel
+        let (client_stream, server_stream) = tokio::io::duplex(64 * 1024);
+        russh_sftp::server::run(server_stream, SftpSession::new(user, root.path().to_path_buf(), tree(root.path(), Vec::new()))).await;
+        let sftp = russh_sftp::client::SftpSession::new(client_stream).await.expect("Failed to start the SFTP session");
+
+        assert_eq!(sftp.canonicalize("docs/../docs/./").await.unwrap(), "/docs");
+        assert_eq!(sftp.read("/docs/readme.txt").await.unwrap(), b"hello");
+        let names: Vec<String> = sftp.read_dir("/docs").await.unwrap().map(|entry| entry.file_name()).collect();
+        assert_eq!(names, ["readme.txt"]);
+        assert!(sftp.metadata("/docs").await.unwrap().is_dir());
+        assert_eq!(status_code(sftp.read("/docs/missing.txt").await), Some(StatusCode::NoSuchFile));
+
+        // Renaming takes the file away from its old path, which needs Delete as for a WebDAV MOVE
+        assert_eq!(status_code(sftp.rename("/docs/readme.txt", "/docs/moved.txt").await), Some(StatusCode::PermissionDenied));
+        assert_eq!(status_code(sftp.remove_file("/docs/readme.txt").await), Some(StatusCode::PermissionDenied));
+        assert_eq!(status_code(sftp.create_dir("/photos").await), Some(StatusCode::PermissionDenied));
+        assert!(root.path().join("docs/readme.txt").exists());
+
+        #[cfg(unix)]
+        {
+            let outside = tempdir().expect("Failed to create temp dir");
+            std::fs::write(outside.path().join("secret.txt"), b"secret").unwrap();
+            std::os::unix::fs::symlink(outside.path(), root.path().join("outside")).unwrap();
+            assert_eq!(status_code(sftp.read("/outside/secret.txt").await), Some(StatusCode::PermissionDenied));
+        }
+        sftp.close().await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_sftp_mounts() {
+        let (root, media, archive) = (
+            tempdir().expect("Failed to create temp dir"),
+            tempdir().expect("Failed to create temp dir"),
+            tempdir().expect("Failed to create temp dir"),
+        );
+        std::fs::create_dir(root.path().join("docs")).unwrap();
+        std::fs::write(media.path().join("song.mp3"), b"music").unwrap();
+        std::fs::write(archive.path().join("old.txt"), b"old").unwrap();
+        let mounts = vec![mount("Media", media.path(), false), mount("Archive", archive.path(), true)];
+        let permissions = PermissionFlags::Read | PermissionFlags::Write | PermissionFlags::Create | PermissionFlags::Delete;
+        let user = User { id: 0, username: "sftpuser".to_string(), password: String::new(), permissions };
+
+        let (client_stream, server_stream) = tokio::io::duplex(64 * 1024);
+        russh_sftp::server::run(server_stream, SftpSession::new(user, root.path().to_path_buf(), tree(root.path(), mounts))).await;
+        let sftp = russh_sftp::client::SftpSession::new(client_stream).await.expect("Failed to start the SFTP session");
+
+        // Mounts are listed at the top of the tree and served from where they are stored, as over HTTP
+        let mut names: Vec<String> = sftp.read_dir("/").await.unwrap().map(|entry| entry.file_name()).collect();
+        names.sort();
+        assert_eq!(names, ["Archive", "Media", "docs"]);
+        let names: Vec<String> = sftp.read_dir("/Media").await.unwrap().map(|entry| entry.file_name()).collect();
+        assert_eq!(names, ["song.mp3"]);
+        assert_eq!(sftp.read("/Media/song.mp3").await.unwrap(), b"music");
+        assert_eq!(sftp.read("/Archive/old.txt").await.unwrap(), b"old");
+
+        let mut file = sftp.create("/Media/new.txt").await.unwrap();
+        file.write_all(b"uploaded").await.unwrap();
+        file.shutdown().await.unwrap();
+        assert_eq!(std::fs::read(media.path().join("new.txt")).unwrap(), b"uploaded");
+
+        // Read-only mounts refuse every change, and mounts can't be removed like the folders they appear as
+        assert_eq!(status_code(sftp.create("/Archive/new.txt").await), Some(StatusCode::PermissionDenied));
+        assert_eq!(status_code(sftp.remove_file("/Archive/old.txt").await), Some(StatusCode::PermissionDenied));
+        assert_eq!(status_code(sftp.create_dir("/Archive/folder").await), Some(StatusCode::PermissionDenied));
+        assert_eq!(status_code(sftp.remove_dir("/Media").await), Some(StatusCode::PermissionDenied));
+        assert!(archive.path().join("old.txt").exists());
+        assert!(!archive.path().join("new.txt").exists());
+        sftp.close().await.unwrap();
+    }
+}
@@ -0,0 +1,6 @@
+pub(crate) mod share_data;
+pub(crate) mod share_db;
+pub(crate) mod share_endpoint;
+
+#[cfg(test)]
+mod share_test;
@@ -0,0 +1,199 @@
+use crate::configuration::configuration_data::Configuration;
+use crate::io::fs::normalize_path::NormalizePath;
+use crate::io::storage::storage_backend;
+use crate::io::storage::storage_backend::StorageEntry;
+use serde::{Deserialize, Serialize, Serializer};
+use sqlx::{FromRow, Row};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a visitor of a share link is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareMode {
+    /// Visitors can only download the shared file or folder.
+    #[default]
+    ReadOnly,
+    /// Visitors can also upload files into the shared folder.
+    Upload,
+    /// Visitors can only upload files into the shared folder, without seeing its contents.
+    /// Uploaded files are prefixed to avoid overwriting each other.
+    Drop,
+}
+
+impl ShareMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ReadOnly => "read_only",
+            Self::Upload => "upload",
+            Self::Drop => "drop",
+        }
+    }
+
+    pub fn accepts_uploads(&self) -> bool {
+        matches!(self, Self::Upload | Self::Drop)
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "upload" => Self::Upload,
+            "drop" => Self::Drop,
+            _ => Self::ReadOnly,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateShareRequest {
+    pub path: String,
+    pub password: Option<String>,
+    /// Unix timestamp in seconds after which the link stops working.
+    pub expires_at: Option<i64>,
+    pub max_downloads: Option<i64>,
+    pub mode: Option<ShareMode>,
+    /// The total number of bytes visitors can upload through the link.
+    pub max_upload_size: Option<i64>,
+    /// The number of files visitors can upload through the link.
+    pub max_files: Option<i64>,
+    /// Extensions, without the leading dot, that uploaded files must have. Empty allows every extension.
+    #[serde(default)]
+    pub allowed_extensions: Vec<String>,
+}
+
+/// Changes to an existing share, fields that are not set are left untouched.
+///
+/// An empty `password` removes the password, and a limit of 0 removes the limit.
+#[derive(Deserialize)]
+pub struct UpdateShareRequest {
+    pub password: Option<String>,
+    pub expires_at: Option<i64>,
+    pub max_downloads: Option<i64>,
+    pub mode: Option<ShareMode>,
+    pub max_upload_size: Option<i64>,
+    pub max_files: Option<i64>,
+    pub allowed_extensions: Option<Vec<String>>,
+}
+
+/// A public link to a single file or folder.
+#[derive(Debug, Clone, Serialize)]
+pub struct Share {
+    pub id: i64,
+    pub slug: String,
+    /// The username of the user who created the share.
+    pub owner: String,
+    /// The shared path, as seen by clients of the filesystem endpoints.
+    pub path: String,
+    /// The bcrypt hash of the share password.
+    #[serde(rename = "has_password", serialize_with = "serialize_is_some")]
+    pub password: Option<String>,
+    pub expires_at: Option<i64>,
+    pub max_downloads: Option<i64>,
+    pub download_count: i64,
+    pub access_count: i64,
+    pub mode: ShareMode,
+    pub max_upload_size: Option<i64>,
+    pub max_files: Option<i64>,
+    pub allowed_extensions: Vec<String>,
+    pub upload_count: i64,
+    pub uploaded_bytes: i64,
+    pub created_at: i64,
+}
+
+fn serialize_is_some<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(value.is_some())
+}
+
+impl Share {
+    /// Generates a random, url-safe slug that is hard to guess.
+    pub fn generate_slug() -> String {
+        uuid::Uuid::new_v4().simple().to_string()[..16].to_string()
+    }
+
+    /// Whether the link has expired at the unix time `now`.
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// The number of downloads left before the link stops serving files, `None` if there is no limit.
+    pub fn downloads_remaining(&self) -> Option<i64> {
+        self.max_downloads.map(|max_downloads| (max_downloads - self.download_count).max(0))
+    }
+
+    /// The number of bytes that can still be uploaded, `None` if there is no limit.
+    pub fn upload_bytes_remaining(&self) -> Option<i64> {
+        self.max_upload_size.map(|max_upload_size| (max_upload_size - self.uploaded_bytes).max(0))
+    }
+
+    /// The number of files that can still be uploaded, `None` if there is no limit.
+    pub fn uploads_remaining(&self) -> Option<i64> {
+        self.max_files.map(|max_files| (max_files - self.upload_count).max(0))
+    }
+
+    /// Whether `filename` has one of the allowed extensions. Compound extensions such as `tar.gz` are supported.
+    pub fn accepts_extension(&self, filename: &str) -> bool {
+        let filename = filename.to_lowercase();
+        self.allowed_extensions.is_empty()
+            || self.allowed_extensions.iter().any(|extension| filename.ends_with(&format!(".{}", extension.trim_start_matches('.').to_lowercase())))
+    }
+
+    /// The name an uploaded file is stored under.
+    ///
+    /// Drop folders prefix the name with the upload time and a random tag, so visitors never overwrite each other's files.
+    pub fn stored_filename(&self, filename: &str) -> String {
+        if self.mode == ShareMode::Drop {
+            format!("{}-{}_{}", unix_now(), &uuid::Uuid::new_v4().simple().to_string()[..6], filename)
+        } else {
+            filename.to_string()
+        }
+    }
+
+    pub fn verify_password(&self, password: Option<&str>) -> anyhow::Result<bool> {
+        match (&self.password, password) {
+            (None, _) => Ok(true),
+            (Some(_), None) => Ok(false),
+            (Some(hash), Some(password)) => Ok(bcrypt::verify(password, hash)?),
+        }
+    }
+
+    /// Resolves the shared path through the storage backend that holds it.
+    ///
+    /// Returns `None` when the path no longer exists, instead of falling back to the root path.
+    pub async fn resolve(&self) -> Option<StorageEntry> {
+        let os_path = self.path.to_os_path();
+        let is_root = self.path.trim_matches('/').is_empty();
+        if !is_root && os_path == Path::new(&Configuration::get().root_path) {
+            return None;
+        }
+        storage_backend::backend_for(&os_path).stat(&os_path).await.ok()
+    }
+}
+
+impl<'r> FromRow<'r, sqlx::sqlite::SqliteRow> for Share {
+    fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let mode: String = row.try_get("mode")?;
+        let allowed_extensions: String = row.try_get("allowed_extensions")?;
+        Ok(Share {
+            id: row.try_get("id")?,
+            slug: row.try_get("slug")?,
+            owner: row.try_get("owner")?,
+            path: row.try_get("path")?,
+            password: row.try_get("password")?,
+            expires_at: row.try_get("expires_at")?,
+            max_downloads: row.try_get("max_downloads")?,
+            download_count: row.try_get("download_count")?,
+            access_count: row.try_get("access_count")?,
+            mode: ShareMode::parse(&mode),
+            max_upload_size: row.try_get("max_upload_size")?,
+            max_files: row.try_get("max_files")?,
+            allowed_extensions: allowed_extensions.split(',').filter(|extension| !extension.is_empty()).map(String::from).collect(),
+            upload_count: row.try_get("upload_count")?,
+            uploaded_bytes: row.try_get("uploaded_bytes")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+/// The current unix time in seconds.
+pub fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or_default()
+}
@@ -0,0 +1,211 @@
+use crate::helpers::db::create_pool;
+use crate::shares::share_data::{Share, unix_now};
+use anyhow::Result;
+use bcrypt::DEFAULT_COST;
+use sqlx::{Executor, SqlitePool};
+
+pub async fn initialize() -> Result<()> {
+    let pool = create_pool().await?;
+    initialize_with_pool(&pool).await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+pub async fn initialize_with_pool(pool: &SqlitePool) -> Result<()> {
+    pool.execute(
+        r#"
+CREATE TABLE IF NOT EXISTS shares
+(
+    id             INTEGER PRIMARY KEY,
+    slug           TEXT    NOT NULL UNIQUE,
+    owner          TEXT    NOT NULL,
+    path           TEXT    NOT NULL,
+    password       TEXT             DEFAULT NULL,
+    expires_at     INTEGER          DEFAULT NULL,
+    max_downloads  INTEGER          DEFAULT NULL,
+    download_count INTEGER NOT NULL DEFAULT 0,
+    access_count   INTEGER NOT NULL DEFAULT 0,
+    mode           TEXT    NOT NULL DEFAULT 'read_only',
+    max_upload_size    INTEGER          DEFAULT NULL,
+    max_files          INTEGER          DEFAULT NULL,
+    allowed_extensions TEXT    NOT NULL DEFAULT '',
+    upload_count       INTEGER NOT NULL DEFAULT 0,
+    uploaded_bytes     INTEGER NOT NULL DEFAULT 0,
+    created_at     INTEGER NOT NULL
+)
+"#,
+    )
+    .await?;
+    Ok(())
+}
+
+impl Share {
+    /// Inserts the share, hashing `password` if one is set.
+    pub async fn create(&mut self) -> Result<()> {
+        let pool = create_pool().await?;
+        self.create_with_pool(&pool).await
+    }
+
+    pub async fn create_with_pool(&mut self, pool: &SqlitePool) -> Result<()> {
+        self.password = self.password.as_ref().map(|password| bcrypt::hash(password, DEFAULT_COST)).transpose()?;
+        self.created_at = unix_now();
+        let result = sqlx::query(
+            "insert into shares (slug, owner, path, password, expires_at, max_downloads, mode, max_upload_size, max_files, allowed_extensions, created_at) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&self.slug)
+        .bind(&self.owner)
+        .bind(&self.path)
+        .bind(&self.password)
+        .bind(self.expires_at)
+        .bind(self.max_downloads)
+        .bind(self.mode.as_str())
+        .bind(self.max_upload_size)
+        .bind(self.max_files)
+        .bind(self.allowed_extensions.join(","))
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+        self.id = result.last_insert_rowid();
+        Ok(())
+    }
+
+    pub async fn list_by_owner(owner: impl AsRef<str>) -> Result<Vec<Self>> {
+        let pool = create_pool().await?;
+        Self::list_by_owner_with_pool(owner, &pool).await
+    }
+
+    pub async fn list_by_owner_with_pool(owner: impl AsRef<str>, pool: &SqlitePool) -> Result<Vec<Self>> {
+        let shares =
+            sqlx::query_as::<_, Self>("select * from shares where owner = ? order by created_at desc").bind(owner.as_ref()).fetch_all(pool).await?;
+        Ok(shares)
+    }
+
+    pub async fn get_by_slug(slug: impl AsRef<str>) -> Result<Option<Self>> {
+        let pool = create_pool().await?;
+        Self::get_by_slug_with_pool(slug, &pool).await
+    }
+
+    pub async fn get_by_slug_with_pool(slug: impl AsRef<str>, pool: &SqlitePool) -> Result<Option<Self>> {
+        let share = sqlx::query_as::<_, Self>("select * from shares where slug = ? limit 1").bind(slug.as_ref()).fetch_optional(pool).await?;
+        Ok(share)
+    }
+
+    /// Updates the limits and mode of the share. The password is expected to already be hashed.
+    pub async fn update(&self) -> Result<()> {
+        let pool = create_pool().await?;
+        self.update_with_pool(&pool).await
+    }
+
+    pub async fn update_with_pool(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query(
+            "update shares set password = ?, expires_at = ?, max_downloads = ?, mode = ?, max_upload_size = ?, max_files = ?, allowed_extensions = ? where slug = ?",
+        )
+            .bind(&self.password)
+            .bind(self.expires_at)
+            .bind(self.max_downloads)
+            .bind(self.mode.as_str())
+            .bind(self.max_upload_size)
+            .bind(self.max_files)
+            .bind(self.allowed_extensions.join(","))
+            .bind(&self.slug)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self) -> Result<()> {
+        let pool = create_pool().await?;
+        self.delete_with_pool(&pool).await
+    }
+
+    pub async fn delete_with_pool(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query("delete from shares where slug = ?").bind(&self.slug).execute(pool).await?;
+        Ok(())
+    }
+
+    /// Deletes the shares that expired before the unix time `now`, returning how many there were.
+    pub async fn delete_expired(now: i64) -> Result<u64> {
+        let pool = create_pool().await?;
+        Self::delete_expired_with_pool(now, &pool).await
+    }
+
+    pub async fn delete_expired_with_pool(now: i64, pool: &SqlitePool) -> Result<u64> {
+        let result = sqlx::query("delete from shares where expires_at is not null and expires_at <= ?").bind(now).execute(pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn record_access(&self) -> Result<()> {
+        let pool = create_pool().await?;
+        self.record_access_with_pool(&pool).await
+    }
+
+    pub async fn record_access_with_pool(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query("update shares set access_count = access_count + 1 where slug = ?").bind(&self.slug).execute(pool).await?;
+        Ok(())
+    }
+
+    /// Counts a download against the share's limit.
+    ///
+    /// Returns `false` when the limit has already been reached. The check and the increment happen in a
+    /// single statement, so concurrent downloads can not exceed the limit.
+    pub async fn record_download(&self) -> Result<bool> {
+        let pool = create_pool().await?;
+        self.record_download_with_pool(&pool).await
+    }
+
+    pub async fn record_download_with_pool(&self, pool: &SqlitePool) -> Result<bool> {
+        let result = sqlx::query(
+            "update shares set download_count = download_count + 1 where slug = ? and (max_downloads is null or download_count < max_downloads)",
+        )
+        .bind(&self.slug)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Reserves one of the share's upload slots.
+    ///
+    /// Returns `false` when the file limit has already been reached. The slot is given back with
+    /// [`Share::release_upload`] if the upload fails.
+    pub async fn reserve_upload(&self) -> Result<bool> {
+        let pool = create_pool().await?;
+        self.reserve_upload_with_pool(&pool).await
+    }
+
+    pub async fn reserve_upload_with_pool(&self, pool: &SqlitePool) -> Result<bool> {
+        let result =
+            sqlx::query("update shares set upload_count = upload_count + 1 where slug = ? and (max_files is null or upload_count < max_files)")
+                .bind(&self.slug)
+                .execute(pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn release_upload(&self) -> Result<()> {
+        let pool = create_pool().await?;
+        sqlx::query("update shares set upload_count = max(upload_count - 1, 0) where slug = ?").bind(&self.slug).execute(&pool).await?;
+        Ok(())
+    }
+
+    /// Adds `bytes` to the share's uploaded total.
+    ///
+    /// Returns `false`, leaving the total untouched, when it would go past the share's size limit. This
+    /// keeps concurrent uploads from exceeding the limit together.
+    pub async fn record_upload(&self, bytes: i64) -> Result<bool> {
+        let pool = create_pool().await?;
+        self.record_upload_with_pool(bytes, &pool).await
+    }
+
+    pub async fn record_upload_with_pool(&self, bytes: i64, pool: &SqlitePool) -> Result<bool> {
+        let result = sqlx::query(
+            "update shares set uploaded_bytes = uploaded_bytes + ? where slug = ? and (max_upload_size is null or uploaded_bytes + ? <= max_upload_size)",
+        )
+        .bind(bytes)
+        .bind(&self.slug)
+        .bind(bytes)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,324 @@
+use crate::auth::auth_middleware::{Authentication, authenticated_user};
+use crate::helpers::http_error::{Error, Result};
+use crate::io::fs::archive_options::{ArchiveFormat, ArchiveOptions};
+use crate::io::fs::filesystem_endpoint::{receive_upload, stream_items, track_upload_progress};
+use crate::io::storage::storage_backend;
+use crate::io::storage::storage_backend::StorageEntry;
+use crate::shares::share_data::{CreateShareRequest, Share, ShareMode, UpdateShareRequest, unix_now};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, put, web};
+use bcrypt::DEFAULT_COST;
+use log::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+
+/// The header visitors can use to send the share password instead of the `password` query parameter.
+const SHARE_PASSWORD_HEADER: &str = "X-Share-Password";
+
+#[derive(Deserialize)]
+struct ShareParameters {
+    password: Option<String>,
+    /// The archive format used when the share is a folder.
+    format: Option<ArchiveFormat>,
+}
+
+fn share_json(share: &Share) -> serde_json::Value {
+    let mut value = json!(share);
+    value["url"] = json!(format!("/s/{}", share.slug));
+    value["downloads_remaining"] = json!(share.downloads_remaining());
+    value
+}
+
+/// Lowercases the allowed extensions and strips their leading dots.
+fn normalize_extensions(extensions: Vec<String>) -> Result<Vec<String>> {
+    let extensions: Vec<String> = extensions
+        .iter()
+        .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .collect();
+    if extensions.iter().any(|extension| extension.contains(',') || extension.contains('/')) {
+        return Err(Error::validation_error("Extensions can not contain commas or slashes", Some("allowed_extensions")));
+    }
+    Ok(extensions)
+}
+
+/// Checks the limits and mode of a share before it is saved.
+async fn validate_share(share: &Share) -> Result<()> {
+    if share.mode.accepts_uploads() {
+        match share.resolve().await {
+            Some(entry) if entry.is_dir => {
+                if storage_backend::backend_for(&entry.path).is_read_only() {
+                    return Err(Error::validation_error("Folders on a read-only mount can not accept uploads", Some("mode")));
+                }
+            }
+            _ => return Err(Error::validation_error("Only folders can accept uploads", Some("mode"))),
+        }
+    }
+    if share.max_downloads.is_some_and(|max_downloads| max_downloads < 1) {
+        return Err(Error::validation_error("The download limit must be at least 1", Some("max_downloads")));
+    }
+    if share.max_upload_size.is_some_and(|max_upload_size| max_upload_size < 1) {
+        return Err(Error::validation_error("The upload size limit must be at least 1 byte", Some("max_upload_size")));
+    }
+    if share.max_files.is_some_and(|max_files| max_files < 1) {
+        return Err(Error::validation_error("The file limit must be at least 1", Some("max_files")));
+    }
+    Ok(())
+}
+
+/// The key uploads through a share are tracked under, kept apart from the uploads of signed in users.
+fn upload_key(share: &Share, upload_id: &str) -> String {
+    format!("share:{}:{}", share.slug, upload_id)
+}
+
+/// Loads a share owned by the current user, hiding shares of other users behind a 404.
+async fn owned_share(req: &HttpRequest, slug: &str) -> Result<Share> {
+    let user = authenticated_user(req)?;
+    match Share::get_by_slug(slug).await? {
+        Some(share) if share.owner == user.username => Ok(share),
+        _ => Err(Error::not_found(format!("Share {} does not exist", slug))),
+    }
+}
+
+#[post("")]
+async fn create_share(req: HttpRequest, body: web::Json<CreateShareRequest>) -> Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    let body = body.into_inner();
+    let mode = body.mode.unwrap_or_default();
+    let mut share = Share {
+        id: 0,
+        slug: Share::generate_slug(),
+        owner: user.username,
+        path: body.path,
+        password: body.password.filter(|password| !password.is_empty()),
+        expires_at: body.expires_at,
+        max_downloads: body.max_downloads,
+        download_count: 0,
+        access_count: 0,
+        mode,
+        max_upload_size: body.max_upload_size,
+        max_files: body.max_files,
+        allowed_extensions: normalize_extensions(body.allowed_extensions)?,
+        upload_count: 0,
+        uploaded_bytes: 0,
+        created_at: 0,
+    };
+
+    share.resolve().await.ok_or_else(|| Error::not_found(share.path.clone()))?;
+    if share.expires_at.is_some_and(|expires_at| expires_at <= unix_now()) {
+        return Err(Error::validation_error("The expiry time must be in the future", Some("expires_at")));
+    }
+    validate_share(&share).await?;
+
+    share.create().await?;
+    info!("{} shared {} as {}", share.owner, share.path, share.slug);
+    Ok(HttpResponse::Created().json(share_json(&share)))
+}
+
+#[get("")]
+async fn list_shares(req: HttpRequest) -> Result<HttpResponse> {
+    let user = authenticated_user(&req)?;
+    let shares = Share::list_by_owner(&user.username).await?;
+    Ok(HttpResponse::Ok().json(shares.iter().map(share_json).collect::<Vec<_>>()))
+}
+
+#[put("/{slug}")]
+async fn update_share(req: HttpRequest, slug: web::Path<String>, body: web::Json<UpdateShareRequest>) -> Result<HttpResponse> {
+    let mut share = owned_share(&req, &slug).await?;
+
+    if let Some(password) = &body.password {
+        share.password = if password.is_empty() { None } else { Some(bcrypt::hash(password, DEFAULT_COST).map_err(anyhow::Error::from)?) };
+    }
+    if let Some(expires_at) = body.expires_at {
+        share.expires_at = if expires_at == 0 { None } else { Some(expires_at) };
+    }
+    if let Some(max_downloads) = body.max_downloads {
+        share.max_downloads = if max_downloads == 0 { None } else { Some(max_downloads) };
+    }
+    if let Some(max_upload_size) = body.max_upload_size {
+        share.max_upload_size = if max_upload_size == 0 { None } else { Some(max_upload_size) };
+    }
+    if let Some(max_files) = body.max_files {
+        share.max_files = if max_files == 0 { None } else { Some(max_files) };
+    }
+    if let Some(allowed_extensions) = &body.allowed_extensions {
+        share.allowed_extensions = normalize_extensions(allowed_extensions.clone())?;
+    }
+    if let Some(mode) = body.mode {
+        share.mode = mode;
+    }
+    validate_share(&share).await?;
+
+    share.update().await?;
+    Ok(HttpResponse::Ok().json(share_json(&share)))
+}
+
+#[delete("/{slug}")]
+async fn delete_share(req: HttpRequest, slug: web::Path<String>) -> Result<HttpResponse> {
+    let share = owned_share(&req, &slug).await?;
+    share.delete().await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "deleted",
+        "slug": share.slug
+    })))
+}
+
+/// Loads a share for an anonymous visitor, checking its expiry and password.
+async fn open_share(req: &HttpRequest, slug: &str, password: Option<&str>) -> Result<(Share, StorageEntry)> {
+    let share = Share::get_by_slug(slug).await?.ok_or_else(|| Error::not_found(format!("Share {} does not exist", slug)))?;
+    if share.is_expired(unix_now()) {
+        return Err(Error::gone("This share link has expired"));
+    }
+
+    let password = password.or_else(|| req.headers().get(SHARE_PASSWORD_HEADER).and_then(|header| header.to_str().ok()));
+    if !share.verify_password(password)? {
+        return Err(Error::authentication_error("A valid share password is required"));
+    }
+
+    let entry = share.resolve().await.ok_or_else(|| Error::gone("The shared file no longer exists"))?;
+    Ok((share, entry))
+}
+
+#[get("/{slug}/info")]
+async fn share_info(req: HttpRequest, slug: web::Path<String>, query: web::Query<ShareParameters>) -> Result<HttpResponse> {
+    let (share, shared) = open_share(&req, &slug, query.password.as_deref()).await?;
+    share.record_access().await?;
+    let mut entries = Vec::new();
+    // Drop folders never reveal what other visitors have uploaded
+    if shared.is_dir && share.mode != ShareMode::Drop {
+        for entry in storage_backend::list(&shared.path).await? {
+            entries.push(json!({
+                "filename": entry.name,
+                "size": entry.size,
+                "is_dir": entry.is_dir,
+            }));
+        }
+    }
+
+    // The path on the server is deliberately not exposed to visitors
+    Ok(HttpResponse::Ok().json(json!({
+        "slug": share.slug,
+        "filename": shared.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        "size": if share.mode == ShareMode::Drop { 0 } else { shared.size },
+        "is_dir": shared.is_dir,
+        "mode": share.mode,
+        "expires_at": share.expires_at,
+        "downloads_remaining": share.downloads_remaining(),
+        "uploads_remaining": share.uploads_remaining(),
+        "upload_bytes_remaining": share.upload_bytes_remaining(),
+        "allowed_extensions": share.allowed_extensions,
+        "entries": entries,
+    })))
+}
+
+#[get("/{slug}")]
+async fn download_share(req: HttpRequest, slug: web::Path<String>, query: web::Query<ShareParameters>) -> Result<HttpResponse> {
+    let (share, shared) = open_share(&req, &slug, query.password.as_deref()).await?;
+    share.record_access().await?;
+    if share.mode == ShareMode::Drop {
+        return Err(Error::permission_denied("Files can not be downloaded from a drop folder"));
+    }
+    if !share.record_download().await? {
+        return Err(Error::gone("This share link has reached its download limit"));
+    }
+
+    let path = shared.path;
+    debug!("Downloading share {}: {}", share.slug, path.display());
+    let cwd = path.parent().map(Path::to_path_buf).unwrap_or_else(|| path.clone());
+    let archive_options = ArchiveOptions { format: query.format, ..Default::default() };
+    stream_items(cwd, vec![path], archive_options).await
+}
+
+#[post("/{slug}/upload")]
+async fn upload_to_share(
+    req: HttpRequest,
+    slug: web::Path<String>,
+    query: web::Query<ShareParameters>,
+    payload: web::Payload,
+) -> Result<HttpResponse> {
+    let (share, shared) = open_share(&req, &slug, query.password.as_deref()).await?;
+    share.record_access().await?;
+    if !share.mode.accepts_uploads() || !shared.is_dir {
+        return Err(Error::permission_denied("This share link does not accept uploads"));
+    }
+    // The mount may have been made read-only since the share was created
+    let backend = storage_backend::backend_for(&shared.path);
+    if backend.is_read_only() {
+        return Err(Error::permission_denied("The shared folder is read-only"));
+    }
+
+    let upload_id = req
+        .headers()
+        .get("X-Upload-ID")
+        .and_then(|header| header.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    // Only the final component of the filename is used, so visitors can't write outside the shared folder
+    let filename = req
+        .headers()
+        .get("X-Filename")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|filename| Path::new(filename).file_name().map(|name| name.to_string_lossy().to_string()))
+        .ok_or_else(|| Error::validation_error("X-Filename header is required", Some("X-Filename")))?;
+    if !share.accepts_extension(&filename) {
+        return Err(Error::validation_error(format!("Only {} files can be uploaded", share.allowed_extensions.join(", ")), Some("X-Filename")));
+    }
+
+    let max_bytes = share.upload_bytes_remaining();
+    let content_length = req.headers().get("Content-Length").and_then(|header| header.to_str().ok()).and_then(|length| length.parse::<i64>().ok());
+    if let (Some(max_bytes), Some(content_length)) = (max_bytes, content_length)
+        && content_length > max_bytes
+    {
+        return Ok(HttpResponse::PayloadTooLarge().json(json!({
+            "error": "The upload exceeds the size limit of this share link"
+        })));
+    }
+
+    let target = shared.path.join(share.stored_filename(&filename));
+    if backend.stat(&target).await.is_ok() {
+        return Err(Error::validation_error(format!("{} already exists", filename), Some("X-Filename")));
+    }
+    if !share.reserve_upload().await? {
+        return Err(Error::gone("This share link has reached its file limit"));
+    }
+
+    let response = receive_upload(payload, target.clone(), upload_key(&share, &upload_id), max_bytes.map(|max_bytes| max_bytes as u64)).await;
+    let uploaded_bytes = backend.stat(&target).await.map(|entry| entry.size as i64).ok();
+    match uploaded_bytes {
+        Some(bytes) if response.status().is_success() => {
+            if !share.record_upload(bytes).await? {
+                backend.delete(&target).await.ok();
+                share.release_upload().await?;
+                return Ok(HttpResponse::PayloadTooLarge().json(json!({
+                    "error": "The upload exceeds the size limit of this share link"
+                })));
+            }
+            info!("Received {} ({} bytes) through share {}", target.display(), bytes, share.slug);
+        }
+        _ => {
+            // Failed and cancelled uploads give their slot back and don't leave partial files behind
+            backend.delete(&target).await.ok();
+            share.release_upload().await?;
+        }
+    }
+    Ok(response)
+}
+
+#[get("/{slug}/upload/progress/{upload_id}")]
+async fn share_upload_progress(req: HttpRequest, path: web::Path<(String, String)>, query: web::Query<ShareParameters>) -> Result<impl Responder> {
+    let (slug, upload_id) = path.into_inner();
+    let (share, _) = open_share(&req, &slug, query.password.as_deref()).await?;
+    Ok(track_upload_progress(upload_key(&share, &upload_id)).await)
+}
+
+/// Registers the owner management endpoints, which require authentication.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/shares").wrap(Authentication::new()).service(create_share).service(list_shares).service(update_share).service(delete_share),
+    );
+}
+
+/// Registers the public `/s/{slug}` routes that visitors of a share link use.
+pub fn configure_public(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/s").service(share_info).service(download_share).service(upload_to_share).service(share_upload_progress));
+}
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use crate::shares::share_data::{Share, ShareMode};
+    use crate::shares::share_db;
+    use sqlx::SqlitePool;
+
+    fn share(password: Option<&str>, expires_at: Option<i64>, max_downloads: Option<i64>) -> Share {
+        Share {
+            id: 0,
+            slug: Share::generate_slug(),
+            owner: "testuser".to_string(),
+            path: "/documents".to_string(),
+            password: password.map(String::from),
+            expires_at,
+            max_downloads,
+            download_count: 0,
+            access_count: 0,
+            mode: ShareMode::ReadOnly,
+            max_upload_size: None,
+            max_files: None,
+            allowed_extensions: Vec::new(),
+            upload_count: 0,
+            uploaded_bytes: 0,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_generate_slug() {
+        let slug = Share::generate_slug();
+        assert_eq!(slug.len(), 16);
+        assert!(slug.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(slug, Share::generate_slug());
+    }
+
+    #[test]
+    fn test_share_expiry_and_limits() {
+        let mut share = share(None, Some(1_000), Some(2));
+        assert!(!share.is_expired(999));
+        assert!(share.is_expired(1_000));
+        assert_eq!(share.downloads_remaining(), Some(2));
+        share.download_count = 5;
+        assert_eq!(share.downloads_remaining(), Some(0));
+        assert!(!self::share(None, None, None).is_expired(i64::MAX));
+    }
+
+    #[test]
+    fn test_share_serialization_hides_password() {
+        let json = serde_json::to_value(share(Some("$2b$04$hash"), None, None)).unwrap();
+        assert_eq!(json["has_password"], true);
+        assert_eq!(json["mode"], "read_only");
+        assert!(!json.to_string().contains("$2b$04$hash"));
+    }
+
+    #[actix_web::test]
+    async fn test_share_password_and_download_limit() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.expect("Failed to open database");
+        share_db::initialize_with_pool(&pool).await.expect("Failed to create shares table");
+
+        let mut created = share(Some("secret"), None, Some(1));
+        created.create_with_pool(&pool).await.expect("Failed to create share");
+        let stored = Share::get_by_slug_with_pool(&created.slug, &pool).await.unwrap().expect("Share not found");
+        assert!(stored.verify_password(Some("secret")).unwrap());
+        assert!(!stored.verify_password(Some("wrong")).unwrap());
+        assert!(!stored.verify_password(None).unwrap());
+
+        assert!(stored.record_download_with_pool(&pool).await.unwrap());
+        assert!(!stored.record_download_with_pool(&pool).await.unwrap());
+        let stored = Share::get_by_slug_with_pool(&created.slug, &pool).await.unwrap().unwrap();
+        assert_eq!(stored.download_count, 1);
+        assert_eq!(Share::list_by_owner_with_pool("testuser", &pool).await.unwrap().len(), 1);
+
+        // Only links past their expiry are cleaned up
+        share(None, Some(1_000), None).create_with_pool(&pool).await.unwrap();
+        share(None, Some(2_000), None).create_with_pool(&pool).await.unwrap();
+        assert_eq!(Share::delete_expired_with_pool(1_500, &pool).await.unwrap(), 1);
+        assert_eq!(Share::list_by_owner_with_pool("testuser", &pool).await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_drop_folder_filenames() {
+        let mut drop = share(None, None, None);
+        drop.allowed_extensions = vec!["pdf".to_string(), "tar.gz".to_string()];
+        assert!(drop.accepts_extension("invoice.PDF"));
+        assert!(drop.accepts_extension("deliverables.tar.gz"));
+        assert!(!drop.accepts_extension("payload.exe"));
+
+        assert_eq!(drop.stored_filename("invoice.pdf"), "invoice.pdf");
+        drop.mode = ShareMode::Drop;
+        let stored = drop.stored_filename("invoice.pdf");
+        assert!(stored.ends_with("_invoice.pdf"));
+        assert_ne!(stored, drop.stored_filename("invoice.pdf"));
+    }
+
+    #[actix_web::test]
+    async fn test_drop_folder_limits() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.expect("Failed to open database");
+        share_db::initialize_with_pool(&pool).await.expect("Failed to create shares table");
+
+        let mut drop = share(None, None, None);
+        drop.mode = ShareMode::Drop;
+        drop.max_files = Some(1);
+        drop.max_upload_size = Some(100);
+        drop.create_with_pool(&pool).await.expect("Failed to create share");
+
+        assert!(drop.reserve_upload_with_pool(&pool).await.unwrap());
+        assert!(!drop.reserve_upload_with_pool(&pool).await.unwrap());
+        assert!(drop.record_upload_with_pool(60, &pool).await.unwrap());
+        assert!(!drop.record_upload_with_pool(60, &pool).await.unwrap());
+
+        let stored = Share::get_by_slug_with_pool(&drop.slug, &pool).await.unwrap().unwrap();
+        assert_eq!(stored.mode, ShareMode::Drop);
+        assert_eq!(stored.uploads_remaining(), Some(0));
+        assert_eq!(stored.upload_bytes_remaining(), Some(40));
+    }
+}
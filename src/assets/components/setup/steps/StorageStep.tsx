@@ -64,7 +64,7 @@ export default function StorageStep()
             "**/Temp/**",
             "**/tmp/**"
         ],
-        included_extensions: [".txt", ".pdf", ".doc", ".docx", ".jpg", ".png", ".mp4", ".mp3"],
+        included_extensions: [".txt", ".md", ".csv", ".json", ".pdf", ".doc", ".docx", ".jpg", ".png", ".mp4", ".mp3"],
         exclude_hidden_files: true
     });
 
@@ -21,6 +21,7 @@ type FilesystemSearchResult = {
     size: number;
     ctime: number;
     mtime: number;
+    score?: number;
 }
 
 /**
@@ -380,7 +381,7 @@ export class FileSystem
             const errorData = await response.json();
             throw new Error(errorData.error || `Failed to search: ${response.statusText}`);
         }
-        const results = await response.json() as FilesystemSearchResult[];
+        const {results} = await response.json() as { results: FilesystemSearchResult[], total: number, next_cursor: string | null };
         return results.map(result =>
         {
             let entry: FilesystemEntry = {